@@ -0,0 +1,74 @@
+use async_trait::async_trait;
+use criterion::{Criterion, criterion_group, criterion_main};
+use healthcheck_service::checks::{CheckRegistry, CheckResult, CheckStatus, HealthCheck, Selector};
+use healthcheck_service::config::Config;
+use healthcheck_service::server::Server;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// Address of the service started for the endpoint benchmarks
+const LISTEN: &str = "127.0.0.1:5199";
+
+struct Up;
+
+#[async_trait]
+impl HealthCheck for Up {
+    async fn check(&self) -> CheckResult {
+        CheckResult::new(CheckStatus::Up, None)
+    }
+}
+
+fn registry(checks: usize) -> CheckRegistry {
+    let mut registry = CheckRegistry::default();
+    let interval = Duration::from_secs(10);
+    for i in 0..checks {
+        registry.register(
+            &format!("check-{i}"),
+            true,
+            interval,
+            interval,
+            Box::new(Up),
+        );
+    }
+    registry
+}
+
+// Executing checks and recording their results, then aggregating them for readiness
+fn recording(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let registry = registry(100);
+    let all = Selector::default();
+    c.bench_function("run_once/100 checks", |b| {
+        b.to_async(&runtime).iter(|| registry.run_once(&all))
+    });
+    c.bench_function("report/100 checks", |b| b.iter(|| registry.report(&all)));
+}
+
+// Requests served by a running instance over a keep-alive connection
+fn endpoints(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let config = Config {
+        listen: Some(LISTEN.into()),
+        ..Config::default()
+    };
+    runtime.spawn(Server::new(config).unwrap().run());
+    let client = reqwest::Client::new();
+    runtime.block_on(async {
+        let url = format!("http://{LISTEN}/health/live");
+        while client.get(&url).send().await.is_err() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    });
+    for path in ["/health/live", "/health/ready", "/metrics"] {
+        let url = format!("http://{LISTEN}{path}");
+        c.bench_function(&format!("GET {path}"), |b| {
+            b.to_async(&runtime).iter(|| async {
+                let response = client.get(&url).send().await.unwrap();
+                response.bytes().await.unwrap()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, recording, endpoints);
+criterion_main!(benches);
@@ -0,0 +1,93 @@
+//! Load-test harness: requests an endpoint of a running instance from concurrent keep-alive
+//! connections, reports the rate and latencies, and fails unless the target rate is sustained
+//!
+//! ```bash
+//! cargo run --release --example loadtest -- http://127.0.0.1:5000/health/live --target-rps 20000
+//! ```
+use clap::Parser;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+#[derive(Parser)]
+struct Args {
+    #[arg(default_value = "http://127.0.0.1:5000/health/live")]
+    url: String,
+    /// Concurrent connections
+    #[arg(long, default_value_t = 64)]
+    concurrency: usize,
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    duration: Duration,
+    /// Successful responses per second below which the run fails
+    #[arg(long)]
+    target_rps: Option<f64>,
+}
+
+#[derive(Default)]
+struct Outcome {
+    latencies: Vec<Duration>,
+    errors: u64,
+}
+
+async fn worker(client: reqwest::Client, url: String, deadline: Instant) -> Outcome {
+    let mut outcome = Outcome::default();
+    while Instant::now() < deadline {
+        let start = Instant::now();
+        match client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => {
+                // Read the body so the connection is reused
+                match response.bytes().await {
+                    Ok(_) => outcome.latencies.push(start.elapsed()),
+                    Err(_) => outcome.errors += 1,
+                }
+            }
+            _ => outcome.errors += 1,
+        }
+    }
+    outcome
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let index = ((sorted.len() as f64 - 1.0) * p).round() as usize;
+    sorted.get(index).copied().unwrap_or_default()
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(args.concurrency)
+        .build()
+        .unwrap();
+    let start = Instant::now();
+    let deadline = start + args.duration;
+    let workers: Vec<_> = (0..args.concurrency)
+        .map(|_| tokio::spawn(worker(client.clone(), args.url.clone(), deadline)))
+        .collect();
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+    for worker in workers {
+        let outcome = worker.await.unwrap();
+        latencies.extend(outcome.latencies);
+        errors += outcome.errors;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    latencies.sort();
+    let rps = latencies.len() as f64 / elapsed;
+    println!(
+        "{} requests in {elapsed:.1}s ({rps:.0}/s), {errors} errors",
+        latencies.len()
+    );
+    println!(
+        "latency p50 {:?}, p99 {:?}, max {:?}",
+        percentile(&latencies, 0.5),
+        percentile(&latencies, 0.99),
+        latencies.last().copied().unwrap_or_default()
+    );
+    match args.target_rps {
+        Some(target) if rps < target => {
+            eprintln!("error: {rps:.0} requests per second, below the target of {target}");
+            ExitCode::FAILURE
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
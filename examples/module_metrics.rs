@@ -1,11 +1,14 @@
 use opentelemetry::KeyValue;
-use opentelemetry::metrics::MeterProvider;
+use opentelemetry::metrics::{Counter, MeterProvider};
 use opentelemetry_sdk::metrics::{MeterProviderBuilder, SdkMeterProvider};
 use prometheus::{Encoder, Registry, TextEncoder};
 
 struct ModuleMetrics {
     registry: Registry,
-    provider: SdkMeterProvider,
+    // Keeps the exporter alive for as long as the instruments are used
+    _provider: SdkMeterProvider,
+    tasks: Counter<u64>,
+    attributes: [KeyValue; 1],
 }
 
 impl ModuleMetrics {
@@ -18,13 +21,18 @@ impl ModuleMetrics {
         let provider = MeterProviderBuilder::default()
             .with_reader(exporter)
             .build();
-        Self { registry, provider }
+        // Instruments and attributes are built once, not on every increment
+        let tasks = provider.meter("module").u64_counter("tasks_total").build();
+        Self {
+            registry,
+            _provider: provider,
+            tasks,
+            attributes: [KeyValue::new("module", name.to_string())],
+        }
     }
 
     fn increment_counter(&self) {
-        let meter = self.provider.meter("module");
-        let counter = meter.u64_counter("tasks_total").build();
-        counter.add(1, &[KeyValue::new("module", "background")]);
+        self.tasks.add(1, &self.attributes);
     }
 
     fn gather(&self) -> String {
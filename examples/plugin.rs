@@ -0,0 +1,54 @@
+//! Minimal checker plugin, built with `cargo build --example plugin --features plugins`
+//! and loaded by copying the library into `plugins_dir`.
+//!
+//! ```toml
+//! [[checks]]
+//! name = "disk"
+//! type = "plugin"
+//! plugin = "free-space"
+//! config = { path = "/", min_free_percent = 10 }
+//! ```
+
+use healthcheck_service::checks::CheckStatus;
+use healthcheck_service::export_plugin;
+use healthcheck_service::plugin::CheckPlugin;
+use std::ffi::CStr;
+use std::path::PathBuf;
+use sysinfo::Disks;
+
+struct FreeSpace {
+    path: PathBuf,
+    min_free_percent: f64,
+}
+
+impl CheckPlugin for FreeSpace {
+    const NAME: &'static CStr = c"free-space";
+    const VERSION: &'static CStr = c"0.1.0";
+
+    fn new(config: serde_json::Value) -> Result<Self, String> {
+        let path = config["path"].as_str().ok_or("missing path")?;
+        Ok(Self {
+            path: PathBuf::from(path),
+            min_free_percent: config["min_free_percent"].as_f64().unwrap_or(10.0),
+        })
+    }
+
+    fn check(&mut self) -> (CheckStatus, Option<String>) {
+        let disks = Disks::new_with_refreshed_list();
+        let Some(disk) = disks
+            .iter()
+            .filter(|disk| self.path.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        else {
+            return (CheckStatus::Down, Some("no disk mounted there".into()));
+        };
+        let free = disk.available_space() as f64 * 100.0 / disk.total_space().max(1) as f64;
+        let message = format!("{free:.1}% free on {}", disk.mount_point().display());
+        match free >= self.min_free_percent {
+            true => (CheckStatus::Up, Some(message)),
+            false => (CheckStatus::Degraded, Some(message)),
+        }
+    }
+}
+
+export_plugin!(FreeSpace);
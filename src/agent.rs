@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, sleep};
+use tracing::{debug, error, info, warn};
+
+use crate::checks::{CheckEvent, CheckResult};
+use crate::config::{AgentConfig, AgentTlsConfig, ConfigError};
+use crate::enrollment::{AgentStatus, ENROLL_PATH, EnrollRequest, EnrollResponse};
+use crate::tls::{self, CERTIFICATE_PATH, CertificateRequest, CertificateResponse};
+
+/// Path of the aggregator ingestion endpoint
+pub const INGEST_PATH: &str = "/api/ingest/results";
+
+/// Timeout of requests to the aggregator
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Batch of check results pushed by an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestBatch {
+    pub agent: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub results: Vec<IngestResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestResult {
+    pub check: String,
+    #[serde(flatten)]
+    pub result: CheckResult,
+}
+
+/// Pushes local check results to a central aggregator
+pub struct Agent {
+    id: String,
+    url: String,
+    base: String,
+    /// Credential issued at enrollment, used instead of the configured token
+    credential: Option<String>,
+    config: AgentConfig,
+    client: reqwest::Client,
+    /// Client presenting the current certificate, when pushing over mutual TLS
+    push_client: Option<reqwest::Client>,
+    /// PEM of the client certificate `push_client` was built with
+    certificate: Option<String>,
+    buffer: Arc<Buffer>,
+}
+
+/// Results waiting to be pushed, filled by the task receiving them and drained by the one
+/// pushing them
+#[derive(Default)]
+struct Buffer {
+    results: Mutex<VecDeque<IngestResult>>,
+    /// Woken once a full batch is buffered
+    batch_ready: Notify,
+}
+
+impl Buffer {
+    fn push(&self, event: CheckEvent, config: &AgentConfig) {
+        let mut results = self.results.lock().unwrap();
+        if results.len() >= config.max_buffer {
+            results.pop_front();
+        }
+        results.push_back(IngestResult {
+            check: event.check,
+            result: event.result,
+        });
+        if results.len() >= config.batch_size {
+            self.batch_ready.notify_one();
+        }
+    }
+
+    fn take(&self, size: usize) -> Vec<IngestResult> {
+        let mut results = self.results.lock().unwrap();
+        let size = results.len().min(size);
+        results.drain(..size).collect()
+    }
+
+    // Put results that could not be pushed back in front of those received meanwhile
+    fn restore(&self, batch: Vec<IngestResult>, max_buffer: usize) {
+        let mut results = self.results.lock().unwrap();
+        for result in batch.into_iter().rev() {
+            results.push_front(result);
+        }
+        let excess = results.len().saturating_sub(max_buffer);
+        results.drain(..excess);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.results.lock().unwrap().len()
+    }
+}
+
+enum PushError {
+    /// The aggregator rejected the batch; retrying will not help
+    Rejected(String),
+    /// The agent enrolled but was not approved yet; pushes succeed once it is
+    Pending,
+    Retryable(String),
+}
+
+/// Body of a rejected push
+#[derive(Deserialize)]
+struct Rejection {
+    status: Option<AgentStatus>,
+}
+
+impl Agent {
+    pub fn from_config(config: &AgentConfig) -> Result<Self, ConfigError> {
+        let base = config
+            .aggregator_url
+            .as_deref()
+            .ok_or_else(|| ConfigError("agent mode requires agent.aggregator_url".into()))?;
+        let id = config
+            .agent_id
+            .clone()
+            .or_else(sysinfo::System::host_name)
+            .unwrap_or_else(|| "unknown".to_string());
+        let push_base = config.tls.as_ref().map_or(base, |tls| tls.url.as_str());
+        let mut agent = Self {
+            id,
+            url: format!("{}{INGEST_PATH}", push_base.trim_end_matches('/')),
+            base: base.trim_end_matches('/').to_string(),
+            credential: None,
+            config: config.clone(),
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap(),
+            push_client: None,
+            certificate: None,
+            buffer: Arc::default(),
+        };
+        if let Some(enrolled) = agent.stored_credentials() {
+            agent.id = enrolled.agent;
+            agent.credential = Some(enrolled.credential);
+        }
+        Ok(agent)
+    }
+
+    fn stored_credentials(&self) -> Option<EnrollResponse> {
+        let path = self.config.credentials_file.as_ref()?;
+        let content = std::fs::read(path).ok()?;
+        serde_json::from_slice(&content)
+            .inspect_err(|e| warn!("Ignoring invalid {}: {}", path.display(), e))
+            .ok()
+    }
+
+    /// Exchange of the enrollment token for an identity and credential, when the agent has
+    /// neither a token nor stored credentials. Retries until the aggregator answers, but a
+    /// rejected token (used, unknown or revoked) is final
+    fn enrollment(
+        &self,
+    ) -> Option<impl Future<Output = Result<EnrollResponse, String>> + Send + 'static> {
+        let token = self.config.enrollment_token.clone()?;
+        if self.credential.is_some() || self.config.token.is_some() {
+            return None;
+        }
+        let request = EnrollRequest {
+            token,
+            agent: self.id.clone(),
+            labels: self.config.labels.clone(),
+        };
+        let url = format!("{}{ENROLL_PATH}", self.base);
+        let client = self.client.clone();
+        Some(async move {
+            let mut attempt = 0;
+            loop {
+                match client.post(&url).json(&request).send().await {
+                    Ok(response) if response.status().is_success() => match response.json().await {
+                        Ok(enrolled) => return Ok(enrolled),
+                        Err(e) => warn!("Invalid enrollment response: {}", e),
+                    },
+                    Ok(response) if is_final(response.status()) => {
+                        return Err(format!("aggregator returned {}", response.status()));
+                    }
+                    Ok(response) => warn!(
+                        "Enrollment with the aggregator failed: {}",
+                        response.status()
+                    ),
+                    Err(e) => warn!("Enrollment with the aggregator failed: {}", e),
+                }
+                sleep(Duration::from_millis(500 << attempt.min(6))).await;
+                attempt += 1;
+            }
+        })
+    }
+
+    fn enrolled(&mut self, enrolled: EnrollResponse) {
+        info!(
+            "Enrolled as agent {} ({:?})",
+            enrolled.agent, enrolled.status
+        );
+        if let Some(path) = &self.config.credentials_file {
+            let content = serde_json::to_vec_pretty(&enrolled).unwrap();
+            if let Err(e) = tls::write_private(path, &content) {
+                warn!("Failed to store credentials in {}: {}", path.display(), e);
+            }
+        }
+        self.id = enrolled.agent;
+        self.credential = Some(enrolled.credential);
+    }
+
+    /// Load the client certificate, renewing it from the built-in CA before it expires,
+    /// and rebuild the push client whenever it changed
+    async fn refresh_certificate(&mut self) {
+        let Some(tls) = self.config.tls.clone() else {
+            return;
+        };
+        let read = |path: &Path| {
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))
+        };
+        let material = match (&tls.cert, &tls.key) {
+            (Some(cert), Some(key)) => (|| {
+                let ca = tls.ca.as_deref().map(read).transpose()?;
+                Ok((read(cert)?, read(key)?, ca))
+            })(),
+            _ => self.issued_certificate(&tls).await,
+        };
+        let (cert, key, ca) = match material {
+            Ok(material) => material,
+            Err(e) => {
+                warn!("Client certificate unavailable: {}", e);
+                return;
+            }
+        };
+        if self.certificate.as_ref() == Some(&cert) {
+            return;
+        }
+        let mut builder = reqwest::Client::builder()
+            .use_rustls_tls()
+            .timeout(REQUEST_TIMEOUT);
+        let identity = reqwest::Identity::from_pem(format!("{cert}{key}").as_bytes());
+        match identity {
+            Ok(identity) => builder = builder.identity(identity),
+            Err(e) => {
+                warn!("Invalid client certificate: {}", e);
+                return;
+            }
+        }
+        if let Some(ca) = ca {
+            match reqwest::Certificate::from_pem(ca.as_bytes()) {
+                Ok(ca) => builder = builder.add_root_certificate(ca),
+                Err(e) => warn!("Invalid aggregator CA: {}", e),
+            }
+        }
+        match builder.build() {
+            Ok(client) => {
+                match tls::validity(&cert) {
+                    Some((_, expires)) => info!(
+                        "Using client certificate valid until {}",
+                        humantime::format_rfc3339_seconds(expires)
+                    ),
+                    None => info!("Using client certificate"),
+                }
+                self.push_client = Some(client);
+                self.certificate = Some(cert);
+            }
+            Err(e) => warn!("Failed to build mutual TLS client: {}", e),
+        }
+    }
+
+    /// Certificate issued by the aggregator's CA, requesting a new one when it is missing
+    /// or a third of its lifetime remains
+    async fn issued_certificate(
+        &self,
+        tls: &AgentTlsConfig,
+    ) -> Result<(String, String, Option<String>), String> {
+        let cert_path = tls.dir.join("cert.pem");
+        let key_path = tls.dir.join("key.pem");
+        let ca_path = tls.ca.clone().unwrap_or_else(|| tls.dir.join("ca.pem"));
+        let stored = std::fs::read_to_string(&cert_path)
+            .and_then(|cert| Ok((cert, std::fs::read_to_string(&key_path)?)));
+        if let Ok((cert, key)) = stored
+            && !tls::needs_renewal(&cert)
+        {
+            return Ok((cert, key, std::fs::read_to_string(&ca_path).ok()));
+        }
+        let (csr, key) = tls::certificate_request(&self.id)?;
+        let request = CertificateRequest {
+            agent: self.id.clone(),
+            csr,
+        };
+        let mut builder = self
+            .client
+            .post(format!("{}{CERTIFICATE_PATH}", self.base))
+            .json(&request);
+        if let Some(token) = self.credential.as_ref().or(self.config.token.as_ref()) {
+            builder = builder.bearer_auth(token);
+        }
+        let issued: CertificateResponse = builder
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("certificate request failed: {e}"))?
+            .json()
+            .await
+            .map_err(|e| format!("invalid certificate response: {e}"))?;
+        let stored = std::fs::create_dir_all(&tls.dir)
+            .and_then(|_| tls::write_private(&key_path, key.as_bytes()))
+            .and_then(|_| std::fs::write(&cert_path, &issued.certificate));
+        let stored = match tls.ca {
+            Some(_) => stored,
+            None => stored.and_then(|_| std::fs::write(&ca_path, &issued.ca)),
+        };
+        if let Err(e) = stored {
+            warn!(
+                "Failed to store certificate in {}: {}",
+                tls.dir.display(),
+                e
+            );
+        }
+        info!("Obtained client certificate for agent {}", self.id);
+        let ca = match tls.ca {
+            Some(_) => std::fs::read_to_string(&ca_path).ok(),
+            None => Some(issued.ca),
+        };
+        Ok((issued.certificate, key, ca))
+    }
+
+    /// Buffer every completed check and flush batches on size or interval. Pushing runs in
+    /// its own task, so enrolling or an unreachable aggregator never holds up receiving results
+    pub fn spawn(mut self, mut events: broadcast::Receiver<CheckEvent>) {
+        info!("Agent {} pushing results to {}", self.id, self.url);
+        let buffer = Arc::clone(&self.buffer);
+        let config = self.config.clone();
+        let receiving = tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => buffer.push(event, &config),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Agent lagged behind, {} results dropped", skipped)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        tokio::spawn(async move {
+            if let Some(enrollment) = self.enrollment() {
+                match enrollment.await {
+                    Ok(enrolled) => self.enrolled(enrolled),
+                    Err(e) => {
+                        error!(
+                            "Enrollment rejected ({}), agent {} stops pushing",
+                            e, self.id
+                        );
+                        receiving.abort();
+                        return;
+                    }
+                }
+            }
+            self.refresh_certificate().await;
+            let mut ticker = interval(self.config.flush_interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = self.buffer.batch_ready.notified() => {}
+                }
+                self.flush().await;
+            }
+        });
+    }
+
+    async fn flush(&mut self) {
+        self.refresh_certificate().await;
+        loop {
+            let results = self.buffer.take(self.config.batch_size);
+            if results.is_empty() {
+                return;
+            }
+            let batch = IngestBatch {
+                agent: self.id.clone(),
+                labels: self.config.labels.clone(),
+                results,
+            };
+            match self.push_with_retry(&batch).await {
+                Ok(()) => debug!("Pushed {} results", batch.results.len()),
+                Err(PushError::Rejected(e)) => warn!("Aggregator rejected batch: {}", e),
+                Err(PushError::Pending) => {
+                    // Keep the results buffered until an administrator approves the agent
+                    info!("Agent {} is awaiting approval", self.id);
+                    self.buffer.restore(batch.results, self.config.max_buffer);
+                    return;
+                }
+                Err(PushError::Retryable(e)) => {
+                    // Keep the results buffered for the next flush
+                    warn!("Push to aggregator failed: {}", e);
+                    self.buffer.restore(batch.results, self.config.max_buffer);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn push_with_retry(&self, batch: &IngestBatch) -> Result<(), PushError> {
+        let mut attempt = 0;
+        loop {
+            match self.push(batch).await {
+                Err(PushError::Retryable(e)) if attempt < self.config.max_retries => {
+                    let backoff = Duration::from_millis(500 << attempt.min(6));
+                    debug!("Push failed ({}), retrying in {:?}", e, backoff);
+                    sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn push(&self, batch: &IngestBatch) -> Result<(), PushError> {
+        let client = match (&self.config.tls, &self.push_client) {
+            (None, _) => &self.client,
+            (Some(_), Some(client)) => client,
+            (Some(_), None) => {
+                return Err(PushError::Retryable("no client certificate yet".into()));
+            }
+        };
+        let mut request = client.post(&self.url).json(batch);
+        if let Some(token) = self.credential.as_ref().or(self.config.token.as_ref()) {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| PushError::Retryable(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else if !is_final(status) {
+            Err(PushError::Retryable(format!(
+                "aggregator returned {status}"
+            )))
+        } else if status == reqwest::StatusCode::FORBIDDEN
+            && response
+                .json::<Rejection>()
+                .await
+                .is_ok_and(|r| r.status == Some(AgentStatus::Pending))
+        {
+            Err(PushError::Pending)
+        } else {
+            Err(PushError::Rejected(format!("aggregator returned {status}")))
+        }
+    }
+}
+
+// Client errors other than timeouts and rate limiting will not go away by retrying
+pub(crate) fn is_final(status: reqwest::StatusCode) -> bool {
+    status.is_client_error()
+        && status != reqwest::StatusCode::REQUEST_TIMEOUT
+        && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::StatusCode, response::IntoResponse, routing::post};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn event(check: &str) -> CheckEvent {
+        CheckEvent {
+            check: check.into(),
+            previous: None,
+            result: CheckResult::up(),
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_results_while_awaiting_approval() {
+        let approved = Arc::new(AtomicBool::new(false));
+        let approval = approved.clone();
+        let app = Router::new().route(
+            INGEST_PATH,
+            post(move || async move {
+                if approval.load(Ordering::Relaxed) {
+                    return StatusCode::ACCEPTED.into_response();
+                }
+                let body = serde_json::json!({ "status": AgentStatus::Pending });
+                (StatusCode::FORBIDDEN, axum::Json(body)).into_response()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = format!("aggregator_url = \"{url}\"\nagent_id = \"edge\"\ntoken = \"t\"");
+        let mut agent = Agent::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        agent.buffer.push(event("db"), &agent.config);
+        agent.flush().await;
+        assert_eq!(agent.buffer.len(), 1);
+        approved.store(true, Ordering::Relaxed);
+        agent.flush().await;
+        assert_eq!(agent.buffer.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn keeps_receiving_while_the_aggregator_hangs() {
+        let app = Router::new().route(INGEST_PATH, post(std::future::pending::<StatusCode>));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = format!("aggregator_url = \"{url}\"\ntoken = \"t\"\nbatch_size = 2");
+        let agent = Agent::from_config(&toml::from_str(&config).unwrap()).unwrap();
+        let buffer = Arc::clone(&agent.buffer);
+        let (sender, receiver) = broadcast::channel(4);
+        agent.spawn(receiver);
+        for _ in 0..50 {
+            sender.send(event("db")).unwrap();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        // Only the batch stuck in its push left the buffer
+        let buffered = buffer.len();
+        assert!((48..50).contains(&buffered), "{buffered}");
+    }
+}
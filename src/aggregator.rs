@@ -0,0 +1,524 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::{info, warn};
+
+use crate::agent::IngestBatch;
+use crate::auth::constant_time_eq;
+use crate::checks::{CheckEvent, CheckRegistry, CheckResult, CheckStatus, unix_now};
+use crate::config::{AggregatorConfig, QuorumConfig};
+use crate::enrollment::{Access, AgentStatus, Enrollment};
+use crate::reconcile::SourceResult;
+
+/// Check results pushed by remote agents, with per-agent staleness tracking
+pub struct Aggregator {
+    config: AggregatorConfig,
+    enrollment: Enrollment,
+    agents: RwLock<HashMap<String, RemoteAgent>>,
+    /// Aggregate state of each target under the quorum policy
+    quorum: RwLock<HashMap<String, CheckStatus>>,
+}
+
+struct RemoteAgent {
+    labels: BTreeMap<String, String>,
+    /// Unix timestamp of the last accepted batch
+    last_seen: u64,
+    stale: bool,
+    checks: BTreeMap<String, VecDeque<CheckResult>>,
+}
+
+/// Summary of a remote agent and the latest result of each of its checks
+#[derive(Debug, Serialize)]
+pub struct AgentSummary {
+    pub agent: String,
+    pub labels: BTreeMap<String, String>,
+    pub last_seen: u64,
+    pub stale: bool,
+    pub checks: BTreeMap<String, CheckResult>,
+}
+
+/// Latest status of a target broken down by the locations probing it
+#[derive(Debug, Serialize)]
+pub struct TargetLocations {
+    pub check: String,
+    /// Worst status across locations
+    pub status: CheckStatus,
+    /// Breakdown such as "down from eu-west only"
+    pub summary: String,
+    pub locations: BTreeMap<String, LocationStatus>,
+    /// Aggregate state under the quorum policy, when configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quorum: Option<CheckStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocationStatus {
+    /// Worst status reported by the agents in this location
+    pub status: CheckStatus,
+    pub agents: BTreeMap<String, CheckStatus>,
+}
+
+impl Aggregator {
+    pub fn new(config: &AggregatorConfig) -> Self {
+        Self {
+            config: config.clone(),
+            enrollment: Enrollment::new(config),
+            agents: RwLock::new(HashMap::new()),
+            quorum: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Whether a bearer token may push results for an agent: either the token declared for
+    /// the agent or the credential issued to it at enrollment
+    pub fn authorize(&self, agent: &str, token: Option<&str>) -> Access {
+        let Some(token) = token else {
+            return Access::Denied;
+        };
+        let declared = self.config.tokens.get(agent);
+        if declared.is_some_and(|known| constant_time_eq(known, token)) {
+            return Access::Granted;
+        }
+        self.enrollment.authenticate(agent, token)
+    }
+
+    /// Whether a bearer token is the credential issued to an agent at enrollment; shared
+    /// tokens do not tie the bearer to an agent
+    pub fn authorize_enrolled(&self, agent: &str, token: Option<&str>) -> Access {
+        match token {
+            Some(token) => self.enrollment.authenticate(agent, token),
+            None => Access::Denied,
+        }
+    }
+
+    /// Whether an agent authenticated by a client certificate may push results; enrolled
+    /// agents must be approved, and agents that never enrolled are only trusted when their
+    /// certificate comes from an external CA, the built-in CA only signing for enrolled agents
+    pub fn authorize_certificate(&self, agent: &str) -> Access {
+        match self.enrollment.status(agent) {
+            Some(AgentStatus::Approved) => Access::Granted,
+            Some(AgentStatus::Pending) => Access::Pending,
+            Some(AgentStatus::Revoked) => Access::Denied,
+            None if self.config.mtls.as_ref().is_some_and(|m| !m.built_in_ca()) => Access::Granted,
+            None => Access::Denied,
+        }
+    }
+
+    pub fn enrollment(&self) -> &Enrollment {
+        &self.enrollment
+    }
+
+    /// Merge a batch into the per-agent history, publishing each result as a check event
+    /// (or only the aggregate state changes when a quorum policy is configured)
+    pub fn ingest(&self, batch: IngestBatch, registry: &CheckRegistry) -> usize {
+        let accepted = self.record(batch, registry);
+        self.evaluate_quorum(registry);
+        accepted
+    }
+
+    fn record(&self, batch: IngestBatch, registry: &CheckRegistry) -> usize {
+        let mut agents = self.agents.write().unwrap();
+        let agent = agents.entry(batch.agent.clone()).or_insert_with(|| {
+            info!("Agent {} registered", batch.agent);
+            RemoteAgent {
+                labels: BTreeMap::new(),
+                last_seen: 0,
+                stale: false,
+                checks: BTreeMap::new(),
+            }
+        });
+        if agent.stale {
+            info!("Agent {} is reporting again", batch.agent);
+        }
+        agent.labels = batch.labels;
+        agent.last_seen = unix_now();
+        agent.stale = false;
+
+        let accepted = batch.results.len();
+        for item in batch.results {
+            let history = agent.checks.entry(item.check.clone()).or_default();
+            let previous = history.back().map(|r| r.status);
+            if history.len() >= self.config.history_size.max(1) {
+                history.pop_front();
+            }
+            history.push_back(item.result.clone());
+            if self.config.quorum.is_none() {
+                registry.publish(CheckEvent {
+                    check: remote_name(&batch.agent, &item.check),
+                    previous,
+                    result: item.result,
+                });
+            }
+        }
+        accepted
+    }
+
+    /// Publish the targets whose aggregate state changed under the quorum policy
+    fn evaluate_quorum(&self, registry: &CheckRegistry) {
+        if self.config.quorum.is_none() {
+            return;
+        }
+        let targets = self.by_location();
+        let mut states = self.quorum.write().unwrap();
+        for target in targets {
+            let Some(status) = target.quorum else {
+                continue;
+            };
+            let previous = states.insert(target.check.clone(), status);
+            if previous == Some(status) {
+                continue;
+            }
+            registry.publish(CheckEvent {
+                check: quorum_name(&target.check),
+                previous,
+                result: CheckResult::new(status, Some(target.summary)),
+            });
+        }
+    }
+
+    /// Latest result of every agent check, by agent
+    pub fn results(&self) -> Vec<SourceResult> {
+        let agents = self.agents.read().unwrap();
+        agents
+            .iter()
+            .flat_map(|(agent, remote)| {
+                remote.checks.iter().filter_map(|(check, history)| {
+                    Some(SourceResult {
+                        source: agent.clone(),
+                        check: check.clone(),
+                        result: history.back()?.clone(),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    pub fn agents(&self) -> Vec<AgentSummary> {
+        let mut agents: Vec<_> = self
+            .agents
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, agent)| AgentSummary {
+                agent: name.clone(),
+                labels: agent.labels.clone(),
+                last_seen: agent.last_seen,
+                stale: agent.stale,
+                checks: agent
+                    .checks
+                    .iter()
+                    .filter_map(|(check, history)| Some((check.clone(), history.back()?.clone())))
+                    .collect(),
+            })
+            .collect();
+        agents.sort_by(|a, b| a.agent.cmp(&b.agent));
+        agents
+    }
+
+    /// Compare each check across probing locations; stale agents are left out
+    pub fn by_location(&self) -> Vec<TargetLocations> {
+        let agents = self.agents.read().unwrap();
+        let mut targets: BTreeMap<String, BTreeMap<String, LocationStatus>> = BTreeMap::new();
+        for (name, agent) in agents.iter().filter(|(_, a)| !a.stale) {
+            for (check, history) in &agent.checks {
+                let Some(latest) = history.back() else {
+                    continue;
+                };
+                let location = latest
+                    .location
+                    .clone()
+                    .or_else(|| agent.labels.get("location").cloned())
+                    .unwrap_or_else(|| "unknown".to_string());
+                let entry = targets
+                    .entry(check.clone())
+                    .or_default()
+                    .entry(location)
+                    .or_insert_with(|| LocationStatus {
+                        status: CheckStatus::Up,
+                        agents: BTreeMap::new(),
+                    });
+                entry.status = entry.status.max(latest.status);
+                entry.agents.insert(name.clone(), latest.status);
+            }
+        }
+        targets
+            .into_iter()
+            .map(|(check, locations)| TargetLocations {
+                check,
+                status: locations
+                    .values()
+                    .map(|l| l.status)
+                    .max()
+                    .unwrap_or(CheckStatus::Up),
+                summary: location_summary(&locations),
+                quorum: self
+                    .config
+                    .quorum
+                    .as_ref()
+                    .map(|q| quorum_status(q, &locations)),
+                locations,
+            })
+            .collect()
+    }
+
+    pub fn history(&self, agent: &str, check: &str) -> Option<Vec<CheckResult>> {
+        let agents = self.agents.read().unwrap();
+        let history = agents.get(agent)?.checks.get(check)?;
+        Some(history.iter().cloned().collect())
+    }
+
+    /// Periodically mark agents that stopped reporting as stale and fail their checks
+    pub fn spawn_staleness(self: &Arc<Self>, registry: Arc<CheckRegistry>) {
+        let aggregator = Arc::clone(self);
+        let period = (self.config.stale_after / 4).max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                ticker.tick().await;
+                aggregator.mark_stale(&registry);
+            }
+        });
+    }
+
+    fn mark_stale(&self, registry: &CheckRegistry) {
+        let now = unix_now();
+        let stale_after = self.config.stale_after.as_secs();
+        let mut agents = self.agents.write().unwrap();
+        for (name, agent) in agents.iter_mut() {
+            if agent.stale || now.saturating_sub(agent.last_seen) <= stale_after {
+                continue;
+            }
+            warn!("Agent {} has not reported for {}s", name, stale_after);
+            agent.stale = true;
+            for (check, history) in agent.checks.iter_mut() {
+                let message = format!("agent {name} stopped reporting");
+                let result = CheckResult::new(CheckStatus::Down, Some(message));
+                let previous = history.back().map(|r| r.status);
+                if history.len() >= self.config.history_size.max(1) {
+                    history.pop_front();
+                }
+                history.push_back(result.clone());
+                if self.config.quorum.is_none() {
+                    registry.publish(CheckEvent {
+                        check: remote_name(name, check),
+                        previous,
+                        result,
+                    });
+                }
+            }
+        }
+        drop(agents);
+        self.evaluate_quorum(registry);
+    }
+}
+
+/// Name under which a remote agent's check is published, apart from local and tenant checks
+pub fn remote_name(agent: &str, check: &str) -> String {
+    format!("agents/{agent}/{check}")
+}
+
+/// Agent ids are one segment of the names their checks are published under
+pub fn valid_agent_name(agent: &str) -> bool {
+    !agent.is_empty() && !agent.contains('/')
+}
+
+/// Name under which the quorum state of a target is published
+pub fn quorum_name(check: &str) -> String {
+    format!("quorum/{check}")
+}
+
+// Down (or degraded) once enough locations agree; when fewer locations are reporting than the
+// quorum, all of them must agree
+fn quorum_status(
+    quorum: &QuorumConfig,
+    locations: &BTreeMap<String, LocationStatus>,
+) -> CheckStatus {
+    let required = quorum.min_failing.clamp(1, locations.len().max(1));
+    let at_least = |status| locations.values().filter(|l| l.status >= status).count();
+    if at_least(CheckStatus::Down) >= required {
+        CheckStatus::Down
+    } else if at_least(CheckStatus::Degraded) >= required {
+        CheckStatus::Degraded
+    } else {
+        CheckStatus::Up
+    }
+}
+
+// Describe where a target is failing from, e.g. "down from eu-west only"
+fn location_summary(locations: &BTreeMap<String, LocationStatus>) -> String {
+    let failing: Vec<_> = locations
+        .iter()
+        .filter(|(_, l)| l.status != CheckStatus::Up)
+        .collect();
+    if let [(location, status)] = failing.as_slice()
+        && locations.len() > 1
+    {
+        return format!("{} from {} only", status.status.as_str(), location);
+    }
+    let mut parts = Vec::new();
+    for status in [CheckStatus::Down, CheckStatus::Degraded, CheckStatus::Up] {
+        let names: Vec<_> = locations
+            .iter()
+            .filter(|(_, l)| l.status == status)
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if names.len() == locations.len() {
+            return format!("{} from all locations", status.as_str());
+        }
+        if !names.is_empty() {
+            parts.push(format!("{} from {}", status.as_str(), names.join(", ")));
+        }
+    }
+    parts.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::IngestResult;
+    use crate::enrollment::EnrollRequest;
+
+    fn aggregator(config: &str) -> Aggregator {
+        Aggregator::new(&toml::from_str(config).unwrap())
+    }
+
+    fn enroll(aggregator: &Aggregator, agent: &str) -> String {
+        let request = EnrollRequest {
+            token: "enroll-me".into(),
+            agent: agent.into(),
+            labels: BTreeMap::new(),
+        };
+        aggregator.enrollment().enroll(request).unwrap().credential
+    }
+
+    #[test]
+    fn declared_tokens_authorize_pushes_but_not_certificates() {
+        let aggregator = aggregator(r#"tokens = { eu-1 = "shared" }"#);
+        assert_eq!(
+            aggregator.authorize("eu-1", Some("shared")),
+            Access::Granted
+        );
+        // Bound to the agent it was declared for
+        assert_eq!(aggregator.authorize("eu-2", Some("shared")), Access::Denied);
+        assert_eq!(aggregator.authorize("eu-1", Some("guess")), Access::Denied);
+        assert_eq!(aggregator.authorize("eu-1", None), Access::Denied);
+        assert_eq!(
+            aggregator.authorize_enrolled("eu-1", Some("shared")),
+            Access::Denied
+        );
+    }
+
+    #[test]
+    fn enrollment_never_issues_declared_agent_ids() {
+        let aggregator = aggregator(
+            r#"
+            tokens = { eu-1 = "shared" }
+            enrollment_tokens = ["enroll-me"]
+            "#,
+        );
+        let request = EnrollRequest {
+            token: "enroll-me".into(),
+            agent: "eu-1".into(),
+            labels: BTreeMap::new(),
+        };
+        let enrolled = aggregator.enrollment().enroll(request).unwrap();
+        assert_ne!(enrolled.agent, "eu-1");
+        assert_eq!(remote_name("eu-1", "db"), "agents/eu-1/db");
+    }
+
+    #[test]
+    fn enrolled_credentials_are_bound_to_their_agent() {
+        let aggregator = aggregator(r#"enrollment_tokens = ["enroll-me"]"#);
+        let credential = enroll(&aggregator, "eu-1");
+        assert_eq!(
+            aggregator.authorize_enrolled("eu-1", Some(&credential)),
+            Access::Granted
+        );
+        assert_eq!(
+            aggregator.authorize("eu-2", Some(&credential)),
+            Access::Denied
+        );
+    }
+
+    #[test]
+    fn pending_agents_wait_for_approval() {
+        let aggregator = aggregator(
+            r#"
+            enrollment_tokens = ["enroll-me"]
+            require_approval = true
+            "#,
+        );
+        let credential = enroll(&aggregator, "eu-1");
+        assert_eq!(
+            aggregator.authorize("eu-1", Some(&credential)),
+            Access::Pending
+        );
+        assert_eq!(aggregator.authorize_certificate("eu-1"), Access::Pending);
+        aggregator
+            .enrollment()
+            .set_status("eu-1", AgentStatus::Revoked);
+        assert_eq!(aggregator.authorize_certificate("eu-1"), Access::Denied);
+    }
+
+    #[test]
+    fn built_in_ca_certificates_require_enrollment() {
+        let aggregator = aggregator(
+            r#"
+            enrollment_tokens = ["enroll-me"]
+            [mtls]
+            "#,
+        );
+        assert_eq!(aggregator.authorize_certificate("eu-1"), Access::Denied);
+        enroll(&aggregator, "eu-1");
+        assert_eq!(aggregator.authorize_certificate("eu-1"), Access::Granted);
+    }
+
+    #[test]
+    fn external_ca_certificates_vouch_for_unenrolled_agents() {
+        let aggregator = aggregator(
+            r#"
+            [mtls]
+            cert = "server.pem"
+            key = "server.key"
+            client_ca = "ca.pem"
+            "#,
+        );
+        assert_eq!(aggregator.authorize_certificate("eu-1"), Access::Granted);
+    }
+
+    #[test]
+    fn targets_are_compared_across_locations() {
+        let aggregator = aggregator("");
+        let registry = CheckRegistry::default();
+        let batch = |agent: &str, location: &str, result: CheckResult| IngestBatch {
+            agent: agent.into(),
+            labels: BTreeMap::from([("location".into(), location.into())]),
+            results: vec![IngestResult {
+                check: "api".into(),
+                result,
+            }],
+        };
+        aggregator.ingest(
+            batch("eu-1", "eu-west", CheckResult::down("refused")),
+            &registry,
+        );
+        aggregator.ingest(batch("us-1", "us-east", CheckResult::up()), &registry);
+        // Results tagged with their location win over the agent's label
+        let mut tagged = CheckResult::up();
+        tagged.location = Some("ap-south".into());
+        aggregator.ingest(batch("us-2", "us-east", tagged), &registry);
+
+        let targets = aggregator.by_location();
+        assert_eq!(targets.len(), 1);
+        let api = &targets[0];
+        assert_eq!(api.status, CheckStatus::Down);
+        assert_eq!(api.summary, "down from eu-west only");
+        assert_eq!(
+            api.locations.keys().collect::<Vec<_>>(),
+            ["ap-south", "eu-west", "us-east"]
+        );
+        assert_eq!(api.locations["us-east"].agents["us-1"], CheckStatus::Up);
+        assert!(api.quorum.is_none());
+    }
+}
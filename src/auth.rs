@@ -0,0 +1,123 @@
+use axum::http::{HeaderMap, header};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+use crate::config::CredentialsConfig;
+
+/// Credentials accepted by a protected endpoint
+#[derive(Debug, Default)]
+pub struct Credentials {
+    token: Option<String>,
+    basic: Option<(String, String)>,
+}
+
+impl Credentials {
+    pub fn from_config(config: &CredentialsConfig) -> Self {
+        Self {
+            token: config.token.clone(),
+            basic: config.username.clone().zip(config.password.clone()),
+        }
+    }
+
+    pub fn bearer(token: &str) -> Self {
+        Self {
+            token: Some(token.to_string()),
+            basic: None,
+        }
+    }
+
+    /// Whether the request's `Authorization` header carries one of the credentials
+    pub fn accepts(&self, headers: &HeaderMap) -> bool {
+        if let (Some(token), Some(given)) = (&self.token, bearer_token(headers)) {
+            return constant_time_eq(token, given);
+        }
+        match (&self.basic, basic_credentials(headers)) {
+            (Some((username, password)), Some((given_username, given_password))) => {
+                // Both compared, so that timing does not tell which one is wrong
+                constant_time_eq(username, &given_username)
+                    & constant_time_eq(password, &given_password)
+            }
+            _ => false,
+        }
+    }
+
+    /// `WWW-Authenticate` challenge of requests without accepted credentials
+    pub fn challenge(&self) -> &'static str {
+        match self.basic {
+            Some(_) => "Basic realm=\"healthcheck-service\"",
+            None => "Bearer",
+        }
+    }
+}
+
+/// Bearer token of a request's `Authorization` header
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+/// User name and password of a request's basic `Authorization` header
+pub fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let encoded = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Compare a presented credential with the expected one without leaking through timing how
+/// much of it matched
+pub fn constant_time_eq(expected: &str, given: &str) -> bool {
+    let (expected, given) = (expected.as_bytes(), given.as_bytes());
+    let difference = expected
+        .iter()
+        .zip(given)
+        .fold(0, |acc, (a, b)| acc | (a ^ b));
+    difference == 0 && expected.len() == given.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authorization(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn accepts_bearer_tokens_and_basic_auth() {
+        let credentials = Credentials::from_config(&CredentialsConfig {
+            token: Some("t0ken".into()),
+            username: Some("prometheus".into()),
+            password: Some("s3cret".into()),
+        });
+        assert!(credentials.accepts(&authorization("Bearer t0ken")));
+        assert!(!credentials.accepts(&authorization("Bearer s3cret")));
+        let basic = |user_password: &str| format!("Basic {}", STANDARD.encode(user_password));
+        assert!(credentials.accepts(&authorization(&basic("prometheus:s3cret"))));
+        assert!(!credentials.accepts(&authorization(&basic("prometheus:t0ken"))));
+        assert!(!credentials.accepts(&authorization("Basic not-base64")));
+        assert!(!credentials.accepts(&HeaderMap::new()));
+
+        let bearer = Credentials::bearer("t0ken");
+        assert!(!bearer.accepts(&authorization(&basic("prometheus:s3cret"))));
+        assert_eq!(bearer.challenge(), "Bearer");
+    }
+
+    #[test]
+    fn compares_whole_credentials() {
+        assert!(constant_time_eq("s3cret", "s3cret"));
+        assert!(!constant_time_eq("s3cret", "s3cres"));
+        assert!(!constant_time_eq("s3cret", "s3cre"));
+        assert!(!constant_time_eq("s3cret", "s3cret!"));
+        assert!(!constant_time_eq("s3cret", ""));
+    }
+}
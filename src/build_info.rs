@@ -0,0 +1,50 @@
+use serde::Serialize;
+
+/// Build metadata embedded by `build.rs`
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub build_time: &'static str,
+    pub rustc_version: &'static str,
+    pub features: Vec<&'static str>,
+}
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_SHA: &str = env!("BUILD_GIT_SHA");
+
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        name: env!("CARGO_PKG_NAME"),
+        version: VERSION,
+        git_sha: GIT_SHA,
+        build_time: env!("BUILD_TIME"),
+        rustc_version: env!("BUILD_RUSTC_VERSION"),
+        features: env!("BUILD_FEATURES")
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_this_build() {
+        let info = build_info();
+        assert!(!info.git_sha.is_empty());
+        // RFC 3339 in UTC, e.g. 2026-10-15T08:31:28Z
+        assert_eq!(info.build_time.len(), 20, "{}", info.build_time);
+        assert!(info.build_time.ends_with('Z'));
+        assert!(
+            info.rustc_version.starts_with("rustc "),
+            "{}",
+            info.rustc_version
+        );
+        assert_eq!(info.features.contains(&"pprof"), cfg!(feature = "pprof"));
+        assert!(!info.features.contains(&"default"));
+    }
+}
@@ -0,0 +1,359 @@
+use regex::Regex;
+use serde_json::Value;
+
+use crate::config::AssertionConfig;
+
+/// JSONPath-style selector: `$`, `.field`, `["field"]`, `[0]`, `[-1]`, `[*]` or `.*`, and an
+/// optional trailing `.length()` counting array items, object fields or string characters
+#[derive(Debug, Clone)]
+pub struct JsonPath {
+    source: String,
+    segments: Vec<Segment>,
+    length: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Field(String),
+    Index(i64),
+    Wildcard,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("invalid path {path:?}: {reason}");
+        let mut rest = path
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("must start with $"))?;
+        let mut segments = Vec::new();
+        let mut length = false;
+        while !rest.is_empty() {
+            if length {
+                return Err(invalid("length() must come last"));
+            }
+            if let Some(after) = rest.strip_prefix(".length()") {
+                length = true;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix(".*") {
+                segments.push(Segment::Wildcard);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = after
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid("expected a field name after ."));
+                }
+                segments.push(Segment::Field(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| invalid("unclosed ["))?;
+                let inner = after[..end].trim();
+                let quoted = inner
+                    .strip_prefix('"')
+                    .and_then(|s| s.strip_suffix('"'))
+                    .or_else(|| inner.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')));
+                segments.push(match (inner, quoted) {
+                    ("*", _) => Segment::Wildcard,
+                    (_, Some(field)) => Segment::Field(field.to_string()),
+                    _ => Segment::Index(
+                        inner
+                            .parse()
+                            .map_err(|_| invalid(&format!("bad index {inner:?}")))?,
+                    ),
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(invalid(&format!("unexpected {rest:?}")));
+            }
+        }
+        Ok(Self {
+            source: path.trim().to_string(),
+            segments,
+            length,
+        })
+    }
+
+    /// Values the path selects in a document; several when it contains wildcards
+    pub fn select(&self, document: &Value) -> Vec<Value> {
+        let mut current = vec![document];
+        for segment in &self.segments {
+            current = current
+                .into_iter()
+                .flat_map(|value| -> Vec<&Value> {
+                    match (segment, value) {
+                        (Segment::Field(name), Value::Object(map)) => {
+                            map.get(name).into_iter().collect()
+                        }
+                        (Segment::Index(index), Value::Array(items)) => {
+                            let index = match *index < 0 {
+                                true => items.len() as i64 + index,
+                                false => *index,
+                            };
+                            usize::try_from(index)
+                                .ok()
+                                .and_then(|index| items.get(index))
+                                .into_iter()
+                                .collect()
+                        }
+                        (Segment::Wildcard, Value::Array(items)) => items.iter().collect(),
+                        (Segment::Wildcard, Value::Object(map)) => map.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        current
+            .into_iter()
+            .map(|value| match self.length {
+                true => Value::from(length(value)),
+                false => value.clone(),
+            })
+            .collect()
+    }
+}
+
+fn length(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => items.len(),
+        Value::Object(map) => map.len(),
+        Value::String(s) => s.chars().count(),
+        Value::Null => 0,
+        _ => 1,
+    }
+}
+
+impl std::fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Condition on the values a path selects in a JSON body; every selected value must satisfy it
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    path: JsonPath,
+    conditions: Vec<Condition>,
+    /// Whether the path must select something; set by `exists`, true by default
+    exists: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Equals(Value),
+    NotEquals(Value),
+    LessThan(f64),
+    AtMost(f64),
+    GreaterThan(f64),
+    AtLeast(f64),
+    Matches(Regex),
+    Contains(Value),
+}
+
+impl Assertion {
+    pub fn compile(config: &AssertionConfig) -> Result<Self, String> {
+        let mut conditions = Vec::new();
+        conditions.extend(config.equals.clone().map(Condition::Equals));
+        conditions.extend(config.not_equals.clone().map(Condition::NotEquals));
+        conditions.extend(config.less_than.map(Condition::LessThan));
+        conditions.extend(config.at_most.map(Condition::AtMost));
+        conditions.extend(config.greater_than.map(Condition::GreaterThan));
+        conditions.extend(config.at_least.map(Condition::AtLeast));
+        conditions.extend(config.contains.clone().map(Condition::Contains));
+        if let Some(pattern) = &config.matches {
+            let regex = Regex::new(pattern).map_err(|e| e.to_string())?;
+            conditions.push(Condition::Matches(regex));
+        }
+        Ok(Self {
+            path: JsonPath::parse(&config.path)?,
+            conditions,
+            exists: config.exists.unwrap_or(true),
+        })
+    }
+
+    /// Describe why the document does not satisfy the assertion
+    pub fn evaluate(&self, document: &Value) -> Result<(), String> {
+        let values = self.path.select(document);
+        match (values.is_empty(), self.exists) {
+            (true, true) => return Err(format!("{} not found", self.path)),
+            (false, false) => return Err(format!("{} is present", self.path)),
+            _ => {}
+        }
+        for value in &values {
+            for condition in &self.conditions {
+                if !condition.holds(value) {
+                    return Err(format!("{} is {}, {}", self.path, value, condition));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Condition {
+    fn holds(&self, value: &Value) -> bool {
+        let number = value.as_f64();
+        match self {
+            Condition::Equals(expected) => loosely_equal(value, expected),
+            Condition::NotEquals(expected) => !loosely_equal(value, expected),
+            Condition::LessThan(limit) => number.is_some_and(|n| n < *limit),
+            Condition::AtMost(limit) => number.is_some_and(|n| n <= *limit),
+            Condition::GreaterThan(limit) => number.is_some_and(|n| n > *limit),
+            Condition::AtLeast(limit) => number.is_some_and(|n| n >= *limit),
+            Condition::Matches(regex) => match value {
+                Value::String(s) => regex.is_match(s),
+                other => regex.is_match(&other.to_string()),
+            },
+            Condition::Contains(item) => match (value, item) {
+                (Value::Array(items), item) => items.iter().any(|v| loosely_equal(v, item)),
+                (Value::String(s), Value::String(part)) => s.contains(part.as_str()),
+                (Value::Object(map), Value::String(key)) => map.contains_key(key),
+                _ => false,
+            },
+        }
+    }
+}
+
+// Numbers compare by value, so that `1` in the configuration equals `1.0` in a body
+fn loosely_equal(value: &Value, expected: &Value) -> bool {
+    match (value.as_f64(), expected.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => value == expected,
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Equals(v) => write!(f, "expected {v}"),
+            Condition::NotEquals(v) => write!(f, "expected anything but {v}"),
+            Condition::LessThan(n) => write!(f, "expected less than {n}"),
+            Condition::AtMost(n) => write!(f, "expected at most {n}"),
+            Condition::GreaterThan(n) => write!(f, "expected more than {n}"),
+            Condition::AtLeast(n) => write!(f, "expected at least {n}"),
+            Condition::Matches(r) => write!(f, "expected to match {}", r.as_str()),
+            Condition::Contains(v) => write!(f, "expected to contain {v}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn document() -> Value {
+        json!({
+            "status": "ok",
+            "queue": { "depth": 12 },
+            "replicas": [
+                { "name": "a", "state": "running", "lag": 0.5 },
+                { "name": "b", "state": "running", "lag": 2 },
+                { "name": "c", "state": "failed", "lag": null },
+            ],
+            "tags": ["eu", "prod"],
+            "dotted.key": true,
+        })
+    }
+
+    fn select(path: &str) -> Vec<Value> {
+        JsonPath::parse(path).unwrap().select(&document())
+    }
+
+    fn evaluate(config: Value) -> Result<(), String> {
+        let config: AssertionConfig = serde_json::from_value(config).unwrap();
+        Assertion::compile(&config).unwrap().evaluate(&document())
+    }
+
+    #[test]
+    fn selects_fields_and_indexes() {
+        assert_eq!(select("$"), [document()]);
+        assert_eq!(select("$.queue.depth"), [json!(12)]);
+        assert_eq!(select("$['queue'][\"depth\"]"), [json!(12)]);
+        assert_eq!(select("$.replicas[0].name"), [json!("a")]);
+        assert_eq!(select("$.replicas[-1].name"), [json!("c")]);
+        assert_eq!(select("$[\"dotted.key\"]"), [json!(true)]);
+    }
+
+    #[test]
+    fn wildcards_select_every_item() {
+        assert_eq!(
+            select("$.replicas[*].state"),
+            [json!("running"), json!("running"), json!("failed")]
+        );
+        assert_eq!(select("$.queue.*"), [json!(12)]);
+        assert_eq!(select("$.tags[*]").len(), 2);
+    }
+
+    #[test]
+    fn length_counts_items_fields_and_characters() {
+        assert_eq!(select("$.replicas.length()"), [json!(3)]);
+        assert_eq!(select("$.queue.length()"), [json!(1)]);
+        assert_eq!(select("$.status.length()"), [json!(2)]);
+        assert_eq!(
+            select("$.replicas[*].name.length()"),
+            [json!(1), json!(1), json!(1)]
+        );
+    }
+
+    #[test]
+    fn missing_paths_select_nothing() {
+        assert!(select("$.missing").is_empty());
+        assert!(select("$.replicas[3]").is_empty());
+        assert!(select("$.replicas[-4]").is_empty());
+        assert!(select("$.status.field").is_empty());
+        assert!(select("$.missing[*]").is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        assert!(JsonPath::parse("status").is_err());
+        assert!(JsonPath::parse("$.").is_err());
+        assert!(JsonPath::parse("$.items[0").is_err());
+        assert!(JsonPath::parse("$.items[x]").is_err());
+        assert!(JsonPath::parse("$.items.length().name").is_err());
+    }
+
+    #[test]
+    fn comparisons_apply_to_every_selected_value() {
+        assert!(evaluate(json!({ "path": "$.queue.depth", "less_than": 100 })).is_ok());
+        assert!(evaluate(json!({ "path": "$.queue.depth", "at_most": 12 })).is_ok());
+        assert!(evaluate(json!({ "path": "$.queue.depth", "greater_than": 12 })).is_err());
+        assert!(evaluate(json!({ "path": "$.queue.depth", "equals": 12.0 })).is_ok());
+        assert!(evaluate(json!({ "path": "$.replicas.length()", "at_least": 3 })).is_ok());
+        assert_eq!(
+            evaluate(json!({ "path": "$.replicas[*].state", "not_equals": "failed" })),
+            Err("$.replicas[*].state is \"failed\", expected anything but \"failed\"".into())
+        );
+    }
+
+    #[test]
+    fn numeric_comparisons_fail_on_other_types() {
+        assert!(evaluate(json!({ "path": "$.status", "less_than": 1 })).is_err());
+        assert!(evaluate(json!({ "path": "$.replicas[*].lag", "less_than": 5 })).is_err());
+    }
+
+    #[test]
+    fn matches_and_contains() {
+        assert!(evaluate(json!({ "path": "$.status", "matches": "^(ok|warn)$" })).is_ok());
+        assert!(evaluate(json!({ "path": "$.queue.depth", "matches": "^1" })).is_ok());
+        assert!(evaluate(json!({ "path": "$.tags", "contains": "prod" })).is_ok());
+        assert!(evaluate(json!({ "path": "$.queue", "contains": "depth" })).is_ok());
+        assert!(evaluate(json!({ "path": "$.status", "contains": "k" })).is_ok());
+        assert!(evaluate(json!({ "path": "$.tags", "contains": "us" })).is_err());
+    }
+
+    #[test]
+    fn existence() {
+        assert_eq!(
+            evaluate(json!({ "path": "$.missing" })),
+            Err("$.missing not found".into())
+        );
+        assert!(evaluate(json!({ "path": "$.missing", "exists": false })).is_ok());
+        assert_eq!(
+            evaluate(json!({ "path": "$.status", "exists": false })),
+            Err("$.status is present".into())
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{AddressFamily, DnsConfig, ResolverKind};
+
+/// How a lookup was answered, as reported by the `dns.lookups` metric
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Static override from the configuration
+    Override,
+    /// Cached answer younger than `cache_ttl`
+    Hit,
+    /// Answered by the resolver
+    Miss,
+    /// The resolver failed and an older cached answer was used
+    Stale,
+    Error,
+}
+
+impl Outcome {
+    const ALL: [Outcome; 5] = [
+        Outcome::Override,
+        Outcome::Hit,
+        Outcome::Miss,
+        Outcome::Stale,
+        Outcome::Error,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Override => "override",
+            Outcome::Hit => "hit",
+            Outcome::Miss => "miss",
+            Outcome::Stale => "stale",
+            Outcome::Error => "error",
+        }
+    }
+}
+
+struct Cached {
+    addresses: Vec<IpAddr>,
+    resolved: Instant,
+}
+
+enum Backend {
+    System,
+    #[cfg(feature = "hickory")]
+    Builtin(Box<hickory_resolver::TokioAsyncResolver>),
+}
+
+struct Inner {
+    backend: Backend,
+    cache_ttl: Duration,
+    stale_if_error: Duration,
+    overrides: BTreeMap<String, Vec<IpAddr>>,
+    cache: Mutex<HashMap<String, Cached>>,
+    lookups: [AtomicU64; Outcome::ALL.len()],
+}
+
+/// Name resolution shared by the probes: static overrides, a cache that can answer while
+/// the resolver fails, and the system or built-in resolver
+#[derive(Clone)]
+pub struct Resolver {
+    inner: Arc<Inner>,
+    family: AddressFamily,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::from_config(&DnsConfig::default())
+    }
+}
+
+impl Resolver {
+    pub fn from_config(config: &DnsConfig) -> Self {
+        let backend = match config.resolver {
+            ResolverKind::System => Backend::System,
+            #[cfg(feature = "hickory")]
+            ResolverKind::Builtin => {
+                match hickory_resolver::TokioAsyncResolver::tokio_from_system_conf() {
+                    Ok(resolver) => Backend::Builtin(Box::new(resolver)),
+                    Err(e) => {
+                        tracing::warn!("Falling back to the system resolver: {}", e);
+                        Backend::System
+                    }
+                }
+            }
+            #[cfg(not(feature = "hickory"))]
+            ResolverKind::Builtin => unreachable!("rejected when the configuration is loaded"),
+        };
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|(host, addresses)| (host.to_lowercase(), addresses.clone()))
+            .collect();
+        Self {
+            inner: Arc::new(Inner {
+                backend,
+                cache_ttl: config.cache_ttl,
+                stale_if_error: config.stale_if_error,
+                overrides,
+                cache: Mutex::default(),
+                lookups: Default::default(),
+            }),
+            family: AddressFamily::Any,
+        }
+    }
+
+    /// The same resolver, only answering with addresses of `family`
+    pub fn with_family(mut self, family: AddressFamily) -> Self {
+        self.family = family;
+        self
+    }
+
+    /// Addresses of a host name, or an IP literal as it is
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, String> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        let host = host.to_lowercase();
+        let (outcome, addresses) = self.resolve(&host).await;
+        self.inner.lookups[outcome as usize].fetch_add(1, Ordering::Relaxed);
+        let mut addresses = addresses?;
+        addresses.retain(|ip| self.family.matches(ip));
+        match (addresses.is_empty(), self.family) {
+            (true, AddressFamily::V6) => Err(format!("{host} has no IPv6 address")),
+            (true, _) => Err(format!("{host} has no IPv4 address")),
+            (false, _) => Ok(addresses),
+        }
+    }
+
+    async fn resolve(&self, host: &str) -> (Outcome, Result<Vec<IpAddr>, String>) {
+        let inner = &self.inner;
+        if let Some(addresses) = inner.overrides.get(host) {
+            return (Outcome::Override, Ok(addresses.clone()));
+        }
+        let cached = |max_age: Duration| {
+            let cache = inner.cache.lock().unwrap();
+            let entry = cache.get(host)?;
+            (entry.resolved.elapsed() < max_age).then(|| entry.addresses.clone())
+        };
+        if let Some(addresses) = cached(inner.cache_ttl) {
+            return (Outcome::Hit, Ok(addresses));
+        }
+        match self.query(host).await {
+            Ok(addresses) => {
+                if !inner.cache_ttl.is_zero() {
+                    let entry = Cached {
+                        addresses: addresses.clone(),
+                        resolved: Instant::now(),
+                    };
+                    inner.cache.lock().unwrap().insert(host.to_string(), entry);
+                }
+                (Outcome::Miss, Ok(addresses))
+            }
+            Err(e) => match cached(inner.cache_ttl + inner.stale_if_error) {
+                Some(addresses) => {
+                    tracing::debug!("Resolving {} failed, using cached addresses: {}", host, e);
+                    (Outcome::Stale, Ok(addresses))
+                }
+                None => (
+                    Outcome::Error,
+                    Err(format!("failed to resolve {host}: {e}")),
+                ),
+            },
+        }
+    }
+
+    async fn query(&self, host: &str) -> Result<Vec<IpAddr>, String> {
+        let addresses: Vec<IpAddr> = match &self.inner.backend {
+            Backend::System => tokio::net::lookup_host((host, 0))
+                .await
+                .map_err(|e| e.to_string())?
+                .map(|address| address.ip())
+                .collect(),
+            #[cfg(feature = "hickory")]
+            Backend::Builtin(resolver) => resolver
+                .lookup_ip(host)
+                .await
+                .map_err(|e| e.to_string())?
+                .iter()
+                .collect(),
+        };
+        match addresses.is_empty() {
+            true => Err("no addresses".into()),
+            false => Ok(addresses),
+        }
+    }
+
+    pub fn register_metrics(&self, meter: &Meter) {
+        let inner = Arc::clone(&self.inner);
+        meter
+            .u64_observable_counter("dns.lookups")
+            .with_description("Name lookups by probes, by how they were answered")
+            .with_callback(move |observer| {
+                for outcome in Outcome::ALL {
+                    let count = inner.lookups[outcome as usize].load(Ordering::Relaxed);
+                    observer.observe(count, &[KeyValue::new("result", outcome.as_str())]);
+                }
+            })
+            .build();
+    }
+}
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addresses = resolver.lookup(name.as_str()).await?;
+            let addresses = addresses.into_iter().map(|ip| SocketAddr::new(ip, 0));
+            Ok(Box::new(addresses) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured(config: &str) -> Resolver {
+        Resolver::from_config(&toml::from_str(config).unwrap())
+    }
+
+    fn count(resolver: &Resolver, outcome: Outcome) -> u64 {
+        resolver.inner.lookups[outcome as usize].load(Ordering::Relaxed)
+    }
+
+    #[tokio::test]
+    async fn overrides_win_over_the_resolver() {
+        let resolver = configured(r#"overrides = { "API.internal" = ["10.0.0.5", "::1"] }"#);
+        let addresses = resolver.lookup("api.INTERNAL").await.unwrap();
+        assert_eq!(
+            addresses,
+            [
+                "10.0.0.5".parse::<IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+        assert_eq!(count(&resolver, Outcome::Override), 1);
+    }
+
+    #[tokio::test]
+    async fn filters_addresses_by_family() {
+        let resolver = configured(r#"overrides = { "api.internal" = ["10.0.0.5", "fd00::5"] }"#);
+        let v6 = resolver.clone().with_family(AddressFamily::V6);
+        assert_eq!(
+            v6.lookup("api.internal").await.unwrap(),
+            ["fd00::5".parse::<IpAddr>().unwrap()]
+        );
+        let resolver = configured(r#"overrides = { "db.internal" = ["fd00::7"] }"#);
+        let v4 = resolver.clone().with_family(AddressFamily::V4);
+        let error = v4.lookup("db.internal").await.unwrap_err();
+        assert_eq!(error, "db.internal has no IPv4 address");
+        assert_eq!(resolver.lookup("db.internal").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn ip_literals_are_not_looked_up() {
+        let resolver = configured("");
+        let addresses = resolver.lookup("[::1]").await.unwrap();
+        assert_eq!(addresses, ["::1".parse::<IpAddr>().unwrap()]);
+        assert!(Outcome::ALL.iter().all(|o| count(&resolver, *o) == 0));
+    }
+
+    #[tokio::test]
+    async fn caches_answers_and_serves_them_when_lookups_fail() {
+        let resolver = configured(r#"cache_ttl = "1h""#);
+        let cached = Cached {
+            addresses: vec!["10.0.0.7".parse().unwrap()],
+            resolved: Instant::now(),
+        };
+        let host = "db.invalid";
+        resolver
+            .inner
+            .cache
+            .lock()
+            .unwrap()
+            .insert(host.into(), cached);
+        assert_eq!(resolver.lookup(host).await.unwrap().len(), 1);
+        assert_eq!(count(&resolver, Outcome::Hit), 1);
+
+        let resolver = configured(r#"cache_ttl = "1s""#);
+        let expired = Cached {
+            addresses: vec!["10.0.0.7".parse().unwrap()],
+            resolved: Instant::now() - Duration::from_secs(5),
+        };
+        resolver
+            .inner
+            .cache
+            .lock()
+            .unwrap()
+            .insert(host.into(), expired);
+        let error = resolver.lookup(host).await.unwrap_err();
+        assert!(error.starts_with("failed to resolve db.invalid"), "{error}");
+        assert_eq!(count(&resolver, Outcome::Error), 1);
+    }
+
+    #[tokio::test]
+    async fn serves_stale_answers_within_the_window() {
+        let resolver = configured("cache_ttl = \"1s\"\nstale_if_error = \"1m\"");
+        let expired = Cached {
+            addresses: vec!["10.0.0.7".parse().unwrap()],
+            resolved: Instant::now() - Duration::from_secs(5),
+        };
+        resolver
+            .inner
+            .cache
+            .lock()
+            .unwrap()
+            .insert("db.invalid".into(), expired);
+        assert_eq!(resolver.lookup("db.invalid").await.unwrap().len(), 1);
+        assert_eq!(count(&resolver, Outcome::Stale), 1);
+    }
+}
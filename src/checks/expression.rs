@@ -0,0 +1,623 @@
+use regex::Regex;
+use serde_json::Value;
+
+/// Expression over the outputs of a probe, e.g.
+/// `status == 200 && latency_ms < 300 && body.json.items > 0`.
+///
+/// Supports `||`, `&&`, `!`, comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`), regex matches
+/// (`=~ "pattern"`), arithmetic, parentheses, field access (`a.b`, `a["b"]`, `a[0]`) and the
+/// functions `len`, `contains`, `lower` and `exists`. Arrays and objects compare with numbers
+/// by their length; other comparisons between different types are false, and arithmetic on
+/// them is null. Missing fields evaluate to null; nothing loops and nesting is limited to
+/// `MAX_DEPTH`, so evaluation is bounded by the size of the expression.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+/// Deepest expression tree accepted: parentheses, indexes, arguments, unary operators and
+/// every operator of a chain such as `a && b && c` nest it one level deeper
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(Value),
+    Variable(String),
+    Field(Box<Node>, String),
+    Index(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Negate(Box<Node>),
+    Binary(Box<Node>, Op, Box<Node>),
+    Matches(Box<Node>, Regex),
+    Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Len,
+    Contains,
+    Lower,
+    Exists,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+        };
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {}", describe(Some(token))));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, context: &Value) -> Value {
+        self.root.evaluate(context)
+    }
+
+    /// Evaluate as a condition: false, null, 0 and "" are false
+    pub fn holds(&self, context: &Value) -> bool {
+        truthy(&self.evaluate(context))
+    }
+}
+
+impl std::fmt::Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    const OPERATORS: [&str; 22] = [
+        "&&", "||", "==", "!=", "<=", ">=", "=~", "<", ">", "!", "+", "-", "*", "/", "%", "(", ")",
+        "[", "]", ".", ",", "=",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '_'))
+                .unwrap_or(rest.len());
+            let number = rest[..end].replace('_', "");
+            let number = number
+                .parse()
+                .map_err(|_| format!("invalid number {:?}", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c == '"' || c == '\'' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, q)) if q == c => break i + 2,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, other)) => value.push(other),
+                        None => return Err("unterminated string".into()),
+                    },
+                    Some((_, other)) => value.push(other),
+                    None => return Err("unterminated string".into()),
+                }
+            };
+            tokens.push(Token::Str(value));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            if *op == "=" {
+                return Err("use == to compare".into());
+            }
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(format!("unexpected character {c:?}"));
+        }
+    }
+    Ok(tokens)
+}
+
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        Some(Token::Number(n)) => n.to_string(),
+        Some(Token::Str(s)) => format!("{s:?}"),
+        Some(Token::Ident(name)) => name.clone(),
+        Some(Token::Op(op)) => op.to_string(),
+        None => "end of expression".into(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let matched = matches!(self.peek(), Some(Token::Op(o)) if *o == op);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.eat(op) {
+            true => Ok(()),
+            false => Err(format!("expected {op:?}, found {}", describe(self.peek()))),
+        }
+    }
+
+    // Errors abort the parse, so the depth is only restored on success
+    fn enter(&mut self) -> Result<(), String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("expression nested deeper than {MAX_DEPTH} levels"));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Parse a nested operand, refusing to recurse without bound
+    fn nested(&mut self, operand: fn(&mut Self) -> Result<Node, String>) -> Result<Node, String> {
+        self.enter()?;
+        let node = operand(self)?;
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    // Left-associative chain of binary operators at one precedence level
+    fn binary(
+        &mut self,
+        ops: &[(&str, Op)],
+        operand: fn(&mut Self) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        let depth = self.depth;
+        let mut left = operand(self)?;
+        'outer: loop {
+            for (symbol, op) in ops {
+                if self.eat(symbol) {
+                    self.enter()?;
+                    left = Node::Binary(Box::new(left), *op, Box::new(operand(self)?));
+                    continue 'outer;
+                }
+            }
+            self.depth = depth;
+            return Ok(left);
+        }
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        self.binary(&[("||", Op::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        self.binary(&[("&&", Op::And)], Self::not)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        match self.eat("!") {
+            true => Ok(Node::Not(Box::new(self.nested(Self::not)?))),
+            false => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let left = self.additive()?;
+        if self.eat("=~") {
+            return match self.next() {
+                Some(Token::Str(pattern)) => {
+                    let regex = Regex::new(&pattern).map_err(|e| e.to_string())?;
+                    Ok(Node::Matches(Box::new(left), regex))
+                }
+                other => Err(format!(
+                    "=~ needs a string pattern, found {}",
+                    describe(other.as_ref())
+                )),
+            };
+        }
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        for (symbol, op) in ops {
+            if self.eat(symbol) {
+                return Ok(Node::Binary(Box::new(left), op, Box::new(self.additive()?)));
+            }
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Node, String> {
+        self.binary(&[("+", Op::Add), ("-", Op::Sub)], Self::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Node, String> {
+        self.binary(
+            &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+            Self::unary,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.eat("-") {
+            true => Ok(Node::Negate(Box::new(self.nested(Self::unary)?))),
+            false => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Node, String> {
+        let mut node = self.primary()?;
+        loop {
+            if self.eat(".") {
+                match self.next() {
+                    Some(Token::Ident(name)) => node = Node::Field(Box::new(node), name),
+                    other => {
+                        let found = describe(other.as_ref());
+                        return Err(format!("expected a field name, found {found}"));
+                    }
+                }
+            } else if self.eat("[") {
+                let index = self.nested(Self::or)?;
+                self.expect("]")?;
+                node = Node::Index(Box::new(node), Box::new(index));
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Node::Literal(Value::from(n))),
+            Some(Token::Str(s)) => Ok(Node::Literal(Value::String(s))),
+            Some(Token::Op("(")) => {
+                let node = self.nested(Self::or)?;
+                self.expect(")")?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Node::Literal(Value::Bool(true))),
+                "false" => Ok(Node::Literal(Value::Bool(false))),
+                "null" => Ok(Node::Literal(Value::Null)),
+                _ if self.eat("(") => {
+                    let (function, arity) = match name.as_str() {
+                        "len" => (Function::Len, 1),
+                        "contains" => (Function::Contains, 2),
+                        "lower" => (Function::Lower, 1),
+                        "exists" => (Function::Exists, 1),
+                        _ => return Err(format!("unknown function {name}")),
+                    };
+                    let mut args = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            args.push(self.nested(Self::or)?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    if args.len() != arity {
+                        return Err(format!("{name} takes {arity} argument(s)"));
+                    }
+                    Ok(Node::Call(function, args))
+                }
+                _ => Ok(Node::Variable(name)),
+            },
+            other => Err(format!("unexpected {}", describe(other.as_ref()))),
+        }
+    }
+}
+
+impl Node {
+    fn evaluate(&self, context: &Value) -> Value {
+        match self {
+            Node::Literal(value) => value.clone(),
+            Node::Variable(name) => context.get(name).cloned().unwrap_or(Value::Null),
+            Node::Field(node, name) => node
+                .evaluate(context)
+                .get(name)
+                .cloned()
+                .unwrap_or_default(),
+            Node::Index(node, index) => {
+                let value = node.evaluate(context);
+                match (&value, index.evaluate(context)) {
+                    (Value::Array(items), Value::Number(n)) => n
+                        .as_f64()
+                        .map(|i| match i < 0.0 {
+                            true => items.len() as f64 + i,
+                            false => i,
+                        })
+                        .filter(|i| *i >= 0.0)
+                        .and_then(|i| items.get(i as usize).cloned())
+                        .unwrap_or_default(),
+                    (Value::Object(map), Value::String(key)) => {
+                        map.get(&key).cloned().unwrap_or_default()
+                    }
+                    _ => Value::Null,
+                }
+            }
+            Node::Not(node) => Value::Bool(!truthy(&node.evaluate(context))),
+            Node::Negate(node) => number(node.evaluate(context).as_f64().map(|n| -n)),
+            Node::Matches(node, regex) => Value::Bool(match node.evaluate(context) {
+                Value::String(s) => regex.is_match(&s),
+                Value::Null => false,
+                other => regex.is_match(&other.to_string()),
+            }),
+            Node::Binary(left, Op::And, right) => {
+                Value::Bool(truthy(&left.evaluate(context)) && truthy(&right.evaluate(context)))
+            }
+            Node::Binary(left, Op::Or, right) => {
+                Value::Bool(truthy(&left.evaluate(context)) || truthy(&right.evaluate(context)))
+            }
+            Node::Binary(left, op, right) => {
+                binary(*op, left.evaluate(context), right.evaluate(context))
+            }
+            Node::Call(function, args) => {
+                let args: Vec<Value> = args.iter().map(|a| a.evaluate(context)).collect();
+                call(*function, &args)
+            }
+        }
+    }
+}
+
+fn binary(op: Op, left: Value, right: Value) -> Value {
+    use std::cmp::Ordering;
+    let ordering = match (&left, &right) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => match (size_or_number(&left, &right), size_or_number(&right, &left)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => None,
+        },
+    };
+    let numbers = left.as_f64().zip(right.as_f64());
+    match op {
+        Op::Eq => Value::Bool(ordering.map_or(left == right, Ordering::is_eq)),
+        Op::Ne => Value::Bool(!ordering.map_or(left == right, Ordering::is_eq)),
+        Op::Lt => Value::Bool(ordering.is_some_and(Ordering::is_lt)),
+        Op::Le => Value::Bool(ordering.is_some_and(Ordering::is_le)),
+        Op::Gt => Value::Bool(ordering.is_some_and(Ordering::is_gt)),
+        Op::Ge => Value::Bool(ordering.is_some_and(Ordering::is_ge)),
+        Op::Add => match (&left, &right) {
+            (Value::String(a), Value::String(b)) => Value::String(format!("{a}{b}")),
+            _ => number(numbers.map(|(a, b)| a + b)),
+        },
+        Op::Sub => number(numbers.map(|(a, b)| a - b)),
+        Op::Mul => number(numbers.map(|(a, b)| a * b)),
+        Op::Div => number(numbers.filter(|(_, b)| *b != 0.0).map(|(a, b)| a / b)),
+        Op::Rem => number(numbers.filter(|(_, b)| *b != 0.0).map(|(a, b)| a % b)),
+        Op::And | Op::Or => unreachable!("short-circuited"),
+    }
+}
+
+// A collection compared with a number stands for its length, e.g. `body.json.items > 0`
+fn size_or_number(value: &Value, other: &Value) -> Option<f64> {
+    match (value, other) {
+        (Value::Array(items), Value::Number(_)) => Some(items.len() as f64),
+        (Value::Object(map), Value::Number(_)) => Some(map.len() as f64),
+        _ => value.as_f64(),
+    }
+}
+
+fn call(function: Function, args: &[Value]) -> Value {
+    match (function, args) {
+        (Function::Len, [Value::Array(items)]) => Value::from(items.len()),
+        (Function::Len, [Value::Object(map)]) => Value::from(map.len()),
+        (Function::Len, [Value::String(s)]) => Value::from(s.chars().count()),
+        (Function::Len, _) => Value::from(0),
+        (Function::Contains, [Value::Array(items), item]) => Value::Bool(
+            items
+                .iter()
+                .any(|v| binary(Op::Eq, v.clone(), item.clone()) == Value::Bool(true)),
+        ),
+        (Function::Contains, [Value::String(s), Value::String(part)]) => {
+            Value::Bool(s.contains(part.as_str()))
+        }
+        (Function::Contains, [Value::Object(map), Value::String(key)]) => {
+            Value::Bool(map.contains_key(key))
+        }
+        (Function::Contains, _) => Value::Bool(false),
+        (Function::Lower, [Value::String(s)]) => Value::String(s.to_lowercase()),
+        (Function::Lower, [other]) => other.clone(),
+        (Function::Exists, [value]) => Value::Bool(!value.is_null()),
+        _ => Value::Null,
+    }
+}
+
+fn number(n: Option<f64>) -> Value {
+    n.and_then(serde_json::Number::from_f64)
+        .map_or(Value::Null, Value::Number)
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(_) | Value::Object(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> Value {
+        json!({
+            "status": 200,
+            "latency_ms": 120,
+            "headers": { "content-type": "application/json" },
+            "body": {
+                "text": "{...}",
+                "json": { "items": [1, 2, 3], "ok": true, "version": "1.2.3", "meta": {} },
+            },
+        })
+    }
+
+    fn eval(source: &str) -> Value {
+        Expression::parse(source).unwrap().evaluate(&context())
+    }
+
+    #[test]
+    fn arithmetic_binds_tighter_than_comparisons() {
+        assert_eq!(eval("1 + 2 * 3"), json!(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), json!(9.0));
+        assert_eq!(eval("10 - 4 - 3"), json!(3.0));
+        assert_eq!(eval("-2 * 3 + 7 % 4"), json!(-3.0));
+        assert_eq!(eval("latency_ms / 2 > 50"), json!(true));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(eval("true || false && false"), json!(true));
+        assert_eq!(eval("(true || false) && false"), json!(false));
+        assert_eq!(eval("!false && status == 200"), json!(true));
+        assert_eq!(eval("!(status == 200) || latency_ms < 100"), json!(false));
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        // The right operands would be false, not an error, but must not change the outcome
+        assert_eq!(eval("true || missing.field > 1"), json!(true));
+        assert_eq!(eval("false && missing.field < 1"), json!(false));
+        assert_eq!(
+            eval("exists(body.json.nope) && body.json.nope > 0"),
+            json!(false)
+        );
+    }
+
+    #[test]
+    fn fields_and_indexes() {
+        assert_eq!(eval("headers[\"content-type\"]"), json!("application/json"));
+        assert_eq!(eval("body.json.items[0]"), json!(1));
+        assert_eq!(eval("body.json.items[-1]"), json!(3));
+        assert_eq!(eval("body.json.items[5]"), Value::Null);
+        assert_eq!(eval("body.json.missing.deeper"), Value::Null);
+    }
+
+    #[test]
+    fn collections_compare_with_numbers_by_length() {
+        assert_eq!(eval("body.json.items > 0"), json!(true));
+        assert_eq!(eval("body.json.items == 3"), json!(true));
+        assert_eq!(eval("0 < body.json.items"), json!(true));
+        assert_eq!(eval("body.json.meta > 0"), json!(false));
+        assert_eq!(eval("len(body.json.items) >= 3"), json!(true));
+    }
+
+    #[test]
+    fn mismatched_types_compare_false_and_compute_null() {
+        assert_eq!(eval("body.json.version > 1"), json!(false));
+        assert_eq!(eval("body.json.version < 1"), json!(false));
+        assert_eq!(eval("body.json.ok == 1"), json!(false));
+        assert_eq!(eval("body.json.version * 2"), Value::Null);
+        assert_eq!(eval("status / 0"), Value::Null);
+        assert_eq!(eval("missing > 0"), json!(false));
+        assert_eq!(eval("\"a\" + \"b\""), json!("ab"));
+        assert_eq!(eval("\"10\" < \"9\""), json!(true));
+    }
+
+    #[test]
+    fn functions_and_matches() {
+        assert_eq!(eval("contains(body.json.items, 2)"), json!(true));
+        assert_eq!(eval("contains(body.text, \"...\")"), json!(true));
+        assert_eq!(eval("contains(body.json, \"ok\")"), json!(true));
+        assert_eq!(eval("lower(\"OK\") == \"ok\""), json!(true));
+        assert_eq!(eval("body.json.version =~ \"^1\\\\.\""), json!(true));
+        assert_eq!(eval("missing =~ \".*\""), json!(false));
+    }
+
+    #[test]
+    fn truthiness() {
+        let holds = |source: &str| Expression::parse(source).unwrap().holds(&context());
+        assert!(holds("body.json.ok"));
+        assert!(holds("body.json.items"));
+        assert!(!holds("body.json.missing"));
+        assert!(!holds("0"));
+        assert!(!holds("\"\""));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        let error = |source: &str| Expression::parse(source).unwrap_err();
+        assert_eq!(error("status = 200"), "use == to compare");
+        assert_eq!(error("status =="), "unexpected end of expression");
+        assert_eq!(error("(status"), "expected \")\", found end of expression");
+        assert_eq!(error("len(a, b)"), "len takes 1 argument(s)");
+        assert_eq!(error("now()"), "unknown function now");
+        assert_eq!(error("status 200"), "unexpected 200");
+        assert_eq!(error("a =~ 1"), "=~ needs a string pattern, found 1");
+        assert_eq!(error("\"open"), "unterminated string");
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Expression::parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Expression::parse(&nested(MAX_DEPTH + 1)).unwrap_err(),
+            format!("expression nested deeper than {MAX_DEPTH} levels")
+        );
+        assert!(Expression::parse(&"!".repeat(10_000)).is_err());
+        let chain = vec!["status"; 10_000].join(" && ");
+        assert!(Expression::parse(&chain).is_err());
+        let chain = vec!["status"; MAX_DEPTH].join(" && ");
+        assert!(Expression::parse(&chain).is_ok());
+    }
+}
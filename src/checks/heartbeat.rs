@@ -0,0 +1,379 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use cron::Schedule;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use super::{CheckResult, CheckStatus, HealthCheck, unix_now};
+
+/// Pings received by heartbeat checks, keyed by ping id
+#[derive(Default)]
+pub struct Heartbeats {
+    pings: RwLock<HashMap<String, PingState>>,
+}
+
+/// Kind of ping sent by a monitored job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PingKind {
+    /// The job completed successfully
+    Success,
+    /// The job ran and failed
+    Fail,
+    /// The job started; it is expected to send `Success` or `Fail` later
+    Start,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PingState {
+    /// Unix timestamp of the registration; scheduled runs before it are not expected
+    registered: u64,
+    /// Last completion ping and whether it reported a failure
+    last: Option<(u64, bool)>,
+    started: Option<u64>,
+}
+
+/// When pings are expected
+pub enum Expectation {
+    /// At most this long apart
+    Period(Duration),
+    /// After every run of a cron schedule, evaluated in a time zone
+    Schedule {
+        schedule: Box<Schedule>,
+        timezone: Tz,
+    },
+}
+
+impl Heartbeats {
+    fn register(&self, id: &str) {
+        self.pings.write().unwrap().insert(
+            id.to_string(),
+            PingState {
+                registered: unix_now(),
+                last: None,
+                started: None,
+            },
+        );
+    }
+
+    /// Record a ping, returning false for unknown ids
+    pub fn ping(&self, id: &str, kind: PingKind) -> bool {
+        let mut pings = self.pings.write().unwrap();
+        let Some(state) = pings.get_mut(id) else {
+            return false;
+        };
+        let now = unix_now();
+        match kind {
+            PingKind::Success => state.last = Some((now, false)),
+            PingKind::Fail => state.last = Some((now, true)),
+            PingKind::Start => state.started = Some(now),
+        }
+        true
+    }
+
+    fn get(&self, id: &str) -> Option<PingState> {
+        self.pings.read().unwrap().get(id).copied()
+    }
+}
+
+impl Expectation {
+    /// Parse a cron expression (5 fields, or 6-7 with seconds) and an IANA time zone
+    pub fn schedule(expression: &str, timezone: Option<&str>) -> Result<Self, String> {
+        let expression = match expression.split_whitespace().count() {
+            5 => format!("0 {expression}"),
+            _ => expression.to_string(),
+        };
+        let schedule = Schedule::from_str(&expression)
+            .map_err(|e| format!("invalid cron expression {expression:?}: {e}"))?;
+        let timezone = match timezone {
+            Some(name) => name
+                .parse()
+                .map_err(|_| format!("unknown time zone {name:?}"))?,
+            None => Tz::UTC,
+        };
+        Ok(Self::Schedule {
+            schedule: Box::new(schedule),
+            timezone,
+        })
+    }
+}
+
+/// Deadman switch: fails when the expected ping does not arrive in time
+pub struct HeartbeatCheck {
+    id: String,
+    expectation: Expectation,
+    grace: Duration,
+    heartbeats: Arc<Heartbeats>,
+}
+
+impl HeartbeatCheck {
+    pub fn new(
+        id: String,
+        expectation: Expectation,
+        grace: Duration,
+        heartbeats: Arc<Heartbeats>,
+    ) -> Self {
+        heartbeats.register(&id);
+        Self {
+            id,
+            expectation,
+            grace,
+            heartbeats,
+        }
+    }
+
+    fn check_period(&self, period: Duration, state: &PingState, now: u64) -> CheckResult {
+        let deadline = (period + self.grace).as_secs();
+        match state.last {
+            Some((_, true)) => outcome(CheckStatus::Down, "failed", "job reported failure"),
+            Some((at, false)) if now.saturating_sub(at) > deadline => outcome(
+                CheckStatus::Down,
+                "missed",
+                format!(
+                    "no ping for {}s (expected every {:?} + {:?} grace)",
+                    now - at,
+                    period,
+                    self.grace
+                ),
+            ),
+            Some(_) => CheckResult::up().with_detail("state", "ok"),
+            None if now.saturating_sub(state.registered) > deadline => outcome(
+                CheckStatus::Down,
+                "missed",
+                format!("no ping received within {}s", deadline),
+            ),
+            None => outcome(CheckStatus::Up, "waiting", "waiting for first ping"),
+        }
+    }
+
+    fn check_schedule(
+        &self,
+        schedule: &Schedule,
+        timezone: Tz,
+        state: &PingState,
+        now: u64,
+    ) -> CheckResult {
+        let grace = self.grace.as_secs();
+        let now_local = DateTime::<Utc>::from_timestamp(now as i64, 0)
+            .unwrap_or_default()
+            .with_timezone(&timezone);
+        let mut runs = schedule.after(&now_local).rev();
+        let Some(latest) = runs.next() else {
+            return outcome(CheckStatus::Up, "waiting", "no scheduled run yet");
+        };
+        // While the latest run is still within its grace period, report on the one before it
+        let pinged_since = |run: &DateTime<Tz>| {
+            state
+                .last
+                .is_some_and(|(at, _)| at >= run.timestamp() as u64)
+        };
+        let run = if pinged_since(&latest) || now > latest.timestamp() as u64 + grace {
+            latest
+        } else {
+            match runs.next() {
+                Some(previous) => previous,
+                None => return outcome(CheckStatus::Up, "waiting", "no scheduled run yet"),
+            }
+        };
+        let scheduled = run.timestamp() as u64;
+        if scheduled < state.registered {
+            let registered = DateTime::<Utc>::from_timestamp(state.registered as i64, 0)
+                .unwrap_or_default()
+                .with_timezone(&timezone);
+            let message = match schedule.after(&registered).next() {
+                Some(next) => format!(
+                    "the run scheduled at {} predates the check, waiting for the one at {}",
+                    run.to_rfc3339(),
+                    next.to_rfc3339()
+                ),
+                None => format!(
+                    "the run scheduled at {} predates the check",
+                    run.to_rfc3339()
+                ),
+            };
+            return outcome(CheckStatus::Up, "waiting", message);
+        }
+        let result = match state.last.filter(|(at, _)| *at >= scheduled) {
+            Some((_, true)) => outcome(CheckStatus::Down, "failed", "job reported failure"),
+            Some((at, false)) if at > scheduled + grace => outcome(
+                CheckStatus::Degraded,
+                "late",
+                format!(
+                    "job ran late, finishing {}s after its scheduled time",
+                    at - scheduled
+                ),
+            ),
+            Some(_) => CheckResult::up().with_detail("state", "ok"),
+            None if state.started.is_some_and(|at| at >= scheduled) => outcome(
+                CheckStatus::Degraded,
+                "running_late",
+                "job started but has not finished within its grace period",
+            ),
+            None => outcome(
+                CheckStatus::Down,
+                "missed",
+                format!("job did not run at {}", run.to_rfc3339()),
+            ),
+        };
+        result.with_detail("scheduled_at", run.to_rfc3339())
+    }
+}
+
+fn outcome(status: CheckStatus, state: &str, message: impl Into<String>) -> CheckResult {
+    CheckResult::new(status, Some(message.into())).with_detail("state", state)
+}
+
+#[async_trait]
+impl HealthCheck for HeartbeatCheck {
+    async fn check(&self) -> CheckResult {
+        let Some(state) = self.heartbeats.get(&self.id) else {
+            return CheckResult::down("heartbeat not registered");
+        };
+        let now = unix_now();
+        let result = match &self.expectation {
+            Expectation::Period(period) => self.check_period(*period, &state, now),
+            Expectation::Schedule { schedule, timezone } => {
+                self.check_schedule(schedule, *timezone, &state, now)
+            }
+        };
+        match state.last {
+            Some((at, _)) => result.with_detail("last_ping", at),
+            None => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-10-15T12:00:00Z
+    const NOON: u64 = 1_792_065_600;
+    const HOUR: u64 = 3600;
+
+    fn check(expectation: Expectation) -> HeartbeatCheck {
+        HeartbeatCheck::new(
+            "job".into(),
+            expectation,
+            Duration::from_secs(HOUR),
+            Arc::new(Heartbeats::default()),
+        )
+    }
+
+    fn pinged(registered: u64, last: Option<(u64, bool)>, started: Option<u64>) -> PingState {
+        PingState {
+            registered,
+            last,
+            started,
+        }
+    }
+
+    fn state(result: &CheckResult) -> &str {
+        result.details["state"].as_str().unwrap()
+    }
+
+    fn daily_at(hour: u32) -> HeartbeatCheck {
+        check(Expectation::schedule(&format!("0 {hour} * * *"), None).unwrap())
+    }
+
+    fn run_schedule(check: &HeartbeatCheck, state: &PingState, now: u64) -> CheckResult {
+        let Expectation::Schedule { schedule, timezone } = &check.expectation else {
+            panic!("expected a schedule");
+        };
+        check.check_schedule(schedule, *timezone, state, now)
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        assert!(Expectation::schedule("not cron", None).is_err());
+        assert!(Expectation::schedule("0 6 * * *", Some("Mars/Olympus")).is_err());
+        assert!(Expectation::schedule("0 6 * * *", Some("Europe/Paris")).is_ok());
+    }
+
+    #[test]
+    fn period_allows_grace() {
+        let check = check(Expectation::Period(Duration::from_secs(HOUR)));
+        let period = Duration::from_secs(HOUR);
+        let last = Some((NOON - HOUR - 30 * 60, false));
+        let result = check.check_period(period, &pinged(0, last, None), NOON);
+        assert_eq!(result.status, CheckStatus::Up);
+        let result = check.check_period(period, &pinged(0, last, None), NOON + HOUR);
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(state(&result), "missed");
+    }
+
+    #[test]
+    fn period_waits_for_the_first_ping_until_the_deadline() {
+        let check = check(Expectation::Period(Duration::from_secs(HOUR)));
+        let period = Duration::from_secs(HOUR);
+        let result = check.check_period(period, &pinged(NOON, None, None), NOON + HOUR);
+        assert_eq!(state(&result), "waiting");
+        let result = check.check_period(period, &pinged(NOON, None, None), NOON + 3 * HOUR);
+        assert_eq!(state(&result), "missed");
+    }
+
+    #[test]
+    fn period_reports_failures() {
+        let check = check(Expectation::Period(Duration::from_secs(HOUR)));
+        let state_ = pinged(0, Some((NOON, true)), None);
+        let result = check.check_period(Duration::from_secs(HOUR), &state_, NOON);
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(state(&result), "failed");
+    }
+
+    #[test]
+    fn schedule_reports_missed_runs() {
+        let check = daily_at(6);
+        let result = run_schedule(&check, &pinged(0, None, None), NOON);
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(state(&result), "missed");
+        assert_eq!(result.details["scheduled_at"], "2026-10-15T06:00:00+00:00");
+    }
+
+    #[test]
+    fn schedule_reports_on_the_previous_run_during_grace() {
+        let check = daily_at(12);
+        // Pinged after yesterday's run; today's run is still within its grace period
+        let yesterday = NOON - 24 * HOUR;
+        let state_ = pinged(0, Some((yesterday + 60, false)), None);
+        let result = run_schedule(&check, &state_, NOON + 30 * 60);
+        assert_eq!(result.status, CheckStatus::Up);
+        assert_eq!(result.details["scheduled_at"], "2026-10-14T12:00:00+00:00");
+        // Once the grace period is over, today's run is missed
+        let result = run_schedule(&check, &state_, NOON + 2 * HOUR);
+        assert_eq!(state(&result), "missed");
+    }
+
+    #[test]
+    fn schedule_reports_late_and_running_jobs() {
+        let check = daily_at(6);
+        let six = NOON - 6 * HOUR;
+        let result = run_schedule(
+            &check,
+            &pinged(0, Some((six + 2 * HOUR, false)), None),
+            NOON,
+        );
+        assert_eq!(result.status, CheckStatus::Degraded);
+        assert_eq!(state(&result), "late");
+        let result = run_schedule(&check, &pinged(0, None, Some(six + 60)), NOON);
+        assert_eq!(result.status, CheckStatus::Degraded);
+        assert_eq!(state(&result), "running_late");
+    }
+
+    #[test]
+    fn schedule_waits_for_the_first_run_after_registration() {
+        let check = daily_at(6);
+        let result = run_schedule(&check, &pinged(NOON - HOUR, None, None), NOON);
+        assert_eq!(result.status, CheckStatus::Up);
+        assert_eq!(state(&result), "waiting");
+        assert_eq!(
+            result.message.as_deref(),
+            Some(
+                "the run scheduled at 2026-10-15T06:00:00+00:00 predates the check, \
+                 waiting for the one at 2026-10-16T06:00:00+00:00"
+            )
+        );
+    }
+}
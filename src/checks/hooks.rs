@@ -0,0 +1,35 @@
+use async_trait::async_trait;
+
+use super::CheckResult;
+
+/// Check execution a hook is called for
+#[derive(Debug, Clone, Copy)]
+pub struct HookContext<'a> {
+    pub check: &'a str,
+    pub critical: bool,
+    pub target: Option<&'a str>,
+    /// Number of earlier executions of the check
+    pub runs: u64,
+}
+
+/// Decision of a hook called before a check executes
+#[derive(Debug)]
+pub enum PreCheck {
+    Run,
+    /// Record this result instead of executing the check, e.g. during a maintenance window
+    Skip(CheckResult),
+}
+
+/// Callbacks around every scheduled or on-demand check execution.
+///
+/// Hooks run in registration order; the first `before` returning `Skip` gates the execution.
+/// `after` sees the result once it is stamped with duration, location and target, and may
+/// enrich or rewrite it before it is stored and published.
+#[async_trait]
+pub trait CheckHook: Send + Sync {
+    async fn before(&self, _context: &HookContext<'_>) -> PreCheck {
+        PreCheck::Run
+    }
+
+    async fn after(&self, _context: &HookContext<'_>, _result: &mut CheckResult) {}
+}
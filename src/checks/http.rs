@@ -0,0 +1,433 @@
+use async_trait::async_trait;
+use opentelemetry::trace::{SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue, global};
+use opentelemetry_http::HeaderInjector;
+use opentelemetry_semantic_conventions::trace::{
+    HTTP_REQUEST_METHOD, HTTP_RESPONSE_STATUS_CODE, URL_FULL,
+};
+use reqwest::header::HeaderMap;
+use serde_json::{Map, Value, json};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Assertion, CheckResult, CheckStatus, Expression, HealthCheck, Resolver};
+
+/// Largest response body read for assertions and conditions
+const MAX_BODY: usize = 1024 * 1024;
+
+/// Checks that an HTTP endpoint responds with the expected status code
+pub struct HttpCheck {
+    url: String,
+    expected_status: u16,
+    assertions: Vec<Assertion>,
+    up_when: Option<Expression>,
+    degraded_when: Option<Expression>,
+    reuse_connections: bool,
+    idle_timeout: Duration,
+    resolver: Option<Resolver>,
+    resolve_to: Option<IpAddr>,
+    client: reqwest::Client,
+}
+
+impl HttpCheck {
+    pub fn new(url: String, expected_status: u16) -> Self {
+        Self {
+            url,
+            expected_status,
+            assertions: Vec::new(),
+            up_when: None,
+            degraded_when: None,
+            reuse_connections: true,
+            idle_timeout: Duration::from_secs(90),
+            resolver: None,
+            resolve_to: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn build_client(mut self) -> Self {
+        let idle_per_host = if self.reuse_connections {
+            usize::MAX
+        } else {
+            0
+        };
+        let mut builder = reqwest::Client::builder()
+            .pool_idle_timeout(self.idle_timeout)
+            .pool_max_idle_per_host(idle_per_host);
+        if let Some(resolver) = &self.resolver {
+            builder = builder.dns_resolver(Arc::new(resolver.clone()));
+        }
+        let host = reqwest::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        if let (Some(ip), Some(host)) = (self.resolve_to, host) {
+            // Port 0 keeps the port of the URL
+            builder = builder.resolve(&host, SocketAddr::new(ip, 0));
+        }
+        // Building only fails when the TLS backend cannot be initialized, as for `new`
+        self.client = builder.build().unwrap();
+        self
+    }
+
+    /// Pool connections to the target, closing them after `idle_timeout`, or open a fresh
+    /// connection for every probe
+    pub fn with_connections(mut self, reuse: bool, idle_timeout: Duration) -> Self {
+        self.reuse_connections = reuse;
+        self.idle_timeout = idle_timeout;
+        self.build_client()
+    }
+
+    /// Resolve the host through the shared resolver, or connect to `resolve_to` instead
+    pub fn with_resolver(mut self, resolver: Resolver, resolve_to: Option<IpAddr>) -> Self {
+        self.resolver = Some(resolver);
+        self.resolve_to = resolve_to;
+        self.build_client()
+    }
+
+    /// Also require the response body to be JSON satisfying every assertion
+    pub fn with_assertions(mut self, assertions: Vec<Assertion>) -> Self {
+        self.assertions = assertions;
+        self
+    }
+
+    /// Decide the status with expressions over `status`, `latency_ms`, `headers` and `body`
+    /// (`body.text`, `body.json`): `up_when` replaces the expected status code and
+    /// `degraded_when` downgrades a successful probe
+    pub fn with_conditions(
+        mut self,
+        up_when: Option<Expression>,
+        degraded_when: Option<Expression>,
+    ) -> Self {
+        self.up_when = up_when;
+        self.degraded_when = degraded_when;
+        self
+    }
+
+    fn inspects_body(&self) -> bool {
+        !self.assertions.is_empty() || self.up_when.is_some() || self.degraded_when.is_some()
+    }
+
+    // Outputs of the probe that conditions are evaluated against
+    async fn context(
+        &self,
+        mut response: reqwest::Response,
+        latency_ms: u64,
+    ) -> Result<Value, String> {
+        let status = response.status().as_u16();
+        let headers: Map<String, Value> = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+                (name.as_str().to_string(), Value::String(value))
+            })
+            .collect();
+        let mut content = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("failed to read response body: {e}"))?
+        {
+            if content.len() + chunk.len() > MAX_BODY {
+                return Err(format!("response body exceeds {} KiB", MAX_BODY / 1024));
+            }
+            content.extend_from_slice(&chunk);
+        }
+        let text = String::from_utf8_lossy(&content).into_owned();
+        Ok(json!({
+            "status": status,
+            "latency_ms": latency_ms,
+            "headers": headers,
+            "body": body(text),
+        }))
+    }
+
+    fn evaluate(&self, context: &Value) -> CheckResult {
+        let status = context["status"].as_u64().unwrap_or_default();
+        match &self.up_when {
+            Some(up_when) if !up_when.holds(context) => {
+                return CheckResult::down(format!("condition not met: {up_when}"));
+            }
+            None if status != self.expected_status as u64 => {
+                return CheckResult::down(format!(
+                    "unexpected status {status}, expected {}",
+                    self.expected_status
+                ));
+            }
+            _ => {}
+        }
+        if !self.assertions.is_empty() {
+            let Some(document) = context["body"].get("json") else {
+                return CheckResult::down("assertion failed: response body is not JSON");
+            };
+            if let Err(e) = self
+                .assertions
+                .iter()
+                .try_for_each(|a| a.evaluate(document))
+            {
+                return CheckResult::down(format!("assertion failed: {e}"));
+            }
+        }
+        match &self.degraded_when {
+            Some(degraded_when) if degraded_when.holds(context) => CheckResult::new(
+                CheckStatus::Degraded,
+                Some(format!("degraded: {degraded_when}")),
+            ),
+            _ => CheckResult::up(),
+        }
+    }
+}
+
+// `body.json` is left out rather than null when the body is not JSON, a `null` body being a
+// valid document
+fn body(text: String) -> Value {
+    let document = serde_json::from_str::<Value>(&text).ok();
+    let mut body = json!({ "text": text });
+    if let Some(document) = document {
+        body["json"] = document;
+    }
+    body
+}
+
+// URL recorded on probe spans, without credentials
+fn redacted(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+impl HttpCheck {
+    async fn probe(&self, headers: HeaderMap) -> CheckResult {
+        let start = Instant::now();
+        match self.client.get(&self.url).headers(headers).send().await {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                let remote = response.remote_addr();
+                let latency_ms = start.elapsed().as_millis() as u64;
+                let result = if !self.inspects_body() {
+                    match status == self.expected_status {
+                        true => CheckResult::up(),
+                        false => CheckResult::down(format!(
+                            "unexpected status {status}, expected {}",
+                            self.expected_status
+                        )),
+                    }
+                } else {
+                    match self.context(response, latency_ms).await {
+                        Ok(context) => self.evaluate(&context),
+                        Err(e) => CheckResult::down(e),
+                    }
+                };
+                let result = result.with_detail("status_code", status);
+                match remote {
+                    Some(remote) => result.with_remote_addr(remote),
+                    None => result,
+                }
+            }
+            Err(e) => CheckResult::down(format!("request to {} failed: {e}", self.url)),
+        }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for HttpCheck {
+    // Each probe is a client span starting a trace, which the target's spans join through the
+    // `traceparent` header. Both are no-ops unless tracing is enabled
+    async fn check(&self) -> CheckResult {
+        let tracer = global::tracer("healthcheck-service");
+        let span = tracer
+            .span_builder("GET")
+            .with_kind(SpanKind::Client)
+            .with_attributes([
+                KeyValue::new(HTTP_REQUEST_METHOD, "GET"),
+                KeyValue::new(URL_FULL, redacted(&self.url)),
+            ])
+            .start(&tracer);
+        let cx = Context::current_with_span(span);
+        let mut headers = HeaderMap::new();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&cx, &mut HeaderInjector(&mut headers))
+        });
+        let result = self.probe(headers).await;
+        let span = cx.span();
+        if let Some(code) = result.details.get("status_code").and_then(Value::as_i64) {
+            span.set_attribute(KeyValue::new(HTTP_RESPONSE_STATUS_CODE, code));
+        }
+        if result.status == CheckStatus::Down {
+            span.set_status(Status::error(result.message.clone().unwrap_or_default()));
+        }
+        span.end();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AssertionConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn assertion(config: Value) -> Assertion {
+        let config: AssertionConfig = serde_json::from_value(config).unwrap();
+        Assertion::compile(&config).unwrap()
+    }
+
+    fn context(text: &str) -> Value {
+        json!({ "status": 200, "latency_ms": 12, "headers": {}, "body": body(text.into()) })
+    }
+
+    fn conditional(up_when: Option<&str>, degraded_when: Option<&str>) -> HttpCheck {
+        let parse = |source: &str| Expression::parse(source).unwrap();
+        HttpCheck::new("http://localhost".into(), 200)
+            .with_conditions(up_when.map(parse), degraded_when.map(parse))
+    }
+
+    // Serve empty 200 responses on kept-alive connections, counting the connections
+    async fn counting_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while let Ok(read @ 1..) = stream.read(&mut buffer).await {
+                        request.extend_from_slice(&buffer[..read]);
+                        if request.ends_with(b"\r\n\r\n") {
+                            request.clear();
+                            let response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+                            stream.write_all(response).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn reuses_connections_unless_disabled() {
+        let idle = Duration::from_secs(90);
+        for (reuse, expected) in [(true, 1), (false, 3)] {
+            let (url, connections) = counting_server().await;
+            let check = HttpCheck::new(url, 200).with_connections(reuse, idle);
+            for _ in 0..3 {
+                assert_eq!(check.check().await.status, CheckStatus::Up);
+            }
+            assert_eq!(
+                connections.load(Ordering::SeqCst),
+                expected,
+                "reuse: {reuse}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn connects_to_the_pinned_address() {
+        let (url, connections) = counting_server().await;
+        let port = reqwest::Url::parse(&url).unwrap().port().unwrap();
+        let url = format!("http://db.invalid:{port}/");
+        let pinned = "127.0.0.1".parse().ok();
+        let check = HttpCheck::new(url, 200).with_resolver(Resolver::default(), pinned);
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Up, "{:?}", result.message);
+        assert_eq!(result.details["remote_addr"], format!("127.0.0.1:{port}"));
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn probes_carry_a_traceparent() {
+        use opentelemetry_sdk::propagation::TraceContextPropagator;
+        use opentelemetry_sdk::trace::SdkTracerProvider;
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        global::set_tracer_provider(SdkTracerProvider::builder().build());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://probe:s3cret@{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 4096];
+            let read = stream.read(&mut buffer).await.unwrap();
+            let response = b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n";
+            stream.write_all(response).await.unwrap();
+            String::from_utf8_lossy(&buffer[..read]).into_owned()
+        });
+        let result = HttpCheck::new(url.clone(), 200).check().await;
+        assert_eq!(result.status, CheckStatus::Up, "{:?}", result.message);
+        let request = server.await.unwrap();
+        let traceparent = request
+            .lines()
+            .find_map(|line| line.strip_prefix("traceparent: "))
+            .expect("no traceparent header");
+        assert!(traceparent.starts_with("00-"), "{traceparent}");
+        assert!(traceparent.ends_with("-01"), "{traceparent}");
+        assert!(!redacted(&url).contains("s3cret"));
+    }
+
+    #[test]
+    fn expected_status_applies_without_up_when() {
+        let mut context = context("{}");
+        context["status"] = json!(503);
+        let result = conditional(None, None).evaluate(&context);
+        assert_eq!(
+            result.message.as_deref(),
+            Some("unexpected status 503, expected 200")
+        );
+    }
+
+    #[test]
+    fn up_when_replaces_the_expected_status() {
+        let mut context = context(r#"{"items": [1]}"#);
+        context["status"] = json!(503);
+        let check = conditional(Some("status == 503 && body.json.items > 0"), None);
+        assert_eq!(check.evaluate(&context).status, CheckStatus::Up);
+        let check = conditional(Some("body.json.items > 1"), None);
+        let result = check.evaluate(&context);
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(
+            result.message.as_deref(),
+            Some("condition not met: body.json.items > 1")
+        );
+    }
+
+    #[test]
+    fn degraded_when_downgrades_successful_probes_only() {
+        let check = conditional(Some("body.json.ok"), Some("latency_ms > 10"));
+        let result = check.evaluate(&context(r#"{"ok": true}"#));
+        assert_eq!(result.status, CheckStatus::Degraded);
+        assert_eq!(result.message.as_deref(), Some("degraded: latency_ms > 10"));
+        let result = check.evaluate(&context(r#"{"ok": false}"#));
+        assert_eq!(result.status, CheckStatus::Down);
+        let check = conditional(None, Some("latency_ms > 100"));
+        assert_eq!(check.evaluate(&context("")).status, CheckStatus::Up);
+    }
+
+    #[test]
+    fn assertions_fail_on_bodies_that_are_not_json() {
+        let check = HttpCheck::new("http://localhost".into(), 200)
+            .with_assertions(vec![assertion(json!({ "path": "$" }))]);
+        let result = check.evaluate(&context("<html>"));
+        assert_eq!(
+            result.message.as_deref(),
+            Some("assertion failed: response body is not JSON")
+        );
+    }
+
+    #[test]
+    fn a_null_body_is_a_json_document() {
+        let check = HttpCheck::new("http://localhost".into(), 200)
+            .with_assertions(vec![assertion(json!({ "path": "$", "not_equals": 0 }))]);
+        assert_eq!(check.evaluate(&context("null")).status, CheckStatus::Up);
+    }
+}
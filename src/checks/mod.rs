@@ -0,0 +1,256 @@
+mod assertion;
+mod dns;
+mod expression;
+mod heartbeat;
+mod hooks;
+mod http;
+#[cfg(feature = "plugins")]
+mod plugin;
+mod postgres;
+mod redis;
+mod registry;
+#[cfg(feature = "scripting")]
+mod script;
+mod tcp;
+mod transform;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use assertion::{Assertion, JsonPath};
+pub use dns::Resolver;
+pub use expression::Expression;
+pub use heartbeat::{Expectation, HeartbeatCheck, Heartbeats, PingKind};
+pub use hooks::{CheckHook, HookContext, PreCheck};
+pub use http::HttpCheck;
+#[cfg(feature = "plugins")]
+pub use plugin::{PluginCheck, Plugins};
+pub use postgres::PostgresCheck;
+pub use redis::RedisCheck;
+pub use registry::{
+    CheckRegistry, CheckReport, ComponentReport, Ownership, Selector, TargetReport, TickReport,
+};
+#[cfg(feature = "scripting")]
+pub use script::ScriptCheck;
+pub use tcp::TcpCheck;
+pub use transform::Transforms;
+#[cfg(feature = "wasm")]
+pub use wasm::WasmCheck;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{CheckConfig, CheckKind};
+
+/// Outcome of a single check execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Up,
+    Degraded,
+    Down,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Up => "up",
+            CheckStatus::Degraded => "degraded",
+            CheckStatus::Down => "down",
+        }
+    }
+}
+
+/// Result of a check, stamped with timing information by the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub status: CheckStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// Unix timestamp (seconds) of when the check finished
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub details: Map<String, Value>,
+    /// Location of the instance that executed the check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// Identity of the probed target, matching the same target checked elsewhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+impl CheckResult {
+    pub fn up() -> Self {
+        Self::new(CheckStatus::Up, None)
+    }
+
+    pub fn down(message: impl Into<String>) -> Self {
+        Self::new(CheckStatus::Down, Some(message.into()))
+    }
+
+    pub fn new(status: CheckStatus, message: Option<String>) -> Self {
+        Self {
+            status,
+            message,
+            duration_ms: 0,
+            timestamp: unix_now(),
+            details: Map::new(),
+            location: None,
+            target: None,
+        }
+    }
+
+    pub fn with_detail(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Record the address a probe connected to and its IP protocol (4 or 6)
+    pub fn with_remote_addr(self, address: SocketAddr) -> Self {
+        let protocol = if address.is_ipv4() { 4 } else { 6 };
+        self.with_detail("remote_addr", address.to_string())
+            .with_detail("ip_protocol", protocol)
+    }
+}
+
+/// Completed check execution, published to subscribers such as the notifier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckEvent {
+    pub check: String,
+    /// Status before this execution; `None` for the first execution
+    pub previous: Option<CheckStatus>,
+    pub result: CheckResult,
+}
+
+impl CheckEvent {
+    /// Whether this execution changed the check's status
+    pub fn is_transition(&self) -> bool {
+        self.previous.is_some_and(|p| p != self.result.status)
+    }
+}
+
+/// A dependency check that can be executed by the registry
+#[async_trait]
+pub trait HealthCheck: Send + Sync {
+    async fn check(&self) -> CheckResult;
+}
+
+/// Stand-in for a check that could not be set up, reporting why on every execution
+pub struct UnavailableCheck(pub String);
+
+#[async_trait]
+impl HealthCheck for UnavailableCheck {
+    async fn check(&self) -> CheckResult {
+        CheckResult::down(self.0.clone())
+    }
+}
+
+/// Build a checker from its configuration
+pub fn from_config(
+    config: &CheckConfig,
+    heartbeats: &Arc<Heartbeats>,
+    resolver: &Resolver,
+) -> Box<dyn HealthCheck> {
+    let resolver = resolver.clone().with_family(config.address_family);
+    match &config.kind {
+        CheckKind::Tcp { address } => {
+            Box::new(TcpCheck::new(address.clone()).with_resolver(resolver, config.resolve_to))
+        }
+        CheckKind::Http {
+            url,
+            expected_status,
+            assertions,
+            up_when,
+            degraded_when,
+            reuse_connections,
+            idle_timeout,
+        } => {
+            // Validated when the configuration is loaded
+            let expression =
+                |e: &Option<String>| e.as_deref().map(|e| Expression::parse(e).unwrap());
+            let assertions = assertions
+                .iter()
+                .map(|assertion| Assertion::compile(assertion).unwrap())
+                .collect();
+            Box::new(
+                HttpCheck::new(url.clone(), *expected_status)
+                    .with_connections(*reuse_connections, *idle_timeout)
+                    .with_resolver(resolver, config.resolve_to)
+                    .with_assertions(assertions)
+                    .with_conditions(expression(up_when), expression(degraded_when)),
+            )
+        }
+        CheckKind::Postgres {
+            address,
+            user,
+            database,
+        } => {
+            let tcp = TcpCheck::new(address.clone()).with_resolver(resolver, config.resolve_to);
+            Box::new(PostgresCheck::new(tcp, user.clone(), database.clone()))
+        }
+        CheckKind::Redis {
+            address,
+            username,
+            password,
+        } => {
+            let tcp = TcpCheck::new(address.clone()).with_resolver(resolver, config.resolve_to);
+            Box::new(RedisCheck::new(tcp, username.clone(), password.clone()))
+        }
+        CheckKind::Heartbeat { id, grace, .. } => Box::new(HeartbeatCheck::new(
+            id.clone(),
+            // Validated when the configuration is loaded
+            config.heartbeat_expectation().unwrap().unwrap(),
+            *grace,
+            heartbeats.clone(),
+        )),
+        #[cfg(feature = "wasm")]
+        CheckKind::Wasm {
+            module,
+            args,
+            env,
+            fuel,
+            max_memory_mb,
+        } => {
+            let env = env.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+            match WasmCheck::load(module, args.clone(), env, *fuel, max_memory_mb << 20) {
+                Ok(check) => Box::new(check),
+                Err(e) => Box::new(UnavailableCheck(e)),
+            }
+        }
+        #[cfg(not(feature = "wasm"))]
+        CheckKind::Wasm { .. } => unreachable!("rejected when the configuration is loaded"),
+        #[cfg(feature = "scripting")]
+        CheckKind::Script {
+            source,
+            file,
+            max_operations,
+        } => match ScriptCheck::load(
+            &config.name,
+            source.as_deref(),
+            file.as_deref(),
+            *max_operations,
+            config.timeout,
+        ) {
+            Ok(check) => Box::new(check),
+            Err(e) => Box::new(UnavailableCheck(e)),
+        },
+        #[cfg(not(feature = "scripting"))]
+        CheckKind::Script { .. } => unreachable!("rejected when the configuration is loaded"),
+        // Plugins are loaded and instantiated by the registry
+        CheckKind::Plugin { plugin, .. } => {
+            Box::new(UnavailableCheck(format!("plugin {plugin} is not loaded")))
+        }
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,188 @@
+use async_trait::async_trait;
+use libloading::Library;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use super::{CheckResult, CheckStatus, HealthCheck, UnavailableCheck};
+use crate::plugin::{
+    ABI_VERSION, ENTRY_SYMBOL, EntryPoint, MIN_ABI_VERSION, PluginDescriptor, STATUS_DEGRADED,
+    STATUS_UP,
+};
+
+/// Native checker plugins loaded from a directory, keyed by the name they register
+#[derive(Default)]
+pub struct Plugins {
+    loaded: BTreeMap<String, Arc<Plugin>>,
+}
+
+struct Plugin {
+    descriptor: *const PluginDescriptor,
+    // Declared last so that it is unloaded after everything referring to it
+    _library: Library,
+}
+
+// The descriptor is immutable and lives as long as the library
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+impl Plugin {
+    fn descriptor(&self) -> &PluginDescriptor {
+        unsafe { &*self.descriptor }
+    }
+
+    // Take ownership of a string returned by the plugin
+    fn take_string(&self, string: *mut c_char) -> Option<String> {
+        if string.is_null() {
+            return None;
+        }
+        let text = unsafe { CStr::from_ptr(string) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { (self.descriptor().free_string)(string) };
+        Some(text)
+    }
+}
+
+impl Plugins {
+    /// Load every shared library in `dir`; libraries that fail to load are skipped
+    pub fn load_dir(dir: &Path) -> Self {
+        let mut plugins = Self::default();
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Failed to read plugin directory {}: {}", dir.display(), e);
+                return plugins;
+            }
+        };
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension() == Some(std::env::consts::DLL_EXTENSION.as_ref()))
+            .collect();
+        paths.sort();
+        for path in paths {
+            match Self::load(&path) {
+                Ok((name, plugin)) if plugins.loaded.contains_key(&name) => {
+                    warn!(
+                        "Skipping {}: plugin {} already loaded",
+                        path.display(),
+                        name
+                    );
+                    drop(plugin);
+                }
+                Ok((name, plugin)) => {
+                    plugins.loaded.insert(name, Arc::new(plugin));
+                }
+                Err(e) => warn!("Skipping plugin {}: {}", path.display(), e),
+            }
+        }
+        plugins
+    }
+
+    fn load(path: &Path) -> Result<(String, Plugin), String> {
+        // Loading runs the library's initializers; the plugin directory is trusted
+        let library = unsafe { Library::new(path) }.map_err(|e| e.to_string())?;
+        let entry = unsafe { library.get::<EntryPoint>(ENTRY_SYMBOL) }
+            .map_err(|e| format!("not a checker plugin: {e}"))?;
+        let descriptor = unsafe { entry(ABI_VERSION) };
+        if descriptor.is_null() {
+            return Err(format!(
+                "no ABI version in common with this host ({ABI_VERSION})"
+            ));
+        }
+        let abi_version = unsafe { (*descriptor).abi_version };
+        if !(MIN_ABI_VERSION..=ABI_VERSION).contains(&abi_version) {
+            return Err(format!(
+                "unsupported ABI version {abi_version}, expected {MIN_ABI_VERSION} to {ABI_VERSION}"
+            ));
+        }
+        let plugin = Plugin {
+            descriptor,
+            _library: library,
+        };
+        let (name, version) = {
+            let descriptor = plugin.descriptor();
+            let name = unsafe { CStr::from_ptr(descriptor.name) };
+            let version = unsafe { CStr::from_ptr(descriptor.version) };
+            (name.to_string_lossy(), version.to_string_lossy())
+        };
+        info!(
+            "Loaded plugin {} {} (ABI {}) from {}",
+            name,
+            version,
+            abi_version,
+            path.display()
+        );
+        Ok((name.into_owned(), plugin))
+    }
+
+    /// Create a checker from a loaded plugin, reporting failures as an unavailable check
+    pub fn checker(&self, name: &str, config: &serde_json::Value) -> Box<dyn HealthCheck> {
+        let Some(plugin) = self.loaded.get(name) else {
+            return Box::new(UnavailableCheck(format!("plugin {name} is not loaded")));
+        };
+        let config = CString::new(config.to_string()).unwrap();
+        let mut error = std::ptr::null_mut();
+        let checker = unsafe { (plugin.descriptor().create)(config.as_ptr(), &mut error) };
+        if checker.is_null() {
+            let error = plugin.take_string(error);
+            let message = error.unwrap_or_else(|| "unknown error".into());
+            return Box::new(UnavailableCheck(format!("plugin {name}: {message}")));
+        }
+        Box::new(PluginCheck {
+            checker: Arc::new(Mutex::new(Checker {
+                plugin: plugin.clone(),
+                checker,
+            })),
+        })
+    }
+}
+
+struct Checker {
+    plugin: Arc<Plugin>,
+    checker: *mut c_void,
+}
+
+// Plugins must accept calls from any thread; the mutex keeps them sequential
+unsafe impl Send for Checker {}
+
+impl Checker {
+    fn run(&self) -> CheckResult {
+        let mut message = std::ptr::null_mut();
+        let code = unsafe { (self.plugin.descriptor().check)(self.checker, &mut message) };
+        let message = self.plugin.take_string(message);
+        let status = match code {
+            STATUS_UP => CheckStatus::Up,
+            STATUS_DEGRADED => CheckStatus::Degraded,
+            _ => CheckStatus::Down,
+        };
+        CheckResult::new(status, message)
+    }
+}
+
+impl Drop for Checker {
+    fn drop(&mut self) {
+        unsafe { (self.plugin.descriptor().destroy)(self.checker) };
+    }
+}
+
+/// Check backed by a checker created by a native plugin, run on a blocking thread
+pub struct PluginCheck {
+    checker: Arc<Mutex<Checker>>,
+}
+
+#[async_trait]
+impl HealthCheck for PluginCheck {
+    async fn check(&self) -> CheckResult {
+        let checker = self.checker.clone();
+        let result = tokio::task::spawn_blocking(move || match checker.try_lock() {
+            Ok(checker) => checker.run(),
+            // An execution that timed out may still be blocked in the plugin
+            Err(_) => CheckResult::down("previous execution is still running"),
+        })
+        .await;
+        result.unwrap_or_else(|e| CheckResult::down(format!("plugin failed: {e}")))
+    }
+}
@@ -0,0 +1,203 @@
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::{CheckResult, CheckStatus, HealthCheck, TcpCheck};
+
+/// Protocol version 3.0
+const PROTOCOL_VERSION: i32 = 196608;
+/// Larger messages are not expected before the server is ready for queries
+const MAX_MESSAGE_LEN: usize = 64 << 10;
+
+/// Checks that a PostgreSQL server accepts connections, like `pg_isready`: the startup
+/// handshake is sent and the connection is closed once the server asks for credentials or is
+/// ready for queries
+pub struct PostgresCheck {
+    tcp: TcpCheck,
+    user: String,
+    database: Option<String>,
+}
+
+/// First answer to the startup message that tells whether the server accepts connections
+#[derive(Debug, PartialEq)]
+enum Answer {
+    AuthenticationRequired,
+    Ready,
+    Error { code: String, message: String },
+}
+
+impl PostgresCheck {
+    pub fn new(tcp: TcpCheck, user: String, database: Option<String>) -> Self {
+        Self {
+            tcp,
+            user,
+            database,
+        }
+    }
+
+    fn startup_message(&self) -> Vec<u8> {
+        let mut parameters = vec![("user", self.user.as_str())];
+        if let Some(database) = &self.database {
+            parameters.push(("database", database));
+        }
+        parameters.push(("application_name", env!("CARGO_PKG_NAME")));
+        let mut body = PROTOCOL_VERSION.to_be_bytes().to_vec();
+        for (key, value) in parameters {
+            body.extend_from_slice(key.as_bytes());
+            body.push(0);
+            body.extend_from_slice(value.as_bytes());
+            body.push(0);
+        }
+        body.push(0);
+        let mut message = ((body.len() + 4) as i32).to_be_bytes().to_vec();
+        message.extend(body);
+        message
+    }
+
+    async fn handshake(&self, stream: &mut TcpStream) -> Result<Answer, String> {
+        let io_error = |e: std::io::Error| format!("startup handshake failed: {e}");
+        stream
+            .write_all(&self.startup_message())
+            .await
+            .map_err(io_error)?;
+        loop {
+            let kind = stream.read_u8().await.map_err(io_error)?;
+            let len = stream.read_i32().await.map_err(io_error)? as usize;
+            if !(4..=MAX_MESSAGE_LEN).contains(&len) {
+                return Err(format!("invalid message length {len}, is this PostgreSQL?"));
+            }
+            let mut body = vec![0; len - 4];
+            stream.read_exact(&mut body).await.map_err(io_error)?;
+            match kind {
+                // AuthenticationOk is followed by parameters and ReadyForQuery
+                b'R' if body.starts_with(&[0, 0, 0, 0]) => continue,
+                b'R' => return Ok(Answer::AuthenticationRequired),
+                b'Z' => return Ok(Answer::Ready),
+                b'E' => return Ok(error_response(&body)),
+                // ParameterStatus, BackendKeyData and notices
+                b'S' | b'K' | b'N' => continue,
+                _ => return Err(format!("unexpected message {:?}", kind as char)),
+            }
+        }
+    }
+}
+
+/// SQLSTATE and message of an ErrorResponse
+fn error_response(body: &[u8]) -> Answer {
+    let mut code = String::new();
+    let mut message = String::new();
+    for field in body.split(|b| *b == 0).filter(|f| !f.is_empty()) {
+        let value = String::from_utf8_lossy(&field[1..]).into_owned();
+        match field[0] {
+            b'C' => code = value,
+            b'M' => message = value,
+            _ => {}
+        }
+    }
+    Answer::Error { code, message }
+}
+
+#[async_trait]
+impl HealthCheck for PostgresCheck {
+    async fn check(&self) -> CheckResult {
+        let (mut stream, address) = match self.tcp.connect().await {
+            Ok(connected) => connected,
+            Err(e) => return CheckResult::down(e),
+        };
+        let result = match self.handshake(&mut stream).await {
+            Ok(Answer::AuthenticationRequired | Answer::Ready) => CheckResult::up(),
+            // cannot_connect_now (starting up, shutting down or in recovery) and
+            // too_many_connections: the server is reachable but cannot serve clients
+            Ok(Answer::Error { code, message }) if code == "57P03" || code == "53300" => {
+                CheckResult::down(message).with_detail("sqlstate", code)
+            }
+            // Rejected user or database: the server works, the check is likely misconfigured
+            Ok(Answer::Error { code, message }) => {
+                CheckResult::new(CheckStatus::Degraded, Some(message)).with_detail("sqlstate", code)
+            }
+            Err(e) => CheckResult::down(e),
+        };
+        result.with_remote_addr(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn message(kind: u8, body: &[u8]) -> Vec<u8> {
+        let mut message = vec![kind];
+        message.extend(((body.len() + 4) as i32).to_be_bytes());
+        message.extend(body);
+        message
+    }
+
+    // Server answering the startup message with `reply`, returning the startup message
+    async fn server(reply: Vec<u8>) -> (PostgresCheck, tokio::task::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let len = stream.read_i32().await.unwrap() as usize;
+            let mut startup = vec![0; len - 4];
+            stream.read_exact(&mut startup).await.unwrap();
+            stream.write_all(&reply).await.unwrap();
+            startup
+        });
+        let check = PostgresCheck::new(TcpCheck::new(address), "app".into(), Some("orders".into()));
+        (check, handle)
+    }
+
+    #[tokio::test]
+    async fn servers_asking_for_credentials_accept_connections() {
+        // AuthenticationSASL
+        let (check, server) = server(message(b'R', &[0, 0, 0, 10])).await;
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Up, "{:?}", result.message);
+        let startup = server.await.unwrap();
+        assert_eq!(startup[..4], PROTOCOL_VERSION.to_be_bytes());
+        let parameters = String::from_utf8_lossy(&startup[4..]);
+        assert!(parameters.starts_with("user\0app\0database\0orders\0"));
+    }
+
+    #[tokio::test]
+    async fn trusted_connections_wait_for_ready_for_query() {
+        let mut reply = message(b'R', &[0, 0, 0, 0]);
+        reply.extend(message(b'S', b"server_version\x0017.2\0"));
+        reply.extend(message(b'Z', b"I"));
+        let (check, _) = server(reply).await;
+        assert_eq!(check.check().await.status, CheckStatus::Up);
+    }
+
+    #[tokio::test]
+    async fn maps_error_responses() {
+        let starting = message(
+            b'E',
+            b"SFATAL\0C57P03\0Mthe database system is starting up\0\0",
+        );
+        let (check, _) = server(starting).await;
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(
+            result.message.as_deref(),
+            Some("the database system is starting up")
+        );
+        assert_eq!(result.details["sqlstate"], "57P03");
+
+        let unknown = message(
+            b'E',
+            b"SFATAL\0C3D000\0Mdatabase \"orders\" does not exist\0\0",
+        );
+        let (check, _) = server(unknown).await;
+        assert_eq!(check.check().await.status, CheckStatus::Degraded);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_protocols() {
+        let (check, _) = server(b"HTTP/1.1 400 Bad Request\r\n\r\n".to_vec()).await;
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Down);
+        assert!(result.message.unwrap().contains("is this PostgreSQL?"));
+    }
+}
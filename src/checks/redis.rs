@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use super::{CheckResult, HealthCheck, TcpCheck};
+
+/// Longest reply line read, well above `+PONG` and error messages
+const MAX_REPLY_LEN: u64 = 4096;
+
+/// Checks that a Redis server answers `PING`, authenticating first when credentials are
+/// configured. Replies such as `-LOADING` while the dataset is loaded report the server down
+pub struct RedisCheck {
+    tcp: TcpCheck,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl RedisCheck {
+    pub fn new(tcp: TcpCheck, username: Option<String>, password: Option<String>) -> Self {
+        Self {
+            tcp,
+            username,
+            password,
+        }
+    }
+
+    async fn ping(&self, stream: TcpStream) -> Result<(), String> {
+        let mut stream = BufReader::new(stream);
+        if let Some(password) = &self.password {
+            let mut auth = vec!["AUTH"];
+            auth.extend(self.username.as_deref());
+            auth.push(password);
+            send(&mut stream, &auth).await?;
+            reply(&mut stream)
+                .await
+                .map_err(|e| format!("authentication failed: {e}"))?;
+        }
+        send(&mut stream, &["PING"]).await?;
+        match reply(&mut stream).await?.as_str() {
+            "PONG" => Ok(()),
+            other => Err(format!("unexpected reply to PING: {other}")),
+        }
+    }
+}
+
+/// Write a command as a RESP array of bulk strings
+async fn send(stream: &mut BufReader<TcpStream>, command: &[&str]) -> Result<(), String> {
+    let mut request = format!("*{}\r\n", command.len());
+    for argument in command {
+        request.push_str(&format!("${}\r\n{argument}\r\n", argument.len()));
+    }
+    stream
+        .get_mut()
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Read a simple string reply; error replies are returned as errors
+async fn reply(stream: &mut BufReader<TcpStream>) -> Result<String, String> {
+    let mut line = String::new();
+    let mut limited = (&mut *stream).take(MAX_REPLY_LEN);
+    match limited.read_line(&mut line).await {
+        Ok(0) => return Err("connection closed".into()),
+        Ok(_) => {}
+        Err(e) => return Err(e.to_string()),
+    }
+    let line = line.trim_end_matches(['\r', '\n']);
+    if let Some(status) = line.strip_prefix('+') {
+        Ok(status.to_string())
+    } else if let Some(error) = line.strip_prefix('-') {
+        Err(error.to_string())
+    } else {
+        Err(format!("unexpected reply {line:?}, is this Redis?"))
+    }
+}
+
+#[async_trait]
+impl HealthCheck for RedisCheck {
+    async fn check(&self) -> CheckResult {
+        let (stream, address) = match self.tcp.connect().await {
+            Ok(connected) => connected,
+            Err(e) => return CheckResult::down(e),
+        };
+        let result = match self.ping(stream).await {
+            Ok(()) => CheckResult::up(),
+            Err(e) => CheckResult::down(e),
+        };
+        result.with_remote_addr(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckStatus;
+    use tokio::net::TcpListener;
+
+    // Server answering each command with the next reply, returning what it received
+    async fn server(replies: &'static [&'static str]) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = String::new();
+            for reply in replies {
+                let mut buffer = [0; 256];
+                let n = stream.read(&mut buffer).await.unwrap();
+                received.push_str(&String::from_utf8_lossy(&buffer[..n]));
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+            received
+        });
+        (address, handle)
+    }
+
+    #[tokio::test]
+    async fn authenticates_and_pings() {
+        let (address, server) = server(&["+OK\r\n", "+PONG\r\n"]).await;
+        let check = RedisCheck::new(
+            TcpCheck::new(address),
+            Some("probe".into()),
+            Some("s3cret".into()),
+        );
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Up, "{:?}", result.message);
+        assert_eq!(
+            server.await.unwrap(),
+            "*3\r\n$4\r\nAUTH\r\n$5\r\nprobe\r\n$6\r\ns3cret\r\n*1\r\n$4\r\nPING\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn error_replies_report_the_server_down() {
+        let (address, _) = server(&["-LOADING Redis is loading the dataset in memory\r\n"]).await;
+        let result = RedisCheck::new(TcpCheck::new(address), None, None)
+            .check()
+            .await;
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(
+            result.message.as_deref(),
+            Some("LOADING Redis is loading the dataset in memory")
+        );
+
+        let (address, _) = server(&["-WRONGPASS invalid username-password pair\r\n"]).await;
+        let check = RedisCheck::new(TcpCheck::new(address), None, Some("nope".into()));
+        let message = check.check().await.message.unwrap();
+        assert!(
+            message.starts_with("authentication failed: WRONGPASS"),
+            "{message}"
+        );
+    }
+}
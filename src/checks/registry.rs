@@ -0,0 +1,1045 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::{Instant, sleep, sleep_until, timeout};
+use tracing::{debug, warn};
+
+use super::{
+    CheckEvent, CheckHook, CheckResult, CheckStatus, HealthCheck, Heartbeats, HookContext,
+    PreCheck, Resolver, Transforms,
+};
+use crate::conditions::{ConditionUpdate, Conditions};
+use crate::config::{CheckConfig, Config, ConfigError, Latency, Overlap, SchedulerConfig};
+use crate::faults::Faults;
+use crate::ha::ActiveFlag;
+
+/// Results kept per check for the history API
+const HISTORY_SIZE: usize = 100;
+
+struct Entry {
+    name: String,
+    critical: bool,
+    interval: Duration,
+    /// Largest random change to each pause between executions
+    jitter: Duration,
+    overlap: Overlap,
+    timeout: Duration,
+    /// Identity of the probed target, stamped on every result
+    target: Option<String>,
+    tenant: Option<String>,
+    check: Box<dyn HealthCheck>,
+    /// Reported until the first result is recorded
+    pending: CheckResult,
+    /// Recent results, oldest first, the last one being the current state
+    history: RwLock<VecDeque<CheckResult>>,
+    /// Completed executions, including those whose result was dropped
+    runs: AtomicU64,
+    /// Unix timestamp of the last completed execution
+    last_run: AtomicU64,
+    /// A scheduled execution is in progress
+    running: AtomicBool,
+    /// Another execution follows the one in progress
+    queued: AtomicBool,
+    /// Scheduled executions left out because the previous one was still running
+    skipped: AtomicU64,
+    /// No longer part of the registry; its schedule stops
+    removed: AtomicBool,
+}
+
+impl Entry {
+    fn new(
+        name: &str,
+        critical: bool,
+        interval: Duration,
+        timeout: Duration,
+        check: Box<dyn HealthCheck>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            critical,
+            interval,
+            jitter: Duration::ZERO,
+            overlap: Overlap::default(),
+            timeout,
+            target: None,
+            tenant: None,
+            check,
+            pending: CheckResult::down("pending first check"),
+            history: RwLock::new(VecDeque::new()),
+            runs: AtomicU64::new(0),
+            last_run: AtomicU64::new(0),
+            running: AtomicBool::new(false),
+            queued: AtomicBool::new(false),
+            skipped: AtomicU64::new(0),
+            removed: AtomicBool::new(false),
+        }
+    }
+
+    /// Metric attributes identifying the check
+    fn attributes(&self) -> Vec<KeyValue> {
+        let mut attributes = vec![KeyValue::new("check", self.name.clone())];
+        if let Some(tenant) = &self.tenant {
+            attributes.push(KeyValue::new("tenant", tenant.clone()));
+        }
+        attributes
+    }
+
+    /// Metric attributes of the probes of a target, with the IP family (`v4` or `v6`) the
+    /// last probe connected over when it reports one
+    fn probe_attributes(&self, last: &CheckResult) -> Option<Vec<KeyValue>> {
+        let mut attributes = self.attributes();
+        attributes.push(KeyValue::new("target", self.target.clone()?));
+        match last.details.get("ip_protocol").and_then(|p| p.as_u64()) {
+            Some(4) => attributes.push(KeyValue::new("ip_family", "v4")),
+            Some(6) => attributes.push(KeyValue::new("ip_family", "v6")),
+            _ => {}
+        }
+        Some(attributes)
+    }
+
+    fn last_result(&self) -> Option<CheckResult> {
+        self.history.read().unwrap().back().cloned()
+    }
+}
+
+// The interval shortened or lengthened by a uniformly random amount up to `jitter`
+fn jittered(interval: Duration, jitter: Duration) -> Duration {
+    (interval + jitter.mul_f64(2.0 * rand::random::<f64>())).saturating_sub(jitter)
+}
+
+/// Decides whether this instance runs a check, e.g. when checks are sharded across a cluster
+pub type Ownership = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Holds the configured checks and their most recent results
+pub struct CheckRegistry {
+    entries: RwLock<Vec<Arc<Entry>>>,
+    faults: Faults,
+    heartbeats: Arc<Heartbeats>,
+    resolver: Resolver,
+    latency: Option<Latency>,
+    location: Option<String>,
+    /// Defaults for the jitter and overlap of checks, and whether to spread their first runs
+    scheduler: SchedulerConfig,
+    /// Scheduling has started; checks added from now on are scheduled right away
+    spawned: AtomicBool,
+    events: broadcast::Sender<CheckEvent>,
+    /// Scheduled probing only runs while this instance is active
+    active: ActiveFlag,
+    owns: Option<Ownership>,
+    hooks: Vec<Arc<dyn CheckHook>>,
+    transforms: Transforms,
+    conditions: Conditions,
+}
+
+impl Default for CheckRegistry {
+    fn default() -> Self {
+        Self {
+            entries: RwLock::default(),
+            faults: Faults::default(),
+            heartbeats: Arc::default(),
+            resolver: Resolver::default(),
+            latency: None,
+            location: None,
+            scheduler: SchedulerConfig::default(),
+            spawned: AtomicBool::new(false),
+            events: broadcast::channel(1024).0,
+            active: ActiveFlag::default(),
+            owns: None,
+            hooks: Vec::new(),
+            transforms: Transforms::default(),
+            conditions: Conditions::default(),
+        }
+    }
+}
+
+/// Subset of checks selected by `only` / `exclude` query parameters
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Selector {
+    /// Comma-separated list of checks to include; all checks when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub only: Option<String>,
+    /// Comma-separated list of checks to leave out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exclude: Option<String>,
+}
+
+/// Aggregated status over a set of checks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub status: CheckStatus,
+    pub checks: BTreeMap<String, ComponentReport>,
+}
+
+/// Scheduler activity of a single check
+#[derive(Debug, Clone, Serialize)]
+pub struct TickReport {
+    pub runs: u64,
+    /// Unix timestamp of the last completed execution
+    pub last_run: Option<u64>,
+    /// Executions skipped because the previous one was still running
+    pub skipped: u64,
+    /// No execution completed within twice the interval plus the timeout
+    pub stalled: bool,
+}
+
+/// A probed target and the last result of the check probing it
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetReport {
+    pub check: String,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// `None` until the first execution completes
+    pub last: Option<CheckResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentReport {
+    pub critical: bool,
+    #[serde(flatten)]
+    pub result: CheckResult,
+}
+
+impl Selector {
+    fn names(list: &Option<String>) -> impl Iterator<Item = &str> {
+        list.as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let included = self.only.is_none() || Self::names(&self.only).any(|n| n == name);
+        included && !Self::names(&self.exclude).any(|n| n == name)
+    }
+}
+
+impl CheckRegistry {
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let mut registry = Self {
+            latency: config.latency.probes,
+            location: config.location.clone(),
+            scheduler: config.scheduler.clone(),
+            resolver: Resolver::from_config(&config.dns),
+            transforms: Transforms::compile(&config.transforms).map_err(ConfigError)?,
+            ..Self::default()
+        };
+        #[cfg(feature = "plugins")]
+        let plugins = config
+            .plugins_dir
+            .as_deref()
+            .map(super::Plugins::load_dir)
+            .unwrap_or_default();
+        for check in &config.checks {
+            let checker = match &check.kind {
+                #[cfg(feature = "plugins")]
+                crate::config::CheckKind::Plugin { plugin, config } => {
+                    plugins.checker(plugin, config)
+                }
+                _ => super::from_config(check, &registry.heartbeats, &registry.resolver),
+            };
+            let entry = registry.entry(check, checker);
+            registry.entries.get_mut().unwrap().push(Arc::new(entry));
+        }
+        Ok(registry)
+    }
+
+    fn entry(&self, check: &CheckConfig, checker: Box<dyn HealthCheck>) -> Entry {
+        let mut entry = Entry::new(
+            &check.name,
+            check.critical,
+            check.interval,
+            check.timeout,
+            checker,
+        );
+        entry.target = check.target_identity();
+        entry.tenant = check.tenant.clone();
+        entry.jitter = check.jitter.unwrap_or(self.scheduler.jitter);
+        entry.overlap = check.overlap.unwrap_or(self.scheduler.overlap);
+        entry
+    }
+
+    /// Add a check at runtime, e.g. one declared as a Kubernetes resource; it is scheduled
+    /// right away once scheduling has started
+    pub fn add(self: &Arc<Self>, check: &CheckConfig) -> Result<(), String> {
+        let checker = super::from_config(check, &self.heartbeats, &self.resolver);
+        let entry = Arc::new(self.entry(check, checker));
+        {
+            let mut entries = self.entries.write().unwrap();
+            if entries.iter().any(|e| e.name == check.name) {
+                return Err(format!("check {} already exists", check.name));
+            }
+            entries.push(Arc::clone(&entry));
+        }
+        if self.spawned.load(Ordering::Acquire) {
+            self.spawn_entry(entry);
+        }
+        Ok(())
+    }
+
+    /// Remove a check and stop its schedule; false if there is no such check
+    pub fn remove(&self, name: &str) -> bool {
+        let mut entries = self.entries.write().unwrap();
+        let Some(index) = entries.iter().position(|e| e.name == name) else {
+            return false;
+        };
+        entries.remove(index).removed.store(true, Ordering::Release);
+        true
+    }
+
+    /// Only run scheduled checks while the flag is set, e.g. on the elected leader
+    pub fn with_active_flag(mut self, active: ActiveFlag) -> Self {
+        self.active = active;
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.is_active()
+    }
+
+    /// Only run and report the checks assigned to this instance
+    pub fn with_ownership(mut self, owns: Ownership) -> Self {
+        self.owns = Some(owns);
+        self
+    }
+
+    /// Run a hook before and after every check execution
+    pub fn with_hook(mut self, hook: impl CheckHook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    fn owns(&self, name: &str) -> bool {
+        self.owns.as_ref().is_none_or(|owns| owns(name))
+    }
+
+    pub fn register(
+        &mut self,
+        name: &str,
+        critical: bool,
+        interval: Duration,
+        timeout: Duration,
+        check: Box<dyn HealthCheck>,
+    ) {
+        let entry = Entry::new(name, critical, interval, timeout, check);
+        self.entries.get_mut().unwrap().push(Arc::new(entry));
+    }
+
+    pub fn faults(&self) -> &Faults {
+        &self.faults
+    }
+
+    /// Pings received by heartbeat checks
+    pub fn heartbeats(&self) -> &Heartbeats {
+        &self.heartbeats
+    }
+
+    /// Name resolution shared by the probes
+    pub fn resolver(&self) -> &Resolver {
+        &self.resolver
+    }
+
+    /// Conditions reported by applications, part of readiness next to the checks
+    pub fn conditions(&self) -> &Conditions {
+        &self.conditions
+    }
+
+    /// Record a condition and publish it like a check execution, so changes are notified
+    pub fn report_condition(&self, name: &str, update: ConditionUpdate) {
+        let previous = self.conditions.set(name, update);
+        // Just recorded
+        let result = self.conditions.get(name).unwrap().result();
+        let _ = self.events.send(CheckEvent {
+            check: name.to_string(),
+            previous: previous.map(|p| p.status),
+            result,
+        });
+    }
+
+    /// Subscribe to completed executions of individual checks
+    pub fn subscribe(&self) -> broadcast::Receiver<CheckEvent> {
+        self.events.subscribe()
+    }
+
+    /// Publish an event for a check executed elsewhere, e.g. by a remote agent
+    pub fn publish(&self, event: CheckEvent) {
+        let _ = self.events.send(event);
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let entries = self.entries.read().unwrap();
+        entries.iter().map(|e| e.name.clone()).collect()
+    }
+
+    /// Whether a check of this name is registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.read().unwrap().iter().any(|e| e.name == name)
+    }
+
+    fn selected(&self, selector: &Selector) -> Vec<Arc<Entry>> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .iter()
+            .filter(|e| selector.matches(&e.name))
+            .cloned()
+            .collect()
+    }
+
+    /// Names referenced by the selector that are neither registered nor reported conditions
+    pub fn unknown(&self, selector: &Selector) -> Vec<String> {
+        Selector::names(&selector.only)
+            .chain(Selector::names(&selector.exclude))
+            .filter(|n| !self.contains(n) && !self.conditions.contains(n))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Start a background task per check that refreshes its cached result
+    pub fn spawn(self: &Arc<Self>) {
+        self.spawned.store(true, Ordering::Release);
+        let entries = self.entries.read().unwrap().clone();
+        for entry in entries {
+            self.spawn_entry(entry);
+        }
+    }
+
+    fn spawn_entry(self: &Arc<Self>, entry: Arc<Entry>) {
+        let registry = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut next = Instant::now();
+            if registry.scheduler.spread {
+                next += entry.interval.mul_f64(rand::random::<f64>());
+            }
+            loop {
+                sleep_until(next).await;
+                if entry.removed.load(Ordering::Acquire) {
+                    break;
+                }
+                if !registry.active.is_active() || !registry.owns(&entry.name) {
+                    // Start probing promptly once this instance becomes active or owner
+                    next = Instant::now() + entry.interval.min(Duration::from_secs(1));
+                    continue;
+                }
+                registry.tick(&entry);
+                // Executions start at a fixed rate, however long they take
+                next = (next + jittered(entry.interval, entry.jitter)).max(Instant::now());
+            }
+        });
+    }
+
+    // Start a scheduled execution of a check, unless the previous one is still running
+    fn tick(self: &Arc<Self>, entry: &Arc<Entry>) {
+        if entry.running.swap(true, Ordering::AcqRel) {
+            if entry.overlap == Overlap::Queue && !entry.queued.swap(true, Ordering::AcqRel) {
+                debug!(
+                    "check {} is still running, queueing an execution",
+                    entry.name
+                );
+            } else {
+                entry.skipped.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "check {} is still running, skipping an execution",
+                    entry.name
+                );
+            }
+            return;
+        }
+        let registry = Arc::clone(self);
+        let entry = Arc::clone(entry);
+        tokio::spawn(async move {
+            loop {
+                registry.run_entry(&entry).await;
+                if entry.queued.swap(false, Ordering::AcqRel) {
+                    continue;
+                }
+                entry.running.store(false, Ordering::Release);
+                // An execution queued since the check above would otherwise be lost
+                if !entry.queued.swap(false, Ordering::AcqRel)
+                    || entry.running.swap(true, Ordering::AcqRel)
+                {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Whether every check run here has completed an execution
+    pub fn has_started(&self) -> bool {
+        let entries = self.entries.read().unwrap();
+        entries
+            .iter()
+            .filter(|entry| self.owns(&entry.name))
+            .all(|entry| entry.runs.load(Ordering::Relaxed) > 0)
+    }
+
+    /// Execute the selected checks once, concurrently, and return the report
+    pub async fn run_once(&self, selector: &Selector) -> CheckReport {
+        let selected = self.selected(selector);
+        futures_util::future::join_all(selected.iter().map(|e| self.run_entry(e))).await;
+        self.report(selector)
+    }
+
+    /// Aggregate the cached results of the selected checks
+    pub fn report(&self, selector: &Selector) -> CheckReport {
+        self.report_matching(|name| selector.matches(name))
+    }
+
+    /// Aggregate the cached results of the checks whose name satisfies `filter`
+    pub fn report_matching(&self, filter: impl Fn(&str) -> bool) -> CheckReport {
+        let mut status = CheckStatus::Up;
+        let mut checks = BTreeMap::new();
+        let selected: Vec<_> = {
+            let entries = self.entries.read().unwrap();
+            entries
+                .iter()
+                .filter(|e| filter(&e.name))
+                .cloned()
+                .collect()
+        };
+        for entry in selected.iter().filter(|e| self.owns(&e.name)) {
+            let result = match entry.history.read().unwrap().back() {
+                Some(result) => result.clone(),
+                // Checks that ran but whose every result was dropped have no state to report yet
+                None if entry.runs.load(Ordering::Relaxed) > 0 => continue,
+                None => entry.pending.clone(),
+            };
+            let result = self.faults.apply_forced(&entry.name, result);
+            let effective = match (result.status, entry.critical) {
+                (CheckStatus::Down, false) => CheckStatus::Degraded,
+                (s, _) => s,
+            };
+            status = status.max(effective);
+            checks.insert(
+                entry.name.clone(),
+                ComponentReport {
+                    critical: entry.critical,
+                    result,
+                },
+            );
+        }
+        let conditions = self.conditions.list();
+        for (name, condition) in conditions {
+            if !filter(&name) {
+                continue;
+            }
+            let result = condition.result();
+            status = status.max(result.status);
+            let component = ComponentReport {
+                critical: true,
+                result,
+            };
+            checks.insert(name, component);
+        }
+        CheckReport { status, checks }
+    }
+
+    /// Recent results of a check, oldest first
+    pub fn history(&self, name: &str) -> Option<Vec<CheckResult>> {
+        let entries = self.entries.read().unwrap();
+        let entry = entries.iter().find(|e| e.name == name)?;
+        Some(entry.history.read().unwrap().iter().cloned().collect())
+    }
+
+    /// Report when each check last ran, flagging checks whose schedule has stalled
+    pub fn ticks(&self) -> BTreeMap<String, TickReport> {
+        let now = super::unix_now();
+        let entries = self.entries.read().unwrap();
+        entries
+            .iter()
+            .filter(|entry| self.owns(&entry.name))
+            .map(|entry| {
+                let runs = entry.runs.load(Ordering::Relaxed);
+                let last_run = (runs > 0).then(|| entry.last_run.load(Ordering::Relaxed));
+                let deadline = (entry.interval * 2 + entry.timeout).as_secs();
+                let stalled = last_run.is_some_and(|last| now.saturating_sub(last) > deadline);
+                let tick = TickReport {
+                    runs,
+                    last_run,
+                    skipped: entry.skipped.load(Ordering::Relaxed),
+                    stalled,
+                };
+                (entry.name.clone(), tick)
+            })
+            .collect()
+    }
+
+    /// Checks probing a target, by target, with their last result
+    pub fn targets(&self) -> BTreeMap<String, Vec<TargetReport>> {
+        let mut targets: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        let entries = self.entries.read().unwrap();
+        for entry in entries.iter().filter(|entry| self.owns(&entry.name)) {
+            let Some(target) = &entry.target else {
+                continue;
+            };
+            targets
+                .entry(target.clone())
+                .or_default()
+                .push(TargetReport {
+                    check: entry.name.clone(),
+                    interval: entry.interval,
+                    timeout: entry.timeout,
+                    last: entry.last_result(),
+                });
+        }
+        targets
+    }
+
+    pub fn register_metrics(self: &Arc<Self>, meter: &Meter) {
+        let registry = Arc::clone(self);
+        meter
+            .u64_observable_counter("scheduler.skipped_runs")
+            .with_description(
+                "Scheduled executions skipped because the previous one was still running",
+            )
+            .with_callback(move |observer| {
+                let entries = registry.entries.read().unwrap();
+                // Checks reassigned to another instance are exported by that instance only
+                for entry in entries.iter().filter(|e| registry.owns(&e.name)) {
+                    let skipped = entry.skipped.load(Ordering::Relaxed);
+                    observer.observe(skipped, &entry.attributes());
+                }
+            })
+            .build();
+        let registry = Arc::clone(self);
+        meter
+            .u64_observable_gauge("probe.success")
+            .with_description("Whether the last probe of a target succeeded (up or degraded)")
+            .with_callback(move |observer| {
+                let entries = registry.entries.read().unwrap();
+                for entry in entries.iter().filter(|e| registry.owns(&e.name)) {
+                    let Some(last) = entry.last_result() else {
+                        continue;
+                    };
+                    let Some(attributes) = entry.probe_attributes(&last) else {
+                        continue;
+                    };
+                    let success = last.status != CheckStatus::Down;
+                    observer.observe(success as u64, &attributes);
+                }
+            })
+            .build();
+        let registry = Arc::clone(self);
+        meter
+            .f64_observable_gauge("probe.duration_seconds")
+            .with_description("Duration of the last probe of a target")
+            .with_callback(move |observer| {
+                let entries = registry.entries.read().unwrap();
+                for entry in entries.iter().filter(|e| registry.owns(&e.name)) {
+                    let Some(last) = entry.last_result() else {
+                        continue;
+                    };
+                    let Some(attributes) = entry.probe_attributes(&last) else {
+                        continue;
+                    };
+                    observer.observe(last.duration_ms as f64 / 1000.0, &attributes);
+                }
+            })
+            .build();
+        let registry = Arc::clone(self);
+        meter
+            .u64_observable_gauge("probe.http_status_code")
+            .with_description("Response status code of the last probe of an http target")
+            .with_callback(move |observer| {
+                let entries = registry.entries.read().unwrap();
+                for entry in entries.iter().filter(|e| registry.owns(&e.name)) {
+                    let Some(last) = entry.last_result() else {
+                        continue;
+                    };
+                    let Some(attributes) = entry.probe_attributes(&last) else {
+                        continue;
+                    };
+                    if let Some(code) = last.details.get("status_code").and_then(|c| c.as_u64()) {
+                        observer.observe(code, &attributes);
+                    }
+                }
+            })
+            .build();
+    }
+
+    async fn run_entry(&self, entry: &Entry) {
+        let context = HookContext {
+            check: &entry.name,
+            critical: entry.critical,
+            target: entry.target.as_deref(),
+            runs: entry.runs.load(Ordering::Relaxed),
+        };
+        let mut skipped = None;
+        for hook in &self.hooks {
+            if let PreCheck::Skip(result) = hook.before(&context).await {
+                skipped = Some(result);
+                break;
+            }
+        }
+        let start = Instant::now();
+        // Faults are injected into executions only, not into results a hook decided on
+        let mut result = match skipped {
+            Some(result) => result,
+            None => self.faults.inject_failure(self.execute(entry).await),
+        };
+        result.duration_ms = start.elapsed().as_millis() as u64;
+        result.location = self.location.clone();
+        result.target = entry.target.clone();
+        entry.last_run.store(super::unix_now(), Ordering::Relaxed);
+        entry.runs.fetch_add(1, Ordering::Relaxed);
+        let Some(mut result) = self.transforms.apply(&entry.name, result) else {
+            debug!("result of check {} dropped by a transform", entry.name);
+            return;
+        };
+        for hook in &self.hooks {
+            hook.after(&context, &mut result).await;
+        }
+        if result.status == CheckStatus::Up {
+            debug!("check {} is up", entry.name);
+        } else {
+            warn!(
+                "check {} is {}: {}",
+                entry.name,
+                result.status.as_str(),
+                result.message.as_deref().unwrap_or_default()
+            );
+        }
+        let previous = {
+            let mut history = entry.history.write().unwrap();
+            let previous = history.back().map(|r| r.status);
+            if history.len() == HISTORY_SIZE {
+                history.pop_front();
+            }
+            history.push_back(result.clone());
+            previous
+        };
+        let _ = self.events.send(CheckEvent {
+            check: entry.name.clone(),
+            previous,
+            result,
+        });
+    }
+
+    async fn execute(&self, entry: &Entry) -> CheckResult {
+        let latency = self.latency.map(|l| l.sample()).unwrap_or_default();
+        let execution = async {
+            sleep(latency).await;
+            entry.check.check().await
+        };
+        match timeout(entry.timeout, execution).await {
+            Ok(result) => result,
+            Err(_) => CheckResult::down(format!("timed out after {:?}", entry.timeout)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::{CheckHook, HookContext, PreCheck, UnavailableCheck};
+    use async_trait::async_trait;
+
+    struct Fixed(CheckStatus);
+
+    #[async_trait]
+    impl HealthCheck for Fixed {
+        async fn check(&self) -> CheckResult {
+            CheckResult::new(self.0, Some("fixed".into()))
+        }
+    }
+
+    struct Maintenance;
+
+    #[async_trait]
+    impl CheckHook for Maintenance {
+        async fn before(&self, _context: &HookContext<'_>) -> PreCheck {
+            PreCheck::Skip(CheckResult::up().with_detail("state", "maintenance"))
+        }
+    }
+
+    fn single(registry: &mut CheckRegistry, status: CheckStatus) {
+        let interval = Duration::from_secs(10);
+        registry.register("db", true, interval, interval, Box::new(Fixed(status)));
+    }
+
+    fn selector(only: Option<&str>, exclude: Option<&str>) -> Selector {
+        Selector {
+            only: only.map(str::to_string),
+            exclude: exclude.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn selector_matches_everything_by_default() {
+        assert!(Selector::default().matches("db"));
+    }
+
+    #[test]
+    fn selector_includes_only_listed_checks() {
+        let selector = selector(Some("db, cache"), None);
+        assert!(selector.matches("db"));
+        assert!(selector.matches("cache"));
+        assert!(!selector.matches("api"));
+    }
+
+    #[test]
+    fn selector_exclusion_wins_over_inclusion() {
+        let selector = selector(Some("db,cache"), Some("cache"));
+        assert!(selector.matches("db"));
+        assert!(!selector.matches("cache"));
+        assert!(!selector.matches("api"));
+    }
+
+    #[test]
+    fn selector_ignores_empty_names() {
+        assert!(selector(None, Some(",")).matches("db"));
+        assert!(!selector(Some(" , "), None).matches("db"));
+    }
+
+    #[test]
+    fn unknown_lists_unregistered_names() {
+        let mut registry = CheckRegistry::default();
+        registry.register(
+            "db",
+            true,
+            Duration::from_secs(10),
+            Duration::from_secs(1),
+            Box::new(UnavailableCheck("test".into())),
+        );
+        let unknown = registry.unknown(&selector(Some("db,redis"), Some("cache")));
+        assert_eq!(unknown, ["redis", "cache"]);
+    }
+
+    #[test]
+    fn report_degrades_for_non_critical_failures() {
+        let mut registry = CheckRegistry::default();
+        let unavailable = || Box::new(UnavailableCheck("test".into()));
+        let interval = Duration::from_secs(10);
+        registry.register("db", true, interval, interval, unavailable());
+        registry.register("cache", false, interval, interval, unavailable());
+        assert_eq!(
+            registry.report(&selector(None, Some("db"))).status,
+            CheckStatus::Degraded
+        );
+        assert_eq!(
+            registry.report(&Selector::default()).status,
+            CheckStatus::Down
+        );
+    }
+
+    #[tokio::test]
+    async fn faults_are_not_injected_into_skipped_checks() {
+        let mut registry = CheckRegistry::default().with_hook(Maintenance);
+        single(&mut registry, CheckStatus::Up);
+        registry.faults().set_failure_rate(1.0).unwrap();
+        let report = registry.run_once(&Selector::default()).await;
+        assert_eq!(report.status, CheckStatus::Up);
+    }
+
+    #[tokio::test]
+    async fn faults_are_injected_into_executed_checks() {
+        let mut registry = CheckRegistry::default();
+        single(&mut registry, CheckStatus::Up);
+        registry.faults().set_failure_rate(1.0).unwrap();
+        let report = registry.run_once(&Selector::default()).await;
+        assert_eq!(report.status, CheckStatus::Down);
+    }
+
+    #[tokio::test]
+    async fn dropped_results_count_as_runs() {
+        let transforms: Vec<crate::config::TransformConfig> =
+            vec![toml::from_str(r#"action = "drop""#).unwrap()];
+        let mut registry = CheckRegistry {
+            transforms: Transforms::compile(&transforms).unwrap(),
+            ..CheckRegistry::default()
+        };
+        single(&mut registry, CheckStatus::Up);
+        assert_eq!(registry.report(&Selector::default()).checks.len(), 1);
+        let report = registry.run_once(&Selector::default()).await;
+        // Not reported as pending forever
+        assert!(report.checks.is_empty());
+        assert_eq!(report.status, CheckStatus::Up);
+        let tick = &registry.ticks()["db"];
+        assert_eq!(tick.runs, 1);
+        assert!(tick.last_run.is_some());
+    }
+
+    #[test]
+    fn invalid_transforms_fail_to_build_the_registry() {
+        // Embedders may build the configuration without loading it
+        let config: Config =
+            toml::from_str("[[transforms]]\nregex = \"(\"\naction = \"drop\"").unwrap();
+        let error = CheckRegistry::from_config(&config).err().unwrap();
+        assert!(error.0.starts_with("transforms[0]"), "{error}");
+    }
+
+    #[test]
+    fn conditions_are_part_of_the_report() {
+        let mut registry = CheckRegistry::default();
+        single(&mut registry, CheckStatus::Up);
+        let mut events = registry.subscribe();
+        let update = |ok| ConditionUpdate {
+            ok,
+            message: None,
+            ttl: None,
+        };
+        registry.report_condition("migrations", update(false));
+        assert!(
+            registry
+                .unknown(&selector(Some("migrations"), None))
+                .is_empty()
+        );
+        let report = registry.report(&Selector::default());
+        assert_eq!(report.status, CheckStatus::Down);
+        assert!(report.checks["migrations"].critical);
+        let report = registry.report(&selector(None, Some("migrations")));
+        assert_eq!(report.checks.len(), 1);
+        registry.report_condition("migrations", update(true));
+        let event = events.try_recv().unwrap();
+        assert!(event.previous.is_none());
+        let event = events.try_recv().unwrap();
+        assert!(event.is_transition());
+    }
+
+    #[tokio::test]
+    async fn probe_latency_counts_against_the_timeout() {
+        let mut registry = CheckRegistry {
+            latency: Some(toml::from_str("fixed = \"50ms\"\njitter = \"10ms\"").unwrap()),
+            ..CheckRegistry::default()
+        };
+        let interval = Duration::from_secs(10);
+        let timeout = Duration::from_millis(20);
+        let check = Box::new(Fixed(CheckStatus::Up));
+        registry.register("db", true, interval, timeout, check);
+        let report = registry.run_once(&Selector::default()).await;
+        assert_eq!(report.status, CheckStatus::Down);
+        let message = report.checks["db"].result.message.clone().unwrap();
+        assert!(message.starts_with("timed out"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn history_keeps_the_latest_results() {
+        let mut registry = CheckRegistry::default();
+        single(&mut registry, CheckStatus::Degraded);
+        assert!(registry.history("db").unwrap().is_empty());
+        for _ in 0..HISTORY_SIZE + 1 {
+            registry.run_once(&Selector::default()).await;
+        }
+        let history = registry.history("db").unwrap();
+        assert_eq!(history.len(), HISTORY_SIZE);
+        assert_eq!(history[0].status, CheckStatus::Degraded);
+        assert!(registry.history("cache").is_none());
+    }
+
+    #[test]
+    fn jitter_varies_pauses_around_the_interval() {
+        let interval = Duration::from_secs(10);
+        let jitter = Duration::from_secs(2);
+        let pauses: Vec<_> = (0..1000).map(|_| jittered(interval, jitter)).collect();
+        assert!(
+            pauses
+                .iter()
+                .all(|p| *p >= interval - jitter && *p <= interval + jitter)
+        );
+        assert!(pauses.iter().any(|p| *p < interval) && pauses.iter().any(|p| *p > interval));
+        assert_eq!(jittered(interval, Duration::ZERO), interval);
+    }
+
+    struct Slow;
+
+    #[async_trait]
+    impl HealthCheck for Slow {
+        async fn check(&self) -> CheckResult {
+            sleep(Duration::from_millis(100)).await;
+            CheckResult::new(CheckStatus::Up, None)
+        }
+    }
+
+    #[tokio::test]
+    async fn targets_group_checks_with_their_last_result() {
+        let mut registry = CheckRegistry::default();
+        let interval = Duration::from_secs(10);
+        for name in ["db", "db-replica", "script"] {
+            let check = Box::new(Fixed(CheckStatus::Down));
+            registry.register(name, true, interval, interval, check);
+        }
+        let entries = registry.entries.get_mut().unwrap();
+        for entry in &mut entries[..2] {
+            Arc::get_mut(entry).unwrap().target = Some("db.internal:5432".into());
+        }
+        let entry = Arc::clone(&entries[0]);
+        registry.run_entry(&entry).await;
+        let targets = registry.targets();
+        assert_eq!(targets.keys().collect::<Vec<_>>(), ["db.internal:5432"]);
+        let checks = &targets["db.internal:5432"];
+        assert_eq!(checks[0].last.as_ref().unwrap().status, CheckStatus::Down);
+        assert_eq!(checks[1].check, "db-replica");
+        assert!(checks[1].last.is_none());
+    }
+
+    #[tokio::test]
+    async fn reassigned_checks_stop_being_exported() {
+        use opentelemetry::metrics::MeterProvider;
+        use opentelemetry_sdk::metrics::SdkMeterProvider;
+        use std::sync::atomic::AtomicBool;
+
+        let owned = Arc::new(AtomicBool::new(true));
+        let ownership = owned.clone();
+        let mut registry = CheckRegistry::default()
+            .with_ownership(Arc::new(move |_: &str| ownership.load(Ordering::Relaxed)));
+        single(&mut registry, CheckStatus::Up);
+        let entries = registry.entries.get_mut().unwrap();
+        Arc::get_mut(&mut entries[0]).unwrap().target = Some("db.internal:5432".into());
+        let registry = Arc::new(registry);
+        registry.run_once(&Selector::default()).await;
+
+        let prometheus = prometheus::Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(prometheus.clone())
+            .build()
+            .unwrap();
+        let provider = SdkMeterProvider::builder().with_reader(exporter).build();
+        registry.register_metrics(&provider.meter("test"));
+        let series = |name: &str| {
+            let families = prometheus.gather();
+            let family = families.iter().find(|f| f.name() == name);
+            family.map_or(0, |f| f.get_metric().len())
+        };
+        assert_eq!(series("probe_success"), 1);
+        assert_eq!(series("probe_duration_seconds"), 1);
+        assert_eq!(series("scheduler_skipped_runs_total"), 1);
+
+        owned.store(false, Ordering::Relaxed);
+        assert_eq!(series("probe_success"), 0);
+        assert_eq!(series("probe_duration_seconds"), 0);
+        // The SDK keeps the last total of a cumulative counter, which no longer grows here
+        assert_eq!(series("scheduler_skipped_runs_total"), 1);
+    }
+
+    #[tokio::test]
+    async fn overlapping_executions_are_skipped_or_queued() {
+        for (overlap, runs, skipped) in [(Overlap::Skip, 1, 2), (Overlap::Queue, 2, 1)] {
+            let mut registry = CheckRegistry::default();
+            let timeout = Duration::from_secs(10);
+            registry.register(
+                "slow",
+                true,
+                Duration::from_secs(1),
+                timeout,
+                Box::new(Slow),
+            );
+            let entries = registry.entries.get_mut().unwrap();
+            Arc::get_mut(&mut entries[0]).unwrap().overlap = overlap;
+            let entry = Arc::clone(&entries[0]);
+            let registry = Arc::new(registry);
+            for _ in 0..3 {
+                registry.tick(&entry);
+            }
+            sleep(Duration::from_millis(500)).await;
+            let tick = &registry.ticks()["slow"];
+            assert_eq!((tick.runs, tick.skipped), (runs, skipped), "{overlap:?}");
+            assert!(!entry.running.load(Ordering::Relaxed));
+        }
+    }
+}
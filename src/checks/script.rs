@@ -0,0 +1,272 @@
+use async_trait::async_trait;
+use rhai::{AST, Dynamic, Engine, EvalAltResult, ImmutableString, Map, Scope};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tracing::debug;
+
+use super::{CheckResult, CheckStatus, HealthCheck};
+
+/// Custom check written as a Rhai script.
+///
+/// The script reports through the `result` object in its scope (`result.degraded("...")`,
+/// `result.down("...")`, `result.detail("key", value)`) and is up unless it says otherwise;
+/// a thrown error makes it down. `http_get`, `http_post`, `parse_json` and `to_json` are
+/// available to it. Scripts run on a blocking thread, bounded by a number of operations and
+/// by the check's timeout.
+#[derive(Clone)]
+pub struct ScriptCheck {
+    name: String,
+    ast: Arc<AST>,
+    client: reqwest::Client,
+    max_operations: u64,
+    timeout: Duration,
+}
+
+/// The `result` object of a script
+#[derive(Debug, Clone)]
+struct Outcome {
+    status: CheckStatus,
+    message: Option<String>,
+    details: serde_json::Map<String, Value>,
+}
+
+impl Outcome {
+    fn set(&mut self, status: CheckStatus, message: Option<ImmutableString>) {
+        self.status = status;
+        self.message = message.map(|m| m.to_string());
+    }
+}
+
+impl ScriptCheck {
+    /// Compile the script once; a fresh engine runs it on every execution
+    pub fn load(
+        name: &str,
+        source: Option<&str>,
+        file: Option<&Path>,
+        max_operations: u64,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let source = match (source, file) {
+            (Some(source), _) => source.to_string(),
+            (None, Some(file)) => std::fs::read_to_string(file)
+                .map_err(|e| format!("failed to read {}: {e}", file.display()))?,
+            (None, None) => return Err("no script configured".into()),
+        };
+        let ast = Engine::new()
+            .compile(&source)
+            .map_err(|e| format!("invalid script: {e}"))?;
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            name: name.to_string(),
+            ast: Arc::new(ast),
+            client,
+            max_operations,
+            timeout,
+        })
+    }
+
+    fn engine(&self, handle: Handle) -> Engine {
+        let mut engine = Engine::new();
+        engine.set_max_operations(self.max_operations);
+        // Stop scripts that outlive the check's timeout instead of leaking the thread
+        let deadline = Instant::now() + self.timeout;
+        engine.on_progress(move |_| (Instant::now() > deadline).then_some(Dynamic::UNIT));
+        let name = self.name.clone();
+        engine.on_print(move |text| debug!(check = %name, "{text}"));
+
+        engine
+            .register_type_with_name::<Outcome>("Result")
+            .register_fn("up", |r: &mut Outcome| r.set(CheckStatus::Up, None))
+            .register_fn("up", |r: &mut Outcome, m: ImmutableString| {
+                r.set(CheckStatus::Up, Some(m))
+            })
+            .register_fn("degraded", |r: &mut Outcome, m: ImmutableString| {
+                r.set(CheckStatus::Degraded, Some(m))
+            })
+            .register_fn("down", |r: &mut Outcome, m: ImmutableString| {
+                r.set(CheckStatus::Down, Some(m))
+            })
+            .register_fn(
+                "detail",
+                |r: &mut Outcome, key: ImmutableString, value: Dynamic| {
+                    let value = serde_json::to_value(&value).unwrap_or(Value::Null);
+                    r.details.insert(key.to_string(), value);
+                },
+            );
+
+        engine
+            .register_fn("parse_json", |text: ImmutableString| {
+                serde_json::from_str::<Dynamic>(&text)
+                    .map_err(|e| Box::<EvalAltResult>::from(format!("invalid JSON: {e}")))
+            })
+            .register_fn("to_json", |value: Dynamic| {
+                serde_json::to_string(&value).unwrap_or_default()
+            });
+
+        let http = Http {
+            client: self.client.clone(),
+            handle,
+        };
+        let (get, get_headers) = (http.clone(), http.clone());
+        let (post, post_headers) = (http.clone(), http);
+        engine
+            .register_fn("http_get", move |url: ImmutableString| {
+                get.request(reqwest::Method::GET, &url, None, Map::new())
+            })
+            .register_fn("http_get", move |url: ImmutableString, headers: Map| {
+                get_headers.request(reqwest::Method::GET, &url, None, headers)
+            })
+            .register_fn("http_post", move |url: ImmutableString, body: Dynamic| {
+                post.request(reqwest::Method::POST, &url, Some(body), Map::new())
+            })
+            .register_fn(
+                "http_post",
+                move |url: ImmutableString, body: Dynamic, headers: Map| {
+                    post_headers.request(reqwest::Method::POST, &url, Some(body), headers)
+                },
+            );
+        engine
+    }
+
+    fn run(&self, handle: Handle) -> Result<Outcome, String> {
+        let engine = self.engine(handle);
+        let mut scope = Scope::new();
+        scope.push(
+            "result",
+            Outcome {
+                status: CheckStatus::Up,
+                message: None,
+                details: serde_json::Map::new(),
+            },
+        );
+        match engine.run_ast_with_scope(&mut scope, &self.ast) {
+            Ok(()) => Ok(scope.get_value::<Outcome>("result").unwrap()),
+            Err(e) => Err(match *e {
+                EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+                EvalAltResult::ErrorTerminated(..) => {
+                    format!("timed out after {:?}", self.timeout)
+                }
+                e => e.to_string(),
+            }),
+        }
+    }
+}
+
+/// HTTP client exposed to scripts, blocking on the async client from the script's thread
+#[derive(Clone)]
+struct Http {
+    client: reqwest::Client,
+    handle: Handle,
+}
+
+impl Http {
+    // Returns #{ status, body, headers }
+    fn request(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<Dynamic>,
+        headers: Map,
+    ) -> Result<Map, Box<EvalAltResult>> {
+        let mut request = self.client.request(method, url);
+        for (name, value) in headers {
+            request = request.header(name.as_str(), value.to_string());
+        }
+        request = match body {
+            Some(body) if body.is_string() => request.body(body.to_string()),
+            Some(body) => request.json(&body),
+            None => request,
+        };
+        let response = self
+            .handle
+            .block_on(async {
+                let response = request.send().await?;
+                let status = response.status().as_u16();
+                let headers = response.headers().clone();
+                let body = response.text().await?;
+                Ok::<_, reqwest::Error>((status, headers, body))
+            })
+            .map_err(|e| format!("request to {url} failed: {e}"))?;
+        let (status, response_headers, body) = response;
+        let headers: Map = response_headers
+            .iter()
+            .map(|(name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+                (name.as_str().into(), value.into())
+            })
+            .collect();
+        let mut response = Map::new();
+        response.insert("status".into(), (status as i64).into());
+        response.insert("body".into(), body.into());
+        response.insert("headers".into(), headers.into());
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl HealthCheck for ScriptCheck {
+    async fn check(&self) -> CheckResult {
+        let script = self.clone();
+        let handle = Handle::current();
+        let outcome = tokio::task::spawn_blocking(move || script.run(handle)).await;
+        match outcome.unwrap_or_else(|e| Err(e.to_string())) {
+            Ok(outcome) => {
+                let mut result = CheckResult::new(outcome.status, outcome.message);
+                result.details = outcome.details;
+                result
+            }
+            Err(e) => CheckResult::down(format!("script failed: {e}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, routing::get};
+
+    fn script(source: &str) -> ScriptCheck {
+        ScriptCheck::load("test", Some(source), None, 100_000, Duration::from_secs(5)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn scripts_report_through_their_result() {
+        let app = Router::new().route(
+            "/stats",
+            get(|| async { Json(serde_json::json!({ "queue": 120 })) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/stats", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let check = script(&format!(
+            r#"
+            let response = http_get("{url}");
+            let stats = parse_json(response.body);
+            result.detail("queue", stats.queue);
+            if response.status == 200 && stats.queue > 100 {{
+                result.degraded("queue backlog");
+            }}
+            "#
+        ));
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Degraded);
+        assert_eq!(result.message.as_deref(), Some("queue backlog"));
+        assert_eq!(result.details["queue"], 120);
+    }
+
+    #[tokio::test]
+    async fn errors_and_runaway_scripts_are_down() {
+        let result = script(r#"throw "no quorum""#).check().await;
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(result.message.as_deref(), Some("script failed: no quorum"));
+        let result = script("loop {}").check().await;
+        assert_eq!(result.status, CheckStatus::Down);
+    }
+}
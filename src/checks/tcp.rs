@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::TcpStream;
+
+use super::{CheckResult, HealthCheck, Resolver};
+
+/// Checks that a TCP connection can be established
+pub struct TcpCheck {
+    address: String,
+    resolver: Resolver,
+    resolve_to: Option<IpAddr>,
+}
+
+impl TcpCheck {
+    pub fn new(address: String) -> Self {
+        Self {
+            address,
+            resolver: Resolver::default(),
+            resolve_to: None,
+        }
+    }
+
+    /// Resolve the host through the shared resolver, or connect to `resolve_to` instead
+    pub fn with_resolver(mut self, resolver: Resolver, resolve_to: Option<IpAddr>) -> Self {
+        self.resolver = resolver;
+        self.resolve_to = resolve_to;
+        self
+    }
+
+    async fn addresses(&self) -> Result<Vec<SocketAddr>, String> {
+        let (host, port) = self
+            .address
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+            .ok_or_else(|| format!("invalid address {}: expected host:port", self.address))?;
+        let ips = match self.resolve_to {
+            Some(ip) => vec![ip],
+            None => self.resolver.lookup(host).await?,
+        };
+        Ok(ips
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+
+    /// Connect to the first reachable address, for checks speaking a protocol over TCP
+    pub(crate) async fn connect(&self) -> Result<(TcpStream, SocketAddr), String> {
+        // Like `TcpStream::connect`, try every address and report the last error
+        let mut error = None;
+        for address in self.addresses().await? {
+            match TcpStream::connect(address).await {
+                Ok(stream) => return Ok((stream, address)),
+                Err(e) => error = Some(e),
+            }
+        }
+        let error = error.map(|e| e.to_string()).unwrap_or_default();
+        Err(format!("connect to {} failed: {error}", self.address))
+    }
+}
+
+#[async_trait]
+impl HealthCheck for TcpCheck {
+    async fn check(&self) -> CheckResult {
+        match self.connect().await {
+            Ok((_, address)) => CheckResult::up().with_remote_addr(address),
+            Err(e) => CheckResult::down(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckStatus;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn connects_to_resolved_and_pinned_addresses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let result = TcpCheck::new(format!("localhost:{port}")).check().await;
+        assert_eq!(result.status, CheckStatus::Up, "{:?}", result.message);
+        let pinned = TcpCheck::new(format!("db.invalid:{port}"))
+            .with_resolver(Resolver::default(), "127.0.0.1".parse().ok());
+        assert_eq!(pinned.check().await.status, CheckStatus::Up);
+    }
+
+    #[tokio::test]
+    async fn connects_to_ipv6_only_targets() {
+        let listener = TcpListener::bind("[::1]:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = toml::from_str(r#"overrides = { "v6.internal" = ["::1"] }"#).unwrap();
+        let resolver = Resolver::from_config(&config);
+        let check = TcpCheck::new(format!("v6.internal:{port}")).with_resolver(resolver, None);
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Up, "{:?}", result.message);
+        assert_eq!(result.details["remote_addr"], format!("[::1]:{port}"));
+        assert_eq!(result.details["ip_protocol"], 6);
+    }
+
+    #[tokio::test]
+    async fn rejects_addresses_without_port() {
+        let result = TcpCheck::new("db.internal".into()).check().await;
+        assert_eq!(
+            result.message.as_deref(),
+            Some("invalid address db.internal: expected host:port")
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use regex::Regex;
+use serde_json::Value;
+
+use super::{CheckResult, CheckStatus};
+use crate::config::{TransformAction, TransformConfig};
+
+/// Compiled transform rules, applied to results before they are stored and notified
+#[derive(Debug, Default)]
+pub struct Transforms {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    checks: Option<Regex>,
+    status: Vec<CheckStatus>,
+    source: Source,
+    regex: Option<Regex>,
+    action: TransformAction,
+}
+
+#[derive(Debug)]
+enum Source {
+    Message,
+    Target,
+    Detail(String),
+}
+
+impl Transforms {
+    pub fn compile(configs: &[TransformConfig]) -> Result<Self, String> {
+        let rules = configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| {
+                Rule::compile(config).map_err(|e| format!("transforms[{index}]: {e}"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Apply every matching rule in order; `None` when a rule drops the result
+    pub fn apply(&self, check: &str, mut result: CheckResult) -> Option<CheckResult> {
+        for rule in &self.rules {
+            let Some(subject) = rule.matches(check, &result) else {
+                continue;
+            };
+            match &rule.action {
+                TransformAction::Reclassify { to } if *to != result.status => {
+                    let from = result.status.as_str();
+                    result = result.with_detail("reclassified_from", from);
+                    result.status = *to;
+                }
+                TransformAction::Reclassify { .. } => {}
+                TransformAction::Drop => return None,
+                TransformAction::Relabel {
+                    target_label,
+                    replacement,
+                } => {
+                    let captures = rule.regex.as_ref().and_then(|r| r.captures(&subject));
+                    let value = match captures {
+                        Some(captures) => {
+                            let mut value = String::new();
+                            captures.expand(replacement, &mut value);
+                            value
+                        }
+                        None => replacement.clone(),
+                    };
+                    result = result.with_detail(target_label, value);
+                }
+            }
+        }
+        Some(result)
+    }
+}
+
+impl Rule {
+    fn compile(config: &TransformConfig) -> Result<Self, String> {
+        let regex = |pattern: &str| Regex::new(pattern).map_err(|e| e.to_string());
+        let source = match config.source.as_str() {
+            "message" => Source::Message,
+            "target" => Source::Target,
+            other => match other.strip_prefix("details.") {
+                Some(key) => Source::Detail(key.to_string()),
+                None => return Err(format!("unknown source {other:?}")),
+            },
+        };
+        // Without a regex there are no captures to expand
+        if let TransformAction::Relabel { replacement, .. } = &config.action
+            && config.regex.is_none()
+            && replacement.contains('$')
+        {
+            return Err(format!(
+                "relabel replacement {replacement:?} references a capture but no regex is set"
+            ));
+        }
+        Ok(Self {
+            checks: config
+                .checks
+                .as_deref()
+                .map(|checks| regex(&format!("^(?:{checks})$")))
+                .transpose()?,
+            status: config.status.clone(),
+            source,
+            regex: config.regex.as_deref().map(regex).transpose()?,
+            action: config.action.clone(),
+        })
+    }
+
+    // The source field when the rule applies, empty for rules without a regex
+    fn matches(&self, check: &str, result: &CheckResult) -> Option<String> {
+        if self.checks.as_ref().is_some_and(|r| !r.is_match(check)) {
+            return None;
+        }
+        if !self.status.is_empty() && !self.status.contains(&result.status) {
+            return None;
+        }
+        let Some(regex) = &self.regex else {
+            return Some(String::new());
+        };
+        let subject = match &self.source {
+            Source::Message => result.message.clone(),
+            Source::Target => result.target.clone(),
+            Source::Detail(key) => result.details.get(key).map(|value| match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            }),
+        }?;
+        regex.is_match(&subject).then_some(subject)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(rules: &str) -> Result<Transforms, String> {
+        #[derive(serde::Deserialize)]
+        struct Rules {
+            transforms: Vec<TransformConfig>,
+        }
+        Transforms::compile(&toml::from_str::<Rules>(rules).unwrap().transforms)
+    }
+
+    #[test]
+    fn relabel_expands_captures() {
+        let transforms = compile(
+            r#"
+            [[transforms]]
+            regex = "unexpected status (\\d+)"
+            action = "relabel"
+            target_label = "http_status"
+            "#,
+        )
+        .unwrap();
+        let result = CheckResult::down("unexpected status 503");
+        let result = transforms.apply("api", result).unwrap();
+        assert_eq!(result.details["http_status"], "503");
+    }
+
+    #[test]
+    fn relabel_without_regex_sets_a_constant() {
+        let transforms = compile(
+            r#"
+            [[transforms]]
+            checks = "api"
+            action = "relabel"
+            target_label = "team"
+            replacement = "payments"
+            "#,
+        )
+        .unwrap();
+        let result = transforms.apply("api", CheckResult::up()).unwrap();
+        assert_eq!(result.details["team"], "payments");
+        assert!(
+            transforms
+                .apply("db", CheckResult::up())
+                .unwrap()
+                .details
+                .get("team")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn relabel_captures_require_a_regex() {
+        let error = compile(
+            r#"
+            [[transforms]]
+            action = "relabel"
+            target_label = "http_status"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error,
+            "transforms[0]: relabel replacement \"$1\" references a capture but no regex is set"
+        );
+    }
+
+    #[test]
+    fn drop_discards_matching_results() {
+        let transforms = compile(
+            r#"
+            [[transforms]]
+            status = ["down"]
+            action = "drop"
+            "#,
+        )
+        .unwrap();
+        assert!(transforms.apply("api", CheckResult::down("flap")).is_none());
+        assert!(transforms.apply("api", CheckResult::up()).is_some());
+    }
+}
@@ -0,0 +1,254 @@
+use async_trait::async_trait;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+use wasmtime::{
+    Config, Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+};
+use wasmtime_wasi::cli::{IsTerminal, StdoutStream};
+use wasmtime_wasi::p1::{self, WasiP1Ctx};
+use wasmtime_wasi::{I32Exit, WasiCtxBuilder};
+
+use super::{CheckResult, CheckStatus, HealthCheck};
+
+/// Output kept from a module; anything beyond it is accepted and discarded
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// Fuel consumed between yields to the runtime, so that timeouts can interrupt a module
+const YIELD_INTERVAL: u64 = 10_000;
+
+/// Custom probe implemented as a WASI command module.
+///
+/// The module's exit code is its status, as for the `check` subcommand: 0 up, 1 degraded and
+/// anything else down; the first line written to stdout becomes the message. It runs without
+/// filesystem, network or host environment access, seeing only the configured arguments and
+/// environment variables, and with bounded memory and CPU (fuel).
+pub struct WasmCheck {
+    engine: Engine,
+    pre: InstancePre<State>,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    fuel: u64,
+    max_memory: usize,
+}
+
+struct State {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+impl WasmCheck {
+    /// Compile the module once; it is instantiated afresh for every execution
+    pub fn load(
+        path: &Path,
+        args: Vec<String>,
+        env: Vec<(String, String)>,
+        fuel: u64,
+        max_memory: usize,
+    ) -> Result<Self, String> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| e.to_string())?;
+        let module = Module::from_file(&engine, path)
+            .map_err(|e| format!("failed to load {}: {e}", path.display()))?;
+        let mut linker: Linker<State> = Linker::new(&engine);
+        p1::add_to_linker_async(&mut linker, |state| &mut state.wasi).map_err(|e| e.to_string())?;
+        let pre = linker
+            .instantiate_pre(&module)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        // The module path is the program name seen by the module
+        let args = std::iter::once(path.display().to_string())
+            .chain(args)
+            .collect();
+        Ok(Self {
+            engine,
+            pre,
+            args,
+            env,
+            fuel,
+            max_memory,
+        })
+    }
+
+    async fn run(&self, stdout: TruncatingOutput) -> Result<i32, String> {
+        let wasi = WasiCtxBuilder::new()
+            .args(&self.args)
+            .envs(&self.env)
+            .stdout(stdout)
+            .build_p1();
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, State { wasi, limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.fuel).map_err(|e| e.to_string())?;
+        store
+            .fuel_async_yield_interval(Some(YIELD_INTERVAL))
+            .map_err(|e| e.to_string())?;
+        let instance = self
+            .pre
+            .instantiate_async(&mut store)
+            .await
+            .map_err(|e| e.to_string())?;
+        let start = instance
+            .get_typed_func::<(), ()>(&mut store, "_start")
+            .map_err(|e| e.to_string())?;
+        match start.call_async(&mut store, ()).await {
+            Ok(()) => Ok(0),
+            Err(e) => match (e.downcast_ref::<I32Exit>(), e.downcast_ref::<Trap>()) {
+                (Some(exit), _) => Ok(exit.0),
+                // e.g. "all fuel consumed by WebAssembly" rather than the wasm backtrace
+                (None, Some(trap)) => Err(trap.to_string()),
+                (None, None) => Err(e.to_string()),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for WasmCheck {
+    async fn check(&self) -> CheckResult {
+        let stdout = TruncatingOutput::default();
+        let code = match self.run(stdout.clone()).await {
+            Ok(code) => code,
+            Err(e) => return CheckResult::down(format!("module failed: {e}")),
+        };
+        let output = stdout.0.lock().unwrap().clone();
+        let message = String::from_utf8_lossy(&output)
+            .lines()
+            .next()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty());
+        let status = match code {
+            0 => CheckStatus::Up,
+            1 => CheckStatus::Degraded,
+            _ => CheckStatus::Down,
+        };
+        CheckResult::new(status, message).with_detail("exit_code", code)
+    }
+}
+
+/// Module stdout keeping the first `MAX_OUTPUT` bytes; writes past it succeed, so that a
+/// chatty module is not failed for its output
+#[derive(Clone, Default)]
+struct TruncatingOutput(Arc<Mutex<Vec<u8>>>);
+
+impl IsTerminal for TruncatingOutput {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdoutStream for TruncatingOutput {
+    fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+impl AsyncWrite for TruncatingOutput {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut output = self.0.lock().unwrap();
+        let kept = buf.len().min(MAX_OUTPUT.saturating_sub(output.len()));
+        output.extend_from_slice(&buf[..kept]);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes "ok\n" followed by 1 MiB of filler to stdout, one 1 KiB chunk at a time
+    const CHATTY: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "ok\n")
+          (func (export "_start")
+            (local $i i32)
+            ;; iovec at 2048: the 3 byte greeting, then 1 KiB chunks at 1024
+            (i32.store (i32.const 2048) (i32.const 0))
+            (i32.store (i32.const 2052) (i32.const 3))
+            (drop (call $fd_write (i32.const 1) (i32.const 2048) (i32.const 1) (i32.const 2056)))
+            (i32.store (i32.const 2048) (i32.const 1024))
+            (i32.store (i32.const 2052) (i32.const 1024))
+            (loop $write
+              (if (call $fd_write (i32.const 1) (i32.const 2048) (i32.const 1) (i32.const 2056))
+                (then unreachable))
+              (local.set $i (i32.add (local.get $i) (i32.const 1)))
+              (br_if $write (i32.lt_u (local.get $i) (i32.const 1024))))))
+    "#;
+
+    /// Prints "slow" and exits with 1
+    const EXITING: &str = r#"
+        (module
+          (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+          (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "slow\n")
+          (func (export "_start")
+            (i32.store (i32.const 16) (i32.const 0))
+            (i32.store (i32.const 20) (i32.const 5))
+            (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))
+            (call $proc_exit (i32.const 1))))
+    "#;
+
+    /// Never returns
+    const SPINNING: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "_start") (loop $spin (br $spin))))
+    "#;
+
+    fn load(name: &str, wat: &str, fuel: u64) -> WasmCheck {
+        let path = std::env::temp_dir().join(format!("{name}-{}.wat", std::process::id()));
+        std::fs::write(&path, wat).unwrap();
+        let check = WasmCheck::load(&path, Vec::new(), Vec::new(), fuel, 1 << 20);
+        std::fs::remove_file(&path).unwrap();
+        check.unwrap()
+    }
+
+    #[tokio::test]
+    async fn exit_codes_map_to_statuses() {
+        let result = load("exiting", EXITING, u64::MAX).check().await;
+        assert_eq!(result.status, CheckStatus::Degraded);
+        assert_eq!(result.message.as_deref(), Some("slow"));
+        assert_eq!(result.details["exit_code"], 1);
+    }
+
+    #[tokio::test]
+    async fn modules_fail_once_out_of_fuel() {
+        let result = load("spinning", SPINNING, 1_000_000).check().await;
+        assert_eq!(result.status, CheckStatus::Down);
+        let message = result.message.unwrap();
+        assert!(message.contains("fuel"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn output_past_the_limit_is_discarded() {
+        let path = std::env::temp_dir().join(format!("chatty-{}.wat", std::process::id()));
+        std::fs::write(&path, CHATTY).unwrap();
+        let check = WasmCheck::load(&path, Vec::new(), Vec::new(), u64::MAX, 1 << 20).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let result = check.check().await;
+        assert_eq!(result.status, CheckStatus::Up, "{:?}", result.message);
+        assert_eq!(result.message.as_deref(), Some("ok"));
+    }
+}
@@ -0,0 +1,305 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use healthcheck_service::checks::{CheckRegistry, CheckReport, CheckStatus, Selector};
+use healthcheck_service::config::{CONFIG_ENV, Config};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+use tokio::time::{Instant, sleep};
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Path to the configuration file, TOML or YAML (`.yaml`/`.yml`)
+    #[arg(long, global = true, env = CONFIG_ENV)]
+    pub config: Option<PathBuf>,
+
+    /// Push check results to the aggregator configured under `[agent]`
+    #[arg(long)]
+    pub agent: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the HTTP service (default)
+    Serve,
+    /// Serve the health and status API as a serverless function: checks run on request
+    /// instead of on a schedule
+    Serverless,
+    /// Run the configured checks once and exit with 0 (up), 1 (degraded) or 2 (down)
+    Check(CheckArgs),
+    /// Block until the named checks pass, for init containers and startup scripts
+    Wait(WaitArgs),
+    /// Probe a URL and exit 0 on a 2xx response, 1 otherwise (Docker `HEALTHCHECK CMD`)
+    Ping(PingArgs),
+}
+
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Only run the named check; may be repeated
+    #[arg(long = "check")]
+    pub checks: Vec<String>,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = Format::Human)]
+    pub format: Format,
+}
+
+#[derive(Args)]
+pub struct WaitArgs {
+    /// Comma-separated checks to wait for; all configured checks when omitted
+    #[arg(long = "for", value_delimiter = ',')]
+    pub checks: Vec<String>,
+
+    /// Give up after this long, e.g. `120s` or `2m`
+    #[arg(long, default_value = "60s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+
+    /// Delay between attempts
+    #[arg(long, default_value = "2s", value_parser = humantime::parse_duration)]
+    pub interval: Duration,
+}
+
+#[derive(Args)]
+pub struct PingArgs {
+    /// URL to request
+    #[arg(default_value = "http://127.0.0.1:5000/health/live")]
+    pub url: String,
+
+    /// Request timeout
+    #[arg(long, default_value = "3s", value_parser = humantime::parse_duration)]
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Human,
+    Json,
+}
+
+/// Exit code used when the configuration or arguments are invalid
+const EXIT_UNKNOWN: u8 = 3;
+
+impl Cli {
+    pub fn load_config(&self) -> Result<Config, ExitCode> {
+        Config::load(self.config.as_deref()).map_err(|e| {
+            eprintln!("error: {e}");
+            ExitCode::from(EXIT_UNKNOWN)
+        })
+    }
+}
+
+// Build the registry for the selected checks, reporting unknown names
+fn select(config: &Config, checks: &[String]) -> Result<(CheckRegistry, Selector), ExitCode> {
+    let registry = CheckRegistry::from_config(config).map_err(|e| {
+        eprintln!("error: {e}");
+        ExitCode::from(EXIT_UNKNOWN)
+    })?;
+    let selector = Selector {
+        only: (!checks.is_empty()).then(|| checks.join(",")),
+        exclude: None,
+    };
+    let unknown = registry.unknown(&selector);
+    if !unknown.is_empty() {
+        eprintln!("error: unknown checks: {}", unknown.join(", "));
+        return Err(ExitCode::from(EXIT_UNKNOWN));
+    }
+    Ok((registry, selector))
+}
+
+// One-shot check mode for CI, cron and container HEALTHCHECK directives
+pub async fn run_check(config: &Config, args: &CheckArgs) -> ExitCode {
+    let (registry, selector) = match select(config, &args.checks) {
+        Ok(selected) => selected,
+        Err(code) => return code,
+    };
+
+    let report = registry.run_once(&selector).await;
+    match args.format {
+        Format::Human => print_human(&report),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+    }
+    exit_code(report.status)
+}
+
+// Wait-for mode: retry the selected checks until every one of them is up
+pub async fn run_wait(config: &Config, args: &WaitArgs) -> ExitCode {
+    let (registry, selector) = match select(config, &args.checks) {
+        Ok(selected) => selected,
+        Err(code) => return code,
+    };
+
+    let deadline = Instant::now() + args.timeout;
+    loop {
+        let report = registry.run_once(&selector).await;
+        let pending: Vec<_> = report
+            .checks
+            .iter()
+            .filter(|(_, c)| c.result.status != CheckStatus::Up)
+            .collect();
+        if pending.is_empty() {
+            println!("all checks up");
+            return ExitCode::SUCCESS;
+        }
+        if Instant::now() + args.interval > deadline {
+            eprintln!(
+                "timed out after {}",
+                humantime::format_duration(args.timeout)
+            );
+            print_human(&report);
+            return exit_code(CheckStatus::Down);
+        }
+        let names: Vec<_> = pending.iter().map(|(name, _)| name.as_str()).collect();
+        println!("waiting for: {}", names.join(", "));
+        sleep(args.interval).await;
+    }
+}
+
+// Minimal HTTP client for container health checks in images without a shell or curl
+pub async fn run_ping(args: &PingArgs) -> ExitCode {
+    let client = match reqwest::Client::builder().timeout(args.timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match client.get(&args.url).send().await {
+        Ok(response) if response.status().is_success() => ExitCode::SUCCESS,
+        Ok(response) => {
+            eprintln!("{} returned {}", args.url, response.status());
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("request to {} failed: {e}", args.url);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub fn exit_code(status: CheckStatus) -> ExitCode {
+    match status {
+        CheckStatus::Up => ExitCode::SUCCESS,
+        CheckStatus::Degraded => ExitCode::from(1),
+        CheckStatus::Down => ExitCode::from(2),
+    }
+}
+
+fn print_human(report: &CheckReport) {
+    let width = report.checks.keys().map(String::len).max().unwrap_or(0);
+    for (name, component) in &report.checks {
+        let result = &component.result;
+        println!(
+            "{name:<width$}  {:<8}  {:>6}ms  {}",
+            result.status.as_str(),
+            result.duration_ms,
+            result.message.as_deref().unwrap_or_default()
+        );
+    }
+    println!("overall: {}", report.status.as_str());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::StatusCode, routing::get};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Server answering 200 on /up and 503 on /down
+    async fn target() -> String {
+        let app = Router::new()
+            .route("/up", get(|| async { StatusCode::OK }))
+            .route("/down", get(|| async { StatusCode::SERVICE_UNAVAILABLE }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    fn config(checks: &[(&str, &str, bool)]) -> Config {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let content: String = checks
+            .iter()
+            .map(|(name, url, critical)| {
+                format!(
+                    "[[checks]]\nname = \"{name}\"\ntype = \"http\"\nurl = \"{url}\"\ncritical = {critical}\n"
+                )
+            })
+            .collect();
+        let file = format!(
+            "cli-{}-{}.toml",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = std::env::temp_dir().join(file);
+        std::fs::write(&path, content).unwrap();
+        let config = Config::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        config.unwrap()
+    }
+
+    fn check_args(checks: &[&str]) -> CheckArgs {
+        CheckArgs {
+            checks: checks.iter().map(|c| c.to_string()).collect(),
+            format: Format::Json,
+        }
+    }
+
+    #[tokio::test]
+    async fn check_exits_with_the_overall_status() {
+        let url = target().await;
+        let config = config(&[
+            ("api", &format!("{url}/up"), true),
+            ("cache", &format!("{url}/down"), false),
+            ("db", &format!("{url}/down"), true),
+        ]);
+        let run = |checks: &[&str]| {
+            let args = check_args(checks);
+            let config = &config;
+            async move { run_check(config, &args).await }
+        };
+        assert_eq!(run(&["api"]).await, ExitCode::SUCCESS);
+        assert_eq!(run(&["api", "cache"]).await, ExitCode::from(1));
+        assert_eq!(run(&[]).await, ExitCode::from(2));
+        assert_eq!(run(&["missing"]).await, ExitCode::from(EXIT_UNKNOWN));
+    }
+
+    #[tokio::test]
+    async fn wait_succeeds_once_checks_are_up_and_times_out_otherwise() {
+        let url = target().await;
+        let config = config(&[
+            ("api", &format!("{url}/up"), true),
+            ("db", &format!("{url}/down"), true),
+        ]);
+        let args = |checks: &[&str]| WaitArgs {
+            checks: checks.iter().map(|c| c.to_string()).collect(),
+            timeout: Duration::from_millis(100),
+            interval: Duration::from_millis(20),
+        };
+        assert_eq!(run_wait(&config, &args(&["api"])).await, ExitCode::SUCCESS);
+        let started = Instant::now();
+        assert_eq!(run_wait(&config, &args(&["db"])).await, ExitCode::from(2));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn ping_fails_on_errors_and_unreachable_servers() {
+        let url = target().await;
+        let ping = |url: String| async move {
+            let args = PingArgs {
+                url,
+                timeout: Duration::from_secs(1),
+            };
+            run_ping(&args).await
+        };
+        assert_eq!(ping(format!("{url}/up")).await, ExitCode::SUCCESS);
+        assert_eq!(ping(format!("{url}/down")).await, ExitCode::FAILURE);
+        // A port nothing listens on any more
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = listener.local_addr().unwrap();
+        drop(listener);
+        assert_eq!(ping(format!("http://{closed}/up")).await, ExitCode::FAILURE);
+    }
+}
@@ -0,0 +1,213 @@
+use futures_util::{Stream, StreamExt, stream};
+use reqwest::{RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+
+use crate::checks::{CheckEvent, CheckReport, CheckResult, Selector};
+use crate::silence::{NewSilence, Silence};
+
+/// Error returned by [`StatusClient`]
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent or its response not read
+    Request(String),
+    /// The service answered with an error status
+    Status(u16, String),
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Request(message) => f.write_str(message),
+            ClientError::Status(status, message) => write!(f, "{status}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Request(e.to_string())
+    }
+}
+
+/// Typed client for the status API of a healthcheck-service instance
+///
+/// ```no_run
+/// # use healthcheck_service::{checks::Selector, client::StatusClient};
+/// # async fn run() -> Result<(), healthcheck_service::client::ClientError> {
+/// let client = StatusClient::new("http://127.0.0.1:5000");
+/// let report = client.get_status(&Selector::default()).await?;
+/// println!("{:?}", report.status);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct StatusClient {
+    base: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl StatusClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Bearer token sent with every request; the admin token for silences
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Use a preconfigured HTTP client, e.g. with timeouts or custom root certificates
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Cached results of the selected checks
+    pub async fn get_status(&self, selector: &Selector) -> Result<CheckReport, ClientError> {
+        let request = self.get("/api/status").query(selector);
+        json(self.send(request).await?).await
+    }
+
+    /// Recent results of a check, oldest first
+    pub async fn get_check_history(&self, check: &str) -> Result<Vec<CheckResult>, ClientError> {
+        let request = self.get(&format!("/api/checks/{check}/history"));
+        json(self.send(request).await?).await
+    }
+
+    /// Every completed execution of the selected checks, as it happens
+    pub async fn stream_events(
+        &self,
+        selector: &Selector,
+    ) -> Result<impl Stream<Item = Result<CheckEvent, ClientError>> + use<>, ClientError> {
+        let request = self.get("/api/events").query(selector);
+        let response = self.send(request).await?;
+        let events = stream::unfold(
+            (response.bytes_stream(), SseDecoder::default()),
+            |(mut body, mut decoder)| async move {
+                loop {
+                    if let Some(data) = decoder.next_data() {
+                        let event = serde_json::from_str(&data)
+                            .map_err(|e| ClientError::Request(format!("invalid event: {e}")));
+                        return Some((event, (body, decoder)));
+                    }
+                    match body.next().await? {
+                        Ok(chunk) => decoder.push(&chunk),
+                        Err(e) => return Some((Err(e.into()), (body, decoder))),
+                    }
+                }
+            },
+        );
+        Ok(events)
+    }
+
+    /// Mute notifications for the matching checks; requires the admin token
+    pub async fn create_silence(&self, silence: &NewSilence) -> Result<Silence, ClientError> {
+        let url = format!("{}/api/admin/silences", self.base);
+        let request = self.client.post(url).json(silence);
+        json(self.send(request).await?).await
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        self.client.get(format!("{}{path}", self.base))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        let request = match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        };
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(ClientError::Status(status.as_u16(), error_message(body)))
+    }
+}
+
+// The `message` of a JSON error body, or the body itself
+pub(crate) fn error_message(body: String) -> String {
+    serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_string))
+        .unwrap_or(body)
+}
+
+async fn json<T: DeserializeOwned>(response: Response) -> Result<T, ClientError> {
+    response
+        .json()
+        .await
+        .map_err(|e| ClientError::Request(format!("invalid response: {e}")))
+}
+
+// Splits a server-sent event stream into the data of its events, whatever the chunking
+#[derive(Default)]
+struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    fn push(&mut self, chunk: &[u8]) {
+        // Lines end with CRLF or LF; JSON data never contains a raw CR
+        self.buffer.extend(chunk.iter().filter(|&&b| b != b'\r'));
+    }
+
+    fn next_data(&mut self) -> Option<String> {
+        loop {
+            let end = self.buffer.windows(2).position(|w| w == b"\n\n")?;
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let block = String::from_utf8_lossy(&block);
+            let data: Vec<_> = block
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            // Keep-alive comments carry no data
+            if !data.is_empty() {
+                return Some(data.join("\n"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_events_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        decoder.push(b":\n\nevent: check\ndata: {\"a\"");
+        assert_eq!(decoder.next_data(), None);
+        decoder.push(b":1}\n\nevent: check\r\ndata: 2\r");
+        decoder.push(b"\n\r\n");
+        assert_eq!(decoder.next_data().as_deref(), Some("{\"a\":1}"));
+        assert_eq!(decoder.next_data().as_deref(), Some("2"));
+        assert_eq!(decoder.next_data(), None);
+    }
+
+    #[test]
+    fn keeps_characters_split_across_chunks() {
+        let mut decoder = SseDecoder::default();
+        let data = "data: \"é\"\n\n".as_bytes();
+        decoder.push(&data[..8]);
+        decoder.push(&data[8..]);
+        assert_eq!(decoder.next_data().as_deref(), Some("\"é\""));
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let mut decoder = SseDecoder::default();
+        decoder.push(b"data: [1,\ndata:2]\n\n");
+        assert_eq!(decoder.next_data().as_deref(), Some("[1,\n2]"));
+    }
+}
@@ -0,0 +1,451 @@
+use ::ring::hmac;
+use rand::seq::{IndexedRandom, SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::sync::oneshot;
+use tokio::time::{Instant, interval, timeout};
+use tracing::{debug, info, warn};
+
+use super::{Cluster, Entry, Member, MemberState};
+use crate::checks::CheckRegistry;
+
+/// Seeds and dead members are contacted every this many probe rounds to heal partitions
+const REJOIN_ROUNDS: u64 = 10;
+
+/// Largest datagram sent; piggybacked members beyond it are left to later messages
+const MAX_DATAGRAM: usize = 8 * 1024;
+
+/// Gossip datagram; every message piggybacks the sender's membership list
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    Ping {
+        seq: u64,
+        members: Vec<Member>,
+    },
+    Ack {
+        seq: u64,
+        members: Vec<Member>,
+    },
+    /// Ask the receiver to probe `target` on the sender's behalf
+    PingReq {
+        seq: u64,
+        target: SocketAddr,
+        members: Vec<Member>,
+    },
+}
+
+impl Message {
+    fn kind(&self) -> &'static str {
+        match self {
+            Message::Ping { .. } => "ping",
+            Message::Ack { .. } => "ack",
+            Message::PingReq { .. } => "ping_req",
+        }
+    }
+
+    fn members_mut(&mut self) -> &mut Vec<Member> {
+        match self {
+            Message::Ping { members, .. }
+            | Message::Ack { members, .. }
+            | Message::PingReq { members, .. } => members,
+        }
+    }
+}
+
+/// Serialize a message, dropping piggybacked members until it fits in `MAX_DATAGRAM`, and
+/// prefix it with its HMAC-SHA256 tag
+fn encode(mut message: Message, key: &hmac::Key) -> Vec<u8> {
+    let mut payload = serde_json::to_vec(&message).unwrap();
+    while payload.len() + TAG_LEN > MAX_DATAGRAM && message.members_mut().len() > 1 {
+        let members = message.members_mut();
+        members.truncate(members.len() / 2);
+        payload = serde_json::to_vec(&message).unwrap();
+    }
+    let mut datagram = hmac::sign(key, &payload).as_ref().to_vec();
+    datagram.extend(payload);
+    datagram
+}
+
+/// Verify a datagram's tag and parse the message
+fn decode(datagram: &[u8], key: &hmac::Key) -> Result<Message, String> {
+    if datagram.len() < TAG_LEN {
+        return Err("truncated datagram".into());
+    }
+    let (tag, payload) = datagram.split_at(TAG_LEN);
+    hmac::verify(key, payload, tag).map_err(|_| "invalid signature".to_string())?;
+    serde_json::from_slice(payload).map_err(|e| e.to_string())
+}
+
+const TAG_LEN: usize = 32;
+
+impl Cluster {
+    /// Run the receive loop and the probe loop
+    pub fn spawn(self: &Arc<Self>, registry: Arc<CheckRegistry>) {
+        let local = self.local.read().unwrap().clone();
+        info!(
+            "Cluster member {} gossiping on {}",
+            local.id, local.gossip_addr
+        );
+        let cluster = Arc::clone(self);
+        tokio::spawn(async move { cluster.receive().await });
+        let cluster = Arc::clone(self);
+        tokio::spawn(async move { cluster.probe_loop(registry).await });
+    }
+
+    async fn receive(&self) {
+        let mut buf = vec![0u8; 65536];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Gossip receive failed: {}", e);
+                    continue;
+                }
+            };
+            match decode(&buf[..len], &self.key) {
+                Ok(message) => self.handle(message, from).await,
+                Err(e) => warn!("Ignoring gossip from {}: {}", from, e),
+            }
+        }
+    }
+
+    async fn handle(&self, message: Message, from: SocketAddr) {
+        self.count("received", message.kind());
+        match message {
+            Message::Ping { seq, members } => {
+                self.merge(members);
+                self.send(
+                    from,
+                    Message::Ack {
+                        seq,
+                        members: self.snapshot(),
+                    },
+                )
+                .await;
+            }
+            Message::Ack { seq, members } => {
+                self.merge(members);
+                if let Some(waiter) = self.pending.lock().unwrap().remove(&seq) {
+                    let _ = waiter.send(());
+                }
+                let forwarded = self.forwarded.lock().unwrap().remove(&seq);
+                if let Some((requester, their_seq, _)) = forwarded {
+                    let ack = Message::Ack {
+                        seq: their_seq,
+                        members: self.snapshot(),
+                    };
+                    self.send(requester, ack).await;
+                }
+            }
+            Message::PingReq {
+                seq,
+                target,
+                members,
+            } => {
+                self.merge(members);
+                let our_seq = self.next_seq();
+                self.forwarded
+                    .lock()
+                    .unwrap()
+                    .insert(our_seq, (from, seq, Instant::now()));
+                let ping = Message::Ping {
+                    seq: our_seq,
+                    members: self.snapshot(),
+                };
+                self.send(target, ping).await;
+            }
+        }
+    }
+
+    async fn probe_loop(self: Arc<Self>, registry: Arc<CheckRegistry>) {
+        let mut ticker = interval(self.config.probe_interval);
+        let mut round = 0u64;
+        loop {
+            ticker.tick().await;
+            self.expire_suspects();
+            self.reap_dead();
+            self.rebalance(&registry);
+            self.refresh_summary(&registry);
+            self.sync_summaries();
+            self.forwarded
+                .lock()
+                .unwrap()
+                .retain(|_, (_, _, sent)| sent.elapsed() < self.config.probe_timeout);
+
+            let (targets, others) = self.candidates();
+            if others.is_empty() || round.is_multiple_of(REJOIN_ROUNDS) {
+                self.rejoin().await;
+            }
+            let target = targets.choose(&mut rand::rng()).cloned();
+            if let Some(target) = target {
+                self.probe(&target, &others).await;
+            }
+            round += 1;
+        }
+    }
+
+    /// Probe a member directly, then indirectly through others before suspecting it
+    async fn probe(&self, target: &Member, others: &[Member]) {
+        let seq = self.next_seq();
+        let (tx, mut rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(seq, tx);
+        let ping = Message::Ping {
+            seq,
+            members: self.snapshot(),
+        };
+        self.send(target.gossip_addr, ping).await;
+
+        let acked = match timeout(self.config.probe_timeout, &mut rx).await {
+            Ok(result) => result.is_ok(),
+            Err(_) => {
+                let mut helpers: Vec<_> = others.iter().filter(|m| m.id != target.id).collect();
+                helpers.shuffle(&mut rand::rng());
+                for helper in helpers.into_iter().take(self.config.indirect_probes) {
+                    let request = Message::PingReq {
+                        seq,
+                        target: target.gossip_addr,
+                        members: self.snapshot(),
+                    };
+                    self.send(helper.gossip_addr, request).await;
+                }
+                matches!(timeout(self.config.probe_timeout, rx).await, Ok(Ok(())))
+            }
+        };
+        self.pending.lock().unwrap().remove(&seq);
+        if !acked {
+            self.suspect(&target.id);
+        }
+    }
+
+    /// Ping seeds and dead members so that restarted nodes and healed partitions rejoin
+    async fn rejoin(&self) {
+        let mut addrs: Vec<SocketAddr> = self
+            .members
+            .read()
+            .unwrap()
+            .values()
+            .filter(|e| e.member.state == MemberState::Dead)
+            .map(|e| e.member.gossip_addr)
+            .collect();
+        for seed in &self.config.seeds {
+            match tokio::net::lookup_host(seed).await {
+                Ok(resolved) => addrs.extend(resolved),
+                Err(e) => debug!("Cannot resolve gossip seed {}: {}", seed, e),
+            }
+        }
+        let local = self.local.read().unwrap().gossip_addr;
+        addrs.retain(|addr| *addr != local);
+        for addr in addrs {
+            let ping = Message::Ping {
+                seq: self.next_seq(),
+                members: self.snapshot(),
+            };
+            self.send(addr, ping).await;
+        }
+    }
+
+    /// Members worth probing (alive or suspect), and the alive ones that can relay probes
+    fn candidates(&self) -> (Vec<Member>, Vec<Member>) {
+        let members = self.members.read().unwrap();
+        let targets: Vec<_> = members
+            .values()
+            .filter(|e| e.member.state != MemberState::Dead)
+            .map(|e| e.member.clone())
+            .collect();
+        let alive = targets
+            .iter()
+            .filter(|m| m.state == MemberState::Alive)
+            .cloned()
+            .collect();
+        (targets, alive)
+    }
+
+    fn suspect(&self, id: &str) {
+        let mut members = self.members.write().unwrap();
+        if let Some(entry) = members.get_mut(id)
+            && entry.member.state == MemberState::Alive
+        {
+            warn!("Cluster member {} is suspected", id);
+            entry.member.state = MemberState::Suspect;
+            entry.since = Instant::now();
+        }
+    }
+
+    fn expire_suspects(&self) {
+        let mut members = self.members.write().unwrap();
+        for entry in members.values_mut() {
+            if entry.member.state == MemberState::Suspect
+                && entry.since.elapsed() >= self.config.suspect_timeout
+            {
+                warn!("Cluster member {} is dead", entry.member.id);
+                entry.member.state = MemberState::Dead;
+                entry.since = Instant::now();
+            }
+        }
+    }
+
+    /// Apply gossiped member records, refuting any suspicion about ourselves
+    fn merge(&self, incoming: Vec<Member>) {
+        let mut local = self.local.write().unwrap();
+        let mut members = self.members.write().unwrap();
+        for member in incoming {
+            if member.id == local.id {
+                if member.state != MemberState::Alive && member.incarnation >= local.incarnation {
+                    info!("Refuting {} report about ourselves", member.state.as_str());
+                    local.incarnation = member.incarnation + 1;
+                }
+                continue;
+            }
+            match members.get_mut(&member.id) {
+                Some(entry) => {
+                    let known = &entry.member;
+                    let newer = member.incarnation > known.incarnation
+                        || (member.incarnation == known.incarnation
+                            && (member.state > known.state
+                                || (member.state == known.state
+                                    && member.heartbeat > known.heartbeat)));
+                    if !newer {
+                        continue;
+                    }
+                    if member.state != known.state {
+                        info!("Cluster member {} is {}", member.id, member.state.as_str());
+                        entry.since = Instant::now();
+                    }
+                    entry.updated = Instant::now();
+                    // Summaries are not gossiped; keep the one fetched last
+                    let checks = std::mem::take(&mut entry.member.checks);
+                    entry.member = Member { checks, ..member };
+                }
+                // Rumors about members already forgotten must not bring them back
+                None if member.state == MemberState::Dead => {}
+                None => {
+                    info!(
+                        "Cluster member {} joined ({})",
+                        member.id,
+                        member.state.as_str()
+                    );
+                    members.insert(
+                        member.id.clone(),
+                        Entry {
+                            member,
+                            since: Instant::now(),
+                            updated: Instant::now(),
+                            summary: 0,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Local member followed by the known remote members in random order, so that those not
+    /// fitting in one datagram are disseminated by the next ones; check summaries are left out
+    fn snapshot(&self) -> Vec<Member> {
+        let mut remote: Vec<_> = self
+            .members
+            .read()
+            .unwrap()
+            .values()
+            .map(|e| e.member.clone())
+            .collect();
+        remote.shuffle(&mut rand::rng());
+        let mut members = vec![self.local.read().unwrap().clone()];
+        members.extend(remote);
+        for member in &mut members {
+            member.checks.clear();
+        }
+        members
+    }
+
+    async fn send(&self, to: SocketAddr, message: Message) {
+        self.count("sent", message.kind());
+        let datagram = encode(message, &self.key);
+        if let Err(e) = self.socket.send_to(&datagram, to).await {
+            warn!("Gossip send to {} failed: {}", to, e);
+        }
+    }
+
+    fn count(&self, direction: &'static str, kind: &'static str) {
+        *self
+            .messages
+            .lock()
+            .unwrap()
+            .entry((direction, kind))
+            .or_default() += 1;
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.seq.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn member(id: usize) -> Member {
+        Member {
+            id: format!("member-{id}"),
+            gossip_addr: "127.0.0.1:7946".parse().unwrap(),
+            api_url: Some(format!("http://10.0.0.{id}:5000")),
+            version: "0.1.0".into(),
+            state: MemberState::Alive,
+            incarnation: 1,
+            heartbeat: 1,
+            checks_digest: 0,
+            checks: BTreeMap::new(),
+        }
+    }
+
+    fn key(secret: &str) -> hmac::Key {
+        hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())
+    }
+
+    #[test]
+    fn datagrams_round_trip() {
+        let message = Message::Ping {
+            seq: 7,
+            members: vec![member(1), member(2)],
+        };
+        match decode(&encode(message, &key("s3cret")), &key("s3cret")).unwrap() {
+            Message::Ping { seq, members } => {
+                assert_eq!(seq, 7);
+                assert_eq!(members.len(), 2);
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_datagrams_signed_with_another_secret() {
+        let message = Message::Ping {
+            seq: 1,
+            members: vec![member(1)],
+        };
+        let datagram = encode(message, &key("s3cret"));
+        assert_eq!(
+            decode(&datagram, &key("other")).unwrap_err(),
+            "invalid signature"
+        );
+        assert!(decode(&datagram[..10], &key("s3cret")).is_err());
+    }
+
+    #[test]
+    fn large_memberships_fit_in_a_datagram() {
+        let message = Message::Ack {
+            seq: 1,
+            members: (0..1000).map(member).collect(),
+        };
+        let datagram = encode(message, &key("s3cret"));
+        assert!(datagram.len() <= MAX_DATAGRAM);
+        let Message::Ack { members, .. } = decode(&datagram, &key("s3cret")).unwrap() else {
+            panic!("expected an ack");
+        };
+        assert_eq!(members[0].id, "member-0");
+        assert!(members.len() > 10);
+    }
+}
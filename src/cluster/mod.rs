@@ -0,0 +1,477 @@
+mod gossip;
+mod ring;
+
+pub use ring::HashRing;
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::net::UdpSocket;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+use crate::checks::{CheckRegistry, CheckStatus, Selector, unix_now};
+use crate::config::{ClusterConfig, ConfigError};
+use tracing::{debug, info, warn};
+
+/// Liveness of a cluster member as seen by the failure detector
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberState {
+    Alive,
+    Suspect,
+    Dead,
+}
+
+/// Member information disseminated by gossip
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Member {
+    pub id: String,
+    pub gossip_addr: SocketAddr,
+    pub api_url: Option<String>,
+    pub version: String,
+    pub state: MemberState,
+    /// Bumped by the member itself to refute suspicion
+    pub incarnation: u64,
+    /// Bumped by the member itself whenever it republishes its summary
+    pub heartbeat: u64,
+    /// Digest of the member's check summary; the summary itself is too large to gossip and
+    /// is fetched from the member's API when the digest changes
+    #[serde(default)]
+    pub checks_digest: u64,
+    /// Latest status of each of the member's checks
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, CheckStatus>,
+}
+
+struct Entry {
+    member: Member,
+    /// When the member entered its current state
+    since: Instant,
+    /// When newer information about the member last reached us
+    updated: Instant,
+    /// Digest of the summary held in `member.checks`
+    summary: u64,
+}
+
+/// Cluster membership view maintained by the gossip protocol
+pub struct Cluster {
+    config: ClusterConfig,
+    local: RwLock<Member>,
+    members: RwLock<HashMap<String, Entry>>,
+    socket: UdpSocket,
+    seq: AtomicU64,
+    pending: Mutex<HashMap<u64, oneshot::Sender<()>>>,
+    /// Probes forwarded on behalf of another member: our seq -> (requester, their seq)
+    forwarded: Mutex<HashMap<u64, (SocketAddr, u64, Instant)>>,
+    /// Gossip messages by direction and type
+    messages: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+    /// Assignment of checks to the members that are not dead
+    ring: RwLock<HashRing>,
+    /// Checks currently assigned to this member
+    owned: AtomicU64,
+    /// Gossip datagrams are signed and verified with the shared secret
+    key: ::ring::hmac::Key,
+    client: reqwest::Client,
+    /// Members whose check summary is being fetched
+    fetching: Mutex<HashSet<String>>,
+}
+
+/// Membership snapshot returned by the cluster API
+#[derive(Debug, Serialize)]
+pub struct MembershipView {
+    pub local: Member,
+    pub members: Vec<MemberView>,
+}
+
+/// Peer as returned by `GET /api/cluster/peers`
+#[derive(Debug, Serialize)]
+pub struct PeerView {
+    pub id: String,
+    pub api_url: Option<String>,
+    pub version: String,
+    pub state: MemberState,
+    /// Unix timestamp of the last update received about the peer
+    pub last_seen: u64,
+    /// Age in seconds of the freshest state replicated from the peer
+    pub replication_lag_secs: f64,
+    /// Checks assigned to the peer
+    pub shard: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemberView {
+    #[serde(flatten)]
+    pub member: Member,
+    /// Seconds since the member entered its current state
+    pub state_age_secs: u64,
+}
+
+impl Cluster {
+    /// Bind the gossip socket
+    pub async fn bind(config: &ClusterConfig) -> Result<Arc<Self>, ConfigError> {
+        let socket = UdpSocket::bind(&config.bind).await.map_err(|e| {
+            ConfigError(format!("failed to bind gossip socket {}: {e}", config.bind))
+        })?;
+        let advertise = config.advertise.as_deref().unwrap_or(&config.bind);
+        let gossip_addr = tokio::net::lookup_host(advertise)
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| ConfigError(format!("invalid gossip address {advertise}")))?;
+        let id = config
+            .node_id
+            .clone()
+            .or_else(sysinfo::System::host_name)
+            .unwrap_or_else(|| gossip_addr.to_string());
+        let local = Member {
+            id,
+            gossip_addr,
+            api_url: config.api_url.clone(),
+            version: crate::build_info::VERSION.to_string(),
+            state: MemberState::Alive,
+            // Start above any incarnation a previous run of this member may have reached
+            incarnation: unix_now(),
+            heartbeat: 0,
+            checks_digest: 0,
+            checks: BTreeMap::new(),
+        };
+        Ok(Arc::new(Self {
+            config: config.clone(),
+            local: RwLock::new(local),
+            members: RwLock::new(HashMap::new()),
+            socket,
+            seq: AtomicU64::new(0),
+            pending: Mutex::new(HashMap::new()),
+            forwarded: Mutex::new(HashMap::new()),
+            messages: Mutex::new(BTreeMap::new()),
+            ring: RwLock::new(HashRing::default()),
+            owned: AtomicU64::new(0),
+            key: ::ring::hmac::Key::new(::ring::hmac::HMAC_SHA256, config.secret.as_bytes()),
+            client: reqwest::Client::builder()
+                .timeout(config.probe_timeout * 4)
+                .build()
+                .unwrap_or_default(),
+            fetching: Mutex::new(HashSet::new()),
+        }))
+    }
+
+    pub fn local_id(&self) -> String {
+        self.local.read().unwrap().id.clone()
+    }
+
+    /// Local member followed by every known remote member
+    pub fn view(&self) -> MembershipView {
+        let mut members: Vec<_> = self
+            .members
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| MemberView {
+                member: entry.member.clone(),
+                state_age_secs: entry.since.elapsed().as_secs(),
+            })
+            .collect();
+        members.sort_by(|a, b| a.member.id.cmp(&b.member.id));
+        MembershipView {
+            local: self.local.read().unwrap().clone(),
+            members,
+        }
+    }
+
+    /// Every known remote member with the checks assigned to it
+    pub fn peers<'a>(&self, checks: impl Iterator<Item = &'a str>) -> Vec<PeerView> {
+        let mut shards: HashMap<String, Vec<String>> = HashMap::new();
+        for (check, owner) in self.assignments(checks) {
+            shards.entry(owner).or_default().push(check);
+        }
+        let now = unix_now();
+        let mut peers: Vec<_> = self
+            .members
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| {
+                let lag = entry.updated.elapsed();
+                PeerView {
+                    id: entry.member.id.clone(),
+                    api_url: entry.member.api_url.clone(),
+                    version: entry.member.version.clone(),
+                    state: entry.member.state,
+                    last_seen: now.saturating_sub(lag.as_secs()),
+                    replication_lag_secs: lag.as_secs_f64(),
+                    shard: shards.remove(&entry.member.id).unwrap_or_default(),
+                }
+            })
+            .collect();
+        peers.sort_by(|a, b| a.id.cmp(&b.id));
+        peers
+    }
+
+    /// Members currently considered alive, including the local member
+    pub fn alive(&self) -> Vec<Member> {
+        let mut alive = vec![self.local.read().unwrap().clone()];
+        alive.extend(
+            self.members
+                .read()
+                .unwrap()
+                .values()
+                .filter(|e| e.member.state == MemberState::Alive)
+                .map(|e| e.member.clone()),
+        );
+        alive
+    }
+
+    /// Number of members per state, including the local member
+    pub fn counts(&self) -> BTreeMap<MemberState, u64> {
+        let mut counts = BTreeMap::from([(MemberState::Alive, 1)]);
+        for entry in self.members.read().unwrap().values() {
+            *counts.entry(entry.member.state).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Whether this member should run a check; always true unless sharding is enabled
+    pub fn owns(&self, check: &str) -> bool {
+        if !self.config.sharding {
+            return true;
+        }
+        let ring = self.ring.read().unwrap();
+        let local = self.local.read().unwrap();
+        ring.owner(check).is_none_or(|owner| owner == local.id)
+    }
+
+    /// Member each check is assigned to
+    pub fn assignments<'a>(
+        &self,
+        checks: impl Iterator<Item = &'a str>,
+    ) -> BTreeMap<String, String> {
+        let ring = self.ring.read().unwrap();
+        let local = self.local.read().unwrap();
+        checks
+            .map(|check| {
+                let owner = match self.config.sharding {
+                    true => ring.owner(check).unwrap_or(&local.id),
+                    false => &local.id,
+                };
+                (check.to_string(), owner.to_string())
+            })
+            .collect()
+    }
+
+    /// Rebuild the ring when membership changed, logging the new share of this member
+    fn rebalance(&self, registry: &CheckRegistry) {
+        let total = registry.names().len();
+        if !self.config.sharding {
+            self.owned.store(total as u64, Ordering::Relaxed);
+            return;
+        }
+        let mut members: Vec<_> = self
+            .members
+            .read()
+            .unwrap()
+            .values()
+            // Checks of a suspect member move on right away rather than going unrun until it
+            // is declared dead
+            .filter(|e| e.member.state == MemberState::Alive)
+            .map(|e| e.member.id.clone())
+            .collect();
+        members.push(self.local_id());
+        members.sort();
+        if self.ring.read().unwrap().members() == members.as_slice() {
+            return;
+        }
+        *self.ring.write().unwrap() = HashRing::new(members.clone());
+        let owned = registry
+            .names()
+            .iter()
+            .filter(|name| self.owns(name))
+            .count() as u64;
+        self.owned.store(owned, Ordering::Relaxed);
+        info!(
+            "Checks rebalanced over {} members, {} of {} assigned here",
+            members.len(),
+            owned,
+            total
+        );
+    }
+
+    /// Export membership and gossip traffic as metrics
+    pub fn register_metrics(self: &Arc<Self>, meter: &Meter) {
+        let cluster = Arc::clone(self);
+        meter
+            .u64_observable_gauge("cluster.members")
+            .with_description("Cluster members by state, including this one")
+            .with_callback(move |observer| {
+                let counts = cluster.counts();
+                for state in [MemberState::Alive, MemberState::Suspect, MemberState::Dead] {
+                    let count = counts.get(&state).copied().unwrap_or(0);
+                    observer.observe(count, &[KeyValue::new("state", state.as_str())]);
+                }
+            })
+            .build();
+        let cluster = Arc::clone(self);
+        meter
+            .u64_observable_gauge("cluster.owned_checks")
+            .with_description("Checks assigned to this member")
+            .with_callback(move |observer| {
+                observer.observe(cluster.owned.load(Ordering::Relaxed), &[]);
+            })
+            .build();
+        let cluster = Arc::clone(self);
+        meter
+            .f64_observable_gauge("cluster.replication.lag")
+            .with_description("Age in seconds of the freshest state replicated from each peer")
+            .with_unit("s")
+            .with_callback(move |observer| {
+                for entry in cluster.members.read().unwrap().values() {
+                    if entry.member.state == MemberState::Dead {
+                        continue;
+                    }
+                    let attributes = [KeyValue::new("peer", entry.member.id.clone())];
+                    observer.observe(entry.updated.elapsed().as_secs_f64(), &attributes);
+                }
+            })
+            .build();
+        let cluster = Arc::clone(self);
+        meter
+            .u64_observable_counter("cluster.gossip.messages")
+            .with_description("Gossip messages by direction and type")
+            .with_callback(move |observer| {
+                let messages = cluster.messages.lock().unwrap().clone();
+                for ((direction, kind), count) in messages {
+                    observer.observe(
+                        count,
+                        &[
+                            KeyValue::new("direction", direction),
+                            KeyValue::new("type", kind),
+                        ],
+                    );
+                }
+            })
+            .build();
+    }
+
+    /// Publish the digest of the latest local check statuses with the next heartbeat
+    fn refresh_summary(&self, registry: &CheckRegistry) {
+        let report = registry.report(&Selector::default());
+        let mut local = self.local.write().unwrap();
+        local.checks = report
+            .checks
+            .into_iter()
+            .map(|(name, component)| (name, component.result.status))
+            .collect();
+        let mut hasher = DefaultHasher::new();
+        local.checks.hash(&mut hasher);
+        local.checks_digest = hasher.finish();
+        local.heartbeat += 1;
+    }
+
+    /// Fetch, over HTTP, the check summaries of peers whose gossiped digest changed
+    fn sync_summaries(self: &Arc<Self>) {
+        let stale: Vec<(String, String)> = {
+            let members = self.members.read().unwrap();
+            let mut fetching = self.fetching.lock().unwrap();
+            members
+                .values()
+                .filter(|e| e.member.state != MemberState::Dead)
+                .filter(|e| e.summary != e.member.checks_digest)
+                .filter_map(|e| Some((e.member.id.clone(), e.member.api_url.clone()?)))
+                .filter(|(id, _)| fetching.insert(id.clone()))
+                .collect()
+        };
+        for (id, api_url) in stale {
+            let cluster = Arc::clone(self);
+            tokio::spawn(async move {
+                let url = format!("{}/api/cluster/members", api_url.trim_end_matches('/'));
+                let fetched = async {
+                    let response = cluster.client.get(&url).send().await?;
+                    response.error_for_status()?.json::<RemoteView>().await
+                };
+                match fetched.await {
+                    Ok(view) if view.local.id == id => {
+                        if let Some(entry) = cluster.members.write().unwrap().get_mut(&id) {
+                            entry.member.checks = view.local.checks;
+                            entry.summary = view.local.checks_digest;
+                        }
+                    }
+                    Ok(view) => warn!("{} answered as member {}, not {}", url, view.local.id, id),
+                    Err(e) => debug!("Fetching the check summary of {} failed: {}", id, e),
+                }
+                cluster.fetching.lock().unwrap().remove(&id);
+            });
+        }
+    }
+
+    /// Forget members that have been dead for `reap_after`
+    fn reap_dead(&self) {
+        let mut members = self.members.write().unwrap();
+        members.retain(|id, entry| {
+            let reap = entry.member.state == MemberState::Dead
+                && entry.since.elapsed() >= self.config.reap_after;
+            if reap {
+                info!("Cluster member {} forgotten", id);
+            }
+            !reap
+        });
+    }
+}
+
+/// Membership view as fetched from a peer's API
+#[derive(Deserialize)]
+struct RemoteView {
+    local: Member,
+}
+
+impl MemberState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemberState::Alive => "alive",
+            MemberState::Suspect => "suspect",
+            MemberState::Dead => "dead",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn peers_list_their_shard_and_replication_lag() {
+        let config = "node_id = \"a\"\nbind = \"127.0.0.1:0\"\nsharding = true\nsecret = \"s\"";
+        let cluster = Cluster::bind(&toml::from_str(config).unwrap())
+            .await
+            .unwrap();
+        let mut peer = cluster.local.read().unwrap().clone();
+        peer.id = "b".into();
+        let updated = Instant::now() - Duration::from_secs(2);
+        let entry = Entry {
+            member: peer,
+            since: updated,
+            updated,
+            summary: 0,
+        };
+        cluster.members.write().unwrap().insert("b".into(), entry);
+        cluster.rebalance(&CheckRegistry::default());
+
+        let checks: Vec<_> = (0..20).map(|i| format!("check-{i}")).collect();
+        let peers = cluster.peers(checks.iter().map(String::as_str));
+        assert_eq!(peers.len(), 1);
+        let b = &peers[0];
+        assert_eq!(b.id, "b");
+        assert!(b.replication_lag_secs >= 2.0);
+        assert!(b.last_seen <= unix_now() - 2);
+        // The peer's shard is exactly what this member does not run
+        let local: Vec<_> = checks.iter().filter(|c| cluster.owns(c)).collect();
+        assert!(!b.shard.is_empty() && !local.is_empty());
+        assert_eq!(b.shard.len() + local.len(), checks.len());
+        assert!(b.shard.iter().all(|c| !cluster.owns(c)));
+    }
+}
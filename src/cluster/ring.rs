@@ -0,0 +1,123 @@
+/// Points placed on the ring per member, smoothing the share each member receives
+const VIRTUAL_NODES: u32 = 64;
+
+/// Consistent hash ring assigning checks to cluster members
+#[derive(Debug, Default)]
+pub struct HashRing {
+    members: Vec<String>,
+    points: Vec<(u64, usize)>,
+}
+
+impl HashRing {
+    pub fn new(mut members: Vec<String>) -> Self {
+        members.sort();
+        members.dedup();
+        let mut points: Vec<_> = members
+            .iter()
+            .enumerate()
+            .flat_map(|(index, id)| {
+                (0..VIRTUAL_NODES)
+                    .map(move |vnode| (fnv1a(format!("{id}#{vnode}").as_bytes()), index))
+            })
+            .collect();
+        points.sort_unstable();
+        Self { members, points }
+    }
+
+    /// Sorted ids of the members on the ring
+    pub fn members(&self) -> &[String] {
+        &self.members
+    }
+
+    /// Member owning a key: the first point clockwise from the key's hash
+    pub fn owner(&self, key: &str) -> Option<&str> {
+        let hash = fnv1a(key.as_bytes());
+        let index = match self.points.partition_point(|(point, _)| *point < hash) {
+            i if i == self.points.len() => 0,
+            i => i,
+        };
+        let (_, member) = self.points.get(index)?;
+        Some(&self.members[*member])
+    }
+}
+
+// FNV-1a is used rather than the std hasher so that every build places keys identically;
+// the murmur3 finalizer spreads the similar virtual node names evenly around the ring
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring(members: &[&str]) -> HashRing {
+        HashRing::new(members.iter().map(|m| m.to_string()).collect())
+    }
+
+    fn keys() -> Vec<String> {
+        (0..1000).map(|i| format!("check-{i}")).collect()
+    }
+
+    #[test]
+    fn empty_ring_has_no_owner() {
+        assert_eq!(HashRing::default().owner("db"), None);
+    }
+
+    #[test]
+    fn placement_ignores_member_order_and_duplicates() {
+        let a = ring(&["hc-a", "hc-b", "hc-c"]);
+        let b = ring(&["hc-c", "hc-a", "hc-b", "hc-a"]);
+        assert_eq!(b.members(), ["hc-a", "hc-b", "hc-c"]);
+        for key in keys() {
+            assert_eq!(a.owner(&key), b.owner(&key));
+        }
+    }
+
+    #[test]
+    fn keys_are_spread_across_members() {
+        let ring = ring(&["hc-a", "hc-b", "hc-c"]);
+        for member in ring.members() {
+            let owned = keys()
+                .iter()
+                .filter(|key| ring.owner(key) == Some(member.as_str()))
+                .count();
+            assert!((200..=470).contains(&owned), "{member} owns {owned}");
+        }
+    }
+
+    #[test]
+    fn only_the_keys_of_a_leaving_member_move() {
+        let before = ring(&["hc-a", "hc-b", "hc-c"]);
+        let after = ring(&["hc-a", "hc-c"]);
+        for key in keys() {
+            match before.owner(&key).unwrap() {
+                "hc-b" => assert_ne!(after.owner(&key), Some("hc-b")),
+                owner => assert_eq!(after.owner(&key), Some(owner)),
+            }
+        }
+    }
+
+    #[test]
+    fn a_joining_member_only_takes_keys() {
+        let before = ring(&["hc-a", "hc-b"]);
+        let after = ring(&["hc-a", "hc-b", "hc-c"]);
+        let mut moved = 0;
+        for key in keys() {
+            let owner = after.owner(&key).unwrap();
+            if owner == "hc-c" {
+                moved += 1;
+            } else {
+                assert_eq!(before.owner(&key), Some(owner));
+            }
+        }
+        assert!(moved > 0);
+    }
+}
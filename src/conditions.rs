@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::checks::{CheckResult, CheckStatus, unix_now};
+
+/// Path prefix of the conditions API
+pub const CONDITIONS_PATH: &str = "/api/conditions";
+
+/// Readiness condition reported by an application, e.g. `migrations` while they run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConditionUpdate {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// The condition fails once it has not been reported for this long, e.g. because the
+    /// application died; kept until cleared when absent
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ttl: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Condition {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Unix timestamp (seconds) of the last report
+    pub updated_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl Condition {
+    /// The condition as a critical component of readiness
+    pub fn result(&self) -> CheckResult {
+        let mut result = match (self.ok, self.expires_at) {
+            (_, Some(expires_at)) if expires_at <= unix_now() => {
+                CheckResult::down("condition was not reported again before its ttl")
+            }
+            (true, _) => CheckResult::new(CheckStatus::Up, self.message.clone()),
+            (false, _) => {
+                let message = self.message.clone().unwrap_or("condition not met".into());
+                CheckResult::down(message)
+            }
+        };
+        result.timestamp = self.updated_at;
+        result.with_detail("condition", true)
+    }
+}
+
+/// Conditions reported through the conditions API, aggregated into readiness with the checks
+#[derive(Default)]
+pub struct Conditions {
+    conditions: RwLock<BTreeMap<String, Condition>>,
+}
+
+impl Conditions {
+    /// Record a condition, returning the result it replaced
+    pub fn set(&self, name: &str, update: ConditionUpdate) -> Option<CheckResult> {
+        let now = unix_now();
+        let condition = Condition {
+            ok: update.ok,
+            message: update.message,
+            updated_at: now,
+            expires_at: update.ttl.map(|ttl| now + ttl.as_secs().max(1)),
+        };
+        let mut conditions = self.conditions.write().unwrap();
+        let previous = conditions.insert(name.to_string(), condition);
+        previous.map(|c| c.result())
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.conditions.write().unwrap().remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Condition> {
+        self.conditions.read().unwrap().get(name).cloned()
+    }
+
+    pub fn list(&self) -> BTreeMap<String, Condition> {
+        self.conditions.read().unwrap().clone()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.conditions.read().unwrap().contains_key(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(ok: bool, ttl: Option<u64>) -> ConditionUpdate {
+        ConditionUpdate {
+            ok,
+            message: None,
+            ttl: ttl.map(Duration::from_secs),
+        }
+    }
+
+    #[test]
+    fn reports_conditions_as_results() {
+        let conditions = Conditions::default();
+        assert!(conditions.set("migrations", update(false, None)).is_none());
+        let result = conditions.get("migrations").unwrap().result();
+        assert_eq!(result.status, CheckStatus::Down);
+        assert_eq!(result.message.as_deref(), Some("condition not met"));
+        let previous = conditions
+            .set("migrations", update(true, Some(60)))
+            .unwrap();
+        assert_eq!(previous.status, CheckStatus::Down);
+        let result = conditions.get("migrations").unwrap().result();
+        assert_eq!(result.status, CheckStatus::Up);
+    }
+
+    #[test]
+    fn expired_conditions_fail() {
+        let conditions = Conditions::default();
+        conditions.set("worker", update(true, Some(60)));
+        conditions
+            .conditions
+            .write()
+            .unwrap()
+            .get_mut("worker")
+            .unwrap()
+            .expires_at = Some(unix_now() - 1);
+        let result = conditions.get("worker").unwrap().result();
+        assert_eq!(result.status, CheckStatus::Down);
+    }
+}
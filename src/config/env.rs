@@ -0,0 +1,123 @@
+use super::{CONFIG_ENV, ConfigError};
+
+/// Prefix of environment variables overriding configuration values
+pub const ENV_PREFIX: &str = "HEALTHCHECK_";
+
+/// Collect `HEALTHCHECK_<SECTION>__<KEY>` variables into a table of overrides, holding the
+/// raw values as strings; see [`typed`] for their interpretation
+pub(super) fn overrides(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<toml::Table, ConfigError> {
+    let mut vars: Vec<_> = vars
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != CONFIG_ENV)
+        .collect();
+    vars.sort();
+    let mut overrides = toml::Table::new();
+    for (name, raw) in vars {
+        let keys: Vec<String> = name[ENV_PREFIX.len()..]
+            .split("__")
+            .map(str::to_lowercase)
+            .collect();
+        if keys.iter().any(String::is_empty) {
+            return Err(ConfigError(format!("{name}: empty configuration key")));
+        }
+        let (last, sections) = keys.split_last().unwrap();
+        let mut table = &mut overrides;
+        for key in sections {
+            let entry = table
+                .entry(key.clone())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            table = entry
+                .as_table_mut()
+                .ok_or_else(|| ConfigError(format!("{name}: {key} is not a section")))?;
+        }
+        table.insert(last.clone(), toml::Value::String(raw));
+    }
+    Ok(overrides)
+}
+
+/// Parse raw override values as TOML (numbers, booleans, arrays, inline tables), falling back
+/// to a plain string, so `HEALTHCHECK_ADMIN__ENABLED=true` sets a boolean and
+/// `HEALTHCHECK_LOCATION=eu-west` a string.
+pub(super) fn typed(raw: &toml::Table) -> toml::Table {
+    raw.iter()
+        .map(|(key, value)| {
+            let value = match value {
+                toml::Value::Table(table) => toml::Value::Table(typed(table)),
+                toml::Value::String(raw) => parse_value(raw),
+                value => value.clone(),
+            };
+            (key.clone(), value)
+        })
+        .collect()
+}
+
+/// Deserialize the merged configuration. An override whose parsed value does not fit its
+/// field, e.g. a numeric `HEALTHCHECK_ADMIN__TOKEN`, is retried as the raw string.
+pub(super) fn deserialize<T: serde::de::DeserializeOwned>(
+    mut merged: toml::Table,
+    raw: &toml::Table,
+) -> Result<T, toml::de::Error> {
+    loop {
+        let error = match merged.clone().try_into() {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        // The error names the offending key last, e.g. "... in `admin.token`"
+        let message = error.to_string();
+        let Some(path) = message
+            .rsplit_once("in `")
+            .and_then(|(_, path)| path.trim_end().strip_suffix('`'))
+        else {
+            return Err(error);
+        };
+        let keys: Vec<&str> = path.split('.').collect();
+        let Some(toml::Value::String(original)) = lookup(raw, &keys) else {
+            return Err(error);
+        };
+        match lookup_mut(&mut merged, &keys) {
+            Some(value) if !value.is_str() => *value = toml::Value::String(original.clone()),
+            _ => return Err(error),
+        }
+    }
+}
+
+fn lookup<'a>(table: &'a toml::Table, keys: &[&str]) -> Option<&'a toml::Value> {
+    let (last, sections) = keys.split_last()?;
+    let mut table = table;
+    for key in sections {
+        table = table.get(*key)?.as_table()?;
+    }
+    table.get(*last)
+}
+
+fn lookup_mut<'a>(table: &'a mut toml::Table, keys: &[&str]) -> Option<&'a mut toml::Value> {
+    let (last, sections) = keys.split_last()?;
+    let mut table = table;
+    for key in sections {
+        table = table.get_mut(*key)?.as_table_mut()?;
+    }
+    table.get_mut(*last)
+}
+
+fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Apply overrides on top of a parsed file, replacing everything but sections
+pub(super) fn merge(table: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(value)) => {
+                merge(existing, value)
+            }
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
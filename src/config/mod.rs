@@ -0,0 +1,2331 @@
+mod env;
+mod redact;
+
+pub use env::ENV_PREFIX;
+pub use redact::{RedactedConfig, ValueSource};
+
+use crate::checks::{Assertion, CheckStatus, Expectation, Expression, Transforms};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Environment variable pointing at the configuration file
+pub const CONFIG_ENV: &str = "HEALTHCHECK_CONFIG";
+
+/// Address the HTTP API listens on when none is configured
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:5000";
+
+/// Service configuration loaded from a TOML file
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Address the HTTP API listens on; `127.0.0.1:5000` by default, so it must be set for
+    /// agents, HA peers, cluster members and federation to reach this instance
+    pub listen: Option<String>,
+    /// Address serving only the health probes, over plain HTTP, so that they stay reachable
+    /// without credentials or TLS when the main listener requires them
+    pub health_listen: Option<String>,
+    /// Connection handling of the HTTP API
+    pub server: ServerConfig,
+    /// Credentials required by `/metrics`; none by default
+    pub metrics_auth: CredentialsConfig,
+    /// Optional endpoints of the HTTP API
+    pub endpoints: EndpointsConfig,
+    /// Region or site this instance probes from, attached to its results and metrics
+    pub location: Option<String>,
+    pub checks: Vec<CheckConfig>,
+    /// Teams sharing this deployment, each seeing and silencing only its own checks
+    pub tenants: Vec<TenantConfig>,
+    /// When scheduled checks start
+    pub scheduler: SchedulerConfig,
+    /// Name resolution for probes
+    pub dns: DnsConfig,
+    /// How check results move the service between starting, ready, degraded and unhealthy
+    pub health: HealthConfig,
+    /// Readiness conditions reported by applications
+    pub conditions: ConditionsConfig,
+    pub admin: AdminConfig,
+    pub latency: LatencyConfig,
+    pub notifications: NotificationsConfig,
+    /// URLs receiving every result of selected checks, apart from alerting
+    pub result_webhooks: Vec<ResultWebhookConfig>,
+    pub agent: AgentConfig,
+    pub aggregator: AggregatorConfig,
+    pub ha: Option<HaConfig>,
+    pub cluster: Option<ClusterConfig>,
+    pub leader_election: Option<LeaderElectionConfig>,
+    /// Probe the targets declared as `HealthCheck` resources in Kubernetes
+    pub operator: Option<OperatorConfig>,
+    /// Other instances whose checks are polled and re-exposed here
+    pub federation: Option<FederationConfig>,
+    /// Directory native checker plugins are loaded from at startup (requires the `plugins`
+    /// feature)
+    pub plugins_dir: Option<PathBuf>,
+    /// Rules rewriting or dropping check results before they are stored and notified
+    pub transforms: Vec<TransformConfig>,
+    pub telemetry: TelemetryConfig,
+    /// File the configuration was loaded from, if any
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+    /// Content hash identifying the loaded configuration
+    #[serde(skip)]
+    pub version: String,
+    /// Where each explicitly set value came from, keyed by dotted path
+    #[serde(skip)]
+    pub origins: BTreeMap<String, ValueSource>,
+}
+
+/// Connection settings of the HTTP API listener
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Keep HTTP/1 connections open between requests
+    pub keep_alive: bool,
+    /// HTTP/1 connections idle, or sending request headers, for longer are closed
+    #[serde(with = "humantime_serde")]
+    pub keep_alive_timeout: Duration,
+    /// Connections served at once; further ones wait in the listen backlog. Unlimited by
+    /// default
+    pub max_connections: Option<usize>,
+    /// Accept HTTP/2 with prior knowledge next to HTTP/1
+    pub http2: bool,
+    /// Disable Nagle's algorithm on accepted connections
+    pub tcp_nodelay: bool,
+    /// On SIGTERM or SIGINT readiness fails for this long before the listener closes, so
+    /// that load balancers stop routing here first
+    #[serde(with = "humantime_serde")]
+    pub shutdown_grace_period: Duration,
+    /// Time in-flight requests are given to complete once the listener closes
+    #[serde(with = "humantime_serde")]
+    pub shutdown_timeout: Duration,
+    /// Serve the HTTP API over TLS
+    pub tls: Option<TlsConfig>,
+}
+
+/// Certificate and key of the HTTP API, reloaded on SIGHUP and when the files change
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert: PathBuf,
+    /// PEM private key
+    pub key: PathBuf,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive: true,
+            keep_alive_timeout: Duration::from_secs(30),
+            max_connections: None,
+            http2: true,
+            tcp_nodelay: false,
+            shutdown_grace_period: Duration::from_secs(5),
+            shutdown_timeout: Duration::from_secs(10),
+            tls: None,
+        }
+    }
+}
+
+/// Thresholds of the health state machine and the liveness watchdog
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Consecutive down results before a check counts as failing
+    pub failure_threshold: u32,
+    /// Consecutive results that are not down before a failing check counts as recovered
+    pub success_threshold: u32,
+    /// Liveness fails when a background task misses its heartbeats for this long
+    #[serde(with = "humantime_serde")]
+    pub watchdog_timeout: Duration,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 1,
+            success_threshold: 1,
+            watchdog_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Endpoints of the HTTP API that can be turned off, all enabled by default. The health
+/// probes are always served
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EndpointsConfig {
+    /// `/metrics`
+    pub metrics: bool,
+    /// `/api/status`, `/api/overview`, `/api/checks/{name}/history`, `/api/events` and
+    /// `/targets`
+    pub status: bool,
+    /// `/version` and `/buildinfo`
+    pub version: bool,
+    /// `/api/example` and `/api/fail`
+    pub examples: bool,
+}
+
+impl Default for EndpointsConfig {
+    fn default() -> Self {
+        Self {
+            metrics: true,
+            status: true,
+            version: true,
+            examples: true,
+        }
+    }
+}
+
+/// Administrative endpoints, all disabled by default
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Enable the `/api/admin` endpoints
+    pub enabled: bool,
+    /// Enable the `/api/admin/faults` chaos testing endpoints
+    pub fault_injection: bool,
+    /// Bearer token accepted by the admin, fault-injection and profiling endpoints
+    pub token: Option<String>,
+    /// Basic auth accepted by the same endpoints, next to the token
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl AdminConfig {
+    pub fn credentials(&self) -> CredentialsConfig {
+        CredentialsConfig {
+            token: self.token.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+        }
+    }
+}
+
+/// Credentials accepted by an endpoint: a bearer token, basic auth, or either
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CredentialsConfig {
+    pub token: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl CredentialsConfig {
+    /// Whether any credential is configured
+    pub fn is_set(&self) -> bool {
+        self.token.is_some() || self.password.is_some()
+    }
+
+    fn validate(&self, section: &str) -> Result<(), ConfigError> {
+        if self.token.as_deref() == Some("") || self.password.as_deref() == Some("") {
+            return Err(ConfigError(format!(
+                "{section}: token and password must not be empty"
+            )));
+        }
+        if self.username.is_some() != self.password.is_some() {
+            return Err(ConfigError(format!(
+                "{section}: username and password go together"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Start times of scheduled checks, randomized so that checks sharing an interval do not
+/// all run at the same instant
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    /// Each pause between two executions of a check is shortened or lengthened by a random
+    /// amount up to this; none by default
+    #[serde(with = "humantime_serde")]
+    pub jitter: Duration,
+    /// Delay the first execution of each check by a random part of its interval instead of
+    /// running every check at startup
+    pub spread: bool,
+    /// What happens when a check is due while its previous execution is still running
+    pub overlap: Overlap,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overlap {
+    /// Skip the execution
+    #[default]
+    Skip,
+    /// Run once more as soon as the previous execution completes, skipping any further ones
+    Queue,
+}
+
+/// Name resolution for probes
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DnsConfig {
+    pub resolver: ResolverKind,
+    /// Answers are reused for this long; disabled by default
+    #[serde(with = "humantime_serde")]
+    pub cache_ttl: Duration,
+    /// When a lookup fails, answers cached up to this long past `cache_ttl` are used instead
+    #[serde(with = "humantime_serde")]
+    pub stale_if_error: Duration,
+    /// Static addresses of host names, bypassing the resolver
+    pub overrides: BTreeMap<String, Vec<IpAddr>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolverKind {
+    /// The operating system's resolver (`getaddrinfo`)
+    #[default]
+    System,
+    /// Resolver built into the service, reading the system's nameservers (requires the
+    /// `hickory` feature)
+    Builtin,
+}
+
+/// Conditions API through which applications report readiness conditions, disabled by default
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConditionsConfig {
+    /// Serve `/api/conditions` on the HTTP listener
+    pub http: bool,
+    /// Bearer token required by the HTTP conditions API
+    pub token: Option<String>,
+    /// Unix socket serving the conditions API without a token, access being governed by
+    /// the socket's file permissions
+    pub socket: Option<PathBuf>,
+}
+
+/// A single dependency check definition
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CheckConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: CheckKind,
+    #[serde(default = "default_interval", with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Non-critical checks only degrade readiness instead of failing it
+    #[serde(default = "default_critical")]
+    pub critical: bool,
+    /// Identity of the probed target, used to merge checks of the same target run by other
+    /// instances or agents; defaults to the URL or address
+    pub target: Option<String>,
+    /// Connect to this address instead of resolving the host of a TCP or HTTP check; HTTP
+    /// requests keep the host name for TLS and the `Host` header
+    pub resolve_to: Option<IpAddr>,
+    /// Overrides `scheduler.jitter` for this check
+    #[serde(default, with = "humantime_serde")]
+    pub jitter: Option<Duration>,
+    /// Overrides `scheduler.overlap` for this check
+    pub overlap: Option<Overlap>,
+    /// Addresses a TCP or HTTP check connects to when its host resolves to both families
+    #[serde(default)]
+    pub address_family: AddressFamily,
+    /// Tenant owning the check; its name is qualified as `<tenant>/<name>` when loaded
+    pub tenant: Option<String>,
+}
+
+/// A team sharing the deployment: its checks are named `<tenant>/<check>` and its token
+/// only reaches the `/api/tenants/<tenant>` endpoints
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TenantConfig {
+    pub name: String,
+    /// Bearer token of the tenant's API
+    pub token: String,
+    /// Most checks the tenant may configure
+    pub max_checks: Option<usize>,
+    /// Shortest interval the tenant's checks may run at
+    #[serde(default, with = "humantime_serde")]
+    pub min_interval: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressFamily {
+    /// Every resolved address, in the order of the resolver
+    #[default]
+    Any,
+    /// IPv4 addresses only
+    V4,
+    /// IPv6 addresses only
+    V6,
+}
+
+impl AddressFamily {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressFamily::Any => "any",
+            AddressFamily::V4 => "v4",
+            AddressFamily::V6 => "v6",
+        }
+    }
+
+    pub fn matches(&self, ip: &IpAddr) -> bool {
+        match self {
+            AddressFamily::Any => true,
+            AddressFamily::V4 => ip.is_ipv4(),
+            AddressFamily::V6 => ip.is_ipv6(),
+        }
+    }
+}
+
+impl CheckConfig {
+    /// Identity of the probed target, if any
+    pub fn target_identity(&self) -> Option<String> {
+        if let Some(target) = &self.target {
+            return Some(target.clone());
+        }
+        match &self.kind {
+            CheckKind::Tcp { address } => Some(crate::reconcile::normalize_target(address)),
+            CheckKind::Http { url, .. } => Some(crate::reconcile::normalize_target(url)),
+            CheckKind::Postgres { address, .. } | CheckKind::Redis { address, .. } => {
+                Some(crate::reconcile::normalize_target(address))
+            }
+            CheckKind::Heartbeat { .. }
+            | CheckKind::Wasm { .. }
+            | CheckKind::Script { .. }
+            | CheckKind::Plugin { .. } => None,
+        }
+    }
+
+    /// When pings are expected, for heartbeat checks
+    pub fn heartbeat_expectation(&self) -> Option<Result<Expectation, String>> {
+        let CheckKind::Heartbeat {
+            period,
+            schedule,
+            timezone,
+            ..
+        } = &self.kind
+        else {
+            return None;
+        };
+        Some(match (period, schedule) {
+            (Some(period), None) => Ok(Expectation::Period(*period)),
+            (None, Some(schedule)) => Expectation::schedule(schedule, timezone.as_deref()),
+            _ => Err("heartbeat checks need exactly one of period or schedule".into()),
+        })
+    }
+}
+
+/// Notification channels for check and health state transitions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Render and log payloads for every channel instead of delivering them
+    pub dry_run: bool,
+    pub channels: Vec<ChannelConfig>,
+    /// Attempts per channel and notification, the first included
+    pub max_attempts: u32,
+    /// Pause before the first retry, doubled on each further one up to `max_backoff`
+    #[serde(with = "humantime_serde")]
+    pub initial_backoff: Duration,
+    #[serde(with = "humantime_serde")]
+    pub max_backoff: Duration,
+    /// At most one notification per check within this window; transitions in between are
+    /// held and only the latest is sent once it ends. Zero turns deduplication off
+    #[serde(with = "humantime_serde")]
+    pub dedupe_window: Duration,
+    /// Also notify transitions of the service's health state, e.g. ready to unhealthy
+    pub health: bool,
+    /// Notifications waiting per channel while it is slow or down; the oldest are dropped
+    /// first
+    pub max_queued: usize,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            channels: Vec::new(),
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            dedupe_window: Duration::ZERO,
+            health: true,
+            max_queued: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChannelConfig {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ChannelKind,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ChannelKind {
+    /// Posts the notification as JSON
+    Webhook { url: String },
+    /// Posts a one-line summary to a Slack incoming webhook, or a compatible one
+    Slack { url: String },
+}
+
+/// Agent mode: push local check results to a central aggregator
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    /// Base URL of the aggregating healthcheck-service
+    pub aggregator_url: Option<String>,
+    /// Identity reported to the aggregator; defaults to the host name
+    pub agent_id: Option<String>,
+    /// Bearer token sent with every push
+    pub token: Option<String>,
+    /// One-time token used to enroll with the aggregator when no `token` is set
+    pub enrollment_token: Option<String>,
+    /// File the identity and credential issued at enrollment are kept in
+    pub credentials_file: Option<PathBuf>,
+    /// Labels attached to every pushed result
+    pub labels: BTreeMap<String, String>,
+    #[serde(with = "humantime_serde")]
+    pub flush_interval: Duration,
+    pub batch_size: usize,
+    pub max_retries: u32,
+    /// Results kept while the aggregator is unreachable; the oldest are dropped first
+    pub max_buffer: usize,
+    /// Push results over mutual TLS
+    pub tls: Option<AgentTlsConfig>,
+}
+
+/// Client side of the mutual TLS push channel
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AgentTlsConfig {
+    /// Base URL of the aggregator's mutual TLS listener
+    pub url: String,
+    /// Externally provided client certificate and key; reloaded when the files change.
+    /// When absent a certificate is requested from the aggregator's built-in CA
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    /// CA the aggregator's certificate is verified against; defaults to the one
+    /// returned with the issued certificate
+    pub ca: Option<PathBuf>,
+    /// Directory certificates issued by the built-in CA are stored in
+    #[serde(default = "default_agent_tls_dir")]
+    pub dir: PathBuf,
+}
+
+fn default_agent_tls_dir() -> PathBuf {
+    PathBuf::from("healthcheck-agent-tls")
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            aggregator_url: None,
+            agent_id: None,
+            token: None,
+            enrollment_token: None,
+            credentials_file: None,
+            labels: BTreeMap::new(),
+            flush_interval: Duration::from_secs(10),
+            batch_size: 100,
+            max_retries: 5,
+            max_buffer: 10_000,
+            tls: None,
+        }
+    }
+}
+
+/// Delivers every result of the selected checks to a URL in batches, e.g. for an analytics
+/// pipeline. Batches are retried until acknowledged with a 2xx response
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResultWebhookConfig {
+    pub name: String,
+    pub url: String,
+    /// Checks whose results are delivered; every check when empty
+    #[serde(default)]
+    pub checks: Vec<String>,
+    /// Bearer token sent with every batch
+    pub token: Option<String>,
+    #[serde(default = "default_webhook_flush_interval", with = "humantime_serde")]
+    pub flush_interval: Duration,
+    #[serde(default = "default_webhook_batch_size")]
+    pub batch_size: usize,
+    /// Attempts per flush before the batch is left for the next one
+    #[serde(default = "default_webhook_max_retries")]
+    pub max_retries: u32,
+    /// Results kept while the URL is unreachable; the oldest are dropped first
+    #[serde(default = "default_webhook_max_buffer")]
+    pub max_buffer: usize,
+}
+
+fn default_webhook_flush_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_webhook_batch_size() -> usize {
+    100
+}
+
+fn default_webhook_max_retries() -> u32 {
+    3
+}
+
+fn default_webhook_max_buffer() -> usize {
+    10_000
+}
+
+/// Aggregator mode: accept results pushed by remote agents
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AggregatorConfig {
+    /// Enable `POST /api/ingest/results`
+    pub enabled: bool,
+    /// Bearer token accepted from each declared agent, by agent id; these or enrollment
+    /// tokens are required, unless agents authenticate with certificates of an external CA
+    pub tokens: BTreeMap<String, String>,
+    /// One-time tokens agents exchange for an identity and credential
+    pub enrollment_tokens: Vec<String>,
+    /// Enrolled agents may only push once approved through the admin API
+    pub require_approval: bool,
+    /// File enrolled agents and used tokens are persisted to
+    pub state_file: Option<PathBuf>,
+    /// Agents that have not reported for this long are considered stale
+    #[serde(with = "humantime_serde")]
+    pub stale_after: Duration,
+    /// Results kept per agent check
+    pub history_size: usize,
+    /// Only flip a target's aggregate state once enough locations agree
+    pub quorum: Option<QuorumConfig>,
+    /// Additionally accept pushes over mutual TLS
+    pub mtls: Option<MtlsConfig>,
+}
+
+/// Mutual TLS listener authenticating agents by client certificate
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MtlsConfig {
+    #[serde(default = "default_mtls_listen")]
+    pub listen: String,
+    /// Externally provided server certificate, key and client CA; reloaded when the
+    /// files change. When absent a built-in CA issues all certificates
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+    /// Directory the built-in CA and server certificate are kept in
+    #[serde(default = "default_ca_dir")]
+    pub ca_dir: PathBuf,
+    /// Host names and addresses the built-in server certificate is valid for
+    #[serde(default = "default_server_names")]
+    pub server_names: Vec<String>,
+    /// Lifetime of certificates issued by the built-in CA; they are renewed once a
+    /// third of it remains
+    #[serde(default = "default_cert_validity", with = "humantime_serde")]
+    pub cert_validity: Duration,
+}
+
+impl MtlsConfig {
+    /// Whether the built-in CA issues certificates, rather than external files being used
+    pub fn built_in_ca(&self) -> bool {
+        self.cert.is_none() && self.key.is_none() && self.client_ca.is_none()
+    }
+}
+
+fn default_mtls_listen() -> String {
+    "0.0.0.0:5443".to_string()
+}
+
+fn default_ca_dir() -> PathBuf {
+    PathBuf::from("healthcheck-ca")
+}
+
+fn default_server_names() -> Vec<String> {
+    vec!["localhost".to_string()]
+}
+
+fn default_cert_validity() -> Duration {
+    Duration::from_secs(30 * 24 * 3600)
+}
+
+/// Quorum across probing locations
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct QuorumConfig {
+    /// Locations that must report a target down before it is declared down
+    #[serde(default = "default_min_failing")]
+    pub min_failing: usize,
+}
+
+fn default_min_failing() -> usize {
+    2
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tokens: BTreeMap::new(),
+            enrollment_tokens: Vec::new(),
+            require_approval: false,
+            state_file: None,
+            stale_after: Duration::from_secs(60),
+            history_size: 100,
+            quorum: None,
+            mtls: None,
+        }
+    }
+}
+
+/// High-availability pair: only the active instance sends notifications
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HaConfig {
+    /// Unique identity of this instance; the lowest id is preferred as active
+    pub node_id: String,
+    /// Base URL of the peer instance
+    pub peer_url: String,
+    #[serde(default = "default_heartbeat_interval", with = "humantime_serde")]
+    pub heartbeat_interval: Duration,
+    /// Take over when the peer has not answered heartbeats for this long
+    #[serde(default = "default_failover_after", with = "humantime_serde")]
+    pub failover_after: Duration,
+}
+
+fn default_heartbeat_interval() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_failover_after() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Kubernetes Lease-based leader election: only the leader probes and notifies
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LeaderElectionConfig {
+    /// Name of the `coordination.k8s.io/v1` Lease
+    #[serde(default = "default_lease_name")]
+    pub lease_name: String,
+    /// Namespace of the Lease; defaults to the pod's namespace
+    pub namespace: Option<String>,
+    /// Holder identity; defaults to `POD_NAME` or the host name
+    pub identity: Option<String>,
+    /// Base URL of the Kubernetes API server
+    #[serde(default = "default_api_server")]
+    pub api_server: String,
+    /// Followers may take over a lease that has not been renewed for this long
+    #[serde(default = "default_lease_duration", with = "humantime_serde")]
+    pub lease_duration: Duration,
+    /// The leader steps down when it could not renew the lease for this long
+    #[serde(default = "default_renew_deadline", with = "humantime_serde")]
+    pub renew_deadline: Duration,
+    /// How often the lease is renewed or its acquisition attempted
+    #[serde(default = "default_retry_period", with = "humantime_serde")]
+    pub retry_period: Duration,
+}
+
+fn default_lease_name() -> String {
+    "healthcheck-service".to_string()
+}
+
+fn default_api_server() -> String {
+    "https://kubernetes.default.svc".to_string()
+}
+
+fn default_lease_duration() -> Duration {
+    Duration::from_secs(15)
+}
+
+fn default_renew_deadline() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_retry_period() -> Duration {
+    Duration::from_secs(2)
+}
+
+/// Operator mode: `healthcheck.houseme.io/v1alpha1` HealthCheck resources are reconciled into
+/// checks, and their status reports the latest result
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OperatorConfig {
+    /// Namespace whose resources are watched; defaults to the pod's namespace
+    pub namespace: Option<String>,
+    /// Base URL of the Kubernetes API server
+    #[serde(default = "default_api_server")]
+    pub api_server: String,
+    /// How often resources are listed and their status updated
+    #[serde(default = "default_resync_interval", with = "humantime_serde")]
+    pub resync_interval: Duration,
+}
+
+fn default_resync_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+/// Federation: poll other healthcheck-service instances and re-expose their checks
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FederationConfig {
+    #[serde(default = "default_federation_interval", with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(default = "default_federation_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+    pub instances: Vec<FederatedInstanceConfig>,
+}
+
+/// Instance polled through its readiness endpoint
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FederatedInstanceConfig {
+    /// Namespace its checks are re-exposed under, as `<name>/<check>`
+    pub name: String,
+    /// Base URL of the instance's HTTP API
+    pub url: String,
+    /// Bearer token sent with every poll
+    pub token: Option<String>,
+}
+
+fn default_federation_interval() -> Duration {
+    Duration::from_secs(15)
+}
+
+fn default_federation_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// Gossip-based cluster membership (SWIM-style failure detection)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClusterConfig {
+    /// Unique identity of this member; defaults to the host name
+    pub node_id: Option<String>,
+    /// UDP address the gossip protocol listens on
+    #[serde(default = "default_gossip_bind")]
+    pub bind: String,
+    /// Gossip address announced to peers; defaults to `bind`
+    pub advertise: Option<String>,
+    /// Base URL of this member's HTTP API announced to peers
+    pub api_url: Option<String>,
+    /// Gossip addresses contacted to join the cluster
+    #[serde(default)]
+    pub seeds: Vec<String>,
+    #[serde(default = "default_probe_interval", with = "humantime_serde")]
+    pub probe_interval: Duration,
+    #[serde(default = "default_probe_timeout", with = "humantime_serde")]
+    pub probe_timeout: Duration,
+    /// Members probed on our behalf when a direct probe goes unanswered
+    #[serde(default = "default_indirect_probes")]
+    pub indirect_probes: usize,
+    /// Suspected members are declared dead after this long without refuting
+    #[serde(default = "default_suspect_timeout", with = "humantime_serde")]
+    pub suspect_timeout: Duration,
+    /// Spread the configured checks across members instead of running all of them everywhere
+    #[serde(default)]
+    pub sharding: bool,
+    /// Shared secret every gossip datagram is authenticated with
+    pub secret: String,
+    /// Dead members are forgotten after this long, unless they come back
+    #[serde(default = "default_reap_after", with = "humantime_serde")]
+    pub reap_after: Duration,
+}
+
+fn default_gossip_bind() -> String {
+    "0.0.0.0:7946".to_string()
+}
+
+fn default_probe_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_probe_timeout() -> Duration {
+    Duration::from_millis(500)
+}
+
+fn default_indirect_probes() -> usize {
+    3
+}
+
+fn default_suspect_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_reap_after() -> Duration {
+    Duration::from_secs(3600)
+}
+
+/// Condition on the values a JSONPath-style `path` selects in a JSON response body
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AssertionConfig {
+    /// e.g. `$.status`, `$.items[0].id`, `$.nodes[*].healthy` or `$.items.length()`
+    pub path: String,
+    /// Whether the path must select something; defaults to true
+    pub exists: Option<bool>,
+    pub equals: Option<serde_json::Value>,
+    pub not_equals: Option<serde_json::Value>,
+    pub less_than: Option<f64>,
+    pub at_most: Option<f64>,
+    pub greater_than: Option<f64>,
+    pub at_least: Option<f64>,
+    /// Regex the value (or its JSON text, for non-strings) must match
+    pub matches: Option<String>,
+    /// Array item, substring or object key the value must contain
+    pub contains: Option<serde_json::Value>,
+}
+
+/// Rule applied to every result of the matching checks, in the order rules are configured
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TransformConfig {
+    /// Regex the check name must fully match; all checks when absent
+    pub checks: Option<String>,
+    /// Statuses the rule applies to; all statuses when empty
+    #[serde(default)]
+    pub status: Vec<CheckStatus>,
+    /// Field `regex` is searched in: `message`, `target` or `details.<key>`
+    #[serde(default = "default_transform_source")]
+    pub source: String,
+    /// The rule only applies when this regex matches the source field
+    pub regex: Option<String>,
+    #[serde(flatten)]
+    pub action: TransformAction,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum TransformAction {
+    /// Change the status, e.g. downgrade known transient errors to degraded
+    Reclassify { to: CheckStatus },
+    /// Discard the result; the previous one stays current and nothing is notified, and a
+    /// check with no kept result yet is left out of reports
+    Drop,
+    /// Set a detail to `replacement`, expanding `$1`-style captures of `regex`; rules
+    /// without a regex must set a replacement free of captures
+    Relabel {
+        target_label: String,
+        #[serde(default = "default_replacement")]
+        replacement: String,
+    },
+}
+
+fn default_transform_source() -> String {
+    "message".into()
+}
+
+fn default_replacement() -> String {
+    "$1".into()
+}
+
+/// OpenTelemetry resource describing this instance
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Detectors enriching the resource with host, cloud and pod attributes; only `host` by
+    /// default
+    pub detectors: Vec<DetectorKind>,
+    /// Time allowed to each detector at startup
+    #[serde(with = "humantime_serde")]
+    pub detector_timeout: Duration,
+    /// Static resource attributes, taking precedence over detected ones
+    pub attributes: BTreeMap<String, String>,
+    /// `service.name` of the resource
+    pub service_name: String,
+    /// `deployment.environment.name` of the resource
+    pub environment: String,
+    /// Push of metrics to an OpenTelemetry collector
+    pub otlp: OtlpConfig,
+    /// Export of request and probe spans
+    pub tracing: TracingConfig,
+    /// Metrics about the host the service runs on
+    pub system: SystemMetricsConfig,
+}
+
+/// Periodic export of metrics over OTLP
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OtlpConfig {
+    pub enabled: bool,
+    /// Collector URL; `http://localhost:4317` over gRPC and `http://localhost:4318/v1/metrics`
+    /// over HTTP by default
+    pub endpoint: Option<String>,
+    pub protocol: OtlpProtocol,
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// Time allowed to each export
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            endpoint: None,
+            protocol: OtlpProtocol::Grpc,
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl OtlpConfig {
+    pub fn endpoint(&self) -> &str {
+        match (&self.endpoint, self.protocol) {
+            (Some(endpoint), _) => endpoint,
+            (None, OtlpProtocol::Grpc) => "http://localhost:4317",
+            (None, OtlpProtocol::Http) => "http://localhost:4318/v1/metrics",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    Grpc,
+    /// Protobuf over HTTP; the endpoint is used as is, including the `/v1/metrics` path
+    Http,
+}
+
+/// Export of spans over OTLP gRPC. Incoming W3C `traceparent` headers are honoured and HTTP
+/// probes carry one, so spans link to those of callers and probed services
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TracingConfig {
+    pub enabled: bool,
+    /// Collector URL; the metrics one when they are pushed over gRPC, otherwise
+    /// `http://localhost:4317`
+    pub endpoint: Option<String>,
+    /// Fraction of traces started here that are recorded; callers' sampling decisions are
+    /// followed
+    pub sample_ratio: f64,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: None,
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+impl TelemetryConfig {
+    pub fn tracing_endpoint(&self) -> &str {
+        match (&self.tracing.endpoint, self.otlp.protocol) {
+            (Some(endpoint), _) => endpoint,
+            (None, OtlpProtocol::Grpc) => self.otlp.endpoint(),
+            (None, OtlpProtocol::Http) => "http://localhost:4317",
+        }
+    }
+}
+
+/// Groups of host metrics, each refreshed only when exported
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SystemMetricsConfig {
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    /// `system_cpu_usage`
+    pub cpu: bool,
+    /// `system_mem_used`
+    pub memory: bool,
+    /// Size, free space and I/O of each mounted file system
+    pub disks: bool,
+    /// Traffic of each network interface
+    pub network: bool,
+    /// `system_load_average`
+    pub load: bool,
+    /// Memory, CPU and file descriptors of this service
+    pub process: bool,
+}
+
+impl Default for SystemMetricsConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+            cpu: true,
+            memory: true,
+            // One series per mount point or interface, which can be many on container hosts
+            disks: false,
+            network: false,
+            load: true,
+            process: true,
+        }
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            // Cloud detectors probe metadata endpoints, which stalls startup elsewhere
+            detectors: vec![DetectorKind::Host],
+            detector_timeout: Duration::from_secs(1),
+            attributes: BTreeMap::new(),
+            service_name: "healthcheck-service".into(),
+            environment: "development".into(),
+            otlp: OtlpConfig::default(),
+            tracing: TracingConfig::default(),
+            system: SystemMetricsConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectorKind {
+    /// Host name, OS, architecture and outbound address
+    Host,
+    /// Pod attributes from the downward API
+    Kubernetes,
+    /// AWS EC2 instance metadata
+    Ec2,
+    /// Google Compute Engine metadata
+    Gce,
+    /// Azure instance metadata
+    Azure,
+}
+
+/// Artificial latency used to validate timeout and alerting thresholds
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LatencyConfig {
+    /// Delay added to `/api/*` handlers
+    pub api: Option<Latency>,
+    /// Delay added to every check execution, counted against its timeout
+    pub probes: Option<Latency>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct Latency {
+    #[serde(default, with = "humantime_serde")]
+    pub fixed: Duration,
+    /// Upper bound of a uniformly distributed random delay added on top of `fixed`
+    #[serde(default, with = "humantime_serde")]
+    pub jitter: Duration,
+}
+
+impl Latency {
+    pub fn sample(&self) -> Duration {
+        let jitter = self.jitter.mul_f64(rand::random::<f64>());
+        self.fixed + jitter
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CheckKind {
+    Tcp {
+        address: String,
+    },
+    Http {
+        url: String,
+        #[serde(default = "default_expected_status")]
+        expected_status: u16,
+        /// Conditions on the JSON response body, all of which must hold
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        assertions: Vec<AssertionConfig>,
+        /// Expression deciding success instead of `expected_status`, e.g.
+        /// `status == 200 && latency_ms < 300 && len(body.json.items) > 0`
+        up_when: Option<String>,
+        /// Expression reporting a successful probe as degraded, e.g. `latency_ms > 300`
+        degraded_when: Option<String>,
+        /// Keep connections open between probes; disable to measure full connection and TLS
+        /// handshake latency on every probe
+        #[serde(default = "default_reuse_connections")]
+        reuse_connections: bool,
+        /// Idle pooled connections are closed after this long; probes less frequent than
+        /// this reconnect every time
+        #[serde(default = "default_idle_timeout", with = "humantime_serde")]
+        idle_timeout: Duration,
+    },
+    /// PostgreSQL server accepting connections, checked with the startup handshake
+    Postgres {
+        address: String,
+        #[serde(default = "default_postgres_user")]
+        user: String,
+        /// Defaults to the user name, like the server does
+        database: Option<String>,
+    },
+    /// Redis server answering PING
+    Redis {
+        address: String,
+        /// ACL user authenticated as, with `password`
+        username: Option<String>,
+        password: Option<String>,
+    },
+    /// Deadman switch fed by pings to `/ping/{id}`, e.g. from cron jobs
+    Heartbeat {
+        /// Ping id, typically a UUID
+        id: String,
+        /// Expected time between pings; mutually exclusive with `schedule`
+        #[serde(default, with = "humantime_serde")]
+        period: Option<Duration>,
+        /// Cron expression of the job's runs; a ping is expected after each run
+        schedule: Option<String>,
+        /// IANA time zone the schedule is evaluated in; UTC by default
+        timezone: Option<String>,
+        /// Extra time allowed after the period or scheduled run before the check fails
+        #[serde(default = "default_grace", with = "humantime_serde")]
+        grace: Duration,
+    },
+    /// WASI command module: exit code 0 is up, 1 degraded, anything else down
+    /// (requires the `wasm` feature)
+    Wasm {
+        module: PathBuf,
+        #[serde(default)]
+        args: Vec<String>,
+        /// The only environment variables visible to the module
+        #[serde(default)]
+        env: BTreeMap<String, String>,
+        /// CPU budget per execution, in wasmtime fuel units
+        #[serde(default = "default_wasm_fuel")]
+        fuel: u64,
+        #[serde(default = "default_wasm_memory")]
+        max_memory_mb: usize,
+    },
+    /// Rhai script reporting through its `result` object (requires the `scripting` feature)
+    Script {
+        /// Inline source; mutually exclusive with `file`
+        source: Option<String>,
+        file: Option<PathBuf>,
+        /// Budget of script operations per execution
+        #[serde(default = "default_script_operations")]
+        max_operations: u64,
+    },
+    /// Checker provided by a native plugin from `plugins_dir` (requires the `plugins` feature)
+    Plugin {
+        /// Name the plugin registers
+        plugin: String,
+        /// Passed to the plugin as JSON
+        #[serde(default = "default_plugin_config")]
+        config: serde_json::Value,
+    },
+}
+
+impl CheckKind {
+    /// Whether the check connects to a host, so that resolution settings apply
+    pub fn is_network(&self) -> bool {
+        matches!(
+            self,
+            CheckKind::Tcp { .. }
+                | CheckKind::Http { .. }
+                | CheckKind::Postgres { .. }
+                | CheckKind::Redis { .. }
+        )
+    }
+}
+
+fn default_postgres_user() -> String {
+    "postgres".into()
+}
+
+fn default_reuse_connections() -> bool {
+    true
+}
+
+fn default_idle_timeout() -> Duration {
+    Duration::from_secs(90)
+}
+
+fn default_plugin_config() -> serde_json::Value {
+    serde_json::Value::Object(Default::default())
+}
+
+fn default_script_operations() -> u64 {
+    10_000_000
+}
+
+fn default_wasm_fuel() -> u64 {
+    1_000_000_000
+}
+
+fn default_wasm_memory() -> usize {
+    64
+}
+
+fn default_grace() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_critical() -> bool {
+    true
+}
+
+fn default_expected_status() -> u16 {
+    200
+}
+
+impl Config {
+    /// Load configuration from a TOML file, or YAML when it ends in `.yaml` or `.yml`, with
+    /// `HEALTHCHECK_*` environment overrides
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError(format!("failed to read {}: {e}", path.display())))?;
+        Self::parse(Some(path), &content, std::env::vars())
+    }
+
+    fn parse(
+        source: Option<&Path>,
+        content: &str,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let name = source.map_or("environment overrides".into(), |p| p.display().to_string());
+        let invalid =
+            |e: &dyn std::fmt::Display| ConfigError(format!("failed to parse {name}: {e}"));
+        let yaml = source
+            .and_then(Path::extension)
+            .is_some_and(|extension| extension == "yaml" || extension == "yml");
+        let table: toml::Table = match yaml {
+            // An empty document, e.g. only comments, is an empty configuration
+            true => serde_yaml::from_str::<Option<toml::Table>>(content)
+                .map_err(|e| invalid(&e))?
+                .unwrap_or_default(),
+            false => toml::from_str(content).map_err(|e| invalid(&e))?,
+        };
+        let raw = env::overrides(vars)?;
+        let overrides = env::typed(&raw);
+        let mut merged = table.clone();
+        env::merge(&mut merged, overrides.clone());
+        let mut config: Self = env::deserialize(merged, &raw).map_err(|e| invalid(&e))?;
+        for check in &mut config.checks {
+            if let Some(tenant) = &check.tenant {
+                check.name = format!("{tenant}/{}", check.name);
+            }
+        }
+        config.validate()?;
+        let file = redact::paths(&table)
+            .into_iter()
+            .map(|path| (path, ValueSource::File));
+        let env = redact::paths(&overrides)
+            .into_iter()
+            .map(|path| (path, ValueSource::Env));
+        config.origins = file.chain(env).collect();
+        config.version = match (source, overrides.is_empty()) {
+            (None, true) => "default".to_string(),
+            _ => {
+                let mut hasher = DefaultHasher::new();
+                content.hash(&mut hasher);
+                overrides.to_string().hash(&mut hasher);
+                format!("{:016x}", hasher.finish())
+            }
+        };
+        config.source = source.map(Path::to_path_buf);
+        Ok(config)
+    }
+
+    /// Address the health probes listen on, when they have a listener of their own
+    pub fn health_listen_address(&self) -> Result<Option<SocketAddr>, ConfigError> {
+        let Some(listen) = &self.health_listen else {
+            return Ok(None);
+        };
+        listen
+            .parse()
+            .map(Some)
+            .map_err(|e| ConfigError(format!("health_listen: invalid address {listen:?}: {e}")))
+    }
+
+    /// Address the HTTP API listens on
+    pub fn listen_address(&self) -> Result<SocketAddr, ConfigError> {
+        let listen = self.listen.as_deref().unwrap_or(DEFAULT_LISTEN);
+        listen
+            .parse()
+            .map_err(|e| ConfigError(format!("listen: invalid address {listen:?}: {e}")))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let listen = self.listen_address()?;
+        if self.health_listen_address()? == Some(listen) {
+            return Err(ConfigError("health_listen must differ from listen".into()));
+        }
+        if self.server.max_connections == Some(0) || self.server.keep_alive_timeout.is_zero() {
+            return Err(ConfigError(
+                "server.max_connections and server.keep_alive_timeout must not be zero".into(),
+            ));
+        }
+        if self.ha.is_some() && self.leader_election.is_some() {
+            return Err(ConfigError(
+                "ha and leader_election are mutually exclusive".into(),
+            ));
+        }
+        let external_ca = self
+            .aggregator
+            .mtls
+            .as_ref()
+            .is_some_and(|m| !m.built_in_ca());
+        if self.aggregator.enabled
+            && self.aggregator.tokens.is_empty()
+            && self.aggregator.enrollment_tokens.is_empty()
+            && !external_ca
+        {
+            return Err(ConfigError(
+                "aggregator mode requires aggregator.tokens or aggregator.enrollment_tokens".into(),
+            ));
+        }
+        for (agent, token) in &self.aggregator.tokens {
+            if !crate::aggregator::valid_agent_name(agent) || token.is_empty() {
+                return Err(ConfigError(format!(
+                    "aggregator.tokens: agent {agent:?} needs a name without '/' and a non-empty token"
+                )));
+            }
+        }
+        if let Some(mtls) = &self.aggregator.mtls
+            && !mtls.built_in_ca()
+            && (mtls.cert.is_none() || mtls.key.is_none() || mtls.client_ca.is_none())
+        {
+            return Err(ConfigError(
+                "aggregator.mtls needs cert, key and client_ca, or none of them".into(),
+            ));
+        }
+        if let Some(tls) = &self.agent.tls
+            && tls.cert.is_some() != tls.key.is_some()
+        {
+            return Err(ConfigError(
+                "agent.tls needs both cert and key, or neither".into(),
+            ));
+        }
+        if self.agent.batch_size == 0 || self.agent.max_buffer == 0 {
+            return Err(ConfigError(
+                "agent.batch_size and agent.max_buffer must be at least 1".into(),
+            ));
+        }
+        let mut webhooks = std::collections::BTreeSet::new();
+        for webhook in &self.result_webhooks {
+            if !webhooks.insert(&webhook.name) {
+                return Err(ConfigError(format!(
+                    "result webhook {} is configured twice",
+                    webhook.name
+                )));
+            }
+            if webhook.batch_size == 0 || webhook.max_buffer == 0 {
+                return Err(ConfigError(format!(
+                    "result webhook {}: batch_size and max_buffer must be at least 1",
+                    webhook.name
+                )));
+            }
+        }
+        if let Some(cluster) = &self.cluster
+            && cluster.secret.is_empty()
+        {
+            return Err(ConfigError("cluster.secret must not be empty".into()));
+        }
+        if let Some(federation) = &self.federation {
+            let mut names = std::collections::BTreeSet::new();
+            for instance in &federation.instances {
+                if !names.insert(&instance.name) {
+                    return Err(ConfigError(format!(
+                        "federation instance {} is configured twice",
+                        instance.name
+                    )));
+                }
+            }
+        }
+        Transforms::compile(&self.transforms).map_err(ConfigError)?;
+        if self.plugins_dir.is_some() && !cfg!(feature = "plugins") {
+            return Err(ConfigError(
+                "plugins_dir requires the `plugins` feature".into(),
+            ));
+        }
+        if self.telemetry.system.interval.is_zero() {
+            return Err(ConfigError(
+                "telemetry.system.interval must not be zero".into(),
+            ));
+        }
+        if self.dns.resolver == ResolverKind::Builtin && !cfg!(feature = "hickory") {
+            return Err(ConfigError(
+                "dns.resolver = \"builtin\" requires the `hickory` feature".into(),
+            ));
+        }
+        let admin = self.admin.credentials();
+        admin.validate("admin")?;
+        if (self.admin.enabled || self.admin.fault_injection) && !admin.is_set() {
+            return Err(ConfigError(
+                "admin endpoints require admin.token or admin.username and admin.password".into(),
+            ));
+        }
+        self.metrics_auth.validate("metrics_auth")?;
+        if self.conditions.http && self.conditions.token.as_deref().is_none_or(str::is_empty) {
+            return Err(ConfigError(
+                "conditions.http requires conditions.token".into(),
+            ));
+        }
+        let mut tenants = std::collections::BTreeMap::new();
+        for tenant in &self.tenants {
+            // Agent and quorum results are published under these namespaces
+            if ["agents", "quorum"].contains(&tenant.name.as_str()) {
+                return Err(ConfigError(format!(
+                    "tenant {}: name is reserved",
+                    tenant.name
+                )));
+            }
+            if tenant.name.is_empty() || tenant.name.contains(['/', ',']) {
+                return Err(ConfigError(format!(
+                    "tenant {:?}: name must be non-empty without '/' or ','",
+                    tenant.name
+                )));
+            }
+            if tenant.token.is_empty() {
+                return Err(ConfigError(format!(
+                    "tenant {}: token must not be empty",
+                    tenant.name
+                )));
+            }
+            if tenants.insert(&tenant.name, (tenant, 0)).is_some() {
+                return Err(ConfigError(format!(
+                    "tenant {} is configured twice",
+                    tenant.name
+                )));
+            }
+        }
+        for check in &self.checks {
+            let Some(name) = &check.tenant else {
+                continue;
+            };
+            let Some((tenant, count)) = tenants.get_mut(name) else {
+                return Err(ConfigError(format!(
+                    "check {}: unknown tenant {name}",
+                    check.name
+                )));
+            };
+            *count += 1;
+            if tenant.max_checks.is_some_and(|max| *count > max) {
+                return Err(ConfigError(format!(
+                    "tenant {name}: more checks than max_checks ({})",
+                    tenant.max_checks.unwrap()
+                )));
+            }
+            if let Some(min) = tenant.min_interval
+                && check.interval < min
+            {
+                return Err(ConfigError(format!(
+                    "check {}: interval is below the minimum of tenant {name} ({})",
+                    check.name,
+                    humantime::format_duration(min)
+                )));
+            }
+        }
+        let mut names = std::collections::BTreeSet::new();
+        let mut ping_ids = std::collections::BTreeMap::new();
+        for check in &self.checks {
+            if !names.insert(&check.name) {
+                return Err(ConfigError(format!(
+                    "check {} is configured twice",
+                    check.name
+                )));
+            }
+            // Pings are routed by id, so a shared id would feed both checks
+            if let CheckKind::Heartbeat { id, .. } = &check.kind
+                && let Some(other) = ping_ids.insert(id, &check.name)
+            {
+                return Err(ConfigError(format!(
+                    "check {}: heartbeat id {id} is already used by check {other}",
+                    check.name
+                )));
+            }
+        }
+        if let Some(operator) = &self.operator
+            && operator.resync_interval.is_zero()
+        {
+            return Err(ConfigError(
+                "operator.resync_interval must not be zero".into(),
+            ));
+        }
+        let notifications = &self.notifications;
+        if notifications.max_attempts == 0 || notifications.max_queued == 0 {
+            return Err(ConfigError(
+                "notifications.max_attempts and notifications.max_queued must be at least 1".into(),
+            ));
+        }
+        if notifications.initial_backoff > notifications.max_backoff {
+            return Err(ConfigError(
+                "notifications.initial_backoff must not exceed notifications.max_backoff".into(),
+            ));
+        }
+        let mut channels = std::collections::BTreeSet::new();
+        for channel in &notifications.channels {
+            if !channels.insert(&channel.name) {
+                return Err(ConfigError(format!(
+                    "notification channel {} is configured twice",
+                    channel.name
+                )));
+            }
+        }
+        let health = &self.health;
+        if health.failure_threshold == 0 || health.success_threshold == 0 {
+            return Err(ConfigError(
+                "health.failure_threshold and health.success_threshold must be at least 1".into(),
+            ));
+        }
+        if health.watchdog_timeout.is_zero() {
+            return Err(ConfigError(
+                "health.watchdog_timeout must not be zero".into(),
+            ));
+        }
+        let otlp = &self.telemetry.otlp;
+        if otlp.interval.is_zero() || otlp.timeout.is_zero() {
+            return Err(ConfigError(
+                "telemetry.otlp.interval and telemetry.otlp.timeout must not be zero".into(),
+            ));
+        }
+        validate_otlp_endpoint("telemetry.otlp.endpoint", otlp.endpoint())?;
+        let tracing = &self.telemetry.tracing;
+        if !(0.0..=1.0).contains(&tracing.sample_ratio) {
+            return Err(ConfigError(format!(
+                "telemetry.tracing.sample_ratio: expected a ratio between 0 and 1, got {}",
+                tracing.sample_ratio
+            )));
+        }
+        validate_otlp_endpoint(
+            "telemetry.tracing.endpoint",
+            self.telemetry.tracing_endpoint(),
+        )?;
+        for check in &self.checks {
+            self.validate_check(check)?;
+        }
+        Ok(())
+    }
+
+    /// Validate a check against the rest of the configuration, e.g. one added at runtime
+    pub(crate) fn validate_check(&self, check: &CheckConfig) -> Result<(), ConfigError> {
+        // A zero interval would spin the scheduler and a zero timeout fail every probe
+        if check.interval.is_zero() || check.timeout.is_zero() {
+            return Err(ConfigError(format!(
+                "check {}: interval/timeout must not be zero",
+                check.name
+            )));
+        }
+        // `only`/`exclude` selectors are comma-separated lists of names
+        if check.name.contains(',') {
+            return Err(ConfigError(format!(
+                "check {}: name must not contain ','",
+                check.name
+            )));
+        }
+        // Tenant APIs select checks by their `<tenant>/` prefix
+        if check.tenant.is_none() && check.name.contains('/') {
+            return Err(ConfigError(format!(
+                "check {}: only checks of a tenant may have '/' in their name",
+                check.name
+            )));
+        }
+        if check.resolve_to.is_some() && !check.kind.is_network() {
+            return Err(ConfigError(format!(
+                "check {}: resolve_to only applies to tcp, http, postgres and redis checks",
+                check.name
+            )));
+        }
+        if check.address_family != AddressFamily::Any {
+            if !check.kind.is_network() {
+                return Err(ConfigError(format!(
+                    "check {}: address_family only applies to tcp, http, postgres and redis checks",
+                    check.name
+                )));
+            }
+            if let Some(ip) = check.resolve_to
+                && !check.address_family.matches(&ip)
+            {
+                return Err(ConfigError(format!(
+                    "check {}: resolve_to {ip} is not of address_family {}",
+                    check.name,
+                    check.address_family.as_str()
+                )));
+            }
+        }
+        let jitter = check.jitter.unwrap_or(self.scheduler.jitter);
+        if !jitter.is_zero() && jitter >= check.interval {
+            return Err(ConfigError(format!(
+                "check {}: jitter must be shorter than the interval",
+                check.name
+            )));
+        }
+        if let CheckKind::Wasm { module, .. } = &check.kind {
+            if !cfg!(feature = "wasm") {
+                return Err(ConfigError(format!(
+                    "check {}: wasm checks require the `wasm` feature",
+                    check.name
+                )));
+            }
+            if !module.is_file() {
+                return Err(ConfigError(format!(
+                    "check {}: module {} not found",
+                    check.name,
+                    module.display()
+                )));
+            }
+        }
+        if let CheckKind::Script { source, file, .. } = &check.kind {
+            if !cfg!(feature = "scripting") {
+                return Err(ConfigError(format!(
+                    "check {}: script checks require the `scripting` feature",
+                    check.name
+                )));
+            }
+            match (source, file) {
+                (Some(_), None) => {}
+                (None, Some(file)) if file.is_file() => {}
+                (None, Some(file)) => {
+                    return Err(ConfigError(format!(
+                        "check {}: script {} not found",
+                        check.name,
+                        file.display()
+                    )));
+                }
+                _ => {
+                    return Err(ConfigError(format!(
+                        "check {}: script checks need either source or file",
+                        check.name
+                    )));
+                }
+            }
+        }
+        if let CheckKind::Http {
+            assertions,
+            up_when,
+            degraded_when,
+            ..
+        } = &check.kind
+        {
+            for expression in [up_when, degraded_when].into_iter().flatten() {
+                Expression::parse(expression).map_err(|e| {
+                    ConfigError(format!("check {}: invalid expression: {e}", check.name))
+                })?;
+            }
+            for (index, assertion) in assertions.iter().enumerate() {
+                Assertion::compile(assertion).map_err(|e| {
+                    ConfigError(format!("check {}: assertions[{index}]: {e}", check.name))
+                })?;
+            }
+        }
+        if let CheckKind::Plugin { .. } = &check.kind {
+            if !cfg!(feature = "plugins") {
+                return Err(ConfigError(format!(
+                    "check {}: plugin checks require the `plugins` feature",
+                    check.name
+                )));
+            }
+            if self.plugins_dir.is_none() {
+                return Err(ConfigError(format!(
+                    "check {}: plugin checks require plugins_dir",
+                    check.name
+                )));
+            }
+        }
+        check
+            .heartbeat_expectation()
+            .transpose()
+            .map_err(|e| ConfigError(format!("check {}: {e}", check.name)))?;
+        Ok(())
+    }
+
+    /// Load configuration from an optional file, falling back to defaults; environment
+    /// overrides apply either way
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        match path {
+            Some(path) => Self::from_file(path),
+            None => Self::parse(None, "", std::env::vars()),
+        }
+    }
+}
+
+/// Configuration loading error
+#[derive(Debug)]
+pub struct ConfigError(pub String);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// OTLP exporters parse their endpoint as a URI and fail to build on anything else
+fn validate_otlp_endpoint(key: &str, endpoint: &str) -> Result<(), ConfigError> {
+    let invalid = || {
+        ConfigError(format!(
+            "{key}: expected an http:// or https:// URL, got {endpoint:?}"
+        ))
+    };
+    if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+        return Err(invalid());
+    }
+    endpoint
+        .parse::<axum::http::Uri>()
+        .map(|_| ())
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(toml: &str) -> Result<Config, ConfigError> {
+        Config::parse(None, toml, [])
+    }
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_empty_configuration() {
+        let config = validate("").unwrap();
+        assert!(config.checks.is_empty());
+    }
+
+    #[test]
+    fn environment_overrides_file_values() {
+        let config = Config::parse(
+            None,
+            "location = \"eu-west\"\n[admin]\nenabled = false\ntoken = \"a\"",
+            env(&[
+                ("HEALTHCHECK_ADMIN__ENABLED", "true"),
+                ("HEALTHCHECK_AGENT__BATCH_SIZE", "50"),
+                ("HEALTHCHECK_AGENT__AGENT_ID", "edge-1"),
+                ("HEALTHCHECK_CONFIG", "ignored.toml"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .unwrap();
+        assert!(config.admin.enabled);
+        assert_eq!(config.admin.token.as_deref(), Some("a"));
+        assert_eq!(config.agent.batch_size, 50);
+        assert_eq!(config.agent.agent_id.as_deref(), Some("edge-1"));
+        assert_eq!(config.origins["location"], ValueSource::File);
+        assert_eq!(config.origins["admin.enabled"], ValueSource::Env);
+        assert_ne!(config.version, "default");
+    }
+
+    #[test]
+    fn numeric_overrides_of_string_fields_stay_strings() {
+        let config = Config::parse(
+            None,
+            "",
+            env(&[
+                ("HEALTHCHECK_ADMIN__ENABLED", "true"),
+                ("HEALTHCHECK_ADMIN__TOKEN", "123456"),
+                ("HEALTHCHECK_AGENT__AGENT_ID", "2024-01-01"),
+                ("HEALTHCHECK_AGENT__BATCH_SIZE", "50"),
+            ]),
+        )
+        .unwrap();
+        assert!(config.admin.enabled);
+        assert_eq!(config.admin.token.as_deref(), Some("123456"));
+        assert_eq!(config.agent.agent_id.as_deref(), Some("2024-01-01"));
+        assert_eq!(config.agent.batch_size, 50);
+    }
+
+    #[test]
+    fn environment_overrides_are_validated() {
+        let error =
+            Config::parse(None, "", env(&[("HEALTHCHECK_ADMIN__ENABLED", "true")])).unwrap_err();
+        assert_eq!(
+            error.0,
+            "admin endpoints require admin.token or admin.username and admin.password"
+        );
+        let error =
+            Config::parse(None, "", env(&[("HEALTHCHECK_AGENT__BATCH_SIZE", "many")])).unwrap_err();
+        assert!(
+            error.0.starts_with("failed to parse environment overrides"),
+            "{error}"
+        );
+        let error = Config::parse(None, "", env(&[("HEALTHCHECK_LOCATION__", "x")])).unwrap_err();
+        assert_eq!(error.0, "HEALTHCHECK_LOCATION__: empty configuration key");
+    }
+
+    #[test]
+    fn applies_check_defaults() {
+        let config = validate(
+            r#"
+            [[checks]]
+            name = "db"
+            type = "tcp"
+            address = "127.0.0.1:5432"
+            "#,
+        )
+        .unwrap();
+        let check = &config.checks[0];
+        assert_eq!(check.interval, Duration::from_secs(10));
+        assert_eq!(check.timeout, Duration::from_secs(2));
+        assert!(check.critical);
+        assert_eq!(check.target_identity().as_deref(), Some("127.0.0.1:5432"));
+    }
+
+    #[test]
+    fn rejects_duplicate_check_names() {
+        let error = validate(
+            r#"
+            [[checks]]
+            name = "db"
+            type = "tcp"
+            address = "127.0.0.1:5432"
+
+            [[checks]]
+            name = "db"
+            type = "http"
+            url = "http://127.0.0.1:8080/health"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(error.0, "check db is configured twice");
+    }
+
+    #[test]
+    fn rejects_zero_intervals_and_timeouts() {
+        for setting in ["interval = \"0s\"", "timeout = \"0s\""] {
+            let error = validate(&format!(
+                "[[checks]]\nname = \"db\"\ntype = \"tcp\"\naddress = \"127.0.0.1:5432\"\n{setting}"
+            ))
+            .unwrap_err();
+            assert_eq!(error.0, "check db: interval/timeout must not be zero");
+        }
+    }
+
+    #[test]
+    fn rejects_ha_with_leader_election() {
+        let error = validate(
+            r#"
+            [ha]
+            node_id = "a"
+            peer_url = "http://b:5000"
+
+            [leader_election]
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(error.0, "ha and leader_election are mutually exclusive");
+    }
+
+    #[test]
+    fn validates_operator_settings() {
+        let operator = validate("[operator]").unwrap().operator.unwrap();
+        assert_eq!(operator.api_server, "https://kubernetes.default.svc");
+        assert_eq!(operator.resync_interval, Duration::from_secs(30));
+        let error = validate("operator.resync_interval = \"0s\"").unwrap_err();
+        assert_eq!(error.0, "operator.resync_interval must not be zero");
+    }
+
+    #[test]
+    fn validates_credentials() {
+        let config = validate(
+            "[admin]\nenabled = true\nusername = \"ops\"\npassword = \"s3cret\"\n[metrics_auth]\ntoken = \"t\"",
+        )
+        .unwrap();
+        assert!(config.admin.credentials().is_set());
+        assert!(config.metrics_auth.is_set());
+        assert!(!validate("").unwrap().metrics_auth.is_set());
+        let error = validate("metrics_auth.username = \"prometheus\"").unwrap_err();
+        assert_eq!(error.0, "metrics_auth: username and password go together");
+        let error = validate("health_listen = \"127.0.0.1:5000\"").unwrap_err();
+        assert_eq!(error.0, "health_listen must differ from listen");
+    }
+
+    #[test]
+    fn health_thresholds_must_be_positive() {
+        let health = validate("").unwrap().health;
+        assert_eq!((health.failure_threshold, health.success_threshold), (1, 1));
+        let error = validate("health.failure_threshold = 0").unwrap_err();
+        assert_eq!(
+            error.0,
+            "health.failure_threshold and health.success_threshold must be at least 1"
+        );
+    }
+
+    #[test]
+    fn validates_notification_retries() {
+        let notifications = validate("").unwrap().notifications;
+        assert_eq!(notifications.max_attempts, 3);
+        assert!(notifications.dedupe_window.is_zero());
+        let error = validate("notifications.max_attempts = 0").unwrap_err();
+        assert_eq!(
+            error.0,
+            "notifications.max_attempts and notifications.max_queued must be at least 1"
+        );
+        assert!(validate("notifications.max_queued = 0").is_err());
+        let error = validate("notifications.initial_backoff = \"1m\"").unwrap_err();
+        assert_eq!(
+            error.0,
+            "notifications.initial_backoff must not exceed notifications.max_backoff"
+        );
+        let channel = "[[notifications.channels]]\nname = \"ops\"\ntype = \"slack\"\nurl = \"http://hooks/x\"\n";
+        assert!(validate(channel).is_ok());
+        let error = validate(&channel.repeat(2)).unwrap_err();
+        assert_eq!(error.0, "notification channel ops is configured twice");
+    }
+
+    #[test]
+    fn tracing_shares_the_grpc_collector() {
+        let config = validate("telemetry.otlp.endpoint = \"http://collector:4317\"").unwrap();
+        assert!(!config.telemetry.tracing.enabled);
+        assert_eq!(config.telemetry.tracing_endpoint(), "http://collector:4317");
+        let config = validate(
+            "[telemetry.otlp]\nprotocol = \"http\"\nendpoint = \"http://collector:4318/v1/metrics\"",
+        )
+        .unwrap();
+        assert_eq!(config.telemetry.tracing_endpoint(), "http://localhost:4317");
+        let error = validate("telemetry.tracing.sample_ratio = 1.5").unwrap_err();
+        assert_eq!(
+            error.0,
+            "telemetry.tracing.sample_ratio: expected a ratio between 0 and 1, got 1.5"
+        );
+    }
+
+    #[test]
+    fn otlp_endpoint_defaults_to_the_protocol_port() {
+        let otlp = validate("").unwrap().telemetry.otlp;
+        assert_eq!(otlp.endpoint(), "http://localhost:4317");
+        let config = validate("[telemetry.otlp]\nprotocol = \"http\"").unwrap();
+        assert_eq!(
+            config.telemetry.otlp.endpoint(),
+            "http://localhost:4318/v1/metrics"
+        );
+        let error = validate("telemetry.otlp.endpoint = \"collector:4317\"").unwrap_err();
+        assert_eq!(
+            error.0,
+            "telemetry.otlp.endpoint: expected an http:// or https:// URL, got \"collector:4317\""
+        );
+        let error = validate("telemetry.tracing.endpoint = \"http://bad host:4317\"").unwrap_err();
+        assert_eq!(
+            error.0,
+            "telemetry.tracing.endpoint: expected an http:// or https:// URL, got \"http://bad host:4317\""
+        );
+        let error = validate("telemetry.otlp.interval = \"0s\"").unwrap_err();
+        assert_eq!(
+            error.0,
+            "telemetry.otlp.interval and telemetry.otlp.timeout must not be zero"
+        );
+    }
+
+    #[test]
+    fn loads_yaml_files() {
+        let yaml = r#"
+listen: 0.0.0.0:8080
+telemetry:
+  environment: production
+  otlp:
+    endpoint: http://otel-collector:4317
+    interval: 15s
+endpoints:
+  examples: false
+checks:
+  - name: db
+    type: tcp
+    address: db:5432
+"#;
+        let vars = env(&[("HEALTHCHECK_TELEMETRY__ENVIRONMENT", "staging")]);
+        let config = Config::parse(Some(Path::new("healthcheck.yaml")), yaml, vars).unwrap();
+        assert_eq!(config.listen_address().unwrap().port(), 8080);
+        assert_eq!(config.telemetry.environment, "staging");
+        assert_eq!(config.telemetry.otlp.interval, Duration::from_secs(15));
+        assert!(!config.endpoints.examples && config.endpoints.metrics);
+        assert_eq!(config.checks[0].name, "db");
+        let empty = Config::parse(Some(Path::new("c.yml")), "# defaults\n", []).unwrap();
+        assert!(empty.checks.is_empty());
+        let error = Config::parse(Some(Path::new("c.yml")), "listen: [", []).unwrap_err();
+        assert!(error.0.starts_with("failed to parse c.yml"), "{error}");
+    }
+
+    #[test]
+    fn rejects_unauthenticated_aggregators() {
+        let error = validate("[aggregator]\nenabled = true").unwrap_err();
+        assert_eq!(
+            error.0,
+            "aggregator mode requires aggregator.tokens or aggregator.enrollment_tokens"
+        );
+        validate("[aggregator]\nenabled = true\nenrollment_tokens = [\"t\"]").unwrap();
+        let error = validate("[aggregator]\ntokens = { \"eu/1\" = \"t\" }").unwrap_err();
+        assert_eq!(
+            error.0,
+            "aggregator.tokens: agent \"eu/1\" needs a name without '/' and a non-empty token"
+        );
+    }
+
+    #[test]
+    fn validates_listen_address() {
+        let default = validate("").unwrap().listen_address().unwrap();
+        assert_eq!(default.to_string(), DEFAULT_LISTEN);
+        let config = validate("listen = \"0.0.0.0:8080\"").unwrap();
+        assert_eq!(config.listen_address().unwrap().port(), 8080);
+        let error = validate("listen = \"0.0.0.0\"").unwrap_err();
+        assert!(error.0.starts_with("listen: invalid address"), "{error}");
+        // Embedders may build the configuration without loading it
+        let config = Config {
+            health_listen: Some("0.0.0.0".into()),
+            ..Config::default()
+        };
+        let error = config.health_listen_address().unwrap_err();
+        assert!(
+            error.0.starts_with("health_listen: invalid address"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn requires_a_token_for_http_conditions() {
+        let error = validate("[conditions]\nhttp = true").unwrap_err();
+        assert_eq!(error.0, "conditions.http requires conditions.token");
+        validate("[conditions]\nsocket = \"/run/healthcheck.sock\"").unwrap();
+    }
+
+    #[test]
+    fn rejects_empty_agent_batches() {
+        let error = validate("[agent]\nbatch_size = 0").unwrap_err();
+        assert_eq!(
+            error.0,
+            "agent.batch_size and agent.max_buffer must be at least 1"
+        );
+    }
+
+    #[test]
+    fn tenant_checks_are_qualified_and_within_quotas() {
+        let tenants = r#"
+            [[tenants]]
+            name = "payments"
+            token = "p"
+            max_checks = 1
+            min_interval = "30s"
+        "#;
+        let check = |name: &str, interval: &str| {
+            format!(
+                "[[checks]]\nname = \"{name}\"\ntype = \"tcp\"\naddress = \"db:5432\"\n\
+                 interval = \"{interval}\"\ntenant = \"payments\"\n"
+            )
+        };
+        let config = validate(&format!("{}{tenants}", check("db", "1m"))).unwrap();
+        assert_eq!(config.checks[0].name, "payments/db");
+        let error = validate(&format!("{}{tenants}", check("db", "10s"))).unwrap_err();
+        assert_eq!(
+            error.0,
+            "check payments/db: interval is below the minimum of tenant payments (30s)"
+        );
+        let two = format!("{}{}{tenants}", check("db", "1m"), check("cache", "1m"));
+        let error = validate(&two).unwrap_err();
+        assert_eq!(error.0, "tenant payments: more checks than max_checks (1)");
+        let error = validate(&check("db", "1m")).unwrap_err();
+        assert_eq!(error.0, "check payments/db: unknown tenant payments");
+        let impostor =
+            "[[checks]]\nname = \"payments/db\"\ntype = \"tcp\"\naddress = \"db:5432\"\n";
+        let error = validate(&format!("{impostor}{tenants}")).unwrap_err();
+        assert_eq!(
+            error.0,
+            "check payments/db: only checks of a tenant may have '/' in their name"
+        );
+        let error = validate("[[tenants]]\nname = \"agents\"\ntoken = \"a\"").unwrap_err();
+        assert_eq!(error.0, "tenant agents: name is reserved");
+        let error = validate(&format!("{}{tenants}", check("db,search", "1m"))).unwrap_err();
+        assert_eq!(
+            error.0,
+            "check payments/db,search: name must not contain ','"
+        );
+    }
+
+    #[test]
+    fn validates_result_webhooks() {
+        let config = validate(
+            r#"
+            [[result_webhooks]]
+            name = "analytics"
+            url = "http://127.0.0.1:9000/events"
+            checks = ["db"]
+            "#,
+        )
+        .unwrap();
+        let webhook = &config.result_webhooks[0];
+        assert_eq!((webhook.batch_size, webhook.max_retries), (100, 3));
+        let error = validate(
+            r#"
+            [[result_webhooks]]
+            name = "analytics"
+            url = "http://127.0.0.1:9000/events"
+            batch_size = 0
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.0,
+            "result webhook analytics: batch_size and max_buffer must be at least 1"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        let error = validate(
+            r#"
+            [[checks]]
+            name = "api"
+            type = "http"
+            url = "http://127.0.0.1:8080/health"
+            up_when = "status =="
+            "#,
+        )
+        .unwrap_err();
+        assert!(
+            error.0.starts_with("check api: invalid expression"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_heartbeat_ids() {
+        let error = validate(
+            r#"
+            [[checks]]
+            name = "backup"
+            type = "heartbeat"
+            id = "nightly"
+            period = "1d"
+
+            [[checks]]
+            name = "report"
+            type = "heartbeat"
+            id = "nightly"
+            period = "1d"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.0,
+            "check report: heartbeat id nightly is already used by check backup"
+        );
+    }
+
+    #[test]
+    fn validates_server_settings() {
+        let server = validate("").unwrap().server;
+        assert!(server.keep_alive && server.http2 && !server.tcp_nodelay);
+        assert_eq!(server.max_connections, None);
+        let config = validate("[server]\nmax_connections = 256\nhttp2 = false").unwrap();
+        assert_eq!(config.server.max_connections, Some(256));
+        let error = validate("server.max_connections = 0").unwrap_err();
+        assert_eq!(
+            error.0,
+            "server.max_connections and server.keep_alive_timeout must not be zero"
+        );
+    }
+
+    #[test]
+    fn system_metric_groups_can_be_disabled() {
+        let system = validate("").unwrap().telemetry.system;
+        assert!(system.cpu && system.memory);
+        let config = validate("[telemetry.system]\ncpu = false\ninterval = \"30s\"").unwrap();
+        let system = config.telemetry.system;
+        assert!(!system.cpu && system.memory);
+        assert_eq!(system.interval, Duration::from_secs(30));
+        let error = validate("telemetry.system.interval = \"0s\"").unwrap_err();
+        assert_eq!(error.0, "telemetry.system.interval must not be zero");
+    }
+
+    #[test]
+    fn jitter_must_be_shorter_than_the_interval() {
+        let checks = r#"
+            [[checks]]
+            name = "db"
+            type = "tcp"
+            address = "127.0.0.1:5432"
+            interval = "10s"
+            [[checks]]
+            name = "queue"
+            type = "tcp"
+            address = "127.0.0.1:5672"
+            interval = "1m"
+        "#;
+        let config = validate(&format!("scheduler.jitter = \"5s\"\n{checks}")).unwrap();
+        assert_eq!(config.scheduler.jitter, Duration::from_secs(5));
+        let error = validate(&format!("scheduler.jitter = \"30s\"\n{checks}")).unwrap_err();
+        assert_eq!(
+            error.0,
+            "check db: jitter must be shorter than the interval"
+        );
+        let overridden = checks.replacen(
+            "interval = \"10s\"",
+            "interval = \"10s\"\njitter = \"1s\"",
+            1,
+        );
+        validate(&format!("scheduler.jitter = \"30s\"\n{overridden}")).unwrap();
+    }
+
+    #[test]
+    fn address_family_must_match_the_pinned_address() {
+        let check = r#"
+            [[checks]]
+            name = "api"
+            type = "http"
+            url = "http://api.internal/health"
+            address_family = "v6"
+        "#;
+        let config = validate(check).unwrap();
+        assert_eq!(config.checks[0].address_family, AddressFamily::V6);
+        validate(&format!("{check}resolve_to = \"fd00::5\"")).unwrap();
+        let error = validate(&format!("{check}resolve_to = \"10.0.0.5\"")).unwrap_err();
+        assert_eq!(
+            error.0,
+            "check api: resolve_to 10.0.0.5 is not of address_family v6"
+        );
+    }
+
+    #[test]
+    fn resolve_to_requires_a_network_check() {
+        let error = validate(
+            r#"
+            [[checks]]
+            name = "backup"
+            type = "heartbeat"
+            id = "nightly"
+            period = "1d"
+            resolve_to = "10.0.0.5"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.0,
+            "check backup: resolve_to only applies to tcp, http, postgres and redis checks"
+        );
+    }
+
+    #[test]
+    fn database_checks_default_their_user_and_resolve_like_tcp() {
+        let config = validate(
+            r#"
+            [[checks]]
+            name = "db"
+            type = "postgres"
+            address = "DB.internal:5432"
+            address_family = "v4"
+
+            [[checks]]
+            name = "cache"
+            type = "redis"
+            address = "cache.internal:6379"
+            password = "s3cret"
+            "#,
+        )
+        .unwrap();
+        let CheckKind::Postgres { user, database, .. } = &config.checks[0].kind else {
+            panic!("not a postgres check");
+        };
+        assert_eq!((user.as_str(), database), ("postgres", &None));
+        assert_eq!(
+            config.checks[0].target_identity().as_deref(),
+            Some("db.internal:5432")
+        );
+        assert!(config.checks[1].kind.is_network());
+    }
+
+    #[test]
+    fn rejects_heartbeats_without_expectation() {
+        let error = validate(
+            r#"
+            [[checks]]
+            name = "backup"
+            type = "heartbeat"
+            id = "nightly"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.0,
+            "check backup: heartbeat checks need exactly one of period or schedule"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::metrics::Temporality;
+use opentelemetry_sdk::metrics::data::ResourceMetrics;
+use opentelemetry_sdk::metrics::exporter::PushMetricExporter;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::checks::unix_now;
+
+/// Outcome of the most recent exports of a metric exporter
+#[derive(Debug, Default)]
+pub struct ExportStatus {
+    exports: AtomicU64,
+    failures: AtomicU64,
+    last_success: AtomicU64,
+    last_failure: AtomicU64,
+    last_error: RwLock<Option<String>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportReport {
+    pub status: &'static str,
+    pub exports: u64,
+    pub failures: u64,
+    /// Unix timestamp of the last successful export
+    pub last_success: Option<u64>,
+    pub last_failure: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+impl ExportStatus {
+    fn record(&self, result: &OTelSdkResult) {
+        self.exports.fetch_add(1, Ordering::Relaxed);
+        match result {
+            Ok(()) => self.last_success.store(unix_now(), Ordering::Relaxed),
+            Err(e) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                self.last_failure.store(unix_now(), Ordering::Relaxed);
+                *self.last_error.write().unwrap() = Some(e.to_string());
+            }
+        }
+    }
+
+    pub fn report(&self) -> ExportReport {
+        let nonzero = |v: u64| (v > 0).then_some(v);
+        let last_success = nonzero(self.last_success.load(Ordering::Relaxed));
+        let last_failure = nonzero(self.last_failure.load(Ordering::Relaxed));
+        let status = match (last_success, last_failure) {
+            (None, None) => "pending",
+            (Some(ok), Some(failed)) if failed > ok => "failing",
+            (None, Some(_)) => "failing",
+            _ => "ok",
+        };
+        ExportReport {
+            status,
+            exports: self.exports.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            last_success,
+            last_failure,
+            last_error: self.last_error.read().unwrap().clone(),
+        }
+    }
+}
+
+/// Wraps a push exporter to record the outcome of every export
+pub struct TrackedExporter<E> {
+    inner: E,
+    status: Arc<ExportStatus>,
+}
+
+impl<E> TrackedExporter<E> {
+    pub fn new(inner: E, status: Arc<ExportStatus>) -> Self {
+        Self { inner, status }
+    }
+}
+
+impl<E: PushMetricExporter> PushMetricExporter for TrackedExporter<E> {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> OTelSdkResult {
+        let result = self.inner.export(metrics).await;
+        self.status.record(&result);
+        result
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown(&self) -> OTelSdkResult {
+        self.inner.shutdown()
+    }
+
+    fn temporality(&self) -> Temporality {
+        self.inner.temporality()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::error::OTelSdkError;
+
+    #[test]
+    fn report_fails_until_an_export_succeeds_again() {
+        let status = ExportStatus::default();
+        assert_eq!(status.report().status, "pending");
+        status.record(&Err(OTelSdkError::InternalFailure("refused".into())));
+        let report = status.report();
+        assert_eq!(report.status, "failing");
+        assert_eq!((report.exports, report.failures), (1, 1));
+        assert!(report.last_error.unwrap().contains("refused"));
+        // Timestamps have a one second resolution, so a success within the same second wins
+        status.record(&Ok(()));
+        let report = status.report();
+        assert_eq!(report.status, "ok");
+        assert_eq!((report.exports, report.failures), (2, 1));
+        assert!(report.last_success.is_some());
+    }
+}
@@ -0,0 +1,283 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::auth::constant_time_eq;
+use crate::checks::unix_now;
+use crate::config::AggregatorConfig;
+use crate::tls::write_private;
+
+/// Path of the endpoint agents enroll through
+pub const ENROLL_PATH: &str = "/api/ingest/enroll";
+
+/// Enrollment request sent by an agent with a one-time token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollRequest {
+    pub token: String,
+    /// Requested identity; a suffix is added when it is already taken
+    pub agent: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Identity and credential issued to an enrolled agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnrollResponse {
+    pub agent: String,
+    pub credential: String,
+    pub status: AgentStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentStatus {
+    /// Enrolled, waiting for an administrator to approve it
+    Pending,
+    Approved,
+    Revoked,
+}
+
+/// Outcome of authenticating a push
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Granted,
+    /// Valid credential of an agent that has not been approved yet
+    Pending,
+    Denied,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EnrolledAgent {
+    credential: String,
+    status: AgentStatus,
+    enrolled_at: u64,
+    labels: BTreeMap<String, String>,
+}
+
+/// Enrolled agent as listed by the admin API
+#[derive(Debug, Serialize)]
+pub struct EnrolledAgentSummary {
+    pub agent: String,
+    pub status: AgentStatus,
+    pub enrolled_at: u64,
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct EnrollmentState {
+    agents: BTreeMap<String, EnrolledAgent>,
+    used_tokens: BTreeSet<String>,
+}
+
+/// Agents registered through one-time enrollment tokens, with their credentials
+pub struct Enrollment {
+    tokens: Vec<String>,
+    /// Agents with a declared token, whose ids are never issued
+    declared: BTreeSet<String>,
+    require_approval: bool,
+    state_file: Option<PathBuf>,
+    state: RwLock<EnrollmentState>,
+}
+
+impl Enrollment {
+    /// Restore previously enrolled agents from the state file, if any
+    pub fn new(config: &AggregatorConfig) -> Self {
+        let state = config
+            .state_file
+            .as_ref()
+            .and_then(|path| match std::fs::read(path) {
+                Ok(content) => serde_json::from_slice(&content)
+                    .inspect_err(|e| warn!("Ignoring invalid {}: {}", path.display(), e))
+                    .ok(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    warn!("Failed to read {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            tokens: config.enrollment_tokens.clone(),
+            declared: config.tokens.keys().cloned().collect(),
+            require_approval: config.require_approval,
+            state_file: config.state_file.clone(),
+            state: RwLock::new(state),
+        }
+    }
+
+    /// Exchange an unused enrollment token for an identity and credential
+    pub fn enroll(&self, request: EnrollRequest) -> Option<EnrollResponse> {
+        let mut state = self.state.write().unwrap();
+        let known = self
+            .tokens
+            .iter()
+            .any(|token| constant_time_eq(token, &request.token));
+        if !known || state.used_tokens.contains(&request.token) {
+            return None;
+        }
+        let mut agent = request.agent.clone();
+        while state.agents.contains_key(&agent) || self.declared.contains(&agent) {
+            agent = format!("{}-{:04x}", request.agent, rand::random::<u16>());
+        }
+        let status = match self.require_approval {
+            true => AgentStatus::Pending,
+            false => AgentStatus::Approved,
+        };
+        let credential = format!("{:032x}", rand::random::<u128>());
+        state.used_tokens.insert(request.token);
+        state.agents.insert(
+            agent.clone(),
+            EnrolledAgent {
+                credential: credential.clone(),
+                status,
+                enrolled_at: unix_now(),
+                labels: request.labels,
+            },
+        );
+        self.persist(&state);
+        info!("Agent {} enrolled ({:?})", agent, status);
+        Some(EnrollResponse {
+            agent,
+            credential,
+            status,
+        })
+    }
+
+    /// Check the credential an agent pushes with
+    pub fn authenticate(&self, agent: &str, credential: &str) -> Access {
+        match self.state.read().unwrap().agents.get(agent) {
+            Some(enrolled) if constant_time_eq(&enrolled.credential, credential) => {
+                match enrolled.status {
+                    AgentStatus::Approved => Access::Granted,
+                    AgentStatus::Pending => Access::Pending,
+                    AgentStatus::Revoked => Access::Denied,
+                }
+            }
+            _ => Access::Denied,
+        }
+    }
+
+    /// Status of an enrolled agent, `None` for agents that did not enroll
+    pub fn status(&self, agent: &str) -> Option<AgentStatus> {
+        let state = self.state.read().unwrap();
+        state.agents.get(agent).map(|enrolled| enrolled.status)
+    }
+
+    pub fn list(&self) -> Vec<EnrolledAgentSummary> {
+        self.state
+            .read()
+            .unwrap()
+            .agents
+            .iter()
+            .map(|(agent, enrolled)| EnrolledAgentSummary {
+                agent: agent.clone(),
+                status: enrolled.status,
+                enrolled_at: enrolled.enrolled_at,
+                labels: enrolled.labels.clone(),
+            })
+            .collect()
+    }
+
+    /// Change an agent's status, returning false for unknown agents
+    pub fn set_status(&self, agent: &str, status: AgentStatus) -> bool {
+        let mut state = self.state.write().unwrap();
+        let Some(enrolled) = state.agents.get_mut(agent) else {
+            return false;
+        };
+        enrolled.status = status;
+        self.persist(&state);
+        info!("Agent {} is now {:?}", agent, status);
+        true
+    }
+
+    fn persist(&self, state: &EnrollmentState) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        // The file holds agent credentials
+        if let Err(e) = write_private(path, &serde_json::to_vec_pretty(state).unwrap()) {
+            warn!(
+                "Failed to persist enrollment state to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enrollment(config: &str) -> Enrollment {
+        Enrollment::new(&toml::from_str(config).unwrap())
+    }
+
+    fn request(token: &str, agent: &str) -> EnrollRequest {
+        EnrollRequest {
+            token: token.into(),
+            agent: agent.into(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn tokens_are_only_accepted_once() {
+        let enrollment = enrollment("enrollment_tokens = [\"t1\", \"t2\"]");
+        let first = enrollment.enroll(request("t1", "edge")).unwrap();
+        assert_eq!(first.agent, "edge");
+        assert_eq!(first.status, AgentStatus::Approved);
+        assert!(enrollment.enroll(request("t1", "other")).is_none());
+        assert!(enrollment.enroll(request("unknown", "other")).is_none());
+        // Taken identities get a suffix instead of replacing the enrolled agent
+        let second = enrollment.enroll(request("t2", "edge")).unwrap();
+        assert!(second.agent.starts_with("edge-"), "{}", second.agent);
+        assert_eq!(
+            enrollment.authenticate("edge", &first.credential),
+            Access::Granted
+        );
+        assert_eq!(
+            enrollment.authenticate("edge", &second.credential),
+            Access::Denied
+        );
+    }
+
+    #[test]
+    fn pending_and_revoked_agents_may_not_push() {
+        let enrollment = enrollment("enrollment_tokens = [\"t\"]\nrequire_approval = true");
+        let enrolled = enrollment.enroll(request("t", "edge")).unwrap();
+        assert_eq!(enrolled.status, AgentStatus::Pending);
+        let access = || enrollment.authenticate("edge", &enrolled.credential);
+        assert_eq!(access(), Access::Pending);
+        assert!(enrollment.set_status("edge", AgentStatus::Approved));
+        assert_eq!(access(), Access::Granted);
+        assert!(enrollment.set_status("edge", AgentStatus::Revoked));
+        assert_eq!(access(), Access::Denied);
+        assert!(!enrollment.set_status("unknown", AgentStatus::Approved));
+    }
+
+    #[test]
+    fn approved_agents_and_used_tokens_survive_restarts() {
+        let path = std::env::temp_dir().join(format!("enrollment-{}.json", std::process::id()));
+        let config = format!(
+            "enrollment_tokens = [\"t\"]\nrequire_approval = true\nstate_file = {:?}",
+            path.display().to_string()
+        );
+        let enrolled = {
+            let enrollment = enrollment(&config);
+            let enrolled = enrollment.enroll(request("t", "edge")).unwrap();
+            enrollment.set_status("edge", AgentStatus::Approved);
+            enrolled
+        };
+        let restored = enrollment(&config);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.status("edge"), Some(AgentStatus::Approved));
+        assert_eq!(
+            restored.authenticate("edge", &enrolled.credential),
+            Access::Granted
+        );
+        assert!(restored.enroll(request("t", "again")).is_none());
+    }
+}
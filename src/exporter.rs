@@ -0,0 +1,131 @@
+use async_trait::async_trait;
+use prometheus::Registry;
+use prometheus::proto::MetricFamily;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::checks::{CheckEvent, CheckRegistry, unix_now};
+
+/// Metrics gathered from a registry at one point in time
+#[derive(Debug, Clone)]
+pub struct MetricsSnapshot {
+    /// Unix timestamp (seconds) of the collection
+    pub timestamp: u64,
+    pub families: Vec<MetricFamily>,
+}
+
+/// Sink for check results and metrics, e.g. a message bus added by an embedder
+#[async_trait]
+pub trait Exporter: Send + Sync {
+    /// Called for every local check execution and, when this instance aggregates or federates,
+    /// for every result an agent pushes (only aggregate state changes under a quorum policy)
+    /// and every check status polled from a federated instance
+    async fn export_result(&self, event: &CheckEvent);
+
+    /// Called at the snapshot interval with the current metrics
+    async fn export_metrics(&self, _snapshot: &MetricsSnapshot) {}
+}
+
+/// Feeds check results and periodic metric snapshots to the registered exporters.
+///
+/// Every exporter runs on its own task, so a slow one only delays itself; an exporter that
+/// falls too far behind skips results rather than holding up the checks.
+pub struct Exporters {
+    exporters: Vec<(String, Arc<dyn Exporter>)>,
+    interval: Duration,
+}
+
+impl Default for Exporters {
+    fn default() -> Self {
+        Self {
+            exporters: Vec::new(),
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Exporters {
+    pub fn with_exporter(mut self, name: &str, exporter: impl Exporter + 'static) -> Self {
+        self.exporters.push((name.to_string(), Arc::new(exporter)));
+        self
+    }
+
+    /// How often metric snapshots are taken; every minute by default
+    pub fn with_snapshot_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exporters.is_empty()
+    }
+
+    /// Start delivering to every exporter until the check registry goes away
+    pub fn spawn(&self, checks: &CheckRegistry, metrics: Registry) {
+        for (name, exporter) in &self.exporters {
+            let (name, exporter) = (name.clone(), exporter.clone());
+            let mut events = checks.subscribe();
+            let metrics = metrics.clone();
+            let mut ticker = tokio::time::interval(self.interval);
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        event = events.recv() => match event {
+                            Ok(event) => exporter.export_result(&event).await,
+                            Err(RecvError::Lagged(skipped)) => {
+                                warn!("Exporter {} lagged behind, {} results dropped", name, skipped)
+                            }
+                            Err(RecvError::Closed) => break,
+                        },
+                        _ = ticker.tick() => {
+                            let snapshot = MetricsSnapshot {
+                                timestamp: unix_now(),
+                                families: metrics.gather(),
+                            };
+                            exporter.export_metrics(&snapshot).await;
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckResult;
+    use tokio::sync::mpsc;
+
+    struct Forward(mpsc::UnboundedSender<String>);
+
+    #[async_trait]
+    impl Exporter for Forward {
+        async fn export_result(&self, event: &CheckEvent) {
+            let _ = self.0.send(event.check.clone());
+        }
+
+        async fn export_metrics(&self, _snapshot: &MetricsSnapshot) {
+            let _ = self.0.send("metrics".into());
+        }
+    }
+
+    #[tokio::test]
+    async fn delivers_results_and_snapshots() {
+        let (sender, mut received) = mpsc::unbounded_channel();
+        let checks = CheckRegistry::default();
+        let exporters = Exporters::default().with_exporter("test", Forward(sender));
+        assert!(!exporters.is_empty());
+        exporters.spawn(&checks, Registry::new());
+        // The first tick is immediate
+        assert_eq!(received.recv().await.unwrap(), "metrics");
+        checks.publish(CheckEvent {
+            check: "agents/eu-1/db".into(),
+            previous: None,
+            result: CheckResult::up(),
+        });
+        assert_eq!(received.recv().await.unwrap(), "agents/eu-1/db");
+    }
+}
@@ -0,0 +1,180 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::checks::{CheckResult, CheckStatus};
+
+/// Faults injected through the admin API to rehearse alerting and failover paths
+#[derive(Default)]
+pub struct Faults {
+    forced: RwLock<HashMap<String, ForcedStatus>>,
+    failure_rate: RwLock<f64>,
+    readiness_until: RwLock<Option<Instant>>,
+}
+
+#[derive(Debug, Clone)]
+struct ForcedStatus {
+    status: CheckStatus,
+    message: String,
+    until: Option<Instant>,
+}
+
+/// Snapshot of the active faults
+#[derive(Debug, Serialize)]
+pub struct FaultsSummary {
+    pub forced_checks: HashMap<String, CheckStatus>,
+    pub probe_failure_rate: f64,
+    pub readiness_failing_for_secs: Option<u64>,
+}
+
+fn active(until: Option<Instant>) -> bool {
+    until.is_none_or(|until| Instant::now() < until)
+}
+
+/// Instant `duration` from now, if it can be represented
+fn deadline(duration: Duration) -> Result<Instant, String> {
+    Instant::now().checked_add(duration).ok_or_else(|| {
+        format!(
+            "duration {} is too long",
+            humantime::format_duration(duration)
+        )
+    })
+}
+
+impl Faults {
+    /// Force a check to report `status` until cleared or `duration` elapses
+    pub fn force_check(
+        &self,
+        name: &str,
+        status: CheckStatus,
+        duration: Option<Duration>,
+    ) -> Result<(), String> {
+        let forced = ForcedStatus {
+            status,
+            message: "status forced by fault injection".to_string(),
+            until: duration.map(deadline).transpose()?,
+        };
+        self.forced
+            .write()
+            .unwrap()
+            .insert(name.to_string(), forced);
+        Ok(())
+    }
+
+    pub fn clear_check(&self, name: &str) -> bool {
+        self.forced.write().unwrap().remove(name).is_some()
+    }
+
+    /// Fail this fraction (0.0-1.0) of probe executions
+    pub fn set_failure_rate(&self, rate: f64) -> Result<(), String> {
+        if !rate.is_finite() {
+            return Err(format!("failure rate {rate} is not a number"));
+        }
+        *self.failure_rate.write().unwrap() = rate.clamp(0.0, 1.0);
+        Ok(())
+    }
+
+    pub fn fail_readiness_for(&self, duration: Duration) -> Result<(), String> {
+        *self.readiness_until.write().unwrap() = Some(deadline(duration)?);
+        Ok(())
+    }
+
+    pub fn readiness_failing(&self) -> bool {
+        self.readiness_until
+            .read()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    pub fn clear(&self) {
+        self.forced.write().unwrap().clear();
+        *self.failure_rate.write().unwrap() = 0.0;
+        *self.readiness_until.write().unwrap() = None;
+    }
+
+    /// Replace a freshly executed result with an injected failure, if one is due
+    pub fn inject_failure(&self, result: CheckResult) -> CheckResult {
+        let rate = *self.failure_rate.read().unwrap();
+        if rate > 0.0 && rand::random::<f64>() < rate {
+            CheckResult::down("injected probe failure")
+        } else {
+            result
+        }
+    }
+
+    /// Whether a check's status is currently forced
+    pub fn is_forced(&self, name: &str) -> bool {
+        let forced = self.forced.read().unwrap();
+        forced.get(name).is_some_and(|forced| active(forced.until))
+    }
+
+    /// Override a cached result with a forced status, if one is active
+    pub fn apply_forced(&self, name: &str, result: CheckResult) -> CheckResult {
+        match self.forced.read().unwrap().get(name) {
+            Some(forced) if active(forced.until) => CheckResult {
+                status: forced.status,
+                message: Some(forced.message.clone()),
+                ..result
+            },
+            _ => result,
+        }
+    }
+
+    pub fn summary(&self) -> FaultsSummary {
+        let now = Instant::now();
+        FaultsSummary {
+            forced_checks: self
+                .forced
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(_, f)| active(f.until))
+                .map(|(name, f)| (name.clone(), f.status))
+                .collect(),
+            probe_failure_rate: *self.failure_rate.read().unwrap(),
+            readiness_failing_for_secs: self
+                .readiness_until
+                .read()
+                .unwrap()
+                .filter(|until| now < *until)
+                .map(|until| (until - now).as_secs()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forced_statuses_override_results_until_cleared() {
+        let faults = Faults::default();
+        faults.force_check("db", CheckStatus::Down, None).unwrap();
+        assert!(faults.is_forced("db"));
+        let result = faults.apply_forced("db", CheckResult::up());
+        assert_eq!(result.status, CheckStatus::Down);
+        assert!(faults.clear_check("db"));
+        assert_eq!(
+            faults.apply_forced("db", CheckResult::up()).status,
+            CheckStatus::Up
+        );
+    }
+
+    #[test]
+    fn rejects_unrepresentable_durations_and_rates() {
+        let faults = Faults::default();
+        let forever = Duration::from_secs(u64::MAX);
+        let error = faults
+            .force_check("db", CheckStatus::Down, Some(forever))
+            .unwrap_err();
+        assert!(error.ends_with("is too long"), "{error}");
+        assert!(!faults.is_forced("db"));
+        assert!(faults.fail_readiness_for(forever).is_err());
+        assert!(!faults.readiness_failing());
+        assert!(faults.set_failure_rate(f64::NAN).is_err());
+        faults.set_failure_rate(2.0).unwrap();
+        assert_eq!(faults.summary().probe_failure_rate, 1.0);
+    }
+}
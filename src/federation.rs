@@ -0,0 +1,295 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use tokio::time::interval;
+use tracing::{info, warn};
+
+use crate::checks::{
+    CheckEvent, CheckRegistry, CheckResult, CheckStatus, ComponentReport, unix_now,
+};
+use crate::config::{FederatedInstanceConfig, FederationConfig};
+use crate::reconcile::SourceResult;
+
+/// Checks of other healthcheck-service instances, polled through their readiness endpoint
+pub struct Federation {
+    config: FederationConfig,
+    client: reqwest::Client,
+    instances: Vec<Instance>,
+}
+
+struct Instance {
+    config: FederatedInstanceConfig,
+    state: RwLock<InstanceState>,
+}
+
+#[derive(Default)]
+struct InstanceState {
+    reachable: bool,
+    /// Unix timestamp of the last successful poll
+    last_success: Option<u64>,
+    error: Option<String>,
+    status: Option<String>,
+    checks: BTreeMap<String, ComponentReport>,
+}
+
+/// Latest state of a federated instance and its checks
+#[derive(Debug, Serialize)]
+pub struct InstanceSummary {
+    pub name: String,
+    pub url: String,
+    pub reachable: bool,
+    pub last_success: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Readiness status reported by the instance, e.g. `ok` or `not_ready`
+    pub status: Option<String>,
+    /// Checks keyed by their namespaced name `<instance>/<check>`
+    pub checks: BTreeMap<String, ComponentReport>,
+}
+
+// Body of `GET /health/ready`
+#[derive(Deserialize)]
+struct Readiness {
+    status: String,
+    #[serde(default)]
+    checks: BTreeMap<String, ComponentReport>,
+}
+
+impl Federation {
+    pub fn new(config: &FederationConfig) -> Self {
+        Self {
+            config: config.clone(),
+            client: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()
+                .unwrap(),
+            instances: config
+                .instances
+                .iter()
+                .map(|instance| Instance {
+                    config: instance.clone(),
+                    state: RwLock::new(InstanceState::default()),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn instances(&self) -> Vec<InstanceSummary> {
+        self.instances
+            .iter()
+            .map(|instance| {
+                let state = instance.state.read().unwrap();
+                InstanceSummary {
+                    name: instance.config.name.clone(),
+                    url: instance.config.url.clone(),
+                    reachable: state.reachable,
+                    last_success: state.last_success,
+                    error: state.error.clone(),
+                    status: state.status.clone(),
+                    checks: state
+                        .checks
+                        .iter()
+                        .map(|(check, report)| {
+                            (federated_name(&instance.config.name, check), report.clone())
+                        })
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Latest result of every federated check, by instance
+    pub fn results(&self) -> Vec<SourceResult> {
+        self.instances
+            .iter()
+            .flat_map(|instance| {
+                let state = instance.state.read().unwrap();
+                state
+                    .checks
+                    .iter()
+                    .map(|(check, report)| SourceResult {
+                        source: instance.config.name.clone(),
+                        check: check.clone(),
+                        result: report.result.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Poll every instance each interval, publishing their results as namespaced check events
+    pub fn spawn(self: &Arc<Self>, registry: Arc<CheckRegistry>) {
+        info!(
+            "Federating {} instances every {:?}",
+            self.instances.len(),
+            self.config.interval
+        );
+        for index in 0..self.instances.len() {
+            let federation = Arc::clone(self);
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                let mut ticker = interval(federation.config.interval);
+                loop {
+                    ticker.tick().await;
+                    federation
+                        .poll(&federation.instances[index], &registry)
+                        .await;
+                }
+            });
+        }
+    }
+
+    async fn poll(&self, instance: &Instance, registry: &CheckRegistry) {
+        let url = format!("{}/health/ready", instance.config.url.trim_end_matches('/'));
+        let mut request = self.client.get(&url);
+        if let Some(token) = &instance.config.token {
+            request = request.bearer_auth(token);
+        }
+        // Not ready instances answer 503 with the same body
+        let readiness = match request.send().await {
+            Ok(response) => response
+                .json::<Readiness>()
+                .await
+                .map_err(|e| format!("invalid readiness response: {e}")),
+            Err(e) => Err(e.to_string()),
+        };
+        let name = &instance.config.name;
+        let mut state = instance.state.write().unwrap();
+        let checks = match readiness {
+            Ok(readiness) => {
+                if !state.reachable {
+                    info!("Federated instance {} is reachable", name);
+                }
+                state.reachable = true;
+                state.last_success = Some(unix_now());
+                state.error = None;
+                state.status = Some(readiness.status);
+                readiness.checks
+            }
+            Err(e) => {
+                if state.reachable || state.error.is_none() {
+                    warn!("Federated instance {} is unreachable: {}", name, e);
+                }
+                state.reachable = false;
+                // Fail the known checks rather than keep reporting their last state
+                let message = format!("instance {name} unreachable: {e}");
+                state.error = Some(e);
+                state
+                    .checks
+                    .iter()
+                    .map(|(check, report)| {
+                        let result = CheckResult::new(CheckStatus::Down, Some(message.clone()));
+                        let report = ComponentReport {
+                            critical: report.critical,
+                            result,
+                        };
+                        (check.clone(), report)
+                    })
+                    .collect()
+            }
+        };
+        for (check, report) in &checks {
+            let previous = state.checks.get(check).map(|r| r.result.status);
+            registry.publish(CheckEvent {
+                check: federated_name(name, check),
+                previous,
+                result: report.result.clone(),
+            });
+        }
+        state.checks = checks;
+    }
+
+    /// Export instance reachability and federated check statuses as metrics
+    pub fn register_metrics(self: &Arc<Self>, meter: &Meter) {
+        let federation = Arc::clone(self);
+        meter
+            .u64_observable_gauge("federation.instance.up")
+            .with_description("Whether the last poll of a federated instance succeeded")
+            .with_callback(move |observer| {
+                for instance in &federation.instances {
+                    let reachable = instance.state.read().unwrap().reachable;
+                    let attributes = [KeyValue::new("instance", instance.config.name.clone())];
+                    observer.observe(reachable as u64, &attributes);
+                }
+            })
+            .build();
+        let federation = Arc::clone(self);
+        meter
+            .u64_observable_gauge("federation.check.status")
+            .with_description("Status of federated checks: 0 up, 1 degraded, 2 down")
+            .with_callback(move |observer| {
+                for instance in &federation.instances {
+                    let state = instance.state.read().unwrap();
+                    for (check, report) in &state.checks {
+                        let attributes = [
+                            KeyValue::new("instance", instance.config.name.clone()),
+                            KeyValue::new("check", check.clone()),
+                        ];
+                        observer.observe(report.result.status as u64, &attributes);
+                    }
+                }
+            })
+            .build();
+    }
+}
+
+/// Name under which a federated instance's check is re-exposed
+pub fn federated_name(instance: &str, check: &str) -> String {
+    format!("{instance}/{check}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, http::StatusCode, response::IntoResponse, routing::get};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[tokio::test]
+    async fn checks_are_namespaced_and_fail_when_the_instance_is_unreachable() {
+        let body = serde_json::json!({
+            "status": "not_ready",
+            "checks": { "db": { "critical": true, "status": "down", "timestamp": 1 } },
+        });
+        // Answers with a readiness report until stopped, then with something else
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopping = stopped.clone();
+        let app = Router::new().route(
+            "/health/ready",
+            get(move || async move {
+                match stopping.load(Ordering::Relaxed) {
+                    true => (StatusCode::BAD_GATEWAY, "no upstream").into_response(),
+                    false => (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response(),
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let config = format!("[[instances]]\nname = \"edge\"\nurl = \"{url}/\"");
+        let federation = Federation::new(&toml::from_str(&config).unwrap());
+        let registry = CheckRegistry::default();
+        let mut events = registry.subscribe();
+
+        federation.poll(&federation.instances[0], &registry).await;
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.check, "edge/db");
+        assert_eq!(event.result.status, CheckStatus::Down);
+        let instance = &federation.instances()[0];
+        assert!(instance.reachable);
+        assert_eq!(instance.status.as_deref(), Some("not_ready"));
+        assert!(instance.checks["edge/db"].critical);
+
+        stopped.store(true, Ordering::Relaxed);
+        federation.poll(&federation.instances[0], &registry).await;
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.previous, Some(CheckStatus::Down));
+        let message = event.result.message.unwrap();
+        assert!(
+            message.starts_with("instance edge unreachable"),
+            "{message}"
+        );
+        assert!(!federation.instances()[0].reachable);
+    }
+}
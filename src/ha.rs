@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::time::{Instant, interval};
+use tracing::{info, warn};
+
+use crate::config::HaConfig;
+
+/// Path of the heartbeat endpoint exchanged between peers
+pub const HEARTBEAT_PATH: &str = "/api/ha/heartbeat";
+
+/// Whether this instance currently holds the active role
+#[derive(Debug, Clone)]
+pub struct ActiveFlag(Arc<AtomicBool>);
+
+impl Default for ActiveFlag {
+    /// Standalone instances are always active
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+impl ActiveFlag {
+    pub fn new(active: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(active)))
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Update the role, returning whether it changed
+    pub fn set(&self, active: bool) -> bool {
+        self.0.swap(active, Ordering::Relaxed) != active
+    }
+}
+
+/// Heartbeat answered to the peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    pub node_id: String,
+    pub active: bool,
+}
+
+/// Active/standby pair coordinated through peer heartbeats
+pub struct HaPair {
+    config: HaConfig,
+    active: ActiveFlag,
+    peer: RwLock<Option<(Heartbeat, Instant)>>,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HaStatus {
+    pub node_id: String,
+    pub active: bool,
+    pub peer_id: Option<String>,
+    pub peer_alive: bool,
+    pub peer_last_seen_secs: Option<u64>,
+}
+
+impl HaPair {
+    pub fn new(config: &HaConfig) -> Self {
+        Self {
+            config: config.clone(),
+            // Start as standby until the peer has been given a chance to answer
+            active: ActiveFlag::new(false),
+            peer: RwLock::new(None),
+            client: reqwest::Client::builder()
+                .timeout(config.heartbeat_interval)
+                .build()
+                .unwrap(),
+        }
+    }
+
+    pub fn active_flag(&self) -> ActiveFlag {
+        self.active.clone()
+    }
+
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            node_id: self.config.node_id.clone(),
+            active: self.active.is_active(),
+        }
+    }
+
+    pub fn status(&self) -> HaStatus {
+        let peer = self.peer.read().unwrap().clone();
+        HaStatus {
+            node_id: self.config.node_id.clone(),
+            active: self.active.is_active(),
+            peer_id: peer.as_ref().map(|(hb, _)| hb.node_id.clone()),
+            peer_alive: self.peer_alive(),
+            peer_last_seen_secs: peer.map(|(_, seen)| seen.elapsed().as_secs()),
+        }
+    }
+
+    fn peer_alive(&self) -> bool {
+        self.peer
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|(_, seen)| seen.elapsed() < self.config.failover_after)
+    }
+
+    /// Poll the peer and decide the role: the lowest live node id is active
+    pub fn spawn(self: &Arc<Self>) {
+        let pair = Arc::clone(self);
+        let started = Instant::now();
+        tokio::spawn(async move {
+            let url = format!(
+                "{}{HEARTBEAT_PATH}",
+                pair.config.peer_url.trim_end_matches('/')
+            );
+            let mut ticker = interval(pair.config.heartbeat_interval);
+            loop {
+                ticker.tick().await;
+                match pair.poll(&url).await {
+                    Ok(heartbeat) if heartbeat.node_id == pair.config.node_id => {
+                        warn!(
+                            "HA peer reports our own node id {}, ignoring",
+                            heartbeat.node_id
+                        )
+                    }
+                    Ok(heartbeat) => {
+                        *pair.peer.write().unwrap() = Some((heartbeat, Instant::now()))
+                    }
+                    Err(e) => warn!("HA peer heartbeat failed: {}", e),
+                }
+                let status = pair.status();
+                let active = match status.peer_id.filter(|_| status.peer_alive) {
+                    Some(peer_id) => pair.config.node_id < peer_id,
+                    None => started.elapsed() >= pair.config.failover_after,
+                };
+                if pair.active.set(active) {
+                    info!(
+                        "HA node {} is now {}",
+                        pair.config.node_id,
+                        if active { "active" } else { "standby" }
+                    );
+                }
+            }
+        });
+    }
+
+    async fn poll(&self, url: &str) -> Result<Heartbeat, reqwest::Error> {
+        self.client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+}
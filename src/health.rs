@@ -0,0 +1,342 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tokio::time::{Instant, interval};
+use tracing::{info, warn};
+
+use crate::checks::{CheckRegistry, CheckReport, CheckStatus, Selector};
+use crate::config::HealthConfig;
+use crate::faults::Faults;
+
+/// Watchdog task name of the health tracker
+const TRACKER_TASK: &str = "health";
+
+/// Overall health of the service, derived from the results of its checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthState {
+    /// Some checks have not completed their first execution yet
+    Starting,
+    Ready,
+    /// Ready, though a non-critical check is failing or a critical one is down without having
+    /// reached the failure threshold
+    Degraded,
+    /// A critical check is failing
+    Unhealthy,
+}
+
+impl HealthState {
+    pub const ALL: [HealthState; 4] = [
+        HealthState::Starting,
+        HealthState::Ready,
+        HealthState::Degraded,
+        HealthState::Unhealthy,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthState::Starting => "starting",
+            HealthState::Ready => "ready",
+            HealthState::Degraded => "degraded",
+            HealthState::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+impl fmt::Display for HealthState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// State of an aggregated report taken as is, without thresholds
+impl From<CheckStatus> for HealthState {
+    fn from(status: CheckStatus) -> Self {
+        match status {
+            CheckStatus::Up => HealthState::Ready,
+            CheckStatus::Degraded => HealthState::Degraded,
+            CheckStatus::Down => HealthState::Unhealthy,
+        }
+    }
+}
+
+/// Consecutive results of a check
+#[derive(Debug, Default)]
+struct Streak {
+    failures: u32,
+    successes: u32,
+    /// Reached the failure threshold and not the success threshold since
+    failing: bool,
+}
+
+/// Moves the service between starting, ready, degraded and unhealthy, counting consecutive
+/// results of each check so that a single failed probe need not fail readiness
+pub struct HealthModel {
+    failure_threshold: u32,
+    success_threshold: u32,
+    inner: Mutex<Inner>,
+    states: watch::Sender<HealthState>,
+}
+
+struct Inner {
+    state: HealthState,
+    streaks: HashMap<String, Streak>,
+}
+
+impl HealthModel {
+    pub fn new(config: &HealthConfig) -> Self {
+        Self {
+            failure_threshold: config.failure_threshold,
+            success_threshold: config.success_threshold,
+            inner: Mutex::new(Inner {
+                state: HealthState::Starting,
+                streaks: HashMap::new(),
+            }),
+            states: watch::Sender::new(HealthState::Starting),
+        }
+    }
+
+    pub fn state(&self) -> HealthState {
+        self.inner.lock().unwrap().state
+    }
+
+    /// Follow changes of the state
+    pub fn subscribe(&self) -> watch::Receiver<HealthState> {
+        self.states.subscribe()
+    }
+
+    /// Count a result towards the streak of its check
+    pub fn record(&self, check: &str, status: CheckStatus) {
+        let mut inner = self.inner.lock().unwrap();
+        let streak = inner.streaks.entry(check.to_string()).or_default();
+        if status == CheckStatus::Down {
+            streak.failures = streak.failures.saturating_add(1);
+            streak.successes = 0;
+            streak.failing |= streak.failures >= self.failure_threshold;
+        } else {
+            streak.successes = streak.successes.saturating_add(1);
+            streak.failures = 0;
+            streak.failing &= streak.successes < self.success_threshold;
+        }
+    }
+
+    /// Derive the state from a report over all checks, returning the previous state when it
+    /// changed. The service starts once every check has completed an execution, and does not
+    /// go back to starting. Statuses forced by fault injection apply without thresholds
+    pub fn evaluate(
+        &self,
+        report: &CheckReport,
+        started: bool,
+        faults: &Faults,
+    ) -> Option<HealthState> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .streaks
+            .retain(|name, _| report.checks.contains_key(name));
+        let state = if inner.state == HealthState::Starting && !started {
+            HealthState::Starting
+        } else {
+            let mut state = HealthState::Ready;
+            for (name, component) in &report.checks {
+                let status = component.result.status;
+                let failing = match inner.streaks.get(name) {
+                    Some(streak) if !faults.is_forced(name) => streak.failing,
+                    _ => status == CheckStatus::Down,
+                };
+                let component_state = match (failing, component.critical) {
+                    (true, true) => HealthState::Unhealthy,
+                    (true, false) => HealthState::Degraded,
+                    (false, _) if status != CheckStatus::Up => HealthState::Degraded,
+                    (false, _) => HealthState::Ready,
+                };
+                state = state.max(component_state);
+            }
+            state
+        };
+        let previous = std::mem::replace(&mut inner.state, state);
+        if previous == state {
+            return None;
+        }
+        self.states.send_replace(state);
+        Some(previous)
+    }
+}
+
+/// Background tasks expected to beat regularly; liveness fails once one stops, the process
+/// being wedged rather than merely failing its checks
+pub struct Watchdog {
+    timeout: Duration,
+    beats: Mutex<HashMap<&'static str, Instant>>,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            beats: Mutex::default(),
+        }
+    }
+
+    pub fn beat(&self, task: &'static str) {
+        self.beats.lock().unwrap().insert(task, Instant::now());
+    }
+
+    /// Tasks that have not beaten within the timeout
+    pub fn missed(&self) -> Vec<&'static str> {
+        let beats = self.beats.lock().unwrap();
+        let mut missed: Vec<_> = beats
+            .iter()
+            .filter(|(_, beat)| beat.elapsed() > self.timeout)
+            .map(|(task, _)| *task)
+            .collect();
+        missed.sort_unstable();
+        missed
+    }
+}
+
+/// Keep the health state up to date from check results, counted as they arrive and evaluated
+/// every second so that a burst of results builds a single report, and beating the watchdog
+/// while doing so. Followers are not evaluated, their checks being probed by the leader
+pub fn spawn(model: Arc<HealthModel>, checks: Arc<CheckRegistry>, watchdog: Arc<Watchdog>) {
+    let mut events = checks.subscribe();
+    watchdog.beat(TRACKER_TASK);
+    tokio::spawn(async move {
+        let mut ticks = interval(Duration::from_secs(1));
+        loop {
+            tokio::select! {
+                event = events.recv() => {
+                    match event {
+                        Ok(event) => model.record(&event.check, event.result.status),
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Health tracker lagging, {} results not counted", skipped)
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                    continue;
+                }
+                _ = ticks.tick() => watchdog.beat(TRACKER_TASK),
+            }
+            if !checks.is_active() {
+                continue;
+            }
+            let report = checks.report(&Selector::default());
+            if let Some(previous) = model.evaluate(&report, checks.has_started(), checks.faults()) {
+                info!("Health changed from {} to {}", previous, model.state());
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::{CheckResult, ComponentReport};
+    use std::collections::BTreeMap;
+
+    fn model(failure_threshold: u32, success_threshold: u32) -> HealthModel {
+        HealthModel::new(&HealthConfig {
+            failure_threshold,
+            success_threshold,
+            ..HealthConfig::default()
+        })
+    }
+
+    fn report(checks: &[(&str, bool, CheckStatus)]) -> CheckReport {
+        let checks: BTreeMap<_, _> = checks
+            .iter()
+            .map(|(name, critical, status)| {
+                let result = CheckResult::new(*status, None);
+                let component = ComponentReport {
+                    critical: *critical,
+                    result,
+                };
+                (name.to_string(), component)
+            })
+            .collect();
+        let status = CheckStatus::Up;
+        CheckReport { status, checks }
+    }
+
+    #[test]
+    fn starts_once_every_check_ran() {
+        let model = model(1, 1);
+        let faults = Faults::default();
+        let up = report(&[("db", true, CheckStatus::Up)]);
+        assert_eq!(model.evaluate(&up, false, &faults), None);
+        assert_eq!(model.state(), HealthState::Starting);
+        let mut states = model.subscribe();
+        model.record("db", CheckStatus::Up);
+        assert_eq!(
+            model.evaluate(&up, true, &faults),
+            Some(HealthState::Starting)
+        );
+        assert_eq!(model.state(), HealthState::Ready);
+        assert_eq!(*states.borrow_and_update(), HealthState::Ready);
+        // A check added later does not make the service start again
+        model.evaluate(&up, false, &faults);
+        assert_eq!(model.state(), HealthState::Ready);
+    }
+
+    #[test]
+    fn thresholds_delay_failing_and_recovering() {
+        let model = model(3, 2);
+        let faults = Faults::default();
+        let down = report(&[("db", true, CheckStatus::Down)]);
+        let up = report(&[("db", true, CheckStatus::Up)]);
+        let mut states = Vec::new();
+        for (status, report) in [
+            (CheckStatus::Down, &down),
+            (CheckStatus::Down, &down),
+            (CheckStatus::Down, &down),
+            (CheckStatus::Up, &up),
+            (CheckStatus::Up, &up),
+        ] {
+            model.record("db", status);
+            model.evaluate(report, true, &faults);
+            states.push(model.state());
+        }
+        assert_eq!(
+            states,
+            [
+                HealthState::Degraded,
+                HealthState::Degraded,
+                HealthState::Unhealthy,
+                HealthState::Unhealthy,
+                HealthState::Ready,
+            ]
+        );
+    }
+
+    #[test]
+    fn non_critical_and_forced_checks() {
+        let model = model(1, 1);
+        let faults = Faults::default();
+        model.record("cache", CheckStatus::Down);
+        model.record("db", CheckStatus::Up);
+        let report = report(&[
+            ("cache", false, CheckStatus::Down),
+            ("db", true, CheckStatus::Down),
+        ]);
+        // db is down by fault injection only, which applies without a streak
+        model.evaluate(&report, true, &faults);
+        assert_eq!(model.state(), HealthState::Degraded);
+        faults.force_check("db", CheckStatus::Down, None).unwrap();
+        model.evaluate(&report, true, &faults);
+        assert_eq!(model.state(), HealthState::Unhealthy);
+    }
+
+    #[test]
+    fn watchdog_reports_missed_beats() {
+        let watchdog = Watchdog::new(Duration::ZERO);
+        watchdog.beat("health");
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(watchdog.missed(), ["health"]);
+        let watchdog = Watchdog::new(Duration::from_secs(30));
+        watchdog.beat("health");
+        assert!(watchdog.missed().is_empty());
+    }
+}
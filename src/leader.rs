@@ -0,0 +1,247 @@
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime};
+use tokio::time::{Instant, interval};
+use tracing::{debug, info, warn};
+
+use crate::config::{ConfigError, LeaderElectionConfig};
+use crate::ha::ActiveFlag;
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+/// File of the pod's service account, e.g. its namespace or token
+pub(crate) fn read_service_account(file: &str) -> Result<Vec<u8>, ConfigError> {
+    let path = format!("{SERVICE_ACCOUNT_DIR}/{file}");
+    std::fs::read(&path).map_err(|e| ConfigError(format!("failed to read {path}: {e}")))
+}
+
+/// Namespace of the pod, unless configured
+pub(crate) fn namespace(configured: &Option<String>) -> Result<String, ConfigError> {
+    match configured {
+        Some(namespace) => Ok(namespace.clone()),
+        None => Ok(String::from_utf8_lossy(&read_service_account("namespace")?)
+            .trim()
+            .to_string()),
+    }
+}
+
+/// Client for the Kubernetes API server, trusting the service account CA when present
+pub(crate) fn api_client(timeout: Duration) -> Result<reqwest::Client, ConfigError> {
+    let mut client = reqwest::Client::builder().timeout(timeout);
+    if let Ok(ca) = read_service_account("ca.crt") {
+        let ca = reqwest::Certificate::from_pem(&ca)
+            .map_err(|e| ConfigError(format!("invalid service account CA: {e}")))?;
+        client = client.add_root_certificate(ca);
+    }
+    Ok(client.build().unwrap())
+}
+
+/// Send a request to the API server, authenticated as the pod's service account
+pub(crate) async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, String> {
+    // Projected service account tokens are rotated, so read it for every request
+    let request = match read_service_account("token") {
+        Ok(token) => request.bearer_auth(String::from_utf8_lossy(&token).trim()),
+        Err(_) => request,
+    };
+    request.send().await.map_err(|e| e.to_string())
+}
+
+/// Leader election through a Kubernetes `coordination.k8s.io/v1` Lease
+pub struct LeaderElector {
+    config: LeaderElectionConfig,
+    identity: String,
+    url: String,
+    client: reqwest::Client,
+    active: ActiveFlag,
+    state: RwLock<ElectionState>,
+}
+
+#[derive(Default)]
+struct ElectionState {
+    holder: Option<String>,
+    /// Last successful acquisition or renewal while leading
+    renewed: Option<Instant>,
+    transitions: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderStatus {
+    pub identity: String,
+    pub lease: String,
+    pub leader: bool,
+    /// Current holder of the lease as last observed
+    pub holder: Option<String>,
+    /// Times this instance became leader
+    pub transitions: u64,
+}
+
+enum Attempt {
+    Acquired,
+    HeldBy(Option<String>),
+}
+
+impl LeaderElector {
+    /// Build an elector authenticating with the pod's service account
+    pub fn from_config(config: &LeaderElectionConfig) -> Result<Self, ConfigError> {
+        let namespace = namespace(&config.namespace)?;
+        let identity = config
+            .identity
+            .clone()
+            .or_else(|| std::env::var("POD_NAME").ok())
+            .or_else(sysinfo::System::host_name)
+            .unwrap_or_else(|| "unknown".to_string());
+        Ok(Self {
+            url: format!(
+                "{}/apis/coordination.k8s.io/v1/namespaces/{namespace}/leases",
+                config.api_server.trim_end_matches('/')
+            ),
+            config: config.clone(),
+            identity,
+            client: api_client(config.retry_period)?,
+            // Follow until the lease has been acquired
+            active: ActiveFlag::new(false),
+            state: RwLock::new(ElectionState::default()),
+        })
+    }
+
+    pub fn active_flag(&self) -> ActiveFlag {
+        self.active.clone()
+    }
+
+    pub fn status(&self) -> LeaderStatus {
+        let state = self.state.read().unwrap();
+        LeaderStatus {
+            identity: self.identity.clone(),
+            lease: self.config.lease_name.clone(),
+            leader: self.active.is_active(),
+            holder: state.holder.clone(),
+            transitions: state.transitions,
+        }
+    }
+
+    /// Try to acquire or renew the lease every retry period
+    pub fn spawn(self: &Arc<Self>) {
+        let elector = Arc::clone(self);
+        info!(
+            "Leader election for lease {} as {}",
+            elector.config.lease_name, elector.identity
+        );
+        tokio::spawn(async move {
+            let mut ticker = interval(elector.config.retry_period);
+            loop {
+                ticker.tick().await;
+                let leading = match elector.try_acquire().await {
+                    Ok(Attempt::Acquired) => {
+                        let mut state = elector.state.write().unwrap();
+                        state.holder = Some(elector.identity.clone());
+                        state.renewed = Some(Instant::now());
+                        true
+                    }
+                    Ok(Attempt::HeldBy(holder)) => {
+                        elector.state.write().unwrap().holder = holder;
+                        false
+                    }
+                    Err(e) => {
+                        warn!("Lease {} update failed: {}", elector.config.lease_name, e);
+                        // Keep leading until the renew deadline has passed
+                        elector
+                            .state
+                            .read()
+                            .unwrap()
+                            .renewed
+                            .is_some_and(|at| at.elapsed() < elector.config.renew_deadline)
+                    }
+                };
+                if elector.active.set(leading) {
+                    let mut state = elector.state.write().unwrap();
+                    if leading {
+                        state.transitions += 1;
+                    } else {
+                        state.renewed = None;
+                    }
+                    info!(
+                        "{} is now {} for lease {}",
+                        elector.identity,
+                        if leading { "leader" } else { "follower" },
+                        elector.config.lease_name
+                    );
+                }
+            }
+        });
+    }
+
+    async fn try_acquire(&self) -> Result<Attempt, String> {
+        let url = format!("{}/{}", self.url, self.config.lease_name);
+        let response = send(self.client.get(&url)).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            let lease = json!({
+                "apiVersion": "coordination.k8s.io/v1",
+                "kind": "Lease",
+                "metadata": { "name": self.config.lease_name },
+                "spec": self.spec(None, 0),
+            });
+            let response = send(self.client.post(&self.url).json(&lease)).await?;
+            return Self::outcome(response, None);
+        }
+        let mut lease: Value = response
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let spec = &lease["spec"];
+        let holder = spec["holderIdentity"].as_str().map(str::to_string);
+        let transitions = spec["leaseTransitions"].as_u64().unwrap_or(0);
+        let ours = holder.as_deref() == Some(self.identity.as_str());
+        if !ours && !Self::expired(spec) {
+            return Ok(Attempt::HeldBy(holder));
+        }
+        debug!(
+            "{} lease {} (previous holder {:?})",
+            if ours { "Renewing" } else { "Taking over" },
+            self.config.lease_name,
+            holder
+        );
+        let acquire_time = ours.then(|| spec["acquireTime"].clone());
+        let transitions = if ours { transitions } else { transitions + 1 };
+        // The resourceVersion in metadata makes the update fail with 409 if another replica won
+        lease["spec"] = self.spec(acquire_time, transitions);
+        let response = send(self.client.put(&url).json(&lease)).await?;
+        Self::outcome(response, holder)
+    }
+
+    fn spec(&self, acquire_time: Option<Value>, transitions: u64) -> Value {
+        let now = humantime::format_rfc3339_micros(SystemTime::now()).to_string();
+        json!({
+            "holderIdentity": self.identity,
+            "leaseDurationSeconds": self.config.lease_duration.as_secs().max(1),
+            "acquireTime": acquire_time.unwrap_or_else(|| now.clone().into()),
+            "renewTime": now,
+            "leaseTransitions": transitions,
+        })
+    }
+
+    /// Whether the holder has let the lease lapse
+    fn expired(spec: &Value) -> bool {
+        let Some(renewed) = spec["renewTime"]
+            .as_str()
+            .and_then(|t| humantime::parse_rfc3339_weak(t).ok())
+        else {
+            return true;
+        };
+        let duration = Duration::from_secs(spec["leaseDurationSeconds"].as_u64().unwrap_or(0));
+        renewed + duration < SystemTime::now()
+    }
+
+    fn outcome(response: reqwest::Response, holder: Option<String>) -> Result<Attempt, String> {
+        match response.status() {
+            status if status.is_success() => Ok(Attempt::Acquired),
+            // Another replica created or updated the lease first
+            reqwest::StatusCode::CONFLICT => Ok(Attempt::HeldBy(holder)),
+            status => Err(format!("API server returned {status}")),
+        }
+    }
+}
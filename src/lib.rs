@@ -0,0 +1,28 @@
+pub mod agent;
+pub mod aggregator;
+pub mod auth;
+pub mod build_info;
+pub mod checks;
+pub mod client;
+pub mod cluster;
+pub mod conditions;
+pub mod config;
+pub mod diagnostics;
+pub mod enrollment;
+pub mod exporter;
+pub mod faults;
+pub mod federation;
+pub mod ha;
+pub mod health;
+pub mod leader;
+pub mod lifecycle;
+pub mod notifier;
+pub mod operator;
+pub mod plugin;
+pub mod readiness;
+pub mod reconcile;
+pub mod resource;
+pub mod server;
+pub mod silence;
+pub mod tls;
+pub mod webhooks;
@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+/// Whether the service takes traffic: draining makes readiness fail while everything else
+/// keeps running, to take the instance out of rotation before it stops or for maintenance
+#[derive(Debug, Default)]
+pub struct ServiceState {
+    draining: AtomicBool,
+}
+
+impl ServiceState {
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// Start or stop draining, returning whether that changed anything
+    pub fn set_draining(&self, draining: bool) -> bool {
+        self.draining.swap(draining, Ordering::Relaxed) != draining
+    }
+}
+
+/// Completes on SIGTERM or SIGINT
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = terminate.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            },
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// SIGHUP, the conventional request to reload certificates and other files
+pub struct Hangups {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangups {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(unix)]
+            signal: {
+                use tokio::signal::unix::{SignalKind, signal};
+                signal(SignalKind::hangup())
+                    .inspect_err(|e| warn!("Failed to listen for SIGHUP: {}", e))
+                    .ok()
+            },
+        }
+    }
+
+    /// Completes on the next SIGHUP; never where it cannot be received
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending().await
+    }
+}
+
+impl Default for Hangups {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Completes when the listener should stop accepting connections: after a signal, readiness
+/// fails for `grace_period` first so that load balancers stop routing here. Another signal
+/// cuts the grace period short
+pub async fn drain_on_signal(state: &ServiceState, grace_period: Duration) {
+    signal().await;
+    state.set_draining(true);
+    info!("Shutting down, draining for {:?}", grace_period);
+    tokio::select! {
+        _ = sleep(grace_period) => {}
+        _ = signal() => warn!("Signal received again, skipping the rest of the grace period"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draining_reports_changes() {
+        let state = ServiceState::default();
+        assert!(!state.is_draining());
+        assert!(state.set_draining(true));
+        assert!(!state.set_draining(true));
+        assert!(state.is_draining());
+        assert!(state.set_draining(false));
+    }
+}
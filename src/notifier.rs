@@ -0,0 +1,961 @@
+use async_trait::async_trait;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Instant, interval, sleep, timeout};
+use tracing::{debug, info, warn};
+
+use crate::checks::{CheckEvent, CheckStatus, unix_now};
+use crate::config::{ChannelKind, NotificationsConfig};
+use crate::ha::ActiveFlag;
+use crate::health::HealthState;
+use crate::silence::Silences;
+
+/// Timeout of a single delivery attempt, so a channel that stops answering only delays its
+/// own queue
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a notification reports a transition of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationKind {
+    Check,
+    /// The health state of the service, e.g. ready to unhealthy
+    Health,
+}
+
+/// Payload delivered to notification channels
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub service: &'static str,
+    pub kind: NotificationKind,
+    /// Name of the check, or `health` for health transitions
+    pub check: String,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_status: Option<CheckStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub timestamp: u64,
+    /// Set on alerts sent through the test endpoint
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub test: bool,
+}
+
+impl Notification {
+    pub fn from_event(event: &CheckEvent) -> Self {
+        Self {
+            service: "healthcheck-service",
+            kind: NotificationKind::Check,
+            check: event.check.clone(),
+            status: event.result.status,
+            previous_status: event.previous,
+            message: event.result.message.clone(),
+            timestamp: event.result.timestamp,
+            test: false,
+        }
+    }
+
+    pub fn test() -> Self {
+        Self {
+            service: "healthcheck-service",
+            kind: NotificationKind::Check,
+            check: "notification-test".to_string(),
+            status: CheckStatus::Down,
+            previous_status: None,
+            message: Some("Test alert from healthcheck-service".to_string()),
+            timestamp: unix_now(),
+            test: true,
+        }
+    }
+
+    /// Health transition, with ready, degraded and unhealthy reported as up, degraded and down
+    pub fn health(previous: HealthState, state: HealthState) -> Self {
+        let status = |state| match state {
+            HealthState::Starting => None,
+            HealthState::Ready => Some(CheckStatus::Up),
+            HealthState::Degraded => Some(CheckStatus::Degraded),
+            HealthState::Unhealthy => Some(CheckStatus::Down),
+        };
+        Self {
+            service: "healthcheck-service",
+            kind: NotificationKind::Health,
+            check: "health".to_string(),
+            status: status(state).unwrap_or(CheckStatus::Down),
+            previous_status: status(previous),
+            message: Some(format!("Health changed from {previous} to {state}")),
+            timestamp: unix_now(),
+            test: false,
+        }
+    }
+
+    /// One-line text rendering, for chat channels
+    pub fn summary(&self) -> String {
+        let icon = match self.status {
+            CheckStatus::Up => ":large_green_circle:",
+            CheckStatus::Degraded => ":large_yellow_circle:",
+            CheckStatus::Down => ":red_circle:",
+        };
+        let mut summary = match self.kind {
+            NotificationKind::Check => {
+                format!("{icon} *{}* is {}", self.check, self.status.as_str())
+            }
+            NotificationKind::Health => {
+                format!("{icon} *{}* is {}", self.service, self.status.as_str())
+            }
+        };
+        if let Some(previous) = self.previous_status {
+            summary.push_str(&format!(" (was {})", previous.as_str()));
+        }
+        if let Some(message) = &self.message {
+            summary.push_str(&format!(": {message}"));
+        }
+        if self.test {
+            summary.insert_str(0, "[test] ");
+        }
+        summary
+    }
+}
+
+/// How a notification was handled by a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Delivery {
+    Sent,
+    DryRun,
+}
+
+/// Notification delivery error
+#[derive(Debug)]
+pub struct NotifyError(pub String);
+
+impl std::fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+/// A notification channel, e.g. a webhook or a custom integration registered by an embedder
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError>;
+}
+
+/// Posts notifications as JSON to a URL
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String, client: reqwest::Client) -> Self {
+        Self { url, client }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(notification)
+            .send()
+            .await
+            .map_err(|e| NotifyError(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(NotifyError(format!(
+                "webhook returned {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Posts a notification summary to a Slack incoming webhook
+pub struct SlackNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl SlackNotifier {
+    pub fn new(url: String, client: reqwest::Client) -> Self {
+        Self { url, client }
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let payload = serde_json::json!({ "text": notification.summary() });
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| NotifyError(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(NotifyError(format!("slack returned {}", response.status())));
+        }
+        Ok(())
+    }
+}
+
+struct Channel {
+    name: String,
+    notifier: Box<dyn Notifier>,
+    dry_run: bool,
+    /// Deliveries attempted, retries included, and those that failed
+    attempts: AtomicU64,
+    failures: AtomicU64,
+    /// Notifications waiting for delivery, in order
+    queue: Mutex<VecDeque<Notification>>,
+    /// Notifications dropped from a full queue
+    dropped: AtomicU64,
+}
+
+impl Channel {
+    fn new(name: String, notifier: Box<dyn Notifier>, dry_run: bool) -> Self {
+        Self {
+            name,
+            notifier,
+            dry_run,
+            attempts: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            queue: Mutex::default(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Last notification of a check within the dedupe window
+struct Window {
+    opened: Instant,
+    notified: CheckStatus,
+    /// Latest transition since, sent when the window ends
+    held: Option<Notification>,
+}
+
+/// Sends check and health state transitions to the configured channels
+pub struct Dispatcher {
+    channels: Vec<Channel>,
+    dry_run: bool,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    dedupe_window: Duration,
+    max_queued: usize,
+    windows: Mutex<HashMap<(NotificationKind, String), Window>>,
+    /// Transitions never sent because a later one within the window superseded them
+    deduplicated: AtomicU64,
+    active: ActiveFlag,
+    silences: Silences,
+    /// Checks whose alerting is disabled, e.g. by their HealthCheck resource
+    muted: RwLock<BTreeSet<String>>,
+    /// Wake the delivery task of each channel; started with the first notification queued
+    queues: OnceLock<Vec<mpsc::Sender<()>>>,
+}
+
+impl Dispatcher {
+    pub fn from_config(config: &NotificationsConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .unwrap();
+        let channels = config
+            .channels
+            .iter()
+            .map(|c| {
+                let notifier: Box<dyn Notifier> = match &c.kind {
+                    ChannelKind::Webhook { url } => {
+                        Box::new(WebhookNotifier::new(url.clone(), client.clone()))
+                    }
+                    ChannelKind::Slack { url } => {
+                        Box::new(SlackNotifier::new(url.clone(), client.clone()))
+                    }
+                };
+                Channel::new(c.name.clone(), notifier, c.dry_run)
+            })
+            .collect();
+        Self {
+            channels,
+            dry_run: config.dry_run,
+            max_attempts: config.max_attempts,
+            initial_backoff: config.initial_backoff,
+            max_backoff: config.max_backoff,
+            dedupe_window: config.dedupe_window,
+            max_queued: config.max_queued,
+            windows: Mutex::default(),
+            deduplicated: AtomicU64::new(0),
+            active: ActiveFlag::default(),
+            silences: Silences::default(),
+            muted: RwLock::default(),
+            queues: OnceLock::new(),
+        }
+    }
+
+    /// Register a custom channel; it replaces a configured channel of the same name, keeping
+    /// its dry-run setting. The global dry-run switch applies to custom channels too
+    pub fn with_channel(mut self, name: &str, notifier: impl Notifier + 'static) -> Self {
+        let dry_run = self.channels.iter().any(|c| c.name == name && c.dry_run);
+        self.channels.retain(|c| c.name != name);
+        self.channels
+            .push(Channel::new(name.to_string(), Box::new(notifier), dry_run));
+        self
+    }
+
+    /// Only deliver transitions while this instance holds the active role
+    pub fn with_active_flag(mut self, active: ActiveFlag) -> Self {
+        self.active = active;
+        self
+    }
+
+    /// Silences muting transitions of matching checks
+    pub fn silences(&self) -> &Silences {
+        &self.silences
+    }
+
+    /// Disable or re-enable notifications of a check
+    pub fn mute(&self, check: &str, muted: bool) {
+        let mut checks = self.muted.write().unwrap();
+        match muted {
+            true => checks.insert(check.to_string()),
+            false => checks.remove(check),
+        };
+    }
+
+    /// Forward check events to every channel until the registry goes away, sending held
+    /// transitions as their dedupe windows end
+    pub fn spawn(self: &Arc<Self>, mut events: broadcast::Receiver<CheckEvent>) {
+        let notifier = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticks = interval(Duration::from_secs(1));
+            loop {
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(event) if event.is_transition() => {
+                            notifier.dispatch(Notification::from_event(&event))
+                        }
+                        Ok(_) => {}
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Notifier lagged behind, {} events dropped", skipped)
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = ticks.tick() => {
+                        for notification in notifier.release() {
+                            notifier.enqueue(notification);
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Notify transitions of the health state, except the end of startup into ready or
+    /// degraded
+    pub fn spawn_health(self: &Arc<Self>, mut states: watch::Receiver<HealthState>) {
+        let notifier = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut previous = *states.borrow_and_update();
+            while states.changed().await.is_ok() {
+                let state = *states.borrow_and_update();
+                if previous != HealthState::Starting || state == HealthState::Unhealthy {
+                    notifier.dispatch(Notification::health(previous, state));
+                }
+                previous = state;
+            }
+        });
+    }
+
+    /// Deliver to every channel and wait until each delivery succeeded or gave up
+    pub async fn notify_all(&self, notification: &Notification) {
+        if self.suppressed(notification) || !self.admit(notification) {
+            return;
+        }
+        self.send_all(notification).await;
+    }
+
+    // Like `notify_all`, but only queue the deliveries, so the event loops never wait on a
+    // channel
+    fn dispatch(self: &Arc<Self>, notification: Notification) {
+        if self.suppressed(&notification) || !self.admit(&notification) {
+            return;
+        }
+        self.enqueue(notification);
+    }
+
+    fn enqueue(self: &Arc<Self>, notification: Notification) {
+        let queues = self.queues.get_or_init(|| {
+            (0..self.channels.len())
+                .map(|index| self.spawn_queue(index))
+                .collect()
+        });
+        for (channel, wake) in self.channels.iter().zip(queues) {
+            let mut queue = channel.queue.lock().unwrap();
+            if queue.len() >= self.max_queued {
+                queue.pop_front();
+                channel.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Notification queue of {} is full, dropped the oldest",
+                    channel.name
+                );
+            }
+            queue.push_back(notification.clone());
+            // A pending wake-up already covers this notification
+            let _ = wake.try_send(());
+        }
+    }
+
+    // Deliver the notifications queued for a channel in order, each with its retries, so a
+    // slow channel holds up neither the others nor the event loops
+    fn spawn_queue(self: &Arc<Self>, index: usize) -> mpsc::Sender<()> {
+        let (wake, mut woken) = mpsc::channel::<()>(1);
+        let dispatcher = Arc::downgrade(self);
+        tokio::spawn(async move {
+            // Only closed once the dispatcher is gone
+            while woken.recv().await.is_some() {
+                let Some(dispatcher) = dispatcher.upgrade() else {
+                    break;
+                };
+                let channel = &dispatcher.channels[index];
+                loop {
+                    let Some(notification) = channel.queue.lock().unwrap().pop_front() else {
+                        break;
+                    };
+                    if let Err(e) = dispatcher.deliver_with_retry(channel, &notification).await {
+                        warn!("Notification to {} failed: {}", channel.name, e);
+                    }
+                }
+            }
+        });
+        wake
+    }
+
+    fn suppressed(&self, notification: &Notification) -> bool {
+        if !self.active.is_active() {
+            debug!(
+                "Standby instance, suppressing notification for {}",
+                notification.check
+            );
+            return true;
+        }
+        // Silences and muting are per check
+        if notification.test || notification.kind != NotificationKind::Check {
+            return false;
+        }
+        if self.silences.is_silenced(&notification.check) {
+            debug!(
+                "Check {} is silenced, suppressing notification",
+                notification.check
+            );
+            return true;
+        }
+        if self.muted.read().unwrap().contains(&notification.check) {
+            debug!(
+                "Alerting is disabled for check {}, suppressing notification",
+                notification.check
+            );
+            return true;
+        }
+        false
+    }
+
+    // Whether to send now: the first notification of a check within the dedupe window is,
+    // later ones are held until the window ends
+    fn admit(&self, notification: &Notification) -> bool {
+        if self.dedupe_window.is_zero() || notification.test {
+            return true;
+        }
+        let mut windows = self.windows.lock().unwrap();
+        let key = (notification.kind, notification.check.clone());
+        match windows.get_mut(&key) {
+            Some(window) if window.opened.elapsed() < self.dedupe_window => {
+                debug!(
+                    "Holding notification for {} until its dedupe window ends",
+                    notification.check
+                );
+                if window.held.replace(notification.clone()).is_some() {
+                    self.deduplicated.fetch_add(1, Ordering::Relaxed);
+                }
+                false
+            }
+            _ => {
+                let window = Window {
+                    opened: Instant::now(),
+                    notified: notification.status,
+                    held: None,
+                };
+                windows.insert(key, window);
+                true
+            }
+        }
+    }
+
+    // Take the transitions held by ended windows, unless the check went back to the status
+    // last notified
+    fn release(&self) -> Vec<Notification> {
+        let mut due = Vec::new();
+        {
+            let mut windows = self.windows.lock().unwrap();
+            windows.retain(|_, window| {
+                if window.opened.elapsed() < self.dedupe_window {
+                    return true;
+                }
+                let Some(mut held) = window.held.take() else {
+                    return false;
+                };
+                if held.status == window.notified {
+                    self.deduplicated.fetch_add(1, Ordering::Relaxed);
+                    return false;
+                }
+                held.previous_status = Some(window.notified);
+                window.opened = Instant::now();
+                window.notified = held.status;
+                due.push(held);
+                true
+            });
+        }
+        due.retain(|notification| !self.suppressed(notification));
+        due
+    }
+
+    // Deliver to every channel concurrently, retrying each on failure
+    async fn send_all(&self, notification: &Notification) {
+        let deliveries = self.channels.iter().map(|channel| async move {
+            if let Err(e) = self.deliver_with_retry(channel, notification).await {
+                warn!("Notification to {} failed: {}", channel.name, e);
+            }
+        });
+        futures_util::future::join_all(deliveries).await;
+    }
+
+    async fn deliver_with_retry(
+        &self,
+        channel: &Channel,
+        notification: &Notification,
+    ) -> Result<Delivery, NotifyError> {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match self.deliver(channel, notification).await {
+                Err(e) if attempt < self.max_attempts => {
+                    debug!(
+                        "Notification to {} failed ({}), retrying in {:?}",
+                        channel.name, e, backoff
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a notification to a single channel by name, without retrying
+    pub async fn notify(
+        &self,
+        channel: &str,
+        notification: &Notification,
+    ) -> Option<Result<Delivery, NotifyError>> {
+        let channel = self.channels.iter().find(|c| c.name == channel)?;
+        Some(self.deliver(channel, notification).await)
+    }
+
+    async fn deliver(
+        &self,
+        channel: &Channel,
+        notification: &Notification,
+    ) -> Result<Delivery, NotifyError> {
+        if self.dry_run || channel.dry_run {
+            let payload = serde_json::to_string(notification).unwrap_or_default();
+            info!("[dry-run] notification to {}: {}", channel.name, payload);
+            return Ok(Delivery::DryRun);
+        }
+        channel.attempts.fetch_add(1, Ordering::Relaxed);
+        let delivery = timeout(DELIVERY_TIMEOUT, channel.notifier.notify(notification)).await;
+        let result = delivery.unwrap_or_else(|_| {
+            Err(NotifyError(format!(
+                "no answer within {}",
+                humantime::format_duration(DELIVERY_TIMEOUT)
+            )))
+        });
+        if let Err(e) = result {
+            channel.failures.fetch_add(1, Ordering::Relaxed);
+            return Err(e);
+        }
+        Ok(Delivery::Sent)
+    }
+
+    pub fn register_metrics(self: &Arc<Self>, meter: &Meter) {
+        let notifier = Arc::clone(self);
+        meter
+            .u64_observable_counter("notification.attempts")
+            .with_description("Notification deliveries attempted, retries included, by channel")
+            .with_callback(move |observer| {
+                for channel in &notifier.channels {
+                    let attempts = channel.attempts.load(Ordering::Relaxed);
+                    observer.observe(attempts, &[KeyValue::new("channel", channel.name.clone())]);
+                }
+            })
+            .build();
+        let notifier = Arc::clone(self);
+        meter
+            .u64_observable_counter("notification.failures")
+            .with_description("Notification deliveries that failed, by channel")
+            .with_callback(move |observer| {
+                for channel in &notifier.channels {
+                    let failures = channel.failures.load(Ordering::Relaxed);
+                    observer.observe(failures, &[KeyValue::new("channel", channel.name.clone())]);
+                }
+            })
+            .build();
+        let notifier = Arc::clone(self);
+        meter
+            .u64_observable_counter("notification.dropped")
+            .with_description("Notifications dropped from a full channel queue, by channel")
+            .with_callback(move |observer| {
+                for channel in &notifier.channels {
+                    let dropped = channel.dropped.load(Ordering::Relaxed);
+                    observer.observe(dropped, &[KeyValue::new("channel", channel.name.clone())]);
+                }
+            })
+            .build();
+        let notifier = Arc::clone(self);
+        meter
+            .u64_observable_counter("notification.deduplicated")
+            .with_description("Transitions not notified because of the dedupe window")
+            .with_callback(move |observer| {
+                observer.observe(notifier.deduplicated.load(Ordering::Relaxed), &[]);
+            })
+            .build();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Default)]
+    struct Counting(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Notifier for Counting {
+        async fn notify(&self, _notification: &Notification) -> Result<(), NotifyError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    // Keeps what it was sent, failing the first `failures` deliveries
+    #[derive(Clone, Default)]
+    struct Recording {
+        sent: Arc<Mutex<Vec<Notification>>>,
+        failures: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Notifier for Recording {
+        async fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+            let failing = self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+            if failing.is_ok() {
+                return Err(NotifyError("unavailable".into()));
+            }
+            self.sent.lock().unwrap().push(notification.clone());
+            Ok(())
+        }
+    }
+
+    fn transition(check: &str, status: CheckStatus) -> Notification {
+        let mut notification = Notification::test();
+        notification.test = false;
+        notification.check = check.into();
+        notification.status = status;
+        notification
+    }
+
+    fn dispatcher(config: &str) -> Dispatcher {
+        Dispatcher::from_config(&toml::from_str(config).unwrap())
+    }
+
+    // Send the transitions held by ended windows and wait for their delivery
+    async fn flush(dispatcher: &Dispatcher) {
+        for notification in dispatcher.release() {
+            dispatcher.send_all(&notification).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_channels_are_delivered_to() {
+        let sent = Counting::default();
+        let dispatcher = dispatcher("").with_channel("pager", sent.clone());
+        let delivery = dispatcher.notify("pager", &Notification::test()).await;
+        assert!(matches!(delivery, Some(Ok(Delivery::Sent))));
+        assert_eq!(sent.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn custom_channels_respect_the_global_dry_run() {
+        let sent = Counting::default();
+        let dispatcher = dispatcher("dry_run = true").with_channel("pager", sent.clone());
+        dispatcher.notify_all(&Notification::test()).await;
+        assert_eq!(sent.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn silenced_checks_are_not_notified() {
+        let sent = Counting::default();
+        let dispatcher = dispatcher("").with_channel("pager", sent.clone());
+        let silence = serde_json::json!({ "checks": "db", "duration": "1h" });
+        let silence = serde_json::from_value(silence).unwrap();
+        dispatcher.silences().create(silence).unwrap();
+        let mut notification = Notification::test();
+        notification.test = false;
+        notification.check = "db".into();
+        dispatcher.notify_all(&notification).await;
+        assert_eq!(sent.0.load(Ordering::Relaxed), 0);
+        notification.check = "cache".into();
+        dispatcher.notify_all(&notification).await;
+        assert_eq!(sent.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn muted_checks_are_not_notified() {
+        let sent = Counting::default();
+        let dispatcher = dispatcher("").with_channel("pager", sent.clone());
+        let mut notification = Notification::test();
+        notification.test = false;
+        notification.check = "db".into();
+        dispatcher.mute("db", true);
+        dispatcher.notify_all(&notification).await;
+        assert_eq!(sent.0.load(Ordering::Relaxed), 0);
+        dispatcher.mute("db", false);
+        dispatcher.notify_all(&notification).await;
+        assert_eq!(sent.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn replaced_channels_keep_their_dry_run() {
+        let sent = Counting::default();
+        let dispatcher = dispatcher(
+            r#"
+            [[channels]]
+            name = "ops"
+            type = "webhook"
+            url = "http://127.0.0.1:9/hook"
+            dry_run = true
+            "#,
+        )
+        .with_channel("ops", sent.clone());
+        let delivery = dispatcher.notify("ops", &Notification::test()).await;
+        assert!(matches!(delivery, Some(Ok(Delivery::DryRun))));
+        assert_eq!(sent.0.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn webhooks_post_the_notification_unless_in_dry_run() {
+        let received: Arc<Mutex<Vec<serde_json::Value>>> = Arc::default();
+        let recorder = received.clone();
+        let app = axum::Router::new().route(
+            "/hook",
+            axum::routing::post(move |body: axum::Json<serde_json::Value>| async move {
+                recorder.lock().unwrap().push(body.0);
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let dispatcher = dispatcher(&format!(
+            r#"
+            [[channels]]
+            name = "live"
+            type = "webhook"
+            url = "{url}"
+
+            [[channels]]
+            name = "rehearsal"
+            type = "webhook"
+            url = "{url}"
+            dry_run = true
+            "#
+        ));
+        let notification = transition("db", CheckStatus::Down);
+        let sent = dispatcher.notify("live", &notification).await;
+        assert!(matches!(sent, Some(Ok(Delivery::Sent))));
+        let rehearsed = dispatcher.notify("rehearsal", &notification).await;
+        assert!(matches!(rehearsed, Some(Ok(Delivery::DryRun))));
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["check"], "db");
+        assert_eq!(received[0]["status"], "down");
+        assert!(received[0].get("test").is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_retried() {
+        let recording = Recording::default();
+        recording.failures.store(2, Ordering::Relaxed);
+        let dispatcher = dispatcher("initial_backoff = \"1ms\"\nmax_backoff = \"2ms\"")
+            .with_channel("pager", recording.clone());
+        dispatcher
+            .notify_all(&transition("db", CheckStatus::Down))
+            .await;
+        assert_eq!(recording.sent.lock().unwrap().len(), 1);
+        let channel = &dispatcher.channels[0];
+        assert_eq!(channel.attempts.load(Ordering::Relaxed), 3);
+        assert_eq!(channel.failures.load(Ordering::Relaxed), 2);
+
+        // Given up after max_attempts
+        recording.failures.store(5, Ordering::Relaxed);
+        dispatcher
+            .notify_all(&transition("db", CheckStatus::Up))
+            .await;
+        assert_eq!(recording.sent.lock().unwrap().len(), 1);
+        assert_eq!(channel.attempts.load(Ordering::Relaxed), 6);
+    }
+
+    #[tokio::test]
+    async fn flapping_is_deduplicated() {
+        let recording = Recording::default();
+        let dispatcher = Arc::new(
+            dispatcher("dedupe_window = \"50ms\"").with_channel("pager", recording.clone()),
+        );
+        let statuses = || {
+            let sent = recording.sent.lock().unwrap();
+            sent.iter()
+                .map(|n| (n.previous_status, n.status))
+                .collect::<Vec<_>>()
+        };
+        // Flapping back to the status notified sends nothing more
+        for status in [CheckStatus::Down, CheckStatus::Up, CheckStatus::Down] {
+            dispatcher.notify_all(&transition("db", status)).await;
+        }
+        dispatcher
+            .notify_all(&transition("cache", CheckStatus::Down))
+            .await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        flush(&dispatcher).await;
+        assert_eq!(statuses().len(), 2);
+        assert_eq!(dispatcher.deduplicated.load(Ordering::Relaxed), 2);
+
+        // The latest held transition is sent once the window ends
+        dispatcher
+            .notify_all(&transition("db", CheckStatus::Up))
+            .await;
+        dispatcher
+            .notify_all(&transition("db", CheckStatus::Degraded))
+            .await;
+        dispatcher
+            .notify_all(&transition("db", CheckStatus::Down))
+            .await;
+        flush(&dispatcher).await;
+        assert_eq!(statuses().len(), 3);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        flush(&dispatcher).await;
+        assert_eq!(
+            statuses()[3..],
+            [(Some(CheckStatus::Up), CheckStatus::Down)]
+        );
+    }
+
+    #[tokio::test]
+    async fn unresponsive_channels_only_delay_themselves() {
+        struct Hanging;
+
+        #[async_trait]
+        impl Notifier for Hanging {
+            async fn notify(&self, _notification: &Notification) -> Result<(), NotifyError> {
+                std::future::pending().await
+            }
+        }
+
+        let sent = Counting::default();
+        let dispatcher = Arc::new(
+            dispatcher("")
+                .with_channel("stuck", Hanging)
+                .with_channel("pager", sent.clone()),
+        );
+        for check in ["db", "cache"] {
+            dispatcher.dispatch(transition(check, CheckStatus::Down));
+        }
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while sent.0.load(Ordering::Relaxed) < 2 {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(dispatcher.channels[0].attempts.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn full_queues_drop_the_oldest_notifications() {
+        let recording = Recording::default();
+        let dispatcher =
+            Arc::new(dispatcher("max_queued = 2").with_channel("ops", recording.clone()));
+        for check in ["a", "b", "c", "d"] {
+            dispatcher.dispatch(transition(check, CheckStatus::Down));
+        }
+        assert_eq!(dispatcher.channels[0].dropped.load(Ordering::Relaxed), 2);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while recording.sent.lock().unwrap().len() < 2 {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let sent: Vec<_> = recording
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|n| n.check.clone())
+            .collect();
+        assert_eq!(sent, ["c", "d"]);
+    }
+
+    #[test]
+    fn summarizes_transitions() {
+        let mut notification = transition("db", CheckStatus::Down);
+        notification.previous_status = Some(CheckStatus::Up);
+        notification.message = Some("connection refused".into());
+        assert_eq!(
+            notification.summary(),
+            ":red_circle: *db* is down (was up): connection refused"
+        );
+        let health = Notification::health(HealthState::Ready, HealthState::Degraded);
+        assert_eq!(
+            health.summary(),
+            ":large_yellow_circle: *healthcheck-service* is degraded (was up): Health changed \
+             from ready to degraded"
+        );
+        assert_eq!(
+            Notification::health(HealthState::Starting, HealthState::Unhealthy).previous_status,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn mutes_do_not_apply_to_health() {
+        let sent = Counting::default();
+        let dispatcher = dispatcher("").with_channel("pager", sent.clone());
+        dispatcher.mute("health", true);
+        let notification = Notification::health(HealthState::Ready, HealthState::Unhealthy);
+        dispatcher.notify_all(&notification).await;
+        assert_eq!(sent.0.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,371 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::time::interval;
+use tracing::{info, warn};
+
+use crate::checks::CheckRegistry;
+use crate::config::{CheckConfig, CheckKind, Config, ConfigError, OperatorConfig};
+use crate::leader;
+use crate::notifier::Dispatcher;
+
+/// Timeout of requests to the API server
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reconciles the `healthcheck.houseme.io/v1alpha1` HealthCheck resources of a namespace into
+/// checks, and writes the latest result of each back into its status
+pub struct Operator {
+    config: OperatorConfig,
+    /// Configuration the declared checks are validated against
+    service: Config,
+    namespace: String,
+    url: String,
+    client: reqwest::Client,
+    resources: Mutex<BTreeMap<String, Managed>>,
+}
+
+// A resource as last reconciled
+struct Managed {
+    check: String,
+    generation: u64,
+    /// Why the spec was rejected, if it was
+    error: Option<String>,
+    /// Status last written to the resource
+    status: Option<Value>,
+}
+
+impl Operator {
+    /// Build an operator authenticating with the pod's service account
+    pub fn from_config(config: &OperatorConfig, service: &Config) -> Result<Self, ConfigError> {
+        let namespace = leader::namespace(&config.namespace)?;
+        Ok(Self {
+            url: format!(
+                "{}/apis/healthcheck.houseme.io/v1alpha1/namespaces/{namespace}/healthchecks",
+                config.api_server.trim_end_matches('/')
+            ),
+            config: config.clone(),
+            service: service.clone(),
+            namespace,
+            client: leader::api_client(REQUEST_TIMEOUT)?,
+            resources: Mutex::default(),
+        })
+    }
+
+    /// List the resources every resync interval, reconcile them and update their status
+    pub fn spawn(self: &Arc<Self>, registry: Arc<CheckRegistry>, notifier: Arc<Dispatcher>) {
+        let operator = Arc::clone(self);
+        info!(
+            "Reconciling HealthCheck resources of namespace {} every {:?}",
+            operator.namespace, operator.config.resync_interval
+        );
+        tokio::spawn(async move {
+            let mut ticker = interval(operator.config.resync_interval);
+            loop {
+                ticker.tick().await;
+                match operator.list().await {
+                    Ok(items) => operator.reconcile(&items, &registry, &notifier),
+                    // Keep probing the resources last listed
+                    Err(e) => warn!("Listing HealthCheck resources failed: {}", e),
+                }
+                // Replicas on standby would overwrite the status with stale results
+                if registry.is_active() {
+                    operator.update_status(&registry).await;
+                }
+            }
+        });
+    }
+
+    async fn list(&self) -> Result<Vec<Value>, String> {
+        let list: Value = leader::send(self.client.get(&self.url))
+            .await?
+            .error_for_status()
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())?;
+        match list["items"].as_array() {
+            Some(items) => Ok(items.clone()),
+            None => Err("response has no items".into()),
+        }
+    }
+
+    /// Add checks for new resources, replace those whose spec changed and remove those deleted
+    fn reconcile(&self, items: &[Value], registry: &Arc<CheckRegistry>, notifier: &Dispatcher) {
+        let mut resources = self.resources.lock().unwrap();
+        let mut listed = BTreeSet::new();
+        for item in items {
+            let Some(name) = item["metadata"]["name"].as_str() else {
+                continue;
+            };
+            listed.insert(name.to_string());
+            let generation = item["metadata"]["generation"].as_u64().unwrap_or(0);
+            if resources
+                .get(name)
+                .is_some_and(|m| m.generation == generation)
+            {
+                continue;
+            }
+            // A rejected spec never got a check, which may belong to the configuration
+            if let Some(previous) = resources.remove(name)
+                && previous.error.is_none()
+            {
+                registry.remove(&previous.check);
+                notifier.mute(&previous.check, false);
+            }
+            let check = format!("{}.{name}", self.namespace);
+            let added =
+                self.declared_check(&check, &item["spec"])
+                    .and_then(|(config, alerting)| {
+                        registry.add(&config)?;
+                        notifier.mute(&check, !alerting);
+                        Ok(())
+                    });
+            match &added {
+                Ok(()) => info!("HealthCheck {} reconciled into check {}", name, check),
+                Err(e) => warn!("HealthCheck {} rejected: {}", name, e),
+            }
+            let managed = Managed {
+                check,
+                generation,
+                error: added.err(),
+                status: None,
+            };
+            resources.insert(name.to_string(), managed);
+        }
+        resources.retain(|name, managed| {
+            if listed.contains(name) {
+                return true;
+            }
+            if managed.error.is_none() {
+                info!(
+                    "HealthCheck {} deleted, removing check {}",
+                    name, managed.check
+                );
+                registry.remove(&managed.check);
+                notifier.mute(&managed.check, false);
+            }
+            false
+        });
+    }
+
+    /// The check a resource spec declares, and whether its transitions are notified
+    fn declared_check(&self, check: &str, spec: &Value) -> Result<(CheckConfig, bool), String> {
+        let Value::Object(spec) = spec else {
+            return Err("spec must be an object".into());
+        };
+        let mut fields = Map::new();
+        let mut alerting = true;
+        for (key, value) in spec {
+            match key.as_str() {
+                "alerting" => alerting = value["enabled"].as_bool().unwrap_or(true),
+                _ => {
+                    fields.insert(snake_case(key), value.clone());
+                }
+            }
+        }
+        fields.insert("name".into(), check.into());
+        let config: CheckConfig = serde_json::from_value(Value::Object(fields))
+            .map_err(|e| format!("invalid spec: {e}"))?;
+        // Scripts, modules and plugins would run off the service's own filesystem
+        if !matches!(config.kind, CheckKind::Tcp { .. } | CheckKind::Http { .. }) {
+            return Err("only tcp and http checks can be declared as resources".into());
+        }
+        // Tenant checks are named, counted and rate-limited against the tenant's quotas when the
+        // configuration is loaded, which resources would bypass
+        if config.tenant.is_some() {
+            return Err("tenant checks cannot be declared as resources".into());
+        }
+        self.service.validate_check(&config).map_err(|e| e.0)?;
+        Ok((config, alerting))
+    }
+
+    /// Patch the status of resources whose result changed since it was last written
+    async fn update_status(&self, registry: &CheckRegistry) {
+        let changed: Vec<_> = self
+            .resources
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(name, managed)| {
+                let status = Self::status(managed, registry)?;
+                (managed.status.as_ref() != Some(&status)).then(|| (name.clone(), status))
+            })
+            .collect();
+        for (name, status) in changed {
+            let request = self
+                .client
+                .patch(format!("{}/{name}/status", self.url))
+                .header(CONTENT_TYPE, "application/merge-patch+json")
+                .body(json!({ "status": status }).to_string());
+            let written = leader::send(request)
+                .await
+                .and_then(|r| r.error_for_status().map_err(|e| e.to_string()));
+            match written {
+                Ok(_) => {
+                    if let Some(managed) = self.resources.lock().unwrap().get_mut(&name) {
+                        managed.status = Some(status);
+                    }
+                }
+                Err(e) => warn!("Updating the status of HealthCheck {} failed: {}", name, e),
+            }
+        }
+    }
+
+    // Status of a resource; none until its check has run, e.g. on another cluster member
+    fn status(managed: &Managed, registry: &CheckRegistry) -> Option<Value> {
+        let status = match &managed.error {
+            Some(error) => json!({ "state": "invalid", "message": error }),
+            None => {
+                let history = registry.history(&managed.check)?;
+                let result = history.last()?;
+                let checked = SystemTime::UNIX_EPOCH + Duration::from_secs(result.timestamp);
+                json!({
+                    "state": result.status.as_str(),
+                    "message": result.message,
+                    "lastChecked": humantime::format_rfc3339_seconds(checked).to_string(),
+                })
+            }
+        };
+        let mut status = status;
+        status["observedGeneration"] = managed.generation.into();
+        Some(status)
+    }
+
+    pub fn register_metrics(self: &Arc<Self>, meter: &Meter) {
+        let operator = Arc::clone(self);
+        meter
+            .u64_observable_gauge("operator.resources")
+            .with_description("HealthCheck resources by whether they were reconciled or rejected")
+            .with_callback(move |observer| {
+                let resources = operator.resources.lock().unwrap();
+                let invalid = resources.values().filter(|m| m.error.is_some()).count() as u64;
+                let reconciled = resources.len() as u64 - invalid;
+                observer.observe(reconciled, &[KeyValue::new("state", "reconciled")]);
+                observer.observe(invalid, &[KeyValue::new("state", "invalid")]);
+            })
+            .build();
+    }
+}
+
+// Field names of the resource spec are camelCase, those of the check configuration snake_case
+fn snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 2);
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            snake.push('_');
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::Selector;
+
+    fn operator(service: &str) -> Operator {
+        let config = OperatorConfig {
+            namespace: Some("shop".into()),
+            api_server: "http://127.0.0.1:9".into(),
+            resync_interval: Duration::from_secs(30),
+        };
+        Operator::from_config(&config, &toml::from_str(service).unwrap()).unwrap()
+    }
+
+    fn resource(name: &str, generation: u64, spec: Value) -> Value {
+        json!({
+            "metadata": { "name": name, "generation": generation },
+            "spec": spec,
+        })
+    }
+
+    #[test]
+    fn converts_field_names() {
+        assert_eq!(snake_case("expectedStatus"), "expected_status");
+        assert_eq!(snake_case("resolveTo"), "resolve_to");
+        assert_eq!(snake_case("url"), "url");
+    }
+
+    #[test]
+    fn specs_declare_tcp_and_http_checks() {
+        let operator = operator("");
+        let spec = json!({
+            "type": "http",
+            "url": "http://checkout.shop/health",
+            "expectedStatus": 204,
+            "interval": "15s",
+            "critical": false,
+            "alerting": { "enabled": false },
+        });
+        let (check, alerting) = operator.declared_check("shop.checkout", &spec).unwrap();
+        assert_eq!(check.name, "shop.checkout");
+        assert_eq!(check.interval, Duration::from_secs(15));
+        assert!(!check.critical && !alerting);
+        assert!(matches!(
+            check.kind,
+            CheckKind::Http {
+                expected_status: 204,
+                ..
+            }
+        ));
+
+        let spec = json!({ "type": "script", "source": "result.up()" });
+        let error = operator.declared_check("shop.x", &spec).unwrap_err();
+        assert_eq!(
+            error,
+            "only tcp and http checks can be declared as resources"
+        );
+        let spec = json!({ "type": "tcp", "address": "db:5432", "tenant": "payments" });
+        let error = operator.declared_check("shop.db", &spec).unwrap_err();
+        assert_eq!(error, "tenant checks cannot be declared as resources");
+        let spec = json!({ "type": "tcp", "address": "db:5432", "interval": "1s", "jitter": "2s" });
+        let error = operator.declared_check("shop.db", &spec).unwrap_err();
+        assert_eq!(
+            error,
+            "check shop.db: jitter must be shorter than the interval"
+        );
+        let error = operator
+            .declared_check("shop.db", &json!({ "type": "tcp" }))
+            .unwrap_err();
+        assert!(error.starts_with("invalid spec"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn reconciles_resources_into_checks() {
+        let operator = operator("");
+        let mut registry = CheckRegistry::default();
+        let interval = Duration::from_secs(10);
+        let configured = Box::new(crate::checks::UnavailableCheck("test".into()));
+        registry.register("shop.db", true, interval, interval, configured);
+        let registry = Arc::new(registry);
+        let notifier = Dispatcher::from_config(&Default::default());
+        let tcp = |address: &str| json!({ "type": "tcp", "address": address });
+
+        let items = [
+            resource("cache", 1, tcp("127.0.0.1:1")),
+            resource("db", 1, tcp("127.0.0.1:2")),
+        ];
+        operator.reconcile(&items, &registry, &notifier);
+        assert_eq!(registry.names(), ["shop.db", "shop.cache"]);
+        let error = operator.resources.lock().unwrap()["db"].error.clone();
+        assert_eq!(error.as_deref(), Some("check shop.db already exists"));
+
+        // Unchanged resources keep their check, changed ones are replaced
+        operator.reconcile(&items[..1], &registry, &notifier);
+        let items = [resource("cache", 2, tcp("127.0.0.1:3"))];
+        operator.reconcile(&items, &registry, &notifier);
+        assert_eq!(registry.names(), ["shop.db", "shop.cache"]);
+        let report = registry.run_once(&Selector::default()).await;
+        let target = report.checks["shop.cache"].result.target.as_deref();
+        assert_eq!(target, Some("127.0.0.1:3"));
+
+        operator.reconcile(&[], &registry, &notifier);
+        assert_eq!(registry.names(), ["shop.db"]);
+        assert!(operator.resources.lock().unwrap().is_empty());
+    }
+}
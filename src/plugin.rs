@@ -0,0 +1,231 @@
+//! C ABI between the service and native checker plugins.
+//!
+//! A plugin is a shared library exporting [`ENTRY_SYMBOL`], an [`EntryPoint`] that receives the
+//! highest ABI version the host supports and returns a [`PluginDescriptor`] for a version it
+//! implements, or null when it has none in common with the host. Strings crossing the boundary
+//! are NUL-terminated UTF-8; strings returned by the plugin are released with its `free_string`.
+//!
+//! Rust plugins implement [`CheckPlugin`] and export it with [`export_plugin!`], which keeps
+//! panics from unwinding into the host by reporting them as failed checks.
+
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use crate::checks::CheckStatus;
+
+/// ABI version implemented by this host and by plugins built with [`export_plugin!`]
+pub const ABI_VERSION: u32 = 1;
+
+/// Oldest plugin ABI version the host still loads
+pub const MIN_ABI_VERSION: u32 = 1;
+
+/// Name of the symbol every plugin exports
+pub const ENTRY_SYMBOL: &[u8] = b"healthcheck_plugin_entry\0";
+
+/// Status codes returned by `check`; anything else counts as down
+pub const STATUS_UP: i32 = 0;
+pub const STATUS_DEGRADED: i32 = 1;
+pub const STATUS_DOWN: i32 = 2;
+
+/// `healthcheck_plugin_entry(host_abi_version)`
+pub type EntryPoint = unsafe extern "C" fn(host_abi_version: u32) -> *const PluginDescriptor;
+
+/// Functions and metadata of a plugin, valid for as long as the library stays loaded
+#[repr(C)]
+pub struct PluginDescriptor {
+    /// ABI version the rest of the descriptor follows
+    pub abi_version: u32,
+    /// Name checks refer to the plugin by
+    pub name: *const c_char,
+    pub version: *const c_char,
+    /// Create a checker from its JSON configuration; on failure return null and optionally set
+    /// `error`
+    pub create: unsafe extern "C" fn(config: *const c_char, error: *mut *mut c_char) -> *mut c_void,
+    /// Run a check, returning a status code and optionally setting `message`. The host never
+    /// calls it concurrently for the same checker.
+    pub check: unsafe extern "C" fn(checker: *mut c_void, message: *mut *mut c_char) -> i32,
+    pub destroy: unsafe extern "C" fn(checker: *mut c_void),
+    pub free_string: unsafe extern "C" fn(string: *mut c_char),
+}
+
+// The pointers refer to static data and functions of the loaded library
+unsafe impl Sync for PluginDescriptor {}
+unsafe impl Send for PluginDescriptor {}
+
+/// Checker implemented by a Rust plugin
+pub trait CheckPlugin: Send + Sized + 'static {
+    const NAME: &'static CStr;
+    const VERSION: &'static CStr;
+
+    /// Build a checker from the `config` table of its check
+    fn new(config: serde_json::Value) -> Result<Self, String>;
+
+    fn check(&mut self) -> (CheckStatus, Option<String>);
+}
+
+/// Descriptor of a Rust plugin; use [`export_plugin!`] rather than calling this directly
+pub const fn descriptor<P: CheckPlugin>() -> PluginDescriptor {
+    PluginDescriptor {
+        abi_version: ABI_VERSION,
+        name: P::NAME.as_ptr(),
+        version: P::VERSION.as_ptr(),
+        create: create::<P>,
+        check: check::<P>,
+        destroy: destroy::<P>,
+        free_string,
+    }
+}
+
+/// Export a [`CheckPlugin`] from a `cdylib` crate
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:ty) => {
+        static DESCRIPTOR: $crate::plugin::PluginDescriptor =
+            $crate::plugin::descriptor::<$plugin>();
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn healthcheck_plugin_entry(
+            host_abi_version: u32,
+        ) -> *const $crate::plugin::PluginDescriptor {
+            match host_abi_version >= $crate::plugin::ABI_VERSION {
+                true => &DESCRIPTOR,
+                false => std::ptr::null(),
+            }
+        }
+    };
+}
+
+fn into_raw(string: String) -> *mut c_char {
+    // Interior NULs would truncate the string on the other side anyway
+    CString::new(string.replace('\0', "")).unwrap().into_raw()
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    let reason = payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown cause".into());
+    format!("plugin panicked: {reason}")
+}
+
+unsafe extern "C" fn create<P: CheckPlugin>(
+    config: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_void {
+    let config = unsafe { CStr::from_ptr(config) }.to_string_lossy();
+    let created = catch_unwind(|| {
+        let config = serde_json::from_str(&config).map_err(|e| e.to_string())?;
+        P::new(config)
+    })
+    .unwrap_or_else(|payload| Err(panic_message(payload)));
+    match created {
+        Ok(checker) => Box::into_raw(Box::new(checker)).cast(),
+        Err(e) => {
+            if !error.is_null() {
+                unsafe { *error = into_raw(e) };
+            }
+            std::ptr::null_mut()
+        }
+    }
+}
+
+unsafe extern "C" fn check<P: CheckPlugin>(checker: *mut c_void, message: *mut *mut c_char) -> i32 {
+    let checker = unsafe { &mut *checker.cast::<P>() };
+    let (status, text) = catch_unwind(AssertUnwindSafe(|| checker.check()))
+        .unwrap_or_else(|payload| (CheckStatus::Down, Some(panic_message(payload))));
+    if let Some(text) = text
+        && !message.is_null()
+    {
+        unsafe { *message = into_raw(text) };
+    }
+    match status {
+        CheckStatus::Up => STATUS_UP,
+        CheckStatus::Degraded => STATUS_DEGRADED,
+        CheckStatus::Down => STATUS_DOWN,
+    }
+}
+
+unsafe extern "C" fn destroy<P: CheckPlugin>(checker: *mut c_void) {
+    let checker = unsafe { Box::from_raw(checker.cast::<P>()) };
+    // A panicking destructor must not unwind into the host
+    let _ = catch_unwind(AssertUnwindSafe(move || drop(checker)));
+}
+
+unsafe extern "C" fn free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Degraded past its threshold, panicking when asked to
+    struct Threshold {
+        limit: u64,
+        calls: u64,
+    }
+
+    impl CheckPlugin for Threshold {
+        const NAME: &'static CStr = c"threshold";
+        const VERSION: &'static CStr = c"1.0.0";
+
+        fn new(config: serde_json::Value) -> Result<Self, String> {
+            let limit = config["limit"].as_u64().ok_or("missing limit")?;
+            Ok(Self { limit, calls: 0 })
+        }
+
+        fn check(&mut self) -> (CheckStatus, Option<String>) {
+            self.calls += 1;
+            match self.calls {
+                calls if calls > self.limit + 1 => panic!("out of range"),
+                calls if calls > self.limit => (CheckStatus::Degraded, Some("over".into())),
+                _ => (CheckStatus::Up, None),
+            }
+        }
+    }
+
+    // Call the descriptor the way the host does, returning the status code and message
+    fn call(descriptor: &PluginDescriptor, checker: *mut c_void) -> (i32, Option<String>) {
+        let mut message = std::ptr::null_mut();
+        let code = unsafe { (descriptor.check)(checker, &mut message) };
+        if message.is_null() {
+            return (code, None);
+        }
+        let text = unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { (descriptor.free_string)(message) };
+        (code, Some(text))
+    }
+
+    #[test]
+    fn rust_plugins_report_through_the_c_abi() {
+        let descriptor = descriptor::<Threshold>();
+        assert_eq!(descriptor.abi_version, ABI_VERSION);
+        assert_eq!(unsafe { CStr::from_ptr(descriptor.name) }, c"threshold");
+
+        let mut error = std::ptr::null_mut();
+        let checker = unsafe { (descriptor.create)(c"{}".as_ptr(), &mut error) };
+        assert!(checker.is_null());
+        let reason = unsafe { CString::from_raw(error) };
+        assert_eq!(reason.to_str().unwrap(), "missing limit");
+
+        let config = c"{\"limit\": 1}";
+        let checker = unsafe { (descriptor.create)(config.as_ptr(), std::ptr::null_mut()) };
+        assert!(!checker.is_null());
+        assert_eq!(call(&descriptor, checker), (STATUS_UP, None));
+        assert_eq!(
+            call(&descriptor, checker),
+            (STATUS_DEGRADED, Some("over".into()))
+        );
+        // Panics are reported as a failed check instead of unwinding into the host
+        assert_eq!(
+            call(&descriptor, checker),
+            (STATUS_DOWN, Some("plugin panicked: out of range".into()))
+        );
+        unsafe { (descriptor.destroy)(checker) };
+    }
+}
@@ -0,0 +1,192 @@
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode, header};
+use hyper_util::rt::TokioIo;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::net::UnixStream;
+
+use crate::client::{ClientError, error_message};
+use crate::conditions::{CONDITIONS_PATH, ConditionUpdate};
+
+/// Largest error body read back from the conditions API
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Reports readiness conditions of an application to a local healthcheck-service instance,
+/// over its HTTP conditions API or its conditions socket
+///
+/// ```no_run
+/// # use healthcheck_service::readiness::ReadinessHandle;
+/// # async fn run() -> Result<(), healthcheck_service::client::ClientError> {
+/// let readiness = ReadinessHandle::unix("/run/healthcheck/conditions.sock");
+/// readiness.set_condition("migrations", false).await?;
+/// // run the migrations
+/// readiness.set_condition("migrations", true).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ReadinessHandle {
+    transport: Transport,
+    ttl: Option<Duration>,
+}
+
+#[derive(Clone)]
+enum Transport {
+    Http {
+        base: String,
+        token: String,
+        client: reqwest::Client,
+    },
+    Unix(PathBuf),
+}
+
+impl ReadinessHandle {
+    /// Report over the HTTP conditions API, authenticated with `conditions.token`
+    pub fn http(base_url: impl Into<String>, token: impl Into<String>) -> Self {
+        let transport = Transport::Http {
+            base: base_url.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+            client: reqwest::Client::new(),
+        };
+        Self {
+            transport,
+            ttl: None,
+        }
+    }
+
+    /// Report over the Unix socket configured as `conditions.socket`
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self {
+            transport: Transport::Unix(path.into()),
+            ttl: None,
+        }
+    }
+
+    /// Have conditions fail unless reported again within `ttl`, so a crashed application
+    /// does not stay ready
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub async fn set_condition(&self, name: &str, ok: bool) -> Result<(), ClientError> {
+        self.report(name, ok, None).await
+    }
+
+    /// Set a condition with a message explaining it, e.g. why it is not met
+    pub async fn report(
+        &self,
+        name: &str,
+        ok: bool,
+        message: Option<String>,
+    ) -> Result<(), ClientError> {
+        let update = ConditionUpdate {
+            ok,
+            message,
+            ttl: self.ttl,
+        };
+        // Serializing plain data cannot fail
+        let body = serde_json::to_vec(&update).unwrap();
+        self.send(Method::PUT, name, body).await
+    }
+
+    /// Stop reporting a condition; it no longer affects readiness
+    pub async fn clear_condition(&self, name: &str) -> Result<(), ClientError> {
+        self.send(Method::DELETE, name, Vec::new()).await
+    }
+
+    async fn send(&self, method: Method, name: &str, body: Vec<u8>) -> Result<(), ClientError> {
+        let path = format!("{CONDITIONS_PATH}/{name}");
+        let (status, message) = match &self.transport {
+            Transport::Http {
+                base,
+                token,
+                client,
+            } => {
+                let response = client
+                    .request(method, format!("{base}{path}"))
+                    .bearer_auth(token)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(body)
+                    .send()
+                    .await?;
+                let status = response.status();
+                (status, response.text().await.unwrap_or_default())
+            }
+            Transport::Unix(socket) => unix_request(socket, method, &path, body).await?,
+        };
+        if status.is_success() {
+            return Ok(());
+        }
+        Err(ClientError::Status(status.as_u16(), error_message(message)))
+    }
+}
+
+// A single HTTP/1.1 request over a fresh connection to the conditions socket
+async fn unix_request(
+    socket: &Path,
+    method: Method,
+    path: &str,
+    body: Vec<u8>,
+) -> Result<(StatusCode, String), ClientError> {
+    let failed = |e: &dyn std::fmt::Display| {
+        ClientError::Request(format!("request to {} failed: {e}", socket.display()))
+    };
+    let stream = UnixStream::connect(socket).await.map_err(|e| failed(&e))?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| failed(&e))?;
+    tokio::spawn(connection);
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::HOST, "localhost")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = sender.send_request(request).await.map_err(|e| failed(&e))?;
+    let status = response.status();
+    let body = axum::body::to_bytes(Body::new(response.into_body()), MAX_ERROR_BODY)
+        .await
+        .unwrap_or_default();
+    Ok((status, String::from_utf8_lossy(&body).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, extract, routing::put};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn reports_conditions_over_a_unix_socket() {
+        let path = std::env::temp_dir().join(format!("conditions-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorded = received.clone();
+        let app = Router::new().route(
+            "/api/conditions/{name}",
+            put(
+                move |extract::Path(name): extract::Path<String>,
+                      Json(update): Json<ConditionUpdate>| async move {
+                    recorded.lock().unwrap().push((name, update.ok, update.ttl));
+                    StatusCode::NO_CONTENT
+                },
+            )
+            .delete(|| async { (StatusCode::NOT_FOUND, "no such condition") }),
+        );
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let handle = ReadinessHandle::unix(&path).with_ttl(Duration::from_secs(30));
+        handle.set_condition("migrations", false).await.unwrap();
+        let error = handle.clear_condition("cache").await.unwrap_err();
+        assert!(matches!(error, ClientError::Status(404, m) if m == "no such condition"));
+        let received = received.lock().unwrap().clone();
+        assert_eq!(
+            received,
+            [("migrations".into(), false, Some(Duration::from_secs(30)))]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
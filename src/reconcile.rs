@@ -0,0 +1,131 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::checks::{CheckResult, CheckStatus};
+
+/// Latest result of a check from one source: this instance, an agent or a federated instance
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceResult {
+    pub source: String,
+    /// Name of the check at its source
+    pub check: String,
+    #[serde(flatten)]
+    pub result: CheckResult,
+}
+
+/// A logical target with the results of every source checking it
+#[derive(Debug, Serialize)]
+pub struct ReconciledTarget {
+    /// Target identity, or `<source>/<check>` for checks without one
+    pub target: String,
+    /// Worst status across sources
+    pub status: CheckStatus,
+    pub sources: Vec<SourceResult>,
+}
+
+/// Merge results of the same target reported by several sources into a single entry
+pub fn reconcile(results: impl IntoIterator<Item = SourceResult>) -> Vec<ReconciledTarget> {
+    let mut targets: BTreeMap<String, Vec<SourceResult>> = BTreeMap::new();
+    for result in results {
+        let key = match &result.result.target {
+            Some(target) => normalize_target(target),
+            None => format!("{}/{}", result.source, result.check),
+        };
+        targets.entry(key).or_default().push(result);
+    }
+    targets
+        .into_iter()
+        .map(|(target, mut sources)| {
+            sources.sort_by(|a, b| a.source.cmp(&b.source));
+            let status = sources
+                .iter()
+                .map(|s| s.result.status)
+                .max()
+                .unwrap_or(CheckStatus::Up);
+            ReconciledTarget {
+                target,
+                status,
+                sources,
+            }
+        })
+        .collect()
+}
+
+/// Canonical form of a target URL or address: scheme and host are case-insensitive, and a
+/// trailing slash does not make a different target
+pub fn normalize_target(target: &str) -> String {
+    let target = target.trim();
+    let (scheme, rest) = match target.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, target),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let mut normalized = String::new();
+    if let Some(scheme) = scheme {
+        normalized.push_str(&scheme.to_ascii_lowercase());
+        normalized.push_str("://");
+    }
+    normalized.push_str(&authority.to_ascii_lowercase());
+    normalized.push_str(path.trim_end_matches('/'));
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(
+        source: &str,
+        check: &str,
+        target: Option<&str>,
+        status: CheckStatus,
+    ) -> SourceResult {
+        let mut result = CheckResult::new(status, None);
+        result.target = target.map(str::to_string);
+        SourceResult {
+            source: source.into(),
+            check: check.into(),
+            result,
+        }
+    }
+
+    #[test]
+    fn sources_of_the_same_target_are_merged() {
+        let targets = reconcile([
+            result(
+                "local",
+                "api",
+                Some("https://API.example.com/"),
+                CheckStatus::Up,
+            ),
+            result(
+                "eu-1",
+                "frontend",
+                Some("https://api.example.com"),
+                CheckStatus::Down,
+            ),
+            result("local", "script", None, CheckStatus::Degraded),
+        ]);
+        assert_eq!(targets.len(), 2);
+        let api = &targets[0];
+        assert_eq!(api.target, "https://api.example.com");
+        assert_eq!(api.status, CheckStatus::Down);
+        let sources: Vec<_> = api.sources.iter().map(|s| s.source.as_str()).collect();
+        assert_eq!(sources, ["eu-1", "local"]);
+        // Checks without a target are never merged with others
+        assert_eq!(targets[1].target, "local/script");
+        assert_eq!(targets[1].status, CheckStatus::Degraded);
+    }
+
+    #[test]
+    fn normalizes_scheme_host_and_trailing_slashes_only() {
+        assert_eq!(
+            normalize_target(" HTTP://Db.Internal:5432/Path/ "),
+            "http://db.internal:5432/Path"
+        );
+        assert_eq!(normalize_target("DB.internal:5432"), "db.internal:5432");
+    }
+}
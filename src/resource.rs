@@ -0,0 +1,385 @@
+use async_trait::async_trait;
+use opentelemetry::KeyValue;
+use opentelemetry_semantic_conventions::attribute::{
+    CLOUD_ACCOUNT_ID, CLOUD_AVAILABILITY_ZONE, CLOUD_PLATFORM, CLOUD_PROVIDER, CLOUD_REGION,
+    HOST_ARCH, HOST_ID, HOST_NAME, HOST_TYPE, K8S_NAMESPACE_NAME, K8S_NODE_NAME, K8S_POD_NAME,
+    K8S_POD_UID, NETWORK_LOCAL_ADDRESS, OS_TYPE,
+};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::config::{DetectorKind, TelemetryConfig};
+
+/// Source of OpenTelemetry resource attributes describing where the service runs
+#[async_trait]
+pub trait ResourceDetector: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Attributes found; an error when not running in the detector's environment
+    async fn detect(&self, client: &reqwest::Client) -> Result<Vec<KeyValue>, String>;
+}
+
+/// Run the configured detectors concurrently and merge their attributes.
+///
+/// Detectors that fail or exceed the timeout contribute nothing; when several report the same
+/// key the one configured first wins. Static attributes from the configuration override all.
+pub async fn detect(
+    detectors: &[Box<dyn ResourceDetector>],
+    config: &TelemetryConfig,
+) -> Vec<KeyValue> {
+    let client = reqwest::Client::builder()
+        .timeout(config.detector_timeout)
+        .build()
+        .unwrap_or_default();
+    let runs = detectors.iter().map(|detector| async {
+        let detected =
+            tokio::time::timeout(config.detector_timeout, detector.detect(&client)).await;
+        match detected {
+            Ok(Ok(attributes)) => {
+                info!(
+                    "Resource detector {} found {} attributes",
+                    detector.name(),
+                    attributes.len()
+                );
+                attributes
+            }
+            Ok(Err(e)) => {
+                debug!("Resource detector {} found nothing: {}", detector.name(), e);
+                Vec::new()
+            }
+            Err(_) => {
+                debug!("Resource detector {} timed out", detector.name());
+                Vec::new()
+            }
+        }
+    });
+    let mut attributes: Vec<KeyValue> = Vec::new();
+    let detected = futures_util::future::join_all(runs)
+        .await
+        .into_iter()
+        .flatten();
+    let configured = config
+        .attributes
+        .iter()
+        .map(|(key, value)| KeyValue::new(key.clone(), value.clone()));
+    for attribute in configured.chain(detected) {
+        if !attributes.iter().any(|a| a.key == attribute.key) {
+            attributes.push(attribute);
+        }
+    }
+    attributes
+}
+
+/// Built-in detectors enabled in the configuration, to which custom ones can be added
+pub fn detectors(config: &TelemetryConfig) -> Vec<Box<dyn ResourceDetector>> {
+    config
+        .detectors
+        .iter()
+        .map(|kind| -> Box<dyn ResourceDetector> {
+            match kind {
+                DetectorKind::Host => Box::new(HostDetector),
+                DetectorKind::Kubernetes => Box::new(KubernetesDetector),
+                DetectorKind::Ec2 => Box::new(Ec2Detector),
+                DetectorKind::Gce => Box::new(GceDetector),
+                DetectorKind::Azure => Box::new(AzureDetector),
+            }
+        })
+        .collect()
+}
+
+/// Host name, OS, architecture and the address outbound traffic leaves from
+pub struct HostDetector;
+
+#[async_trait]
+impl ResourceDetector for HostDetector {
+    fn name(&self) -> &str {
+        "host"
+    }
+
+    async fn detect(&self, _client: &reqwest::Client) -> Result<Vec<KeyValue>, String> {
+        let mut attributes = vec![
+            KeyValue::new(OS_TYPE, std::env::consts::OS),
+            KeyValue::new(HOST_ARCH, std::env::consts::ARCH),
+        ];
+        if let Some(name) = sysinfo::System::host_name() {
+            attributes.push(KeyValue::new(HOST_NAME, name));
+        }
+        // Connecting a UDP socket selects the outbound interface without sending anything
+        let local = std::net::UdpSocket::bind("0.0.0.0:0")
+            .and_then(|socket| socket.connect("192.0.2.1:9").map(|_| socket))
+            .and_then(|socket| socket.local_addr());
+        if let Ok(address) = local {
+            attributes.push(KeyValue::new(
+                NETWORK_LOCAL_ADDRESS,
+                address.ip().to_string(),
+            ));
+        }
+        Ok(attributes)
+    }
+}
+
+/// Pod attributes exposed through the downward API as `POD_NAME`, `POD_NAMESPACE`,
+/// `POD_UID` and `NODE_NAME`, falling back to the service account namespace and host name
+pub struct KubernetesDetector;
+
+#[async_trait]
+impl ResourceDetector for KubernetesDetector {
+    fn name(&self) -> &str {
+        "kubernetes"
+    }
+
+    async fn detect(&self, _client: &reqwest::Client) -> Result<Vec<KeyValue>, String> {
+        if std::env::var_os("KUBERNETES_SERVICE_HOST").is_none() {
+            return Err("not running in a pod".into());
+        }
+        let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let namespace = env("POD_NAMESPACE").or_else(|| {
+            std::fs::read_to_string("/var/run/secrets/kubernetes.io/serviceaccount/namespace")
+                .ok()
+                .map(|namespace| namespace.trim().to_string())
+        });
+        let attributes = [
+            (K8S_POD_NAME, env("POD_NAME").or_else(|| env("HOSTNAME"))),
+            (K8S_NAMESPACE_NAME, namespace),
+            (K8S_POD_UID, env("POD_UID")),
+            (K8S_NODE_NAME, env("NODE_NAME")),
+        ];
+        Ok(attributes
+            .into_iter()
+            .filter_map(|(key, value)| Some(KeyValue::new(key, value?)))
+            .collect())
+    }
+}
+
+const LINK_LOCAL_METADATA: &str = "http://169.254.169.254";
+
+/// EC2 instance identity from the instance metadata service (IMDSv2)
+pub struct Ec2Detector;
+
+#[async_trait]
+impl ResourceDetector for Ec2Detector {
+    fn name(&self) -> &str {
+        "ec2"
+    }
+
+    async fn detect(&self, client: &reqwest::Client) -> Result<Vec<KeyValue>, String> {
+        let token = client
+            .put(format!("{LINK_LOCAL_METADATA}/latest/api/token"))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+        let document = fetch_json(
+            client
+                .get(format!(
+                    "{LINK_LOCAL_METADATA}/latest/dynamic/instance-identity/document"
+                ))
+                .header("X-aws-ec2-metadata-token", token),
+        )
+        .await?;
+        Ok(cloud_attributes(
+            "aws",
+            "aws_ec2",
+            &[
+                (CLOUD_REGION, &document["region"]),
+                (CLOUD_AVAILABILITY_ZONE, &document["availabilityZone"]),
+                (CLOUD_ACCOUNT_ID, &document["accountId"]),
+                (HOST_ID, &document["instanceId"]),
+                (HOST_TYPE, &document["instanceType"]),
+            ],
+        ))
+    }
+}
+
+/// Compute Engine instance attributes from the metadata server
+pub struct GceDetector;
+
+#[async_trait]
+impl ResourceDetector for GceDetector {
+    fn name(&self) -> &str {
+        "gce"
+    }
+
+    async fn detect(&self, client: &reqwest::Client) -> Result<Vec<KeyValue>, String> {
+        let base = "http://metadata.google.internal/computeMetadata/v1";
+        let metadata = |path: &str| {
+            client
+                .get(format!("{base}/{path}"))
+                .header("Metadata-Flavor", "Google")
+        };
+        let instance = fetch_json(metadata("instance/?recursive=true")).await?;
+        let project = fetch_json(metadata("project/?recursive=true")).await?;
+        // Zone and machine type are resource paths, e.g. projects/123/zones/us-central1-a
+        let last = |value: &Value| {
+            let last = value.as_str().and_then(|path| path.rsplit('/').next());
+            last.map_or(Value::Null, Value::from)
+        };
+        let zone = last(&instance["zone"]);
+        let region = zone
+            .as_str()
+            .and_then(|zone| zone.rsplit_once('-'))
+            .map_or(Value::Null, |(region, _)| Value::from(region));
+        Ok(cloud_attributes(
+            "gcp",
+            "gcp_compute_engine",
+            &[
+                (CLOUD_REGION, &region),
+                (CLOUD_AVAILABILITY_ZONE, &zone),
+                (CLOUD_ACCOUNT_ID, &project["projectId"]),
+                (HOST_ID, &instance["id"]),
+                (HOST_NAME, &instance["name"]),
+                (HOST_TYPE, &last(&instance["machineType"])),
+            ],
+        ))
+    }
+}
+
+/// Azure VM attributes from the instance metadata service
+pub struct AzureDetector;
+
+#[async_trait]
+impl ResourceDetector for AzureDetector {
+    fn name(&self) -> &str {
+        "azure"
+    }
+
+    async fn detect(&self, client: &reqwest::Client) -> Result<Vec<KeyValue>, String> {
+        let compute = fetch_json(
+            client
+                .get(format!(
+                    "{LINK_LOCAL_METADATA}/metadata/instance/compute?api-version=2021-12-13"
+                ))
+                .header("Metadata", "true"),
+        )
+        .await?;
+        Ok(cloud_attributes(
+            "azure",
+            "azure_vm",
+            &[
+                (CLOUD_REGION, &compute["location"]),
+                (CLOUD_AVAILABILITY_ZONE, &compute["zone"]),
+                (CLOUD_ACCOUNT_ID, &compute["subscriptionId"]),
+                (HOST_ID, &compute["vmId"]),
+                (HOST_NAME, &compute["name"]),
+                (HOST_TYPE, &compute["vmSize"]),
+            ],
+        ))
+    }
+}
+
+async fn fetch_json(request: reqwest::RequestBuilder) -> Result<Value, String> {
+    request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?
+        .json()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// Provider and platform plus the non-empty metadata values
+fn cloud_attributes(provider: &str, platform: &str, values: &[(&str, &Value)]) -> Vec<KeyValue> {
+    let mut attributes = vec![
+        KeyValue::new(CLOUD_PROVIDER, provider.to_string()),
+        KeyValue::new(CLOUD_PLATFORM, platform.to_string()),
+    ];
+    for (key, value) in values {
+        let value = match value {
+            Value::String(s) if !s.is_empty() => s.clone(),
+            Value::Number(n) => n.to_string(),
+            _ => continue,
+        };
+        attributes.push(KeyValue::new(key.to_string(), value));
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct Fixed(&'static str, Vec<KeyValue>);
+
+    #[async_trait]
+    impl ResourceDetector for Fixed {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn detect(&self, _client: &reqwest::Client) -> Result<Vec<KeyValue>, String> {
+            Ok(self.1.clone())
+        }
+    }
+
+    struct Hanging;
+
+    #[async_trait]
+    impl ResourceDetector for Hanging {
+        fn name(&self) -> &str {
+            "hanging"
+        }
+
+        async fn detect(&self, _client: &reqwest::Client) -> Result<Vec<KeyValue>, String> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(vec![KeyValue::new(HOST_ID, "late")])
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_then_earlier_detectors_win() {
+        let config: TelemetryConfig = toml::from_str(
+            r#"
+            detector_timeout = "50ms"
+            attributes = { "cloud.region" = "pinned" }
+            "#,
+        )
+        .unwrap();
+        let detectors: Vec<Box<dyn ResourceDetector>> = vec![
+            Box::new(Hanging),
+            Box::new(Fixed(
+                "first",
+                vec![
+                    KeyValue::new(HOST_NAME, "first"),
+                    KeyValue::new(CLOUD_REGION, "eu-west-1"),
+                ],
+            )),
+            Box::new(Fixed("second", vec![KeyValue::new(HOST_NAME, "second")])),
+        ];
+        let attributes = detect(&detectors, &config).await;
+        let value = |key: &str| {
+            let attribute = attributes.iter().find(|a| a.key.as_str() == key);
+            attribute.map(|a| a.value.to_string())
+        };
+        assert_eq!(value(CLOUD_REGION).as_deref(), Some("pinned"));
+        assert_eq!(value(HOST_NAME).as_deref(), Some("first"));
+        assert_eq!(value(HOST_ID), None);
+        assert_eq!(attributes.len(), 2);
+    }
+
+    #[test]
+    fn cloud_attributes_skip_missing_metadata() {
+        let document = serde_json::json!({ "region": "us-east-1", "zone": "", "id": 42 });
+        let attributes = cloud_attributes(
+            "aws",
+            "aws_ec2",
+            &[
+                (CLOUD_REGION, &document["region"]),
+                (CLOUD_AVAILABILITY_ZONE, &document["zone"]),
+                (HOST_ID, &document["id"]),
+                (HOST_TYPE, &document["missing"]),
+            ],
+        );
+        let keys: Vec<_> = attributes.iter().map(|a| a.key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [CLOUD_PROVIDER, CLOUD_PLATFORM, CLOUD_REGION, HOST_ID]
+        );
+        assert_eq!(attributes[3].value.to_string(), "42");
+    }
+}
@@ -0,0 +1,242 @@
+use axum::{
+    Router,
+    body::Body,
+    extract::{Path, State},
+    http::{Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post, put},
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::auth::Credentials;
+use crate::checks::CheckStatus;
+use crate::enrollment::AgentStatus;
+use crate::notifier::Notification;
+use crate::silence::NewSilence;
+
+use super::AppState;
+
+#[derive(Deserialize)]
+struct NotifyTest {
+    channel: String,
+}
+
+#[derive(Deserialize)]
+struct ForceCheck {
+    #[serde(default = "default_forced_status")]
+    status: CheckStatus,
+    #[serde(default, with = "humantime_serde")]
+    duration: Option<Duration>,
+}
+
+fn default_forced_status() -> CheckStatus {
+    CheckStatus::Down
+}
+
+#[derive(Deserialize)]
+struct FailureRate {
+    rate: f64,
+}
+
+#[derive(Deserialize)]
+struct FailReadiness {
+    #[serde(with = "humantime_serde")]
+    duration: Duration,
+}
+
+// Reject requests to protected endpoints without one of the accepted credentials
+pub async fn require_credentials(
+    State(credentials): State<Arc<Credentials>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    if credentials.accepts(req.headers()) {
+        return next.run(req).await;
+    }
+    let challenge = [(header::WWW_AUTHENTICATE, credentials.challenge())];
+    (StatusCode::UNAUTHORIZED, challenge).into_response()
+}
+
+// Administrative endpoints, mounted under /api/admin
+pub fn admin_router() -> Router<AppState> {
+    Router::new()
+        .route("/notify/test", post(notify_test))
+        .route("/config", get(config_dump))
+        .route("/agents", get(list_enrolled_agents))
+        .route("/agents/{agent}/approve", post(approve_agent))
+        .route("/agents/{agent}/revoke", post(revoke_agent))
+        .route("/silences", get(list_silences).post(create_silence))
+        .route("/silences/{id}", delete(remove_silence))
+}
+
+// Rotation toggles for deploy tooling, mounted under /admin
+pub fn rotation_router() -> Router<AppState> {
+    Router::new()
+        .route("/drain", post(drain))
+        .route("/ready", post(resume))
+}
+
+// Effective configuration with secrets masked and the source of each value
+async fn config_dump(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.config.redacted())
+}
+
+// Agents enrolled with the aggregator and their approval status
+async fn list_enrolled_agents(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.aggregator.enrollment().list())
+}
+
+async fn approve_agent(State(state): State<AppState>, Path(agent): Path<String>) -> StatusCode {
+    set_agent_status(&state, &agent, AgentStatus::Approved)
+}
+
+async fn revoke_agent(State(state): State<AppState>, Path(agent): Path<String>) -> StatusCode {
+    warn!("Revoking agent {}", agent);
+    set_agent_status(&state, &agent, AgentStatus::Revoked)
+}
+
+fn set_agent_status(state: &AppState, agent: &str, status: AgentStatus) -> StatusCode {
+    if state.aggregator.enrollment().set_status(agent, status) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn list_silences(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.notifier.silences().list())
+}
+
+// Mute notifications for the matching checks, e.g. during maintenance
+async fn create_silence(State(state): State<AppState>, Json(body): Json<NewSilence>) -> Response {
+    match state.notifier.silences().create(body) {
+        Ok(silence) => {
+            warn!(
+                "Silencing checks {} until {}",
+                silence.checks, silence.ends_at
+            );
+            (StatusCode::CREATED, Json(silence)).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({ "message": e }))).into_response(),
+    }
+}
+
+async fn remove_silence(State(state): State<AppState>, Path(id): Path<u64>) -> StatusCode {
+    if state.notifier.silences().remove(id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+// Take the instance out of rotation: readiness fails until /ready is posted
+async fn drain(State(state): State<AppState>) -> Json<serde_json::Value> {
+    if state.service.set_draining(true) {
+        warn!("Draining: readiness fails until POST /admin/ready");
+    }
+    Json(json!({ "draining": true }))
+}
+
+async fn resume(State(state): State<AppState>) -> Json<serde_json::Value> {
+    if state.service.set_draining(false) {
+        warn!("No longer draining");
+    }
+    Json(json!({ "draining": false }))
+}
+
+// Send a test alert to a notification channel
+async fn notify_test(State(state): State<AppState>, Json(body): Json<NotifyTest>) -> Response {
+    match state
+        .notifier
+        .notify(&body.channel, &Notification::test())
+        .await
+    {
+        Some(Ok(delivery)) => {
+            Json(json!({ "channel": body.channel, "delivery": delivery })).into_response()
+        }
+        Some(Err(e)) => (
+            StatusCode::BAD_GATEWAY,
+            Json(json!({ "channel": body.channel, "message": e.to_string() })),
+        )
+            .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "message": format!("Unknown channel: {}", body.channel) })),
+        )
+            .into_response(),
+    }
+}
+
+// Fault-injection endpoints for chaos testing, mounted under /api/admin/faults
+pub fn faults_router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_faults).delete(clear_faults))
+        .route("/checks/{name}", put(force_check).delete(clear_check))
+        .route("/probe-failures", put(set_failure_rate))
+        .route("/readiness", put(fail_readiness))
+}
+
+async fn list_faults(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.checks.faults().summary())
+}
+
+async fn clear_faults(State(state): State<AppState>) -> StatusCode {
+    warn!("Clearing all injected faults");
+    state.checks.faults().clear();
+    StatusCode::NO_CONTENT
+}
+
+async fn force_check(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(body): Json<ForceCheck>,
+) -> Response {
+    if !state.checks.contains(&name) {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "message": format!("Unknown check: {name}") })),
+        )
+            .into_response();
+    }
+    let faults = state.checks.faults();
+    if let Err(e) = faults.force_check(&name, body.status, body.duration) {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "message": e }))).into_response();
+    }
+    warn!("Forcing check {} to {}", name, body.status.as_str());
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn clear_check(State(state): State<AppState>, Path(name): Path<String>) -> StatusCode {
+    if state.checks.faults().clear_check(&name) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn set_failure_rate(
+    State(state): State<AppState>,
+    Json(body): Json<FailureRate>,
+) -> Response {
+    if let Err(e) = state.checks.faults().set_failure_rate(body.rate) {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "message": e }))).into_response();
+    }
+    warn!("Injecting probe failures at rate {}", body.rate);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn fail_readiness(
+    State(state): State<AppState>,
+    Json(body): Json<FailReadiness>,
+) -> Response {
+    if let Err(e) = state.checks.faults().fail_readiness_for(body.duration) {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "message": e }))).into_response();
+    }
+    warn!("Failing readiness for {:?}", body.duration);
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -0,0 +1,126 @@
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, put},
+};
+use futures_util::{Stream, stream};
+use serde_json::json;
+use std::convert::Infallible;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::checks::Selector;
+use crate::conditions::{CONDITIONS_PATH, ConditionUpdate};
+
+use super::AppState;
+
+// Read-only status API used by tools and the client SDK
+pub fn status_router() -> Router<AppState> {
+    Router::new()
+        .route("/api/status", get(status))
+        .route("/api/checks/{name}/history", get(check_history))
+        .route("/api/events", get(events))
+        .route("/targets", get(targets))
+}
+
+// Readiness conditions reported by applications, on the HTTP listener or a Unix socket
+pub fn conditions_router() -> Router<AppState> {
+    Router::new()
+        .route(CONDITIONS_PATH, get(list_conditions))
+        .route(
+            &format!("{CONDITIONS_PATH}/{{name}}"),
+            put(set_condition).delete(clear_condition),
+        )
+}
+
+fn unknown_checks(state: &AppState, selector: &Selector) -> Option<Response> {
+    let unknown = state.checks.unknown(selector);
+    if unknown.is_empty() {
+        return None;
+    }
+    let message = format!("Unknown checks: {}", unknown.join(", "));
+    Some((StatusCode::BAD_REQUEST, Json(json!({ "message": message }))).into_response())
+}
+
+// Cached results of the selected checks; unlike readiness always 200
+async fn status(State(state): State<AppState>, Query(selector): Query<Selector>) -> Response {
+    if let Some(response) = unknown_checks(&state, &selector) {
+        return response;
+    }
+    Json(state.checks.report(&selector)).into_response()
+}
+
+async fn check_history(State(state): State<AppState>, Path(name): Path<String>) -> Response {
+    match state.checks.history(&name) {
+        Some(history) => Json(history).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+// Probed targets with the last result of every check probing them, like a blackbox prober
+async fn targets(State(state): State<AppState>) -> Response {
+    Json(state.checks.targets()).into_response()
+}
+
+// Every completed execution of the selected checks as server-sent `check` events
+async fn events(State(state): State<AppState>, Query(selector): Query<Selector>) -> Response {
+    if let Some(response) = unknown_checks(&state, &selector) {
+        return response;
+    }
+    Sse::new(check_events(state, selector))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn check_events(
+    state: AppState,
+    selector: Selector,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let events = state.checks.subscribe();
+    stream::unfold((events, selector), |(mut events, selector)| async move {
+        loop {
+            match events.recv().await {
+                Ok(event) if selector.matches(&event.check) => {
+                    // Serializing plain data cannot fail
+                    let sse = Event::default().event("check").json_data(&event).unwrap();
+                    return Some((Ok(sse), (events, selector)));
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Event stream lagged behind, {} events dropped", skipped)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+async fn list_conditions(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.checks.conditions().list())
+}
+
+async fn set_condition(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(update): Json<ConditionUpdate>,
+) -> Response {
+    if state.checks.contains(&name) {
+        let message = format!("{name} is a configured check");
+        return (StatusCode::CONFLICT, Json(json!({ "message": message }))).into_response();
+    }
+    state.checks.report_condition(&name, update);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn clear_condition(State(state): State<AppState>, Path(name): Path<String>) -> StatusCode {
+    if state.checks.conditions().remove(&name) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
@@ -0,0 +1,226 @@
+use axum::{
+    Router,
+    extract::State,
+    response::{IntoResponse, Json},
+    routing::get,
+};
+use serde_json::json;
+
+use super::{AppState, GLOBAL_REGISTRY};
+
+// Admin-only debug endpoints, mounted under /debug: self-diagnostics and, when built with
+// the profiling features, CPU and heap profiles
+pub fn debug_router() -> Router<AppState> {
+    let router = Router::new().route("/self", get(self_diagnostics));
+    #[cfg(feature = "pprof")]
+    let router = router.route("/pprof/profile", get(pprof::cpu_profile));
+    #[cfg(feature = "jemalloc")]
+    let router = router.route("/heap", get(heap::heap_profile));
+    router
+}
+
+// Health of the health checker itself: exporters, scheduler and configuration
+async fn self_diagnostics(State(state): State<AppState>) -> impl IntoResponse {
+    let otlp = state.otlp_status.report();
+    let families = GLOBAL_REGISTRY.lock().unwrap().gather().len();
+    let ticks = state.checks.ticks();
+    let stalled: Vec<_> = ticks
+        .iter()
+        .filter(|(_, t)| t.stalled)
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let healthy = otlp.status != "failing" && stalled.is_empty();
+
+    Json(json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "uptime_secs": state.started.elapsed().as_secs(),
+        "exporters": {
+            "otlp": otlp,
+            "prometheus": { "status": "ok", "metric_families": families },
+        },
+        "scheduler": {
+            "status": if stalled.is_empty() { "ok" } else { "stalled" },
+            "stalled": stalled,
+            "checks": ticks,
+        },
+        "config": {
+            "version": state.config.version,
+            "source": state.config.source,
+        },
+    }))
+}
+
+#[cfg(feature = "pprof")]
+mod pprof {
+    use axum::{
+        extract::Query,
+        http::{StatusCode, header},
+        response::{IntoResponse, Response},
+    };
+    use flate2::{Compression, write::GzEncoder};
+    use pprof::protos::Message;
+    use serde::Deserialize;
+    use std::io::Write;
+    use tokio::time::{Duration, sleep};
+    use tracing::info;
+
+    const MAX_SECONDS: u64 = 300;
+
+    #[derive(Deserialize)]
+    pub struct ProfileParams {
+        #[serde(default = "default_seconds")]
+        seconds: u64,
+        #[serde(default = "default_frequency")]
+        frequency: i32,
+        #[serde(default)]
+        format: ProfileFormat,
+    }
+
+    #[derive(Default, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum ProfileFormat {
+        #[default]
+        Protobuf,
+        Flamegraph,
+    }
+
+    fn default_seconds() -> u64 {
+        30
+    }
+
+    fn default_frequency() -> i32 {
+        99
+    }
+
+    fn internal_error(e: impl std::fmt::Display) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+    }
+
+    // Sample the process for `seconds` and return a gzipped pprof profile or an SVG flamegraph
+    pub async fn cpu_profile(Query(params): Query<ProfileParams>) -> Response {
+        let seconds = params.seconds.clamp(1, MAX_SECONDS);
+        let guard = match pprof::ProfilerGuardBuilder::default()
+            .frequency(params.frequency)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+        {
+            Ok(guard) => guard,
+            Err(e) => return (StatusCode::CONFLICT, e.to_string()).into_response(),
+        };
+        info!("CPU profiling for {}s", seconds);
+        sleep(Duration::from_secs(seconds)).await;
+        let report = match guard.report().build() {
+            Ok(report) => report,
+            Err(e) => return internal_error(e),
+        };
+
+        match params.format {
+            ProfileFormat::Flamegraph => {
+                let mut svg = Vec::new();
+                if let Err(e) = report.flamegraph(&mut svg) {
+                    return internal_error(e);
+                }
+                ([(header::CONTENT_TYPE, "image/svg+xml")], svg).into_response()
+            }
+            ProfileFormat::Protobuf => {
+                let encoded = report
+                    .pprof()
+                    .map_err(|e| e.to_string())
+                    .and_then(|profile| profile.write_to_bytes().map_err(|e| e.to_string()))
+                    .and_then(|bytes| {
+                        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+                        gz.write_all(&bytes)
+                            .and_then(|_| gz.finish())
+                            .map_err(|e| e.to_string())
+                    });
+                match encoded {
+                    Ok(body) => (
+                        [
+                            (header::CONTENT_TYPE, "application/octet-stream"),
+                            (
+                                header::CONTENT_DISPOSITION,
+                                "attachment; filename=\"profile.pb.gz\"",
+                            ),
+                        ],
+                        body,
+                    )
+                        .into_response(),
+                    Err(e) => internal_error(e),
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn profiles_are_gzipped_pprof_protobufs() {
+            let params = ProfileParams {
+                // Clamped to a one second profile
+                seconds: 0,
+                frequency: default_frequency(),
+                format: ProfileFormat::Protobuf,
+            };
+            let response = cpu_profile(Query(params)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body[..2], [0x1f, 0x8b]);
+        }
+    }
+}
+
+#[cfg(feature = "jemalloc")]
+mod heap {
+    use axum::{
+        http::{StatusCode, header},
+        response::{IntoResponse, Response},
+    };
+
+    // Dump the jemalloc allocation profile as a gzipped pprof protobuf
+    pub async fn heap_profile() -> Response {
+        let Some(prof_ctl) = jemalloc_pprof::PROF_CTL.as_ref() else {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "jemalloc profiling is not available",
+            )
+                .into_response();
+        };
+        let mut prof_ctl = prof_ctl.lock().await;
+        if !prof_ctl.activated() {
+            return (StatusCode::CONFLICT, "jemalloc profiling is not active").into_response();
+        }
+        match prof_ctl.dump_pprof() {
+            Ok(body) => (
+                [
+                    (header::CONTENT_TYPE, "application/octet-stream"),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        "attachment; filename=\"heap.pb.gz\"",
+                    ),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // The test binary neither allocates through jemalloc nor enables its profiling
+        #[tokio::test]
+        async fn inactive_profiling_is_reported_instead_of_dumped() {
+            let status = heap_profile().await.status();
+            assert!(
+                [StatusCode::SERVICE_UNAVAILABLE, StatusCode::CONFLICT].contains(&status),
+                "{status}"
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Duration, timeout};
+use tracing::{debug, info, warn};
+
+use crate::config::ServerConfig;
+use crate::tls::TlsServer;
+
+/// Time allowed to clients to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Bind a TCP listener; on the IPv6 wildcard address it also accepts IPv4 connections, whatever
+// the system default for IPV6_V6ONLY
+pub fn bind_tcp(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() && addr.ip().is_unspecified() {
+        socket.set_only_v6(false)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+// Serve the HTTP API with the connection settings of `[server]`, over TLS when `tls` is given,
+// until `shutdown` completes, then give in-flight requests `server.shutdown_timeout` to finish
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    config: ServerConfig,
+    tls: Option<Arc<TlsServer>>,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.keep_alive)
+        .header_read_timeout(config.keep_alive_timeout);
+    let builder = Arc::new(match config.http2 {
+        true => builder,
+        false => builder.http1_only(),
+    });
+    let limit = config
+        .max_connections
+        .map(|max| Arc::new(Semaphore::new(max)));
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        // Stop accepting while at the limit; pending connections wait in the backlog
+        let permit = tokio::select! {
+            permit = acquire(&limit) => permit,
+            _ = &mut shutdown => break,
+        };
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        if config.tcp_nodelay
+            && let Err(e) = stream.set_nodelay(true)
+        {
+            debug!("Failed to set TCP_NODELAY for {}: {}", peer, e);
+        }
+        let service = TowerToHyperService::new(app.clone());
+        let Some(tls) = &tls else {
+            let connection = builder
+                .serve_connection(TokioIo::new(stream), service)
+                .into_owned();
+            let connection = graceful.watch(connection);
+            tokio::spawn(async move {
+                let _permit = permit;
+                if let Err(e) = connection.await {
+                    debug!("Connection from {} failed: {}", peer, e);
+                }
+            });
+            continue;
+        };
+        // The handshake happens on the connection's task, not to hold up accepting
+        let (acceptor, builder, watcher) = (tls.acceptor(), builder.clone(), graceful.watcher());
+        tokio::spawn(async move {
+            let _permit = permit;
+            let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => return debug!("TLS handshake with {} failed: {}", peer, e),
+                Err(_) => return debug!("TLS handshake with {} timed out", peer),
+            };
+            let connection = builder
+                .serve_connection(TokioIo::new(stream), service)
+                .into_owned();
+            if let Err(e) = watcher.watch(connection).await {
+                debug!("Connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+    drop(listener);
+    info!("Stopped accepting connections, waiting for in-flight requests");
+    if timeout(config.shutdown_timeout, graceful.shutdown())
+        .await
+        .is_err()
+    {
+        warn!(
+            "Connections still open after {:?}, closing them",
+            config.shutdown_timeout
+        );
+    }
+}
+
+async fn acquire(limit: &Option<Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+    // The semaphore is never closed
+    match limit {
+        Some(limit) => Some(limit.clone().acquire_owned().await.unwrap()),
+        None => None,
+    }
+}
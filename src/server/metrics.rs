@@ -0,0 +1,245 @@
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{Method, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Histogram, Meter};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::time::Instant;
+
+use crate::checks::CheckRegistry;
+use crate::health::{HealthModel, HealthState};
+use crate::lifecycle::ServiceState;
+
+/// Ticks of the `service.up` counter
+const UP_TICK_SECS: u64 = 10;
+
+/// Instruments of the service, created once at startup and shared through `AppState`.
+/// Observable instruments are registered once too, with callbacks reading shared state
+#[derive(Clone)]
+pub(crate) struct Metrics {
+    pub(crate) api: ApiMetrics,
+}
+
+impl Metrics {
+    pub(crate) fn register(
+        meter: &Meter,
+        checks: Arc<CheckRegistry>,
+        service: Arc<ServiceState>,
+        health: Arc<HealthModel>,
+        started: Instant,
+    ) -> Self {
+        meter
+            .u64_observable_counter("service.up")
+            .with_description("Liveness ticks, one every 10 seconds since the service started")
+            .with_callback(move |observer| {
+                let ticks = started.elapsed().as_secs() / UP_TICK_SECS + 1;
+                observer.observe(ticks, &[KeyValue::new("status", "alive")]);
+            })
+            .build();
+        let model = health.clone();
+        meter
+            .u64_observable_gauge("service.ready")
+            .with_description("Whether readiness over all checks currently succeeds")
+            .with_callback(move |observer| {
+                let ready = is_ready(&checks, &service, &model);
+                observer.observe(ready as u64, &[KeyValue::new("status", "ready")]);
+            })
+            .build();
+        meter
+            .u64_observable_gauge("service.health_state")
+            .with_description("1 for the current health state, 0 for the others")
+            .with_callback(move |observer| {
+                let current = health.state();
+                for state in HealthState::ALL {
+                    let attributes = [KeyValue::new("state", state.as_str())];
+                    observer.observe((state == current) as u64, &attributes);
+                }
+            })
+            .build();
+        Self {
+            api: ApiMetrics::new(meter),
+        }
+    }
+}
+
+// Readiness as `/health/ready` reports it without a selector
+fn is_ready(checks: &CheckRegistry, service: &ServiceState, health: &HealthModel) -> bool {
+    if service.is_draining() || checks.faults().readiness_failing() {
+        return false;
+    }
+    // Followers report ready while the leader probes
+    !checks.is_active() || matches!(health.state(), HealthState::Ready | HealthState::Degraded)
+}
+
+/// Instruments of the API metrics middleware, created once, with the attribute set of every
+/// route, method and status seen so far so that requests record without allocating
+#[derive(Clone)]
+pub(crate) struct ApiMetrics {
+    requests: Counter<u64>,
+    duration: Histogram<f64>,
+    errors: Counter<u64>,
+    attributes: Arc<RwLock<HashMap<String, Vec<AttributeSet>>>>,
+}
+
+struct AttributeSet {
+    method: &'static str,
+    status: StatusCode,
+    attributes: Arc<[KeyValue]>,
+}
+
+impl ApiMetrics {
+    fn new(meter: &Meter) -> Self {
+        Self {
+            requests: meter.u64_counter("api_requests_total").build(),
+            duration: meter.f64_histogram("api_request_duration_seconds").build(),
+            errors: meter.u64_counter("api_errors_total").build(),
+            attributes: Arc::default(),
+        }
+    }
+
+    fn attributes(&self, method: &Method, route: &str, status: StatusCode) -> Arc<[KeyValue]> {
+        let method = method_label(method);
+        let find = |sets: &[AttributeSet]| {
+            let set = sets
+                .iter()
+                .find(|s| s.method == method && s.status == status)?;
+            Some(set.attributes.clone())
+        };
+        if let Some(attributes) = self
+            .attributes
+            .read()
+            .unwrap()
+            .get(route)
+            .and_then(|s| find(s))
+        {
+            return attributes;
+        }
+        let mut known = self.attributes.write().unwrap();
+        let sets = known.entry(route.to_string()).or_default();
+        // Built by a concurrent request in the meantime
+        if let Some(attributes) = find(sets) {
+            return attributes;
+        }
+        let attributes: Arc<[KeyValue]> = Arc::new([
+            KeyValue::new("method", method),
+            KeyValue::new("path", route.to_string()),
+            KeyValue::new("status", status.as_u16().to_string()),
+        ]);
+        sets.push(AttributeSet {
+            method,
+            status,
+            attributes: attributes.clone(),
+        });
+        attributes
+    }
+}
+
+// Method label with bounded cardinality
+pub(super) fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        Method::PATCH => "PATCH",
+        _ => "OTHER",
+    }
+}
+
+// API metrics middleware, labelling requests with their route rather than the raw path
+pub(crate) async fn track_api_metrics(
+    State(metrics): State<Metrics>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let start_time = Instant::now();
+    let method = req.method().clone();
+    let route = req.extensions().get::<MatchedPath>().cloned();
+
+    let response = next.run(req).await;
+    let duration = start_time.elapsed().as_secs_f64();
+
+    let route = route.as_ref().map_or("unmatched", MatchedPath::as_str);
+    let api = &metrics.api;
+    let attributes = api.attributes(&method, route, response.status());
+    api.requests.add(1, &attributes);
+    api.duration.record(duration, &attributes);
+    if response.status().is_server_error() || response.status().is_client_error() {
+        api.errors.add(1, &attributes);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckReport;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+    use prometheus::Registry;
+
+    #[test]
+    fn attribute_sets_are_built_once_per_route_method_and_status() {
+        let api = ApiMetrics::new(&SdkMeterProvider::default().meter("test"));
+        let ok = api.attributes(&Method::GET, "/api/checks/{name}", StatusCode::OK);
+        let again = api.attributes(&Method::GET, "/api/checks/{name}", StatusCode::OK);
+        assert!(Arc::ptr_eq(&ok, &again));
+        let missing = api.attributes(&Method::GET, "/api/checks/{name}", StatusCode::NOT_FOUND);
+        assert!(!Arc::ptr_eq(&ok, &missing));
+        assert_eq!(missing[2], KeyValue::new("status", "404"));
+        // Unusual methods share a label instead of growing the cardinality
+        let method = Method::from_bytes(b"PURGE").unwrap();
+        let purge = api.attributes(&method, "/api/checks/{name}", StatusCode::OK);
+        assert_eq!(purge[0], KeyValue::new("method", "OTHER"));
+        assert_eq!(purge[1], KeyValue::new("path", "/api/checks/{name}"));
+    }
+
+    #[test]
+    fn observable_instruments_read_the_shared_state() {
+        let registry = Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()
+            .unwrap();
+        let provider = SdkMeterProvider::builder().with_reader(exporter).build();
+        let checks = Arc::new(CheckRegistry::default());
+        let service = Arc::new(ServiceState::default());
+        let health = Arc::new(HealthModel::new(&Default::default()));
+        let meter = provider.meter("test");
+        Metrics::register(
+            &meter,
+            checks.clone(),
+            service.clone(),
+            health.clone(),
+            Instant::now(),
+        );
+        let gauge = |name: &str, state: Option<&str>| {
+            let family = registry.gather().into_iter().find(|f| f.name() == name)?;
+            let metric = family.get_metric().iter().find(|m| {
+                state.is_none_or(|state| m.get_label().iter().any(|l| l.value() == state))
+            })?;
+            Some(metric.get_gauge().value())
+        };
+
+        assert_eq!(gauge("service_ready", None), Some(0.0));
+        assert_eq!(gauge("service_health_state", Some("starting")), Some(1.0));
+        let report = CheckReport {
+            status: crate::checks::CheckStatus::Up,
+            checks: Default::default(),
+        };
+        health.evaluate(&report, true, checks.faults());
+        assert_eq!(gauge("service_ready", None), Some(1.0));
+        assert_eq!(gauge("service_health_state", Some("ready")), Some(1.0));
+        assert_eq!(gauge("service_health_state", Some("starting")), Some(0.0));
+        service.set_draining(true);
+        assert_eq!(gauge("service_ready", None), Some(0.0));
+    }
+}
@@ -0,0 +1,840 @@
+mod admin;
+mod api;
+mod debug;
+mod listener;
+mod metrics;
+mod mtls;
+mod ping;
+mod remote;
+mod serverless;
+mod system_metrics;
+mod tenants;
+mod traces;
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{Query, State},
+    http::{Request, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use futures_util::stream;
+use once_cell::sync::Lazy;
+use opentelemetry::{KeyValue, global};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::metrics::{
+    MeterProviderBuilder, PeriodicReader, SdkMeterProvider, Temporality,
+};
+use opentelemetry_semantic_conventions::{
+    SCHEMA_URL,
+    attribute::{DEPLOYMENT_ENVIRONMENT_NAME, SERVICE_NAME, SERVICE_VERSION},
+};
+use prometheus::{Encoder, Registry, TextEncoder};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant, sleep, timeout};
+use tracing::{info, warn};
+
+use crate::agent::Agent;
+use crate::aggregator::Aggregator;
+use crate::auth::Credentials;
+use crate::build_info::{self, GIT_SHA, VERSION};
+use crate::checks::{CheckHook, CheckRegistry, CheckReport, Selector};
+use crate::cluster::Cluster;
+use crate::config::{Config, ConfigError, Latency, OtlpProtocol};
+use crate::diagnostics::{ExportStatus, TrackedExporter};
+use crate::exporter::{Exporter, Exporters};
+use crate::federation::Federation;
+use crate::ha::HaPair;
+use crate::health::{self, HealthModel, HealthState, Watchdog};
+use crate::leader::LeaderElector;
+use crate::lifecycle::{self, ServiceState};
+use crate::notifier::{Dispatcher, Notifier};
+use crate::operator::Operator;
+use crate::resource::{self, ResourceDetector};
+use crate::tls::{MtlsServer, TlsServer};
+use crate::webhooks;
+use metrics::{Metrics, track_api_metrics};
+
+#[derive(Clone)]
+#[allow(dead_code)]
+pub(crate) struct AppState {
+    meter: opentelemetry::metrics::Meter,
+    checks: Arc<CheckRegistry>,
+    notifier: Arc<Dispatcher>,
+    aggregator: Arc<Aggregator>,
+    ha: Option<Arc<HaPair>>,
+    cluster: Option<Arc<Cluster>>,
+    leader: Option<Arc<LeaderElector>>,
+    mtls: Option<Arc<MtlsServer>>,
+    federation: Option<Arc<Federation>>,
+    config: Arc<Config>,
+    otlp_status: Arc<ExportStatus>,
+    metrics: Metrics,
+    service: Arc<ServiceState>,
+    health: Arc<HealthModel>,
+    watchdog: Arc<Watchdog>,
+    started: Instant,
+}
+
+/// Metric families are encoded into chunks of about this size when streaming `/metrics`
+const METRICS_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Global registry for metrics
+pub(crate) static GLOBAL_REGISTRY: Lazy<Mutex<Registry>> =
+    Lazy::new(|| Mutex::new(Registry::new()));
+
+/// Error preventing the server from starting, e.g. an address already in use
+#[derive(Debug)]
+pub struct ServerError(pub String);
+
+impl std::fmt::Display for ServerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+/// The health check service: probes, notifications, the HTTP APIs and, depending on the
+/// configuration, clustering, federation and aggregation.
+///
+/// Embedders extend it before running it, e.g. with notification channels of their own:
+///
+/// ```no_run
+/// # use healthcheck_service::{config::Config, notifier::WebhookNotifier, server::Server};
+/// # async fn run(config: Config) -> Result<(), healthcheck_service::server::ServerError> {
+/// let pager = WebhookNotifier::new("https://pager.example.com".into(), Default::default());
+/// Server::new(config)?.with_channel("pager", pager).run().await
+/// # }
+/// ```
+pub struct Server {
+    config: Config,
+    agent: Option<Agent>,
+    checks: CheckRegistry,
+    notifier: Dispatcher,
+    exporters: Exporters,
+    detectors: Vec<Box<dyn ResourceDetector>>,
+}
+
+impl Server {
+    /// Set up the configured checks and channels; nothing runs until `run`
+    pub fn new(config: Config) -> Result<Self, ServerError> {
+        Ok(Self {
+            detectors: resource::detectors(&config.telemetry),
+            checks: CheckRegistry::from_config(&config).map_err(|e| ServerError(e.to_string()))?,
+            notifier: Dispatcher::from_config(&config.notifications),
+            config,
+            agent: None,
+            exporters: Exporters::default(),
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Also push every check result to the aggregator
+    pub fn with_agent(mut self, agent: Agent) -> Self {
+        self.agent = Some(agent);
+        self
+    }
+
+    /// Register a custom notification channel; it replaces a configured channel of the same
+    /// name, keeping its dry-run setting
+    pub fn with_channel(mut self, name: &str, notifier: impl Notifier + 'static) -> Self {
+        self.notifier = self.notifier.with_channel(name, notifier);
+        self
+    }
+
+    /// Run a hook around every execution of the local checks
+    pub fn with_hook(mut self, hook: impl CheckHook + 'static) -> Self {
+        self.checks = self.checks.with_hook(hook);
+        self
+    }
+
+    /// Add resource attributes from a custom detector, run next to the configured ones
+    pub fn with_detector(mut self, detector: impl ResourceDetector + 'static) -> Self {
+        self.detectors.push(Box::new(detector));
+        self
+    }
+
+    /// Register a sink for check results and periodic metric snapshots
+    pub fn with_exporter(mut self, name: &str, exporter: impl Exporter + 'static) -> Self {
+        self.exporters = self.exporters.with_exporter(name, exporter);
+        self
+    }
+
+    /// How often exporters receive metric snapshots; every minute by default
+    pub fn with_snapshot_interval(mut self, interval: Duration) -> Self {
+        self.exporters = self.exporters.with_snapshot_interval(interval);
+        self
+    }
+
+    /// Serve until the process is asked to shut down, or fail to start
+    pub async fn run(self) -> Result<(), ServerError> {
+        serve(self).await
+    }
+
+    /// Router for serverless platforms: nothing is scheduled, notified or exported, and
+    /// `/health/ready` and `/probe` execute the selected checks on each request
+    pub fn into_serverless_router(self) -> Router {
+        serverless::serverless_router(Arc::new(self.checks))
+    }
+
+    /// Serve the serverless router: as an AWS Lambda function when built with the `lambda`
+    /// feature and started by the Lambda runtime, otherwise over HTTP on `$PORT` (Cloud
+    /// Functions, Cloud Run) or the configured listen address
+    pub async fn run_serverless(self) -> Result<(), ServerError> {
+        let addr = match std::env::var("PORT") {
+            Ok(port) => match port.parse::<u16>() {
+                Ok(port) => SocketAddr::from(([0, 0, 0, 0], port)),
+                Err(e) => return Err(ServerError(format!("invalid PORT {port:?}: {e}"))),
+            },
+            Err(_) => self
+                .config
+                .listen_address()
+                .map_err(|e| ServerError(e.to_string()))?,
+        };
+        let server = self.config.server.clone();
+        serverless::run(self.into_serverless_router(), addr, server).await
+    }
+}
+
+async fn serve(server: Server) -> Result<(), ServerError> {
+    let Server {
+        config,
+        agent,
+        mut checks,
+        mut notifier,
+        exporters,
+        detectors,
+    } = server;
+    let otlp_status = Arc::new(ExportStatus::default());
+    let detected = resource::detect(&detectors, &config.telemetry).await;
+    let resource = setup_resource(&config, detected);
+    let meter_provider = setup_meter_provider(otlp_status.clone(), &config, resource.clone())?;
+    global::set_meter_provider(meter_provider.clone());
+    let tracer_provider = traces::setup_tracer_provider(&config.telemetry, resource)?;
+
+    let leader = match &config.leader_election {
+        Some(election) => match LeaderElector::from_config(election) {
+            Ok(elector) => Some(Arc::new(elector)),
+            Err(e) => return Err(ServerError(e.to_string())),
+        },
+        None => None,
+    };
+    if let Some(leader) = &leader {
+        // Followers neither probe nor notify; they keep serving the read APIs
+        checks = checks.with_active_flag(leader.active_flag());
+        notifier = notifier.with_active_flag(leader.active_flag());
+        leader.spawn();
+    }
+    let cluster = match &config.cluster {
+        Some(cluster_config) => match Cluster::bind(cluster_config).await {
+            Ok(cluster) => Some(cluster),
+            Err(e) => return Err(ServerError(e.to_string())),
+        },
+        None => None,
+    };
+    if let Some(cluster) = &cluster {
+        let cluster = cluster.clone();
+        checks = checks.with_ownership(Arc::new(move |check| cluster.owns(check)));
+    }
+    let checks = Arc::new(checks);
+    let ha = config.ha.as_ref().map(|ha| Arc::new(HaPair::new(ha)));
+    if let Some(ha) = &ha {
+        notifier = notifier.with_active_flag(ha.active_flag());
+        ha.spawn();
+    }
+    let notifier = Arc::new(notifier);
+    notifier.spawn(checks.subscribe());
+    if let Some(agent) = agent {
+        agent.spawn(checks.subscribe());
+    }
+    if !exporters.is_empty() {
+        let metrics = GLOBAL_REGISTRY.lock().unwrap().clone();
+        exporters.spawn(&checks, metrics);
+    }
+    let aggregator = Arc::new(Aggregator::new(&config.aggregator));
+    if config.aggregator.enabled {
+        aggregator.spawn_staleness(checks.clone());
+    }
+    let mtls = match &config.aggregator.mtls {
+        Some(mtls_config) if config.aggregator.enabled => {
+            let server = MtlsServer::from_config(mtls_config).and_then(|server| {
+                let listener = std::net::TcpListener::bind(&mtls_config.listen)
+                    .and_then(|listener| {
+                        listener.set_nonblocking(true)?;
+                        tokio::net::TcpListener::from_std(listener)
+                    })
+                    .map_err(|e| {
+                        ConfigError(format!("failed to bind {}: {e}", mtls_config.listen))
+                    })?;
+                Ok((Arc::new(server), listener))
+            });
+            match server {
+                Ok(server) => Some(server),
+                Err(e) => return Err(ServerError(e.to_string())),
+            }
+        }
+        _ => None,
+    };
+    checks.spawn();
+
+    let meter = global::meter("healthcheck-service");
+    let service = Arc::new(ServiceState::default());
+    let started = Instant::now();
+    let health = Arc::new(HealthModel::new(&config.health));
+    let watchdog = Arc::new(Watchdog::new(config.health.watchdog_timeout));
+    health::spawn(health.clone(), checks.clone(), watchdog.clone());
+    if config.notifications.health {
+        notifier.spawn_health(health.subscribe());
+    }
+    let metrics = Metrics::register(
+        &meter,
+        checks.clone(),
+        service.clone(),
+        health.clone(),
+        started,
+    );
+    system_metrics::spawn(&meter, config.telemetry.system.clone());
+    checks.register_metrics(&meter);
+    checks.resolver().register_metrics(&meter);
+    notifier.register_metrics(&meter);
+    webhooks::spawn(&config.result_webhooks, &checks, &meter);
+    if let Some(cluster) = &cluster {
+        cluster.register_metrics(&meter);
+        cluster.spawn(checks.clone());
+    }
+    let federation = config
+        .federation
+        .as_ref()
+        .map(|federation| Arc::new(Federation::new(federation)));
+    if let Some(federation) = &federation {
+        federation.register_metrics(&meter);
+        federation.spawn(checks.clone());
+    }
+    if let Some(operator_config) = &config.operator {
+        let operator = match Operator::from_config(operator_config, &config) {
+            Ok(operator) => Arc::new(operator),
+            Err(e) => return Err(ServerError(e.to_string())),
+        };
+        operator.register_metrics(&meter);
+        operator.spawn(checks.clone(), notifier.clone());
+    }
+    let app_state = AppState {
+        meter,
+        checks,
+        notifier,
+        aggregator,
+        ha,
+        cluster,
+        leader,
+        mtls: mtls.as_ref().map(|(server, _)| server.clone()),
+        federation,
+        config: Arc::new(config.clone()),
+        otlp_status,
+        metrics: metrics.clone(),
+        service: service.clone(),
+        health,
+        watchdog,
+        started,
+    };
+
+    let mut app = health_router().merge(ping::ping_router());
+    let endpoints = &config.endpoints;
+    if endpoints.metrics {
+        let mut metrics_route = get(metrics_handler);
+        if config.metrics_auth.is_set() {
+            let credentials = Arc::new(Credentials::from_config(&config.metrics_auth));
+            let auth = middleware::from_fn_with_state(credentials, admin::require_credentials);
+            metrics_route = metrics_route.layer(auth);
+        }
+        app = app.route("/metrics", metrics_route);
+    }
+    if endpoints.version {
+        app = app
+            .route("/version", get(version_handler))
+            .route("/buildinfo", get(buildinfo_handler));
+    }
+    if endpoints.status {
+        app = app
+            .merge(remote::overview_router())
+            .merge(api::status_router());
+    }
+    if endpoints.examples {
+        app = app.merge(api_router(&config));
+    }
+    if config.aggregator.enabled {
+        app = app.merge(remote::ingest_router());
+    }
+    if config.ha.is_some() {
+        app = app.merge(remote::ha_router());
+    }
+    if config.cluster.is_some() {
+        app = app.merge(remote::cluster_router());
+    }
+    if config.leader_election.is_some() {
+        app = app.merge(remote::leader_router());
+    }
+    if config.federation.is_some() {
+        app = app.merge(remote::federation_router());
+    }
+    if !config.tenants.is_empty() {
+        app = app.merge(tenants::tenants_router(app_state.clone()));
+    }
+    // Validated when the configuration is loaded: enabling either requires credentials
+    let admin_credentials = Arc::new(Credentials::from_config(&config.admin.credentials()));
+    let admin_auth = middleware::from_fn_with_state(admin_credentials, admin::require_credentials);
+    if config.admin.enabled {
+        app = app
+            .nest(
+                "/api/admin",
+                admin::admin_router().layer(admin_auth.clone()),
+            )
+            .nest("/admin", admin::rotation_router().layer(admin_auth.clone()))
+            .nest("/debug", debug::debug_router().layer(admin_auth.clone()));
+    }
+    if config.admin.fault_injection {
+        warn!("Fault injection endpoints enabled at /api/admin/faults");
+        let faults = admin::faults_router().layer(admin_auth);
+        app = app.nest("/api/admin/faults", faults);
+    }
+    if config.conditions.http {
+        // Checked when the configuration is loaded, but embedders may skip that
+        let token = config.conditions.token.as_deref().filter(|t| !t.is_empty());
+        let Some(token) = token else {
+            return Err(ServerError(
+                "conditions.http requires conditions.token".into(),
+            ));
+        };
+        let credentials = Arc::new(Credentials::bearer(token));
+        let auth = middleware::from_fn_with_state(credentials, admin::require_credentials);
+        app = app.merge(api::conditions_router().layer(auth));
+    }
+    // Side listeners stop with the main one, after the grace period
+    let stopping = watch::Sender::new(false);
+    let mut listeners = Vec::new();
+    if let Some(path) = &config.conditions.socket {
+        let listener = match bind_socket(path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                let path = path.display();
+                return Err(ServerError(format!("failed to bind {path}: {e}")));
+            }
+        };
+        let conditions = api::conditions_router().with_state(app_state.clone());
+        info!("Conditions API listening on {}", path.display());
+        let stopping = stopped(stopping.subscribe());
+        listeners.push(tokio::spawn(async move {
+            let served = axum::serve(listener, conditions).with_graceful_shutdown(stopping);
+            if let Err(e) = served.await {
+                warn!("Conditions API failed: {}", e);
+            }
+        }));
+    }
+    if let Some((server, listener)) = mtls {
+        // Agents pushing over mutual TLS only reach the ingestion endpoints
+        let ingest = remote::ingest_router().with_state(app_state.clone()).layer(
+            middleware::from_fn_with_state(metrics.clone(), track_api_metrics),
+        );
+        server.spawn_rotation();
+        let stopping = stopped(stopping.subscribe());
+        listeners.push(tokio::spawn(mtls::serve(
+            server, listener, ingest, stopping,
+        )));
+    }
+    let health_addr = config
+        .health_listen_address()
+        .map_err(|e| ServerError(e.to_string()))?;
+    if let Some(addr) = health_addr {
+        let listener = match listener::bind_tcp(addr) {
+            Ok(listener) => listener,
+            Err(e) => return Err(ServerError(format!("failed to bind {addr}: {e}"))),
+        };
+        let health = health_router().with_state(app_state.clone());
+        info!("Health probes listening at http://{}", addr);
+        let stopping = stopped(stopping.subscribe());
+        listeners.push(tokio::spawn(async move {
+            let served = axum::serve(listener, health).with_graceful_shutdown(stopping);
+            if let Err(e) = served.await {
+                warn!("Health probe listener failed: {}", e);
+            }
+        }));
+    }
+    let mut app = app
+        .with_state(app_state)
+        .layer(middleware::from_fn_with_state(metrics, track_api_metrics));
+    if tracer_provider.is_some() {
+        app = app.layer(middleware::from_fn(traces::trace_requests));
+    }
+
+    let tls = match &config.server.tls {
+        Some(tls) => match TlsServer::from_config(tls, config.server.http2) {
+            Ok(tls) => Some(Arc::new(tls)),
+            Err(e) => return Err(ServerError(e.to_string())),
+        },
+        None => None,
+    };
+    let addr = config
+        .listen_address()
+        .map_err(|e| ServerError(e.to_string()))?;
+    let listener = match listener::bind_tcp(addr) {
+        Ok(listener) => listener,
+        Err(e) => return Err(ServerError(format!("failed to bind {addr}: {e}"))),
+    };
+    match &tls {
+        Some(tls) => {
+            tls.spawn_reload();
+            info!("Server running at https://{}", addr);
+        }
+        None => info!("Server running at http://{}", addr),
+    }
+    let grace_period = config.server.shutdown_grace_period;
+    let shutdown = async {
+        lifecycle::drain_on_signal(&service, grace_period).await;
+        stopping.send_replace(true);
+    };
+    listener::serve(listener, app, config.server.clone(), tls, shutdown).await;
+    for served in listeners {
+        if timeout(config.server.shutdown_timeout, served)
+            .await
+            .is_err()
+        {
+            warn!("Side listener did not shut down in time");
+        }
+    }
+
+    // Push the last metrics before exiting
+    if let Err(e) = meter_provider.shutdown() {
+        warn!("Failed to flush metrics: {}", e);
+    }
+    if let Some(tracer_provider) = tracer_provider
+        && let Err(e) = tracer_provider.shutdown()
+    {
+        warn!("Failed to flush traces: {}", e);
+    }
+    info!("Shut down");
+    Ok(())
+}
+
+// Completes once the main listener stops accepting connections
+async fn stopped(mut stopping: watch::Receiver<bool>) {
+    let _ = stopping.wait_for(|stopping| *stopping).await;
+}
+
+// Health probes, served on the main listener and on `health_listen` when set
+fn health_router() -> Router<AppState> {
+    Router::new()
+        .route("/health/live", get(watched_liveness_probe))
+        .route("/health/ready", get(readiness_probe))
+        .route("/health/startup", get(startup_probe))
+}
+
+// Bind a Unix socket, replacing one left behind by a previous run
+fn bind_socket(path: &std::path::Path) -> std::io::Result<tokio::net::UnixListener> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    tokio::net::UnixListener::bind(path)
+}
+
+// Example API routes, with optional artificial latency
+fn api_router(config: &Config) -> Router<AppState> {
+    let api = Router::new()
+        .route("/api/example", get(api_example_handler)) // 示例 API 端点
+        .route("/api/fail", get(api_fail_handler)); // 示例失败端点
+    match config.latency.api {
+        Some(latency) => {
+            warn!("Injecting latency into /api routes: {:?}", latency);
+            api.layer(middleware::from_fn_with_state(latency, inject_latency))
+        }
+        None => api,
+    }
+}
+
+// Resource shared by metrics and traces
+fn setup_resource(config: &Config, detected: Vec<KeyValue>) -> opentelemetry_sdk::Resource {
+    let telemetry = &config.telemetry;
+    let service_name = telemetry.service_name.clone();
+    let mut attributes = vec![
+        KeyValue::new(SERVICE_NAME, service_name.clone()),
+        KeyValue::new(SERVICE_VERSION, VERSION),
+        KeyValue::new(DEPLOYMENT_ENVIRONMENT_NAME, telemetry.environment.clone()),
+    ];
+    // Host, cloud and pod attributes, and the ones set in the configuration
+    attributes.retain(|a| !detected.iter().any(|d| d.key == a.key));
+    attributes.extend(detected);
+    // Probing location, so results from several regions can be told apart
+    if let Some(location) = &config.location {
+        attributes.push(KeyValue::new("location", location.clone()));
+    }
+    opentelemetry_sdk::Resource::builder()
+        .with_service_name(service_name)
+        .with_schema_url(attributes, SCHEMA_URL)
+        .build()
+}
+
+// Configuration MeterProvider
+fn setup_meter_provider(
+    otlp_status: Arc<ExportStatus>,
+    config: &Config,
+    resource: opentelemetry_sdk::Resource,
+) -> Result<SdkMeterProvider, ServerError> {
+    // Get a reference to the registry for reading metrics
+    let registry = GLOBAL_REGISTRY.lock().unwrap().to_owned();
+    let prometheus_exporter = opentelemetry_prometheus::exporter()
+        .with_registry(registry)
+        .build()
+        .unwrap();
+
+    let provider = MeterProviderBuilder::default()
+        .with_resource(resource)
+        .with_reader(prometheus_exporter);
+    let otlp = &config.telemetry.otlp;
+    if !otlp.enabled {
+        return Ok(provider.build());
+    }
+    let otlp_exporter = match otlp.protocol {
+        OtlpProtocol::Grpc => opentelemetry_otlp::MetricExporter::builder()
+            .with_tonic()
+            .with_endpoint(otlp.endpoint())
+            .with_timeout(otlp.timeout)
+            .with_temporality(Temporality::default())
+            .build(),
+        OtlpProtocol::Http => opentelemetry_otlp::MetricExporter::builder()
+            .with_http()
+            .with_protocol(opentelemetry_otlp::Protocol::HttpBinary)
+            .with_endpoint(otlp.endpoint())
+            .with_timeout(otlp.timeout)
+            .with_temporality(Temporality::default())
+            .build(),
+    };
+    // Checked when the configuration is loaded, but embedders may skip that
+    let otlp_exporter = otlp_exporter
+        .map_err(|e| ServerError(format!("failed to build the OTLP metric exporter: {e}")))?;
+    info!(
+        "Exporting metrics to {} every {:?}",
+        otlp.endpoint(),
+        otlp.interval
+    );
+    let otlp_reader = PeriodicReader::builder(TrackedExporter::new(otlp_exporter, otlp_status))
+        .with_interval(otlp.interval)
+        .build();
+    Ok(provider.with_reader(otlp_reader).build())
+}
+
+// Latency injection middleware
+async fn inject_latency(
+    State(latency): State<Latency>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    sleep(latency.sample()).await;
+    next.run(req).await
+}
+
+// Survivability check endpoints
+async fn liveness_probe() -> Json<serde_json::Value> {
+    Json(json!({
+        "status": "ok",
+        "message": "Service is alive"
+    }))
+}
+
+// Liveness failing when a background task misses its heartbeats or a check schedule stalls
+async fn watched_liveness_probe(State(state): State<AppState>) -> Response {
+    let mut wedged: Vec<String> = state
+        .watchdog
+        .missed()
+        .into_iter()
+        .map(|task| format!("{task} task missed its heartbeats"))
+        .collect();
+    // Followers do not run their checks
+    if state.checks.is_active() {
+        let ticks = state.checks.ticks();
+        let stalled = ticks.iter().filter(|(_, tick)| tick.stalled);
+        wedged.extend(stalled.map(|(name, _)| format!("check {name} stopped running")));
+    }
+    if wedged.is_empty() {
+        return liveness_probe().await.into_response();
+    }
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "status": "wedged",
+            "message": "Service is wedged",
+            "reasons": wedged
+        })),
+    )
+        .into_response()
+}
+
+// Startup endpoint, failing until every check has completed an execution
+async fn startup_probe(State(state): State<AppState>) -> Response {
+    if state.checks.is_active() && state.health.state() == HealthState::Starting {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "starting",
+                "message": "Waiting for the first execution of every check"
+            })),
+        )
+            .into_response();
+    }
+    Json(json!({
+        "status": "started",
+        "message": "Service has started"
+    }))
+    .into_response()
+}
+
+// Readiness check endpoints, optionally over a subset of checks (`?only=db` / `?exclude=redis,cache`)
+async fn readiness_probe(
+    State(state): State<AppState>,
+    Query(selector): Query<Selector>,
+) -> Response {
+    let unknown = state.checks.unknown(&selector);
+    if !unknown.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "status": "error",
+                "message": format!("Unknown checks: {}", unknown.join(", "))
+            })),
+        )
+            .into_response();
+    }
+
+    if state.service.is_draining() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "draining",
+                "message": "Service is draining"
+            })),
+        )
+            .into_response();
+    }
+
+    if state.checks.faults().readiness_failing() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "status": "not_ready",
+                "message": "Readiness failure injected"
+            })),
+        )
+            .into_response();
+    }
+
+    if !state.checks.is_active() {
+        return (
+            StatusCode::OK,
+            Json(json!({
+                "status": "standby",
+                "message": "Follower instance, checks are probed by the leader"
+            })),
+        )
+            .into_response();
+    }
+
+    let report = state.checks.report(&selector);
+    // Thresholds apply to the service as a whole; a subset is reported as it is
+    let health = match (&selector.only, &selector.exclude) {
+        (None, None) => state.health.state(),
+        _ => report.status.into(),
+    };
+    readiness_response(health, report)
+}
+
+// Readiness status and body for a health state: 503 when starting or unhealthy
+fn readiness_response(health: HealthState, report: CheckReport) -> Response {
+    let (code, status, message) = match health {
+        HealthState::Ready => (StatusCode::OK, "ok", "Service is ready"),
+        HealthState::Degraded => (StatusCode::OK, "degraded", "Service is ready (degraded)"),
+        HealthState::Starting => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "starting",
+            "Service is starting",
+        ),
+        HealthState::Unhealthy => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "not_ready",
+            "Service is not ready",
+        ),
+    };
+    (
+        code,
+        Json(json!({
+            "status": status,
+            "message": message,
+            "checks": report.checks
+        })),
+    )
+        .into_response()
+}
+
+// Version endpoint
+async fn version_handler() -> Json<serde_json::Value> {
+    Json(json!({
+        "version": VERSION,
+        "git_sha": GIT_SHA
+    }))
+}
+
+// Build information endpoint for fleet auditing
+async fn buildinfo_handler() -> Json<build_info::BuildInfo> {
+    Json(build_info::build_info())
+}
+
+// Sample API endpoint
+async fn api_example_handler() -> impl IntoResponse {
+    Json(json!({
+        "message": "API example response"
+    }))
+}
+
+// Example failed endpoint
+async fn api_fail_handler() -> impl IntoResponse {
+    (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+}
+
+// Prometheus metrics endpoint. The registry can only gather every metric family at once, so
+// those stay in memory for the scrape; their text encoding, the larger part, is streamed in
+// chunks of a few families instead of being buffered whole
+async fn metrics_handler() -> Response {
+    // Get a reference to the registry for reading metrics
+    let registry = GLOBAL_REGISTRY.lock().unwrap().to_owned();
+    let metric_families = registry.gather();
+    if metric_families.is_empty() {
+        warn!("No metrics available in Prometheus registry");
+    } else {
+        info!("Metrics collected: {} families", metric_families.len());
+    }
+    let chunks = stream::unfold(metric_families.into_iter(), |mut families| async move {
+        let encoder = TextEncoder::new();
+        let mut chunk = Vec::new();
+        while chunk.len() < METRICS_CHUNK_SIZE
+            && let Some(family) = families.next()
+        {
+            if let Err(e) = encoder.encode(std::slice::from_ref(&family), &mut chunk) {
+                warn!("Failed to encode metric family {}: {}", family.name(), e);
+                return Some((Err(e), families));
+            }
+        }
+        (!chunk.is_empty()).then_some((Ok(chunk), families))
+    });
+    let content_type = TextEncoder::new().format_type().to_string();
+    (
+        [(header::CONTENT_TYPE, content_type)],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
@@ -0,0 +1,63 @@
+use axum::{Extension, Router};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::time::{Duration, timeout};
+use tracing::{debug, info, warn};
+
+use crate::tls::{self, ClientIdentity, MtlsServer};
+
+// Serve the agent ingestion API over mutual TLS, tagging each request with the agent
+// identity from the verified client certificate, until `shutdown` completes
+pub async fn serve(
+    server: Arc<MtlsServer>,
+    listener: TcpListener,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) {
+    info!(
+        "Mutual TLS ingestion running at https://{}",
+        server.listen()
+    );
+    tokio::pin!(shutdown);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = &mut shutdown => break,
+        };
+        let (stream, peer) = match accepted {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Failed to accept mutual TLS connection: {}", e);
+                continue;
+            }
+        };
+        let acceptor = server.acceptor();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match timeout(Duration::from_secs(10), acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => return debug!("TLS handshake with {} failed: {}", peer, e),
+                Err(_) => return debug!("TLS handshake with {} timed out", peer),
+            };
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(tls::peer_identity);
+            let Some(identity) = identity else {
+                return debug!("Client certificate from {} names no agent", peer);
+            };
+            let service = TowerToHyperService::new(app.layer(Extension(ClientIdentity(identity))));
+            let connection = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+            if let Err(e) = connection {
+                debug!("Mutual TLS connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,47 @@
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+};
+
+use crate::checks::PingKind;
+
+use super::AppState;
+
+// Ping URLs for heartbeat checks, e.g. `curl -fsS http://hc:5000/ping/<id>` at the end of a cron job
+pub fn ping_router() -> Router<AppState> {
+    Router::new()
+        .route("/ping/{id}", get(ping_success).post(ping_success))
+        .route("/ping/{id}/fail", get(ping_failure).post(ping_failure))
+        .route("/ping/{id}/start", get(ping_start).post(ping_start))
+}
+
+async fn ping_success(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> (StatusCode, &'static str) {
+    record(&state, &id, PingKind::Success)
+}
+
+async fn ping_start(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> (StatusCode, &'static str) {
+    record(&state, &id, PingKind::Start)
+}
+
+async fn ping_failure(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> (StatusCode, &'static str) {
+    record(&state, &id, PingKind::Fail)
+}
+
+fn record(state: &AppState, id: &str, kind: PingKind) -> (StatusCode, &'static str) {
+    if state.checks.heartbeats().ping(id, kind) {
+        (StatusCode::OK, "OK")
+    } else {
+        (StatusCode::NOT_FOUND, "not found")
+    }
+}
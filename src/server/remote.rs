@@ -0,0 +1,254 @@
+use axum::{
+    Extension, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+};
+use serde_json::json;
+
+use crate::agent::{INGEST_PATH, IngestBatch};
+use crate::aggregator::{Aggregator, valid_agent_name};
+use crate::auth::bearer_token;
+use crate::checks::Selector;
+use crate::enrollment::{Access, AgentStatus, ENROLL_PATH, EnrollRequest};
+use crate::ha::HEARTBEAT_PATH;
+use crate::reconcile::{SourceResult, reconcile};
+use crate::tls::{CERTIFICATE_PATH, CertificateRequest, CertificateResponse, ClientIdentity};
+
+use super::AppState;
+
+// Aggregator endpoints for results pushed by remote agents
+pub fn ingest_router() -> Router<AppState> {
+    Router::new()
+        .route(INGEST_PATH, post(ingest_results))
+        .route(ENROLL_PATH, post(enroll_agent))
+        .route(CERTIFICATE_PATH, post(issue_certificate))
+        .route("/api/agents", get(list_agents))
+        .route(
+            "/api/agents/{agent}/checks/{check}/history",
+            get(check_history),
+        )
+        .route("/api/locations", get(by_location))
+}
+
+// High-availability pair endpoints
+pub fn ha_router() -> Router<AppState> {
+    Router::new()
+        .route(HEARTBEAT_PATH, get(ha_heartbeat))
+        .route("/api/ha/status", get(ha_status))
+}
+
+// Gossip cluster membership endpoints
+pub fn cluster_router() -> Router<AppState> {
+    Router::new()
+        .route("/api/cluster/members", get(cluster_members))
+        .route("/api/cluster/shards", get(cluster_shards))
+        .route("/api/cluster/peers", get(cluster_peers))
+}
+
+// Kubernetes Lease leader election endpoints
+pub fn leader_router() -> Router<AppState> {
+    Router::new().route("/api/leader", get(leader_status))
+}
+
+// Checks re-exposed from federated instances
+pub fn federation_router() -> Router<AppState> {
+    Router::new().route("/api/federation", get(federation_instances))
+}
+
+// Single view of every target, merging checks of the same target from all sources
+pub fn overview_router() -> Router<AppState> {
+    Router::new().route("/api/overview", get(overview))
+}
+
+// Authenticate an agent by its client certificate over mutual TLS, by bearer token otherwise,
+// returning the rejection when it may not push
+fn reject_agent(
+    state: &AppState,
+    agent: &str,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: &HeaderMap,
+    authorize: fn(&Aggregator, &str, Option<&str>) -> Access,
+) -> Option<Response> {
+    let access = match identity {
+        Some(Extension(ClientIdentity(identity))) if identity != agent => {
+            let message = json!({ "message": "client certificate was issued to another agent" });
+            return Some((StatusCode::FORBIDDEN, Json(message)).into_response());
+        }
+        Some(_) => state.aggregator.authorize_certificate(agent),
+        None => authorize(&state.aggregator, agent, bearer_token(headers)),
+    };
+    match access {
+        Access::Granted => None,
+        Access::Pending => {
+            // Agents keep their results until approved, recognizing this by `status`
+            let message = json!({
+                "message": "agent is awaiting approval",
+                "status": AgentStatus::Pending,
+            });
+            Some((StatusCode::FORBIDDEN, Json(message)).into_response())
+        }
+        Access::Denied => Some(StatusCode::UNAUTHORIZED.into_response()),
+    }
+}
+
+// Accept a batch of check results from an agent
+async fn ingest_results(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: HeaderMap,
+    Json(batch): Json<IngestBatch>,
+) -> Response {
+    if !valid_agent_name(&batch.agent) {
+        let message = json!({ "message": "agent must be non-empty without '/'" });
+        return (StatusCode::BAD_REQUEST, Json(message)).into_response();
+    }
+    if let Some(response) = reject_agent(
+        &state,
+        &batch.agent,
+        identity,
+        &headers,
+        Aggregator::authorize,
+    ) {
+        return response;
+    }
+    let accepted = state.aggregator.ingest(batch, &state.checks);
+    (StatusCode::ACCEPTED, Json(json!({ "accepted": accepted }))).into_response()
+}
+
+// Exchange a one-time enrollment token for an agent identity and credential
+async fn enroll_agent(
+    State(state): State<AppState>,
+    Json(request): Json<EnrollRequest>,
+) -> Response {
+    if !valid_agent_name(&request.agent) {
+        let message = json!({ "message": "agent must be non-empty without '/'" });
+        return (StatusCode::BAD_REQUEST, Json(message)).into_response();
+    }
+    match state.aggregator.enrollment().enroll(request) {
+        Some(enrolled) => (StatusCode::CREATED, Json(enrolled)).into_response(),
+        None => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+// Sign an agent's certificate request with the built-in CA, for a new or renewed certificate
+async fn issue_certificate(
+    State(state): State<AppState>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: HeaderMap,
+    Json(request): Json<CertificateRequest>,
+) -> Response {
+    let Some((mtls, ca)) = state.mtls.as_ref().and_then(|m| Some((m, m.ca()?))) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // Certificates are only issued to enrolled agents, proving it with their credential or
+    // the certificate being renewed
+    if let Some(response) = reject_agent(
+        &state,
+        &request.agent,
+        identity,
+        &headers,
+        Aggregator::authorize_enrolled,
+    ) {
+        return response;
+    }
+    match ca.sign_request(&request.agent, &request.csr, mtls.cert_validity()) {
+        Ok(certificate) => Json(CertificateResponse {
+            certificate,
+            ca: ca.pem().to_string(),
+        })
+        .into_response(),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({ "message": e }))).into_response(),
+    }
+}
+
+async fn list_agents(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.aggregator.agents())
+}
+
+async fn by_location(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.aggregator.by_location())
+}
+
+async fn check_history(
+    State(state): State<AppState>,
+    Path((agent, check)): Path<(String, String)>,
+) -> Response {
+    match state.aggregator.history(&agent, &check) {
+        Some(history) => Json(history).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn ha_heartbeat(State(state): State<AppState>) -> Response {
+    match &state.ha {
+        Some(ha) => Json(ha.heartbeat()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn ha_status(State(state): State<AppState>) -> Response {
+    match &state.ha {
+        Some(ha) => Json(ha.status()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn cluster_members(State(state): State<AppState>) -> Response {
+    match &state.cluster {
+        Some(cluster) => Json(cluster.view()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn cluster_peers(State(state): State<AppState>) -> Response {
+    match &state.cluster {
+        Some(cluster) => {
+            Json(cluster.peers(state.checks.names().iter().map(String::as_str))).into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn leader_status(State(state): State<AppState>) -> Response {
+    match &state.leader {
+        Some(leader) => Json(leader.status()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn cluster_shards(State(state): State<AppState>) -> Response {
+    match &state.cluster {
+        Some(cluster) => Json(cluster.assignments(state.checks.names().iter().map(String::as_str)))
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn federation_instances(State(state): State<AppState>) -> Response {
+    match &state.federation {
+        Some(federation) => Json(federation.instances()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn overview(State(state): State<AppState>) -> impl IntoResponse {
+    let report = state.checks.report(&Selector::default());
+    let mut results: Vec<_> = report
+        .checks
+        .into_iter()
+        .map(|(check, report)| SourceResult {
+            source: "local".to_string(),
+            check,
+            result: report.result,
+        })
+        .collect();
+    if state.config.aggregator.enabled {
+        results.extend(state.aggregator.results());
+    }
+    if let Some(federation) = &state.federation {
+        results.extend(federation.results());
+    }
+    Json(reconcile(results))
+}
@@ -0,0 +1,129 @@
+use axum::{
+    Router,
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tracing::info;
+
+use crate::checks::{CheckRegistry, CheckStatus, Selector};
+use crate::config::ServerConfig;
+
+use super::{ServerError, listener, readiness_response, version_handler};
+
+// Nothing runs between invocations on serverless platforms, so checks run when requested
+pub fn serverless_router(checks: Arc<CheckRegistry>) -> Router {
+    Router::new()
+        .route("/health/live", get(super::liveness_probe))
+        .route("/health/ready", get(readiness))
+        .route("/probe", get(probe))
+        .route("/api/status", get(status))
+        .route("/version", get(version_handler))
+        .with_state(checks)
+}
+
+fn unknown_checks(checks: &CheckRegistry, selector: &Selector) -> Option<Response> {
+    let unknown = checks.unknown(selector);
+    if unknown.is_empty() {
+        return None;
+    }
+    let message = format!("Unknown checks: {}", unknown.join(", "));
+    Some((StatusCode::BAD_REQUEST, Json(json!({ "message": message }))).into_response())
+}
+
+// Readiness over the selected checks, executed now
+async fn readiness(
+    State(checks): State<Arc<CheckRegistry>>,
+    Query(selector): Query<Selector>,
+) -> Response {
+    if let Some(response) = unknown_checks(&checks, &selector) {
+        return response;
+    }
+    let report = checks.run_once(&selector).await;
+    readiness_response(report.status.into(), report)
+}
+
+// Execute the selected checks and return their report; 503 when it is down, for external
+// monitors that only look at the status code
+async fn probe(
+    State(checks): State<Arc<CheckRegistry>>,
+    Query(selector): Query<Selector>,
+) -> Response {
+    if let Some(response) = unknown_checks(&checks, &selector) {
+        return response;
+    }
+    let report = checks.run_once(&selector).await;
+    let code = match report.status {
+        CheckStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    (code, Json(report)).into_response()
+}
+
+// Results of the last probes served by this instance, while the platform keeps it warm
+async fn status(
+    State(checks): State<Arc<CheckRegistry>>,
+    Query(selector): Query<Selector>,
+) -> Response {
+    if let Some(response) = unknown_checks(&checks, &selector) {
+        return response;
+    }
+    Json(checks.report(&selector)).into_response()
+}
+
+// Run as an AWS Lambda function when started by the Lambda runtime, otherwise serve HTTP as
+// Cloud Functions and Cloud Run expect
+pub async fn run(app: Router, addr: SocketAddr, config: ServerConfig) -> Result<(), ServerError> {
+    #[cfg(feature = "lambda")]
+    if std::env::var_os("AWS_LAMBDA_RUNTIME_API").is_some() {
+        info!("Running as an AWS Lambda function");
+        return lambda_http::run(app)
+            .await
+            .map_err(|e| ServerError(e.to_string()));
+    }
+    let listener = match listener::bind_tcp(addr) {
+        Ok(listener) => listener,
+        Err(e) => return Err(ServerError(format!("failed to bind {addr}: {e}"))),
+    };
+    info!("Serverless handler running at http://{}", addr);
+    listener::serve(listener, app, config, None, crate::lifecycle::signal()).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::UnavailableCheck;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn checks_run_when_probed() {
+        let mut checks = CheckRegistry::default();
+        let interval = Duration::from_secs(60);
+        let unavailable = Box::new(UnavailableCheck("refused".into()));
+        checks.register("db", true, interval, interval, unavailable);
+        let app = serverless_router(Arc::new(checks));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let get = |path: &str| reqwest::get(format!("{base}{path}"));
+
+        // Nothing ran in the background
+        let status: serde_json::Value = get("/api/status").await.unwrap().json().await.unwrap();
+        assert_eq!(status["checks"]["db"]["message"], "pending first check");
+
+        let probe = get("/probe").await.unwrap();
+        assert_eq!(probe.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let report: serde_json::Value = probe.json().await.unwrap();
+        assert_eq!(report["checks"]["db"]["message"], "refused");
+        let status: serde_json::Value = get("/api/status").await.unwrap().json().await.unwrap();
+        assert_eq!(status["checks"]["db"]["message"], "refused");
+
+        let unknown = get("/health/ready?only=cache").await.unwrap();
+        assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,356 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use std::sync::{Arc, Mutex};
+use sysinfo::{
+    Disks, MemoryRefreshKind, Networks, Pid, ProcessRefreshKind, System, get_current_pid,
+};
+use tokio::time::sleep;
+use tracing::{debug, warn};
+
+use crate::config::SystemMetricsConfig;
+
+/// Bytes per sector in `/proc/diskstats`, whatever the device's sector size
+const SECTOR_SIZE: u64 = 512;
+
+/// Host measurements of the last refresh, read by the instrument callbacks
+#[derive(Default)]
+struct Snapshot {
+    /// Fraction of all CPUs
+    cpu: f64,
+    memory: u64,
+    disks: Vec<DiskStats>,
+    /// Bytes received and transmitted, by interface
+    networks: Vec<(String, u64, u64)>,
+    /// 1, 5 and 15 minute load averages
+    load: [f64; 3],
+    process: ProcessStats,
+}
+
+struct DiskStats {
+    attributes: [KeyValue; 2],
+    total: u64,
+    available: u64,
+    /// Bytes read and written, for block devices only
+    io: Option<(u64, u64)>,
+}
+
+#[derive(Default)]
+struct ProcessStats {
+    resident: u64,
+    /// In cores, so a fully busy thread is 1
+    cpu: f64,
+    open_fds: Option<u64>,
+}
+
+/// Register the instruments of the enabled host metric groups and refresh their values every
+/// `interval`. Nothing is collected for disabled groups
+pub(crate) fn spawn(meter: &Meter, config: SystemMetricsConfig) {
+    let groups = [
+        config.cpu,
+        config.memory,
+        config.disks,
+        config.network,
+        config.load,
+        config.process,
+    ];
+    if !groups.contains(&true) {
+        return;
+    }
+    let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+    register(meter, &config, &snapshot);
+    tokio::spawn(async move {
+        let mut collector = Collector::new(&config);
+        loop {
+            // Reading procfs and statting disks blocks, so it stays off the runtime's workers
+            let blocking_config = config.clone();
+            let refresh = tokio::task::spawn_blocking(move || {
+                let refreshed = collector.refresh(&blocking_config);
+                (collector, refreshed)
+            });
+            let (returned, refreshed) = match refresh.await {
+                Ok(refreshed) => refreshed,
+                Err(e) => {
+                    warn!("Host metrics collection stopped: {}", e);
+                    return;
+                }
+            };
+            collector = returned;
+            *snapshot.lock().unwrap() = refreshed;
+            sleep(config.interval).await;
+        }
+    });
+}
+
+/// Reports the values of an instrument from a snapshot, each with its attributes
+type Read = fn(&Snapshot, &mut dyn FnMut(f64, &[KeyValue]));
+
+fn gauge(meter: &Meter, snapshot: &Arc<Mutex<Snapshot>>, name: &'static str, read: Read) {
+    let snapshot = snapshot.clone();
+    meter
+        .f64_observable_gauge(name)
+        .with_callback(move |observer| {
+            read(&snapshot.lock().unwrap(), &mut |value, attributes| {
+                observer.observe(value, attributes)
+            })
+        })
+        .build();
+}
+
+fn counter(meter: &Meter, snapshot: &Arc<Mutex<Snapshot>>, name: &'static str, read: Read) {
+    let snapshot = snapshot.clone();
+    meter
+        .f64_observable_counter(name)
+        .with_callback(move |observer| {
+            read(&snapshot.lock().unwrap(), &mut |value, attributes| {
+                observer.observe(value, attributes)
+            })
+        })
+        .build();
+}
+
+fn register(meter: &Meter, config: &SystemMetricsConfig, snapshot: &Arc<Mutex<Snapshot>>) {
+    let gauge = |name, read| gauge(meter, snapshot, name, read);
+    let counter = |name, read| counter(meter, snapshot, name, read);
+    if config.cpu {
+        gauge("system_cpu_usage", |s, observe| observe(s.cpu, &[]));
+    }
+    if config.memory {
+        gauge("system_mem_used", |s, observe| {
+            observe(s.memory as f64, &[])
+        });
+    }
+    if config.disks {
+        gauge("system_disk_total_bytes", |s, observe| {
+            for disk in &s.disks {
+                observe(disk.total as f64, &disk.attributes);
+            }
+        });
+        gauge("system_disk_available_bytes", |s, observe| {
+            for disk in &s.disks {
+                observe(disk.available as f64, &disk.attributes);
+            }
+        });
+        counter("system_disk_read_bytes", |s, observe| {
+            for disk in &s.disks {
+                if let Some((read, _)) = disk.io {
+                    observe(read as f64, &disk.attributes);
+                }
+            }
+        });
+        counter("system_disk_written_bytes", |s, observe| {
+            for disk in &s.disks {
+                if let Some((_, written)) = disk.io {
+                    observe(written as f64, &disk.attributes);
+                }
+            }
+        });
+    }
+    if config.network {
+        counter("system_network_received_bytes", |s, observe| {
+            for (interface, received, _) in &s.networks {
+                observe(
+                    *received as f64,
+                    &[KeyValue::new("interface", interface.clone())],
+                );
+            }
+        });
+        counter("system_network_transmitted_bytes", |s, observe| {
+            for (interface, _, transmitted) in &s.networks {
+                let attributes = [KeyValue::new("interface", interface.clone())];
+                observe(*transmitted as f64, &attributes);
+            }
+        });
+    }
+    if config.load {
+        gauge("system_load_average", |s, observe| {
+            for (period, load) in ["1m", "5m", "15m"].into_iter().zip(s.load) {
+                observe(load, &[KeyValue::new("period", period)]);
+            }
+        });
+    }
+    if config.process {
+        gauge("process_resident_memory_bytes", |s, observe| {
+            observe(s.process.resident as f64, &[])
+        });
+        gauge("process_cpu_usage", |s, observe| {
+            observe(s.process.cpu, &[])
+        });
+        gauge("process_open_fds", |s, observe| {
+            if let Some(open) = s.process.open_fds {
+                observe(open as f64, &[]);
+            }
+        });
+    }
+}
+
+/// sysinfo state kept between refreshes, which CPU usage needs
+struct Collector {
+    system: System,
+    disks: Disks,
+    networks: Networks,
+    pid: Option<Pid>,
+}
+
+impl Collector {
+    fn new(config: &SystemMetricsConfig) -> Self {
+        Self {
+            system: System::new(),
+            disks: match config.disks {
+                true => Disks::new_with_refreshed_list(),
+                false => Disks::new(),
+            },
+            networks: match config.network {
+                true => Networks::new_with_refreshed_list(),
+                false => Networks::new(),
+            },
+            pid: get_current_pid()
+                .inspect_err(|e| debug!("Process metrics unavailable: {}", e))
+                .ok(),
+        }
+    }
+
+    fn refresh(&mut self, config: &SystemMetricsConfig) -> Snapshot {
+        let mut snapshot = Snapshot::default();
+        if config.cpu {
+            self.system.refresh_cpu_usage();
+            snapshot.cpu = self.system.global_cpu_info().cpu_usage() as f64 / 100.0;
+        }
+        if config.memory {
+            let ram = MemoryRefreshKind::new().with_ram();
+            self.system.refresh_memory_specifics(ram);
+            snapshot.memory = self.system.used_memory();
+        }
+        if config.disks {
+            // Mounts come and go, e.g. volumes attached at runtime
+            self.disks.refresh_list();
+            let io = diskstats();
+            snapshot.disks = self
+                .disks
+                .list()
+                .iter()
+                .map(|disk| {
+                    let device = disk.name().to_string_lossy().into_owned();
+                    let name = device.strip_prefix("/dev/").unwrap_or(&device);
+                    DiskStats {
+                        io: io
+                            .iter()
+                            .find(|(n, ..)| n == name)
+                            .map(|(_, r, w)| (*r, *w)),
+                        attributes: [
+                            KeyValue::new("mount", disk.mount_point().display().to_string()),
+                            KeyValue::new("device", device.clone()),
+                        ],
+                        total: disk.total_space(),
+                        available: disk.available_space(),
+                    }
+                })
+                .collect();
+        }
+        if config.network {
+            self.networks.refresh_list();
+            snapshot.networks = self
+                .networks
+                .list()
+                .iter()
+                .map(|(name, data)| {
+                    (
+                        name.clone(),
+                        data.total_received(),
+                        data.total_transmitted(),
+                    )
+                })
+                .collect();
+        }
+        if config.load {
+            let load = System::load_average();
+            snapshot.load = [load.one, load.five, load.fifteen];
+        }
+        if config.process
+            && let Some(pid) = self.pid
+        {
+            let refresh = ProcessRefreshKind::new().with_cpu().with_memory();
+            self.system.refresh_process_specifics(pid, refresh);
+            if let Some(process) = self.system.process(pid) {
+                snapshot.process = ProcessStats {
+                    resident: process.memory(),
+                    cpu: process.cpu_usage() as f64 / 100.0,
+                    open_fds: open_fds(),
+                };
+            }
+        }
+        snapshot
+    }
+}
+
+// Bytes read and written by each block device, from /proc/diskstats
+fn diskstats() -> Vec<(String, u64, u64)> {
+    let Ok(stats) = std::fs::read_to_string("/proc/diskstats") else {
+        return Vec::new();
+    };
+    stats
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let sectors = |i: usize| fields.get(i)?.parse::<u64>().ok();
+            let (read, written) = (sectors(5)?, sectors(9)?);
+            Some((
+                fields[2].to_string(),
+                read * SECTOR_SIZE,
+                written * SECTOR_SIZE,
+            ))
+        })
+        .collect()
+}
+
+fn open_fds() -> Option<u64> {
+    let entries = std::fs::read_dir("/proc/self/fd").ok()?;
+    Some(entries.count() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::SdkMeterProvider;
+    use prometheus::Registry;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn publishes_refreshed_values_of_enabled_groups() {
+        let registry = Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()
+            .unwrap();
+        let provider = SdkMeterProvider::builder().with_reader(exporter).build();
+        let config: SystemMetricsConfig =
+            toml::from_str("interval = \"10ms\"\ncpu = false\nload = false").unwrap();
+        spawn(&provider.meter("test"), config);
+
+        // Refreshes run on the blocking pool and publish their snapshot to the instruments
+        let mut ticks = 0;
+        loop {
+            sleep(Duration::from_millis(10)).await;
+            ticks += 1;
+            let resident = registry
+                .gather()
+                .into_iter()
+                .find(|family| family.name() == "process_resident_memory_bytes")
+                .map(|family| family.get_metric()[0].get_gauge().value());
+            match resident {
+                Some(resident) if resident > 0.0 => break,
+                _ => assert!(ticks < 500, "no host metrics collected"),
+            }
+        }
+        let names: Vec<_> = registry
+            .gather()
+            .into_iter()
+            .map(|family| family.name().to_string())
+            .collect();
+        assert!(names.contains(&"system_mem_used".to_string()), "{names:?}");
+        assert!(
+            !names.contains(&"system_cpu_usage".to_string()),
+            "{names:?}"
+        );
+    }
+}
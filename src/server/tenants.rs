@@ -0,0 +1,123 @@
+use axum::{
+    Router,
+    body::Body,
+    extract::{Path, State},
+    http::{Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get},
+};
+use serde_json::json;
+use std::collections::HashMap;
+use tracing::warn;
+
+use crate::auth::{bearer_token, constant_time_eq};
+use crate::silence::NewSilence;
+
+use super::AppState;
+
+// APIs of a single tenant, authenticated with its token and limited to its checks
+pub fn tenants_router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/api/tenants/{tenant}/status", get(status))
+        .route(
+            "/api/tenants/{tenant}/checks/{name}/history",
+            get(check_history),
+        )
+        .route(
+            "/api/tenants/{tenant}/silences",
+            get(list_silences).post(create_silence),
+        )
+        .route(
+            "/api/tenants/{tenant}/silences/{id}",
+            delete(remove_silence),
+        )
+        .route_layer(middleware::from_fn_with_state(state, require_tenant_token))
+}
+
+// Reject requests without the token of the tenant in the path
+async fn require_tenant_token(
+    State(state): State<AppState>,
+    Path(params): Path<HashMap<String, String>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let tenant = state
+        .config
+        .tenants
+        .iter()
+        .find(|t| Some(&t.name) == params.get("tenant"));
+    match (tenant, bearer_token(req.headers())) {
+        (Some(tenant), Some(given)) if constant_time_eq(&tenant.token, given) => {
+            next.run(req).await
+        }
+        // Unknown tenants are indistinguishable from a wrong token
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+// Cached results of the tenant's checks, by their name within the tenant
+async fn status(State(state): State<AppState>, Path(tenant): Path<String>) -> Response {
+    let prefix = format!("{tenant}/");
+    let mut report = state
+        .checks
+        .report_matching(|name| name.starts_with(&prefix));
+    report.checks = std::mem::take(&mut report.checks)
+        .into_iter()
+        .filter_map(|(name, check)| Some((name.strip_prefix(&prefix)?.to_string(), check)))
+        .collect();
+    Json(report).into_response()
+}
+
+async fn check_history(
+    State(state): State<AppState>,
+    Path((tenant, name)): Path<(String, String)>,
+) -> Response {
+    match state.checks.history(&format!("{tenant}/{name}")) {
+        Some(history) => Json(history).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn list_silences(State(state): State<AppState>, Path(tenant): Path<String>) -> Response {
+    let silences = state.notifier.silences().list();
+    let owned: Vec<_> = silences
+        .into_iter()
+        .filter(|s| s.tenant.as_deref() == Some(tenant.as_str()))
+        .collect();
+    Json(owned).into_response()
+}
+
+// Mute notifications of the tenant's checks matching the pattern
+async fn create_silence(
+    State(state): State<AppState>,
+    Path(tenant): Path<String>,
+    Json(body): Json<NewSilence>,
+) -> Response {
+    match state.notifier.silences().create_for(Some(&tenant), body) {
+        Ok(silence) => {
+            warn!(
+                "Tenant {} silencing checks {} until {}",
+                tenant, silence.checks, silence.ends_at
+            );
+            (StatusCode::CREATED, Json(silence)).into_response()
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, Json(json!({ "message": e }))).into_response(),
+    }
+}
+
+async fn remove_silence(
+    State(state): State<AppState>,
+    Path((tenant, id)): Path<(String, u64)>,
+) -> StatusCode {
+    let silences = state.notifier.silences();
+    let owned = silences
+        .list()
+        .iter()
+        .any(|s| s.id == id && s.tenant.as_deref() == Some(tenant.as_str()));
+    if owned && silences.remove(id) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
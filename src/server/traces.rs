@@ -0,0 +1,90 @@
+use axum::{body::Body, extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use opentelemetry::trace::{FutureExt, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{KeyValue, global};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use opentelemetry_semantic_conventions::trace::{
+    HTTP_REQUEST_METHOD, HTTP_RESPONSE_STATUS_CODE, HTTP_ROUTE, URL_PATH,
+};
+use tracing::info;
+
+use super::ServerError;
+use super::metrics::method_label;
+use crate::config::TelemetryConfig;
+
+/// Export spans over OTLP gRPC and propagate W3C trace context, when tracing is enabled
+pub(crate) fn setup_tracer_provider(
+    telemetry: &TelemetryConfig,
+    resource: Resource,
+) -> Result<Option<SdkTracerProvider>, ServerError> {
+    let tracing = &telemetry.tracing;
+    if !tracing.enabled {
+        return Ok(None);
+    }
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(telemetry.tracing_endpoint())
+        .with_timeout(telemetry.otlp.timeout)
+        .build()
+        // Checked when the configuration is loaded, but embedders may skip that
+        .map_err(|e| ServerError(format!("failed to build the OTLP span exporter: {e}")))?;
+    // Traces started by callers keep their sampling decision
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(tracing.sample_ratio)));
+    let provider = SdkTracerProvider::builder()
+        .with_resource(resource)
+        .with_sampler(sampler)
+        .with_batch_exporter(exporter)
+        .build();
+    info!(
+        "Exporting traces to {}, sampling {}",
+        telemetry.tracing_endpoint(),
+        tracing.sample_ratio
+    );
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider.clone());
+    Ok(Some(provider))
+}
+
+// Server span per request, child of the caller's span when the request has a `traceparent`
+pub(crate) async fn trace_requests(req: Request<Body>, next: Next) -> Response {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    let method = method_label(req.method());
+    let route = req.extensions().get::<MatchedPath>().cloned();
+    let mut attributes = vec![
+        KeyValue::new(HTTP_REQUEST_METHOD, method),
+        KeyValue::new(URL_PATH, req.uri().path().to_string()),
+    ];
+    let name = match &route {
+        Some(route) => {
+            attributes.push(KeyValue::new(HTTP_ROUTE, route.as_str().to_string()));
+            format!("{method} {}", route.as_str())
+        }
+        None => method.to_string(),
+    };
+    let tracer = global::tracer("healthcheck-service");
+    let span = tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Server)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, &parent);
+    let cx = parent.with_span(span);
+
+    let response = next.run(req).with_context(cx.clone()).await;
+
+    let span = cx.span();
+    let status = response.status();
+    span.set_attribute(KeyValue::new(
+        HTTP_RESPONSE_STATUS_CODE,
+        status.as_u16() as i64,
+    ));
+    if status.is_server_error() {
+        span.set_status(Status::error(status.to_string()));
+    }
+    span.end();
+    response
+}
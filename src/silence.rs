@@ -0,0 +1,201 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::checks::unix_now;
+
+/// Notifications muted for the matching checks until `ends_at`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Silence {
+    pub id: u64,
+    /// Regex the check name must fully match
+    pub checks: String,
+    /// Tenant that created the silence; it only matches the tenant's checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// Unix timestamps (seconds)
+    pub created_at: u64,
+    pub ends_at: u64,
+}
+
+/// Request to silence checks for a while
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewSilence {
+    pub checks: String,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Active silences, consulted before every notification
+#[derive(Default)]
+pub struct Silences {
+    next_id: AtomicU64,
+    active: RwLock<Vec<(Silence, Regex)>>,
+}
+
+impl Silence {
+    // The tenant's prefix is matched literally so the pattern cannot reach other namespaces
+    fn matches(&self, regex: &Regex, check: &str) -> bool {
+        match &self.tenant {
+            Some(tenant) => check
+                .strip_prefix(tenant.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+                .is_some_and(|name| regex.is_match(name)),
+            None => regex.is_match(check),
+        }
+    }
+}
+
+impl Silences {
+    pub fn create(&self, request: NewSilence) -> Result<Silence, String> {
+        self.create_for(None, request)
+    }
+
+    /// Create a silence on behalf of a tenant, matching the check names within its namespace
+    pub fn create_for(&self, tenant: Option<&str>, request: NewSilence) -> Result<Silence, String> {
+        // Compiled on its own first: an unbalanced pattern such as `x)|(?:.*` would otherwise
+        // close the anchoring group and match every name
+        let regex = Regex::new(&request.checks)
+            .and_then(|_| Regex::new(&format!("^(?:{})$", request.checks)))
+            .map_err(|e| format!("invalid checks pattern: {e}"))?;
+        let created_at = unix_now();
+        let ends_at = created_at
+            .checked_add(request.duration.as_secs().max(1))
+            .ok_or_else(|| {
+                format!(
+                    "duration {} is too long",
+                    humantime::format_duration(request.duration)
+                )
+            })?;
+        let silence = Silence {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            checks: request.checks,
+            tenant: tenant.map(str::to_string),
+            comment: request.comment,
+            created_at,
+            ends_at,
+        };
+        let mut active = self.active.write().unwrap();
+        active.retain(|(s, _)| s.ends_at > created_at);
+        active.push((silence.clone(), regex));
+        Ok(silence)
+    }
+
+    /// Silences that have not ended yet
+    pub fn list(&self) -> Vec<Silence> {
+        let now = unix_now();
+        let active = self.active.read().unwrap();
+        active
+            .iter()
+            .filter(|(s, _)| s.ends_at > now)
+            .map(|(s, _)| s.clone())
+            .collect()
+    }
+
+    /// End a silence early
+    pub fn remove(&self, id: u64) -> bool {
+        let mut active = self.active.write().unwrap();
+        let before = active.len();
+        active.retain(|(s, _)| s.id != id);
+        active.len() != before
+    }
+
+    pub fn is_silenced(&self, check: &str) -> bool {
+        let now = unix_now();
+        let active = self.active.read().unwrap();
+        active
+            .iter()
+            .any(|(s, regex)| s.ends_at > now && s.matches(regex, check))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn silence(checks: &str) -> NewSilence {
+        NewSilence {
+            checks: checks.into(),
+            duration: Duration::from_secs(60),
+            comment: None,
+        }
+    }
+
+    #[test]
+    fn silences_fully_matching_checks() {
+        let silences = Silences::default();
+        silences.create(silence("db|cache-.*")).unwrap();
+        assert!(silences.is_silenced("db"));
+        assert!(silences.is_silenced("cache-eu"));
+        assert!(!silences.is_silenced("db-replica"));
+    }
+
+    #[test]
+    fn tenant_silences_only_match_the_tenant_checks() {
+        let silences = Silences::default();
+        let created = silences
+            .create_for(Some("payments"), silence("db|.*/x"))
+            .unwrap();
+        assert_eq!(created.tenant.as_deref(), Some("payments"));
+        assert!(silences.is_silenced("payments/db"));
+        assert!(!silences.is_silenced("db"));
+        assert!(!silences.is_silenced("search/db"));
+        assert!(!silences.is_silenced("search/x"));
+    }
+
+    #[test]
+    fn tenant_patterns_cannot_escape_the_tenant_namespace() {
+        let silences = Silences::default();
+        silences
+            .create_for(Some("payments"), silence(".*"))
+            .unwrap();
+        assert!(silences.is_silenced("payments/db"));
+        assert!(!silences.is_silenced("search/db"));
+        assert!(!silences.is_silenced("db"));
+        assert!(!silences.is_silenced("paymentsx/db"));
+    }
+
+    #[test]
+    fn unbalanced_patterns_cannot_escape_the_anchors() {
+        let silences = Silences::default();
+        // Would compile to `^(?:x)|(?:.*)$` once wrapped, which matches any name
+        let error = silences.create(silence("x)|(?:.*")).unwrap_err();
+        assert!(error.starts_with("invalid checks pattern"), "{error}");
+        assert!(!silences.is_silenced("db"));
+    }
+
+    #[test]
+    fn removed_silences_stop_matching() {
+        let silences = Silences::default();
+        let created = silences.create(silence("db")).unwrap();
+        assert_eq!(silences.list().len(), 1);
+        assert!(silences.remove(created.id));
+        assert!(!silences.remove(created.id));
+        assert!(!silences.is_silenced("db"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let error = Silences::default().create(silence("(")).unwrap_err();
+        assert!(error.starts_with("invalid checks pattern"), "{error}");
+    }
+
+    #[test]
+    fn rejects_durations_past_the_end_of_time() {
+        let request = NewSilence {
+            duration: Duration::MAX,
+            ..silence("db")
+        };
+        let error = Silences::default().create(request).unwrap_err();
+        assert!(
+            error.starts_with("duration") && error.ends_with("is too long"),
+            "{error}"
+        );
+    }
+}
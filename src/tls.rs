@@ -0,0 +1,433 @@
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, CertificateSigningRequestParams,
+    DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::interval;
+use tokio_rustls::TlsAcceptor;
+use tracing::{info, warn};
+
+use crate::config::{ConfigError, MtlsConfig, TlsConfig};
+use crate::lifecycle::Hangups;
+
+/// Path of the endpoint agents obtain certificates from the built-in CA through
+pub const CERTIFICATE_PATH: &str = "/api/ingest/certificate";
+
+const CA_VALIDITY: Duration = Duration::from_secs(10 * 365 * 24 * 3600);
+
+/// Certificate signing request sent by an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateRequest {
+    pub agent: String,
+    /// PEM-encoded PKCS#10 request
+    pub csr: String,
+}
+
+/// Certificate issued by the built-in CA, with the CA certificate to trust
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateResponse {
+    pub certificate: String,
+    pub ca: String,
+}
+
+/// Agent identity taken from the verified client certificate of a mutual TLS connection
+#[derive(Debug, Clone)]
+pub struct ClientIdentity(pub String);
+
+/// Lightweight CA issuing the server and agent certificates of the push channel
+pub struct CertificateAuthority {
+    cert: Certificate,
+    key: KeyPair,
+    pem: String,
+}
+
+impl CertificateAuthority {
+    /// Load the CA kept in `dir`, creating it on first use
+    pub fn load_or_create(dir: &Path) -> Result<Self, ConfigError> {
+        let cert_path = dir.join("ca.pem");
+        let key_path = dir.join("ca-key.pem");
+        let invalid =
+            |e: rcgen::Error| ConfigError(format!("invalid CA in {}: {e}", dir.display()));
+        if cert_path.exists() {
+            let pem = read(&cert_path)?;
+            let key = KeyPair::from_pem(&read(&key_path)?).map_err(invalid)?;
+            // Re-signing the parsed parameters yields an issuer with the same name and key
+            let cert = CertificateParams::from_ca_cert_pem(&pem)
+                .and_then(|params| params.self_signed(&key))
+                .map_err(invalid)?;
+            return Ok(Self { cert, key, pem });
+        }
+        let key = KeyPair::generate().map_err(invalid)?;
+        let mut params = CertificateParams::default();
+        params.distinguished_name = common_name("healthcheck-service CA");
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![
+            KeyUsagePurpose::KeyCertSign,
+            KeyUsagePurpose::CrlSign,
+            KeyUsagePurpose::DigitalSignature,
+        ];
+        set_validity(&mut params, CA_VALIDITY);
+        let cert = params.self_signed(&key).map_err(invalid)?;
+        let pem = cert.pem();
+        let written = std::fs::create_dir_all(dir)
+            .and_then(|_| write_private(&key_path, key.serialize_pem().as_bytes()))
+            .and_then(|_| std::fs::write(&cert_path, &pem));
+        written
+            .map_err(|e| ConfigError(format!("failed to write CA to {}: {e}", dir.display())))?;
+        info!("Created certificate authority in {}", dir.display());
+        Ok(Self { cert, key, pem })
+    }
+
+    pub fn pem(&self) -> &str {
+        &self.pem
+    }
+
+    /// Issue a server certificate and key for the given host names and addresses
+    pub fn issue_server(
+        &self,
+        names: &[String],
+        validity: Duration,
+    ) -> Result<(String, String), String> {
+        let key = KeyPair::generate().map_err(|e| e.to_string())?;
+        let mut params = CertificateParams::new(names.to_vec()).map_err(|e| e.to_string())?;
+        params.distinguished_name =
+            common_name(names.first().map_or("healthcheck-service", String::as_str));
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        set_validity(&mut params, validity);
+        let cert = params
+            .signed_by(&key, &self.cert, &self.key)
+            .map_err(|e| e.to_string())?;
+        Ok((cert.pem(), key.serialize_pem()))
+    }
+
+    /// Sign an agent's certificate request; the certificate names the agent as its common name
+    pub fn sign_request(
+        &self,
+        agent: &str,
+        csr: &str,
+        validity: Duration,
+    ) -> Result<String, String> {
+        let csr = CertificateSigningRequestParams::from_pem(csr)
+            .map_err(|e| format!("invalid certificate request: {e}"))?;
+        // Only the public key is taken from the request, the identity is the authenticated one
+        let mut params = CertificateParams::default();
+        params.distinguished_name = common_name(agent);
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        set_validity(&mut params, validity);
+        let cert = params
+            .signed_by(&csr.public_key, &self.cert, &self.key)
+            .map_err(|e| e.to_string())?;
+        Ok(cert.pem())
+    }
+}
+
+fn common_name(name: &str) -> DistinguishedName {
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, name);
+    distinguished_name
+}
+
+fn set_validity(params: &mut CertificateParams, validity: Duration) {
+    let now = time::OffsetDateTime::now_utc();
+    // Tolerate some clock skew between agents and the aggregator
+    params.not_before = now - Duration::from_secs(300);
+    params.not_after = now + validity;
+}
+
+/// Create a key and a certificate request for an agent, both PEM-encoded
+pub fn certificate_request(agent: &str) -> Result<(String, String), String> {
+    let key = KeyPair::generate().map_err(|e| e.to_string())?;
+    let mut params = CertificateParams::default();
+    params.distinguished_name = common_name(agent);
+    let csr = params
+        .serialize_request(&key)
+        .and_then(|csr| csr.pem())
+        .map_err(|e| e.to_string())?;
+    Ok((csr, key.serialize_pem()))
+}
+
+/// Validity period of the first certificate in a PEM bundle
+pub fn validity(pem: &str) -> Option<(SystemTime, SystemTime)> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).ok()?;
+    let cert = pem.parse_x509().ok()?;
+    let time = |at: i64| UNIX_EPOCH + Duration::from_secs(at.max(0) as u64);
+    let validity = cert.validity();
+    Some((
+        time(validity.not_before.timestamp()),
+        time(validity.not_after.timestamp()),
+    ))
+}
+
+/// Whether less than a third of a certificate's lifetime remains
+pub fn needs_renewal(pem: &str) -> bool {
+    let Some((not_before, not_after)) = validity(pem) else {
+        return true;
+    };
+    let lifetime = not_after.duration_since(not_before).unwrap_or_default();
+    let remaining = not_after
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    remaining < lifetime / 3
+}
+
+/// Common name of a DER-encoded peer certificate
+pub fn peer_identity(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    let name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+    Some(name.to_string())
+}
+
+/// Write a file only readable by its owner, e.g. holding keys or credentials
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
+
+fn read(path: &Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path)
+        .map_err(|e| ConfigError(format!("failed to read {}: {e}", path.display())))
+}
+
+/// Server side of the mutual TLS channel; certificates are rotated without a restart
+pub struct MtlsServer {
+    config: MtlsConfig,
+    ca: Option<CertificateAuthority>,
+    acceptor: RwLock<TlsAcceptor>,
+    /// Certificate material the acceptor was built from
+    loaded: RwLock<String>,
+}
+
+impl MtlsServer {
+    pub fn from_config(config: &MtlsConfig) -> Result<Self, ConfigError> {
+        let ca = match config.built_in_ca() {
+            true => Some(CertificateAuthority::load_or_create(&config.ca_dir)?),
+            false => None,
+        };
+        let (cert, key, client_ca) = material(config, ca.as_ref()).map_err(ConfigError)?;
+        Ok(Self {
+            config: config.clone(),
+            ca,
+            acceptor: RwLock::new(
+                acceptor(&cert, &key, Some(&client_ca), true).map_err(ConfigError)?,
+            ),
+            loaded: RwLock::new(format!("{cert}{key}{client_ca}")),
+        })
+    }
+
+    pub fn listen(&self) -> &str {
+        &self.config.listen
+    }
+
+    /// The built-in CA, when certificates are not provided externally
+    pub fn ca(&self) -> Option<&CertificateAuthority> {
+        self.ca.as_ref()
+    }
+
+    /// Lifetime of certificates issued to agents
+    pub fn cert_validity(&self) -> Duration {
+        self.config.cert_validity
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        self.acceptor.read().unwrap().clone()
+    }
+
+    /// Renew the built-in server certificate or pick up changed files every minute
+    pub fn spawn_rotation(self: &Arc<Self>) {
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(60));
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = server.reload() {
+                    warn!("Failed to reload mutual TLS certificates: {}", e);
+                }
+            }
+        });
+    }
+
+    fn reload(&self) -> Result<(), String> {
+        let (cert, key, client_ca) = material(&self.config, self.ca.as_ref())?;
+        let loaded = format!("{cert}{key}{client_ca}");
+        if loaded == *self.loaded.read().unwrap() {
+            return Ok(());
+        }
+        *self.acceptor.write().unwrap() = acceptor(&cert, &key, Some(&client_ca), true)?;
+        *self.loaded.write().unwrap() = loaded;
+        info!("Reloaded mutual TLS server certificate");
+        Ok(())
+    }
+}
+
+/// TLS of the HTTP API; the certificate and key are reloaded on SIGHUP and when the files
+/// change, connections in progress keeping the previous ones
+pub struct TlsServer {
+    config: TlsConfig,
+    http2: bool,
+    acceptor: RwLock<TlsAcceptor>,
+    /// Certificate material the acceptor was built from
+    loaded: RwLock<String>,
+}
+
+impl TlsServer {
+    /// Load the certificate and key, offering HTTP/2 through ALPN when `http2` is set
+    pub fn from_config(config: &TlsConfig, http2: bool) -> Result<Self, ConfigError> {
+        let cert = read(&config.cert)?;
+        let key = read(&config.key)?;
+        let acceptor = acceptor(&cert, &key, None, http2)
+            .map_err(|e| ConfigError(format!("server.tls: {e}")))?;
+        Ok(Self {
+            config: config.clone(),
+            http2,
+            acceptor: RwLock::new(acceptor),
+            loaded: RwLock::new(format!("{cert}{key}")),
+        })
+    }
+
+    pub fn acceptor(&self) -> TlsAcceptor {
+        self.acceptor.read().unwrap().clone()
+    }
+
+    /// Reload on SIGHUP, and when the files have changed, checking every 10 seconds
+    pub fn spawn_reload(self: &Arc<Self>) {
+        let server = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut hangups = Hangups::new();
+            let mut ticker = interval(Duration::from_secs(10));
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = hangups.recv() => info!("SIGHUP received, reloading the TLS certificate"),
+                }
+                if let Err(e) = server.reload() {
+                    warn!("Failed to reload TLS certificate: {}", e);
+                }
+            }
+        });
+    }
+
+    fn reload(&self) -> Result<(), String> {
+        let read = |path: &Path| {
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))
+        };
+        let (cert, key) = (read(&self.config.cert)?, read(&self.config.key)?);
+        let loaded = format!("{cert}{key}");
+        if loaded == *self.loaded.read().unwrap() {
+            return Ok(());
+        }
+        // A certificate written before its key fails here and is picked up on the next check
+        *self.acceptor.write().unwrap() = acceptor(&cert, &key, None, self.http2)?;
+        *self.loaded.write().unwrap() = loaded;
+        info!("Reloaded TLS certificate");
+        Ok(())
+    }
+}
+
+// Current server certificate, key and client CA, issuing a new server certificate from the
+// built-in CA when it is missing or due for renewal
+fn material(
+    config: &MtlsConfig,
+    ca: Option<&CertificateAuthority>,
+) -> Result<(String, String, String), String> {
+    let read =
+        |path: &Path| std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()));
+    let (cert, key, client_ca) = match ca {
+        Some(ca) => {
+            let cert_path = config.ca_dir.join("server.pem");
+            let key_path = config.ca_dir.join("server-key.pem");
+            match (read(&cert_path), read(&key_path)) {
+                (Ok(cert), Ok(key)) if !needs_renewal(&cert) => (cert, key, ca.pem.clone()),
+                _ => {
+                    let (cert, key) =
+                        ca.issue_server(&config.server_names, config.cert_validity)?;
+                    write_private(&key_path, key.as_bytes())
+                        .and_then(|_| std::fs::write(&cert_path, &cert))
+                        .map_err(|e| format!("failed to store server certificate: {e}"))?;
+                    info!("Issued mutual TLS server certificate");
+                    (cert, key, ca.pem.clone())
+                }
+            }
+        }
+        None => match (&config.cert, &config.key, &config.client_ca) {
+            (Some(cert), Some(key), Some(client_ca)) => (read(cert)?, read(key)?, read(client_ca)?),
+            _ => {
+                return Err(
+                    "aggregator.mtls needs cert, key and client_ca, or none of them".to_string(),
+                );
+            }
+        },
+    };
+    Ok((cert, key, client_ca))
+}
+
+// Server configuration, requiring client certificates signed by `client_ca` if given
+fn acceptor(
+    cert: &str,
+    key: &str,
+    client_ca: Option<&str>,
+    http2: bool,
+) -> Result<TlsAcceptor, String> {
+    let certs = |pem: &str| {
+        rustls_pemfile::certs(&mut pem.as_bytes())
+            .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+            .map_err(|e| format!("invalid certificate: {e}"))
+    };
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut key.as_bytes())
+        .map_err(|e| format!("invalid private key: {e}"))?
+        .ok_or("no private key found")?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for ca in certs(client_ca)? {
+                roots
+                    .add(ca)
+                    .map_err(|e| format!("invalid client CA: {e}"))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| e.to_string())?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder
+        .with_single_cert(certs(cert)?, key)
+        .map_err(|e| e.to_string())?;
+    config.alpn_protocols = match http2 {
+        true => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        false => vec![b"http/1.1".to_vec()],
+    };
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incomplete_external_certificates_are_an_error() {
+        let config: MtlsConfig =
+            toml::from_str("cert = \"server.pem\"\nkey = \"server.key\"").unwrap();
+        let error = MtlsServer::from_config(&config).err().unwrap();
+        assert_eq!(
+            error.0,
+            "aggregator.mtls needs cert, key and client_ca, or none of them"
+        );
+    }
+}
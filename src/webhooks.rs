@@ -0,0 +1,396 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Meter;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{interval, sleep};
+use tracing::{debug, info, warn};
+
+use crate::agent::is_final;
+use crate::checks::{CheckEvent, CheckRegistry};
+use crate::config::ResultWebhookConfig;
+
+/// Timeout of a single batch delivery
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Batch of results delivered to a result webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookBatch {
+    pub webhook: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+/// A completed check execution. Batches may be delivered more than once, e.g. when an
+/// acknowledgement is lost, so receivers deduplicate on `id`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: String,
+    #[serde(flatten)]
+    pub event: CheckEvent,
+}
+
+#[derive(Default)]
+struct Stats {
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    buffered: AtomicU64,
+}
+
+enum DeliveryError {
+    /// The receiver rejected the batch; retrying will not help
+    Rejected(String),
+    Retryable(String),
+}
+
+/// Delivers every result of the selected checks to a URL, in batches
+pub struct ResultWebhook {
+    config: ResultWebhookConfig,
+    client: reqwest::Client,
+    /// Random per process, so event ids stay unique across restarts
+    instance: String,
+    sequence: AtomicU64,
+    /// Results waiting for delivery, at most `max_buffer`
+    buffer: Mutex<VecDeque<WebhookEvent>>,
+    /// Woken once a full batch is buffered
+    batch_ready: Notify,
+    stats: Arc<Stats>,
+}
+
+/// Start a delivery task per configured webhook
+pub fn spawn(configs: &[ResultWebhookConfig], registry: &CheckRegistry, meter: &Meter) {
+    let webhooks: Vec<_> = configs.iter().map(ResultWebhook::from_config).collect();
+    register_metrics(&webhooks, meter);
+    for webhook in webhooks {
+        webhook.spawn(registry.subscribe());
+    }
+}
+
+impl ResultWebhook {
+    pub fn from_config(config: &ResultWebhookConfig) -> Self {
+        Self {
+            config: config.clone(),
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap(),
+            instance: format!("{:016x}", rand::random::<u64>()),
+            sequence: AtomicU64::new(0),
+            buffer: Mutex::default(),
+            batch_ready: Notify::new(),
+            stats: Arc::default(),
+        }
+    }
+
+    /// Buffer results of the selected checks and flush batches on size or interval. Delivery
+    /// runs in its own task, so a slow or unreachable URL never holds up receiving results
+    pub fn spawn(self, mut events: broadcast::Receiver<CheckEvent>) {
+        info!(
+            "Result webhook {} delivering to {}",
+            self.config.name, self.config.url
+        );
+        let webhook = Arc::new(self);
+        let receiving = Arc::clone(&webhook);
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => receiving.enqueue(event),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Result webhook {} lagged behind, {} results dropped",
+                            receiving.config.name, skipped
+                        );
+                        receiving
+                            .stats
+                            .dropped
+                            .fetch_add(skipped, Ordering::Relaxed);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        tokio::spawn(async move {
+            let mut ticker = interval(webhook.config.flush_interval);
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = webhook.batch_ready.notified() => {}
+                }
+                webhook.flush().await;
+            }
+        });
+    }
+
+    fn enqueue(&self, event: CheckEvent) {
+        let selected = self.config.checks.is_empty() || self.config.checks.contains(&event.check);
+        if !selected {
+            return;
+        }
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        let event = WebhookEvent {
+            id: format!("{}-{}", self.instance, sequence),
+            event,
+        };
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.push_back(event);
+        self.trim(&mut buffer);
+        if buffer.len() >= self.config.batch_size {
+            self.batch_ready.notify_one();
+        }
+    }
+
+    // Drop the oldest results beyond `max_buffer`
+    fn trim(&self, buffer: &mut VecDeque<WebhookEvent>) {
+        let excess = buffer.len().saturating_sub(self.config.max_buffer);
+        if excess > 0 {
+            buffer.drain(..excess);
+            self.stats
+                .dropped
+                .fetch_add(excess as u64, Ordering::Relaxed);
+        }
+        self.stats
+            .buffered
+            .store(buffer.len() as u64, Ordering::Relaxed);
+    }
+
+    async fn flush(&self) {
+        loop {
+            let events: Vec<_> = {
+                let mut buffer = self.buffer.lock().unwrap();
+                let size = buffer.len().min(self.config.batch_size);
+                buffer.drain(..size).collect()
+            };
+            if events.is_empty() {
+                return;
+            }
+            let size = events.len();
+            let batch = WebhookBatch {
+                webhook: self.config.name.clone(),
+                events,
+            };
+            match self.deliver_with_retry(&batch).await {
+                Ok(()) => {
+                    debug!("Delivered {} results to {}", size, self.config.name);
+                    self.stats
+                        .delivered
+                        .fetch_add(size as u64, Ordering::Relaxed);
+                }
+                Err(DeliveryError::Rejected(e)) => {
+                    warn!("Result webhook {} rejected batch: {}", self.config.name, e);
+                    self.stats.dropped.fetch_add(size as u64, Ordering::Relaxed);
+                }
+                Err(DeliveryError::Retryable(e)) => {
+                    // Put the results back in front of those received meanwhile, for the next
+                    // flush
+                    warn!(
+                        "Delivery to result webhook {} failed: {}",
+                        self.config.name, e
+                    );
+                    let mut buffer = self.buffer.lock().unwrap();
+                    for event in batch.events.into_iter().rev() {
+                        buffer.push_front(event);
+                    }
+                    self.trim(&mut buffer);
+                    return;
+                }
+            }
+            let buffered = self.buffer.lock().unwrap().len() as u64;
+            self.stats.buffered.store(buffered, Ordering::Relaxed);
+        }
+    }
+
+    async fn deliver_with_retry(&self, batch: &WebhookBatch) -> Result<(), DeliveryError> {
+        let mut attempt = 0;
+        loop {
+            match self.deliver(batch).await {
+                Err(DeliveryError::Retryable(e)) if attempt < self.config.max_retries => {
+                    let backoff = Duration::from_millis(500 << attempt.min(6));
+                    debug!("Delivery failed ({}), retrying in {:?}", e, backoff);
+                    sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn deliver(&self, batch: &WebhookBatch) -> Result<(), DeliveryError> {
+        let mut request = self.client.post(&self.config.url).json(batch);
+        if let Some(token) = &self.config.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| DeliveryError::Retryable(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(())
+        } else if !is_final(status) {
+            Err(DeliveryError::Retryable(format!(
+                "receiver returned {status}"
+            )))
+        } else {
+            Err(DeliveryError::Rejected(format!(
+                "receiver returned {status}"
+            )))
+        }
+    }
+}
+
+fn register_metrics(webhooks: &[ResultWebhook], meter: &Meter) {
+    let stats: Vec<_> = webhooks
+        .iter()
+        .map(|w| (w.config.name.clone(), Arc::clone(&w.stats)))
+        .collect();
+    let counted = stats.clone();
+    meter
+        .u64_observable_counter("webhook.results")
+        .with_description("Results handed to result webhooks, by outcome")
+        .with_callback(move |observer| {
+            for (name, stats) in &counted {
+                for (outcome, count) in
+                    [("delivered", &stats.delivered), ("dropped", &stats.dropped)]
+                {
+                    let attributes = [
+                        KeyValue::new("webhook", name.clone()),
+                        KeyValue::new("outcome", outcome),
+                    ];
+                    observer.observe(count.load(Ordering::Relaxed), &attributes);
+                }
+            }
+        })
+        .build();
+    meter
+        .u64_observable_gauge("webhook.buffered_results")
+        .with_description("Results waiting to be delivered to result webhooks")
+        .with_callback(move |observer| {
+            for (name, stats) in &stats {
+                let buffered = stats.buffered.load(Ordering::Relaxed);
+                observer.observe(buffered, &[KeyValue::new("webhook", name.clone())]);
+            }
+        })
+        .build();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checks::CheckResult;
+    use axum::{Json, Router, http::StatusCode, routing::post};
+    use std::sync::Mutex;
+
+    fn webhook(url: String, checks: &[&str]) -> ResultWebhook {
+        let config = format!(
+            "name = \"analytics\"\nurl = \"{url}\"\nchecks = {checks:?}\nbatch_size = 2\nmax_retries = 0"
+        );
+        ResultWebhook::from_config(&toml::from_str(&config).unwrap())
+    }
+
+    fn event(check: &str) -> CheckEvent {
+        CheckEvent {
+            check: check.into(),
+            previous: None,
+            result: CheckResult::up(),
+        }
+    }
+
+    // Receiver answering with the given statuses in turn, recording the batches it accepted
+    async fn receiver(statuses: Vec<StatusCode>) -> (String, Arc<Mutex<Vec<WebhookBatch>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+        let recorded = received.clone();
+        let app = Router::new().route(
+            "/events",
+            post(move |Json(batch): Json<WebhookBatch>| async move {
+                let status = statuses
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+                    .unwrap_or(StatusCode::OK);
+                if status.is_success() {
+                    recorded.lock().unwrap().push(batch);
+                }
+                status
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn delivers_results_of_selected_checks_in_batches() {
+        let (url, received) = receiver(vec![]).await;
+        let webhook = webhook(url, &["db", "cache"]);
+        for check in ["db", "api", "cache", "db"] {
+            webhook.enqueue(event(check));
+        }
+        webhook.flush().await;
+        let received = received.lock().unwrap();
+        let batches: Vec<Vec<_>> = received
+            .iter()
+            .map(|b| b.events.iter().map(|e| e.event.check.as_str()).collect())
+            .collect();
+        assert_eq!(batches, [vec!["db", "cache"], vec!["db"]]);
+        let ids: std::collections::BTreeSet<_> = received
+            .iter()
+            .flat_map(|b| &b.events)
+            .map(|e| &e.id)
+            .collect();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(webhook.stats.delivered.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn keeps_results_until_they_are_acknowledged() {
+        let (url, received) = receiver(vec![StatusCode::SERVICE_UNAVAILABLE]).await;
+        let webhook = webhook(url, &[]);
+        webhook.enqueue(event("db"));
+        webhook.flush().await;
+        assert_eq!(webhook.buffer.lock().unwrap().len(), 1);
+        assert!(received.lock().unwrap().is_empty());
+        webhook.flush().await;
+        assert!(webhook.buffer.lock().unwrap().is_empty());
+        assert_eq!(
+            received.lock().unwrap()[0].events[0].id,
+            format!("{}-1", webhook.instance)
+        );
+    }
+
+    #[tokio::test]
+    async fn drops_rejected_batches() {
+        let (url, received) = receiver(vec![StatusCode::BAD_REQUEST]).await;
+        let webhook = webhook(url, &[]);
+        webhook.enqueue(event("db"));
+        webhook.flush().await;
+        assert!(webhook.buffer.lock().unwrap().is_empty() && received.lock().unwrap().is_empty());
+        assert_eq!(webhook.stats.dropped.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn keeps_receiving_while_a_delivery_hangs() {
+        let app = Router::new().route("/events", post(std::future::pending::<StatusCode>));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let webhook = webhook(url, &[]);
+        let stats = Arc::clone(&webhook.stats);
+        let (sender, receiver) = broadcast::channel(4);
+        webhook.spawn(receiver);
+        for _ in 0..50 {
+            sender.send(event("db")).unwrap();
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 0);
+        // The first batch is stuck in delivery, the rest stays buffered
+        let buffered = stats.buffered.load(Ordering::Relaxed);
+        assert!((48..50).contains(&buffered), "{buffered}");
+    }
+}